@@ -0,0 +1,59 @@
+//! `--background`: runs generation at idle priority, and on efficiency cores where the OS
+//! supports it, so the machine stays responsive while a world is generated.
+
+/// Lowers the priority of the whole process and the current thread.
+/// Threads started afterwards should call `lower_current_thread` as well.
+pub fn enter_background_mode() {
+    #[cfg(target_os = "windows")]
+    unsafe {
+        use windows::Win32::System::Threading::{
+            GetCurrentProcess, ProcessPowerThrottling, SetPriorityClass, SetProcessInformation,
+            IDLE_PRIORITY_CLASS, PROCESS_MODE_BACKGROUND_BEGIN,
+            PROCESS_POWER_THROTTLING_CURRENT_VERSION, PROCESS_POWER_THROTTLING_EXECUTION_SPEED,
+            PROCESS_POWER_THROTTLING_STATE,
+        };
+
+        let process = GetCurrentProcess();
+        let _ = SetPriorityClass(process, IDLE_PRIORITY_CLASS);
+        // Also lowers disk and memory priority
+        let _ = SetPriorityClass(process, PROCESS_MODE_BACKGROUND_BEGIN);
+
+        // EcoQoS: prefer efficiency cores and low clock speeds
+        let state = PROCESS_POWER_THROTTLING_STATE {
+            Version: PROCESS_POWER_THROTTLING_CURRENT_VERSION,
+            ControlMask: PROCESS_POWER_THROTTLING_EXECUTION_SPEED,
+            StateMask: PROCESS_POWER_THROTTLING_EXECUTION_SPEED,
+        };
+        let _ = SetProcessInformation(
+            process,
+            ProcessPowerThrottling,
+            &state as *const _ as *const std::ffi::c_void,
+            std::mem::size_of::<PROCESS_POWER_THROTTLING_STATE>() as u32,
+        );
+    }
+
+    lower_current_thread();
+}
+
+/// Lowers the priority of the calling thread
+pub fn lower_current_thread() {
+    // Linux schedules threads individually, new threads inherit the policy of their creator
+    #[cfg(target_os = "linux")]
+    unsafe {
+        let param = libc::sched_param { sched_priority: 0 };
+        if libc::sched_setscheduler(0, libc::SCHED_IDLE, &param) != 0 {
+            libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+        }
+    }
+
+    // The background QoS class runs on the efficiency cores of Apple Silicon
+    #[cfg(target_os = "macos")]
+    unsafe {
+        libc::pthread_set_qos_class_self_np(libc::qos_class_t::QOS_CLASS_BACKGROUND, 0);
+    }
+
+    #[cfg(all(unix, not(any(target_os = "linux", target_os = "macos"))))]
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, 0, 19);
+    }
+}
@@ -1,19 +1,35 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use arnis_core::block_palette::{self, BlockPalette};
+use arnis_core::categories::{Category, FeatureFilter};
+use arnis_core::coordinate_system::cartesian::XZBBox;
+use arnis_core::coordinate_system::geographic::LLBBox;
+use arnis_core::coordinate_system::transformation::{geo_distance, CoordTransformer};
 #[cfg(feature = "metrics")]
 use arnis_core::metrics::MetricsRecorder;
+use arnis_core::osm_parser::RawOsmData;
+use arnis_core::tag_coverage::TagCoverage;
 use arnis_core::{
-    data_processing, ground, map_transformation, osm_parser, retrieve_data, version_check, Args,
+    address_lots, api_scheduler, data_processing, diff, disk_io, footprints, geojson_export, gpx,
+    ground, inspect, low_memory, map_transformation, merge, osm_cache, osm_parser, osm_xml,
+    overture, pbf, perf_config, retrieve_data, schematic, seeded_rng, simplify, stats_overlay,
+    telemetry, trim, update, vector_tiles, version_check, world_hash, Args, ArnisError,
     PerformanceConfig,
 };
 use clap::Parser;
 use colored::*;
 use rayon::ThreadPoolBuilder;
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{env, fs, io::Write};
 
 #[cfg(feature = "gui")]
 use arnis_core::gui;
 
+mod background;
+
 #[cfg(target_os = "windows")]
 use windows::Win32::System::Console::{AttachConsole, FreeConsole, ATTACH_PARENT_PROCESS};
 
@@ -47,23 +63,333 @@ fn run_cli() {
         );
     }
 
-    let args: Args = Args::parse();
+    let mut args: Args = Args::parse();
+    if !FeatureFilter::from_args(&args).allows(Some(Category::Terrain)) {
+        args.terrain = false;
+    }
+
+    if let Some(url) = &args.telemetry_url {
+        telemetry::enable(url);
+    }
+    telemetry::enter_stage("setup");
+
+    let perf = PerformanceConfig::init(args.max_ram_gb, args.threads);
+    perf.log_config();
+    let background = args.background;
+    if background {
+        println!("Running in the background at idle priority");
+        background::enter_background_mode();
+    }
+    ThreadPoolBuilder::new()
+        .num_threads(perf.effective_threads)
+        .start_handler(move |_| {
+            if background {
+                background::lower_current_thread();
+            }
+        })
+        .build_global()
+        .ok();
+
+    #[cfg(unix)]
+    install_pause_signal();
+    api_scheduler::set_contact(args.contact.as_deref());
+    api_scheduler::set_retry_policy(api_scheduler::RetryPolicy {
+        max_attempts: args.download_attempts,
+        ..Default::default()
+    });
+    disk_io::set_temp_dir(args.temp_dir.clone());
+    if args.osm_cache {
+        osm_cache::enable();
+    }
+    disk_io::set_write_limit(args.max_write_speed.map(|mb| mb * 1024 * 1024));
+    if let Some(path) = &args.block_palette {
+        let palette = BlockPalette::load(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", "Error loading block palette".red().bold(), e);
+            telemetry::exit_failure();
+        });
+        let mods = palette.required_mods(args.vanilla_fallback);
+        if !mods.is_empty() {
+            let mods: Vec<String> = mods.into_iter().collect();
+            println!("The world will need these mods: {}", mods.join(", "));
+        }
+        block_palette::set_palette(Some(palette), args.vanilla_fallback);
+    }
+    let second_palette: Option<BlockPalette> = args.second_palette.as_ref().map(|path| {
+        BlockPalette::load(path).unwrap_or_else(|e| {
+            eprintln!(
+                "{}: {}",
+                "Error loading second block palette".red().bold(),
+                e
+            );
+            telemetry::exit_failure();
+        })
+    });
+    if let Some(seed) = args.seed {
+        seeded_rng::set_seed(seed);
+    } else if second_palette.is_some() {
+        // Both worlds get the same random details, so only the palette differs
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos());
+        seeded_rng::set_seed(nanos as u64);
+    }
+
+    // The area is resolved before anything else, so every mode generates the same area.
+    // In route mode, the bounding box is derived from the GPX track.
+    let route = args.gpx.as_deref().map(|gpx_path| {
+        let route = gpx::load_route(gpx_path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", "Error loading GPX track".red().bold(), e);
+            telemetry::exit_failure();
+        });
+        let corridor = gpx::corridor_bbox(&route, args.corridor_width).unwrap_or_else(|e| {
+            eprintln!("{}: {}", "Error building route corridor".red().bold(), e);
+            telemetry::exit_failure();
+        });
+        args.bbox = Some(corridor);
+        route
+    });
 
-    let raw_data = match &args.file {
-        Some(file) => retrieve_data::fetch_data_from_file(file),
-        None => retrieve_data::fetch_data_from_overpass(
-            args.bbox,
+    // With additional bounding boxes, the world covers the area enclosing all of them
+    let areas: Vec<LLBBox> = std::iter::once(args.bbox())
+        .chain(args.extra_bbox.iter().copied())
+        .collect();
+    let enclosing = areas[1..]
+        .iter()
+        .try_fold(areas[0], |enclosing, area| enclosing.union(area))
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {}", "Error".red().bold(), e);
+            telemetry::exit_failure();
+        });
+    args.bbox = Some(enclosing);
+
+    // In low memory mode, every part is generated by a separate run, so all of its memory is
+    // returned before the next part starts
+    if args.low_memory {
+        let mut command_line: Vec<String> = env::args().skip(1).collect();
+        if args.seed.is_none() {
+            // All parts get the same random details
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_nanos());
+            command_line.push(format!("--seed={}", nanos as u64));
+        }
+        let exe = env::current_exe().unwrap_or_else(|e| {
+            eprintln!("{}: {}", "Error locating the executable".red().bold(), e);
+            telemetry::exit_failure();
+        });
+        let result = low_memory::generate_in_parts(&args, perf, |part| {
+            let status = Command::new(&exe)
+                .args(low_memory::part_command_line(&command_line, part))
+                .status()
+                .map_err(|e| ArnisError::Io(format!("Failed to start {}: {e}", exe.display())))?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(ArnisError::Io(format!("Generation exited with {status}")))
+            }
+        });
+        if let Err(e) = result {
+            eprintln!(
+                "{}: {}",
+                "Error generating in low memory mode".red().bold(),
+                e
+            );
+            telemetry::exit_failure();
+        }
+        verify_world(&args);
+        write_into_world(&args);
+        export_schematic(&args);
+        telemetry::finish(true);
+        return;
+    }
+
+    // An update is generated into a staging world, from which only the changed chunks are
+    // copied into the world
+    let update_world = args.update.then(|| {
+        let staging = update::staging_world(&args.path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", "Error preparing the update".red().bold(), e);
+            telemetry::exit_failure();
+        });
+        std::mem::replace(&mut args.path, staging)
+    });
+    let snapshot = update_world.as_deref().map(|world| {
+        update::load_snapshot(world).unwrap_or_else(|e| {
+            eprintln!("{}: {}", "Error".red().bold(), e);
+            telemetry::exit_failure();
+        })
+    });
+
+    let query_options = retrieve_data::QueryOptions {
+        date: args.date.clone(),
+        template: args.query_template.as_deref().map(|path| {
+            retrieve_data::load_query_template(path).unwrap_or_else(|e| {
+                eprintln!("{}: {}", "Error loading query template".red().bold(), e);
+                telemetry::exit_failure();
+            })
+        }),
+        filter: FeatureFilter::from_args(&args),
+    };
+
+    // Fetches the current data, or an older snapshot of the same area when comparing
+    let fetch_data = |file: Option<&str>,
+                      query_options: &retrieve_data::QueryOptions,
+                      save_file: Option<&str>| match (file, &route) {
+        (Some(file), _) if pbf::is_pbf(file) => {
+            pbf::fetch_data_from_pbf(file, args.bbox()).map(RawOsmData::from)
+        }
+        (Some(file), _) if osm_xml::is_osm_xml(file) => {
+            retrieve_data::fetch_data_from_osm_xml(file).map(RawOsmData::from)
+        }
+        (Some(file), _) => retrieve_data::fetch_raw_from_file(file).map(RawOsmData::from),
+        (None, _) if !args.overture.is_empty() => {
+            overture::fetch_data_from_overture(args.bbox(), &args.overture).map(RawOsmData::from)
+        }
+        (None, _) if args.tile_url.is_some() => vector_tiles::fetch_data_from_tiles(
+            args.bbox(),
+            args.tile_url.as_deref().unwrap_or_default(),
+            args.tile_zoom,
+        )
+        .map(RawOsmData::from),
+        (None, Some(route)) => retrieve_data::fetch_data_along_route(
+            route,
+            args.corridor_width,
+            query_options,
             args.debug,
             args.downloader.as_str(),
-            args.save_json_file.as_deref(),
-        ),
+            save_file,
+        )
+        .map(RawOsmData::from),
+        (None, None) if areas.len() > 1 => retrieve_data::fetch_data_from_bboxes(
+            &areas,
+            query_options,
+            args.debug,
+            args.downloader.as_str(),
+            save_file,
+        )
+        .map(RawOsmData::from),
+        (None, None) => retrieve_data::fetch_raw_from_overpass(
+            args.bbox(),
+            query_options,
+            args.downloader.as_str(),
+            save_file,
+        )
+        .map(RawOsmData::from),
+    };
+
+    telemetry::enter_stage("fetch");
+    let raw_data = fetch_data(
+        args.file.as_deref(),
+        &query_options,
+        args.save_json_file.as_deref(),
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("{}: {}", "Failed to fetch data".red().bold(), e);
+        telemetry::exit_failure();
+    });
+    // An update keeps the new data in the staging world, from where it replaces the old
+    if args.track_updates || args.update {
+        if let Err(e) = update::save_snapshot(&args.path, &raw_data) {
+            eprintln!("Warning: {e}");
+        }
     }
-    .expect("Failed to fetch data");
+    let compare_data = snapshot.or_else(|| {
+        (args.compare_file.is_some() || args.compare_date.is_some()).then(|| {
+            let compare_options = retrieve_data::QueryOptions {
+                date: args.compare_date.clone(),
+                ..query_options.clone()
+            };
+            fetch_data(args.compare_file.as_deref(), &compare_options, None).unwrap_or_else(|e| {
+                eprintln!(
+                    "{}: {}",
+                    "Failed to fetch data to compare against".red().bold(),
+                    e
+                );
+                telemetry::exit_failure();
+            })
+        })
+    });
 
+    telemetry::enter_stage("parse");
     let mut ground = ground::generate_ground_data(&args);
 
     let (mut parsed_elements, mut xzbbox) =
-        osm_parser::parse_osm_data(raw_data, args.bbox, args.scale, args.debug);
+        osm_parser::parse_osm_data(raw_data, args.bbox(), args.scale, args.debug, args.strict)
+            .unwrap_or_else(|e| {
+                eprintln!("{}: {}", "Failed to parse OSM data".red().bold(), e);
+                telemetry::exit_failure();
+            });
+    if !args.footprints.is_empty() {
+        match footprints::merge_footprints(
+            &mut parsed_elements,
+            &args.footprints,
+            &args.bbox(),
+            args.scale,
+        ) {
+            Ok(added) => println!("Added {added} buildings from footprint data"),
+            Err(e) => {
+                eprintln!("{}: {}", "Failed to load footprints".red().bold(), e);
+                telemetry::exit_failure();
+            }
+        }
+    }
+    if args.address_lots {
+        let added = address_lots::add_address_lots(&mut parsed_elements, args.scale);
+        println!("Added {added} houses for addresses without buildings");
+    }
+    if !args.no_simplify {
+        let simplified = simplify::simplify_for_scale(&mut parsed_elements, args.scale);
+        if simplified != simplify::SimplifyStats::default() {
+            println!(
+                "Simplified for scale {}: merged {} small buildings, dropped {} tiny features",
+                args.scale, simplified.merged_buildings, simplified.dropped
+            );
+        }
+    }
+    if let Some(route) = &route {
+        xzbbox = gpx::corridor_xzbbox(route, &args.bbox(), args.scale, args.corridor_width)
+            .unwrap_or_else(|e| {
+                eprintln!("{}: {}", "Error building route corridor".red().bold(), e);
+                telemetry::exit_failure();
+            });
+    } else if areas.len() > 1 && !args.fill_gaps {
+        // Leave the gaps between the areas as void
+        let (transformer, _) = CoordTransformer::llbbox_to_xzbbox(&args.bbox(), args.scale)
+            .expect("Failed to transform bounding box");
+        let parts: Vec<XZBBox> = areas
+            .iter()
+            .map(|area| transformer.transform_llbbox(area))
+            .collect::<Result<_, _>>()
+            .expect("Failed to transform bounding box");
+        xzbbox = XZBBox::union(parts).expect("Failed to merge bounding boxes");
+    }
+
+    // Shows why features are missing from the world
+    let coverage = TagCoverage::collect(&parsed_elements);
+    coverage.print_summary(if args.debug { usize::MAX } else { 5 });
+    if let Some(path) = &args.unknown_tags_out {
+        if let Err(e) = coverage.write_json(path) {
+            eprintln!("{}: {}", "Failed to write unknown tags".red().bold(), e);
+        }
+    }
+
+    // Lets users check in GIS tools what was understood of the data, before any transformation
+    if let (Some(layers), Some(save_file)) = (&args.export_geojson, &args.save_json_file) {
+        let dir = Path::new(save_file)
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        match geojson_export::export_geojson(
+            &parsed_elements,
+            &args.bbox(),
+            args.scale,
+            layers,
+            dir,
+        ) {
+            Ok(paths) => println!("Saved {} GeoJSON layers to: {}", paths.len(), dir.display()),
+            Err(e) => eprintln!("{}: {}", "Failed to export GeoJSON".red().bold(), e),
+        }
+    }
+
     parsed_elements
         .sort_by_key(|element: &osm_parser::ProcessedElement| osm_parser::get_priority(element));
 
@@ -83,8 +409,151 @@ fn run_cli() {
         }
     }
 
-    map_transformation::transform_map(&mut parsed_elements, &mut xzbbox, &mut ground);
-    let _ = data_processing::generate_world(parsed_elements, xzbbox, args.bbox, ground, &args);
+    let mut changed_chunks = HashSet::new();
+    // Changes are computed on the transformed elements, so both snapshots are transformed alike
+    let changes: Vec<diff::ElementChange> = match compare_data {
+        Some(compare_data) => {
+            let (mut old_elements, _) = osm_parser::parse_osm_data(
+                compare_data,
+                args.bbox(),
+                args.scale,
+                args.debug,
+                args.strict,
+            )
+            .unwrap_or_else(|e| {
+                eprintln!(
+                    "{}: {}",
+                    "Failed to parse data to compare against".red().bold(),
+                    e
+                );
+                telemetry::exit_failure();
+            });
+            if !args.no_simplify {
+                simplify::simplify_for_scale(&mut old_elements, args.scale);
+            }
+            map_transformation::transform_map(
+                &mut old_elements,
+                &mut xzbbox.clone(),
+                &mut ground.clone(),
+            );
+            map_transformation::transform_map(&mut parsed_elements, &mut xzbbox, &mut ground);
+
+            let changes = diff::diff_elements(&old_elements, &parsed_elements);
+            let (added, removed, changed) = diff::summarize(&changes);
+            if args.update {
+                println!(
+                    "Changes since the last update: {added} added, {removed} removed, {changed} changed"
+                );
+                changed_chunks = update::changed_chunks(&old_elements, &parsed_elements);
+                Vec::new()
+            } else {
+                println!(
+                    "Highlighting changes: {added} added, {removed} removed, {changed} changed"
+                );
+                changes
+            }
+        }
+        None => {
+            map_transformation::transform_map(&mut parsed_elements, &mut xzbbox, &mut ground);
+            Vec::new()
+        }
+    };
+
+    if let Some(world) = &update_world {
+        if changed_chunks.is_empty() {
+            println!(
+                "Nothing changed since the last update of {}",
+                world.display()
+            );
+            let _ = fs::remove_dir_all(&args.path);
+            telemetry::finish(true);
+            return;
+        }
+    }
+
+    let (height_m, width_m) = geo_distance(args.bbox().min(), args.bbox().max());
+    telemetry::set_area(
+        height_m * width_m / 1_000_000.0,
+        xzbbox.bounding_rect().total_blocks(),
+        parsed_elements.len(),
+    );
+
+    // Refuse areas that would not fit into memory before spending time on them
+    let estimated_bytes = perf_config::estimate_generation_bytes(
+        xzbbox.bounding_rect().total_blocks(),
+        parsed_elements.len(),
+        args.fillground,
+    );
+    if let Err(e) = perf.check_memory_budget(estimated_bytes) {
+        eprintln!("{}: {}", "Error".red().bold(), e);
+        telemetry::exit_failure();
+    }
+
+    telemetry::enter_stage("generate");
+    // The second world is generated from the same elements, so they are kept for it
+    let second_run = second_palette
+        .is_some()
+        .then(|| (parsed_elements.clone(), xzbbox.clone(), ground.clone()));
+    let generated = if let Some(metric) = args.stats {
+        stats_overlay::generate_stats_world(&parsed_elements, xzbbox, args.bbox(), metric, &args)
+    } else {
+        data_processing::generate_world(
+            parsed_elements,
+            xzbbox,
+            args.bbox(),
+            ground,
+            &changes,
+            &args,
+        )
+    };
+    if let Err(e) = generated {
+        eprintln!("{}: {}", "Failed to generate the world".red().bold(), e);
+        telemetry::exit_failure();
+    }
+    if let (Some((elements, xzbbox, ground)), Some(second_path)) = (second_run, &args.second_path) {
+        println!(
+            "{} Generating the second world with the second palette...",
+            "[A/B]".bold()
+        );
+        block_palette::set_palette(second_palette, args.vanilla_fallback);
+        let second_args = Args {
+            path: second_path.clone(),
+            ..args.clone()
+        };
+        let generated = data_processing::generate_world(
+            elements,
+            xzbbox,
+            args.bbox(),
+            ground,
+            &changes,
+            &second_args,
+        );
+        if let Err(e) = generated {
+            eprintln!(
+                "{}: {}",
+                "Failed to generate the second world".red().bold(),
+                e
+            );
+            telemetry::exit_failure();
+        }
+    }
+
+    if let Some(world) = update_world {
+        let applied = update::apply(&args.path, &world, &changed_chunks);
+        let _ = fs::remove_dir_all(&args.path);
+        args.path = world;
+        match applied {
+            Ok(chunks) => println!("{} {chunks} chunks", "Updated".green().bold()),
+            Err(e) => {
+                eprintln!("{}: {}", "Failed to update the world".red().bold(), e);
+                telemetry::exit_failure();
+            }
+        }
+    }
+
+    verify_world(&args);
+    write_into_world(&args);
+    export_schematic(&args);
 
     #[cfg(feature = "metrics")]
     if let Some(metrics_out) = &args.metrics_out {
@@ -95,6 +564,80 @@ fn run_cli() {
             println!("Metrics written to {}", metrics_out.display());
         }
     }
+
+    telemetry::finish(true);
+}
+
+/// Checks the hash of the generated world against the one given with `--verify`
+fn verify_world(args: &Args) {
+    let Some(expected_hash) = &args.verify else {
+        return;
+    };
+    telemetry::enter_stage("verify");
+    let hash = world_hash::world_hash(&args.path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", "Failed to hash world".red().bold(), e);
+        telemetry::exit_failure();
+    });
+    match expected_hash {
+        Some(expected) if !expected.eq_ignore_ascii_case(&hash) => {
+            eprintln!(
+                "{}: expected {expected}, got {hash}",
+                "World hash mismatch".red().bold()
+            );
+            telemetry::exit_failure();
+        }
+        Some(_) => println!("{} {hash}", "World hash verified:".green().bold()),
+        None => println!("World hash: {hash}"),
+    }
+}
+
+/// Writes the generated area into the existing world given with `--into-world`
+fn write_into_world(args: &Args) {
+    let Some(target) = &args.into_world else {
+        return;
+    };
+    telemetry::enter_stage("into-world");
+    match merge::merge_into(&args.path, target, args.offset.unwrap_or_default()) {
+        Ok(chunks) => println!(
+            "{} {} ({chunks} chunks)",
+            "Area written into".green().bold(),
+            target.display()
+        ),
+        Err(e) => {
+            eprintln!("{}: {}", "Failed to write into the world".red().bold(), e);
+            telemetry::exit_failure();
+        }
+    }
+}
+
+/// Writes the generated world as the schematic asked for with `--format`
+fn export_schematic(args: &Args) {
+    if args.format == schematic::OutputFormat::World {
+        return;
+    }
+    telemetry::enter_stage("export");
+    match schematic::export(&args.path, args.format, &args.structure_void) {
+        Ok(path) => println!(
+            "{} {}",
+            "Schematic written to".green().bold(),
+            path.display()
+        ),
+        Err(e) => {
+            eprintln!("{}: {}", "Failed to write schematic".red().bold(), e);
+            telemetry::exit_failure();
+        }
+    }
+}
+
+/// Lets `kill -USR1 <pid>` pause and resume the generation
+#[cfg(unix)]
+fn install_pause_signal() {
+    extern "C" fn on_signal(_: libc::c_int) {
+        arnis_core::pause::toggle();
+    }
+    unsafe {
+        libc::signal(libc::SIGUSR1, on_signal as *const () as libc::sighandler_t);
+    }
 }
 
 fn main() {
@@ -112,12 +655,35 @@ fn main() {
         }
     }
 
-    let perf = PerformanceConfig::init_default();
-    perf.log_config();
-    ThreadPoolBuilder::new()
-        .num_threads(perf.effective_threads)
-        .build_global()
-        .ok();
+    // `arnis inspect ...` examines saved data instead of generating a world
+    if env::args().nth(1).as_deref() == Some("inspect") {
+        let args = inspect::InspectArgs::parse_from(env::args().skip(1));
+        if let Err(e) = inspect::run(args) {
+            eprintln!("{}: {}", "Error".red().bold(), e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // `arnis trim ...` deletes chunks outside of an area from a generated world
+    if env::args().nth(1).as_deref() == Some("trim") {
+        let args = trim::TrimArgs::parse_from(env::args().skip(1));
+        if let Err(e) = trim::run(args) {
+            eprintln!("{}: {}", "Error".red().bold(), e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // `arnis merge ...` combines separately generated worlds into one
+    if env::args().nth(1).as_deref() == Some("merge") {
+        let args = merge::MergeArgs::parse_from(env::args().skip(1));
+        if let Err(e) = merge::run(args) {
+            eprintln!("{}: {}", "Error".red().bold(), e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
     run_cli();
 }
@@ -0,0 +1,266 @@
+//! Houses from addresses (`--address-lots`), for regions mapped only with roads and
+//! addresses. Every address node and every number interpolated along an `addr:interpolation`
+//! way becomes a lot with a small house, unless a mapped building is already there. The
+//! houses are added as building ways, so the building processor generates them.
+
+use crate::coordinate_system::cartesian::XZPoint;
+use crate::footprints::{polygon_of_points, BuildingIndex};
+use crate::osm_parser::{ProcessedElement, ProcessedNode, ProcessedWay};
+use std::collections::HashMap;
+
+/// IDs of generated houses and their nodes start here, apart from OSM and footprint IDs
+const ADDRESS_ID_BASE: u64 = 3 << 61;
+/// Size of a house at scale 1, in blocks
+const HOUSE_WIDTH: f64 = 10.0;
+const HOUSE_DEPTH: f64 = 8.0;
+/// Houses get at most this share of the distance to their neighbors
+const LOT_FILL: f64 = 0.7;
+
+/// A house to place: its center, the direction of the street and its width along it
+struct Lot {
+    center: (f64, f64),
+    direction: (f64, f64),
+    width: f64,
+    housenumber: String,
+}
+
+/// Adds a house for every address without a building, returns how many
+pub fn add_address_lots(elements: &mut Vec<ProcessedElement>, scale: f64) -> usize {
+    let mut lots: Vec<Lot> = Vec::new();
+    for element in elements.iter() {
+        match element {
+            ProcessedElement::Node(node) => {
+                let tags = &node.tags;
+                // Only plain addresses, not shops or other POIs with an address
+                if !tags.contains_key("addr:housenumber")
+                    || tags.keys().any(|key| !key.starts_with("addr:"))
+                {
+                    continue;
+                }
+                lots.push(Lot {
+                    center: (f64::from(node.x), f64::from(node.z)),
+                    direction: (1.0, 0.0),
+                    width: HOUSE_WIDTH * scale,
+                    housenumber: tags["addr:housenumber"].clone(),
+                });
+            }
+            ProcessedElement::Way(way) => {
+                let interpolation = way.tags.get("addr:interpolation");
+                if let Some(step) = interpolation.map(String::as_str).and_then(step_of) {
+                    lots.extend(interpolate(&way.nodes, step, scale));
+                }
+            }
+            ProcessedElement::Relation(_) => {}
+        }
+    }
+
+    let mut index = BuildingIndex::of_buildings(elements);
+    let mut next_id = ADDRESS_ID_BASE;
+    let mut added: usize = 0;
+    for lot in lots {
+        let outline = house_outline(&lot, HOUSE_DEPTH * scale);
+        let polygon = polygon_of_points(&outline);
+        if index.intersects(&polygon) {
+            continue;
+        }
+        index.insert(polygon);
+
+        let mut nodes: Vec<ProcessedNode> = outline[..4]
+            .iter()
+            .map(|point| {
+                next_id += 1;
+                ProcessedNode {
+                    id: next_id,
+                    tags: HashMap::new(),
+                    x: point.x,
+                    z: point.z,
+                }
+            })
+            .collect();
+        nodes.push(nodes[0].clone());
+
+        next_id += 1;
+        elements.push(ProcessedElement::Way(ProcessedWay {
+            id: next_id,
+            nodes,
+            tags: HashMap::from([
+                ("building".to_string(), "house".to_string()),
+                ("addr:housenumber".to_string(), lot.housenumber),
+            ]),
+        }));
+        added += 1;
+    }
+    added
+}
+
+/// Step between house numbers of an interpolation, `None` for letters
+fn step_of(interpolation: &str) -> Option<u32> {
+    match interpolation {
+        "odd" | "even" => Some(2),
+        "all" => Some(1),
+        other => other.parse().ok().filter(|step| *step > 0),
+    }
+}
+
+/// Lots for the numbers between the numbered nodes of an interpolation way
+fn interpolate(nodes: &[ProcessedNode], step: u32, scale: f64) -> Vec<Lot> {
+    let mut lots = Vec::new();
+    let numbered: Vec<(usize, u32)> = nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, node)| {
+            let number = node.tags.get("addr:housenumber")?.parse().ok()?;
+            Some((i, number))
+        })
+        .collect();
+
+    for pair in numbered.windows(2) {
+        let ((start, from), (end, to)) = (pair[0], pair[1]);
+        let (low, high) = (from.min(to), from.max(to));
+        let count = (high - low) / step;
+        if count < 2 {
+            continue;
+        }
+
+        let section = &nodes[start..=end];
+        let lengths: Vec<f64> = section
+            .windows(2)
+            .map(|pair| {
+                let dx = f64::from(pair[1].x - pair[0].x);
+                let dz = f64::from(pair[1].z - pair[0].z);
+                dx.hypot(dz)
+            })
+            .collect();
+        let total: f64 = lengths.iter().sum();
+        let spacing = total / f64::from(count);
+        let width = (spacing * LOT_FILL).min(HOUSE_WIDTH * scale);
+        if width < 3.0 {
+            continue;
+        }
+
+        for k in 1..count {
+            let number = if from < to {
+                from + k * step
+            } else {
+                from - k * step
+            };
+            let (center, direction) = point_along(section, &lengths, spacing * f64::from(k));
+            lots.push(Lot {
+                center,
+                direction,
+                width,
+                housenumber: number.to_string(),
+            });
+        }
+    }
+    lots
+}
+
+/// Point at `distance` along a polyline, with the direction of the segment it is on
+fn point_along(
+    nodes: &[ProcessedNode],
+    lengths: &[f64],
+    distance: f64,
+) -> ((f64, f64), (f64, f64)) {
+    let mut remaining = distance;
+    for (i, &length) in lengths.iter().enumerate() {
+        if remaining <= length || i == lengths.len() - 1 {
+            let (a, b) = (&nodes[i], &nodes[i + 1]);
+            let (dx, dz) = (f64::from(b.x - a.x), f64::from(b.z - a.z));
+            let t = if length > 0.0 {
+                (remaining / length).min(1.0)
+            } else {
+                0.0
+            };
+            let direction = if length > 0.0 {
+                (dx / length, dz / length)
+            } else {
+                (1.0, 0.0)
+            };
+            return (
+                (f64::from(a.x) + dx * t, f64::from(a.z) + dz * t),
+                direction,
+            );
+        }
+        remaining -= length;
+    }
+    ((f64::from(nodes[0].x), f64::from(nodes[0].z)), (1.0, 0.0))
+}
+
+/// Closed outline of a house, turned along the street
+fn house_outline(lot: &Lot, depth: f64) -> Vec<XZPoint> {
+    let (cx, cz) = lot.center;
+    let (ux, uz) = lot.direction;
+    let (vx, vz) = (-uz, ux);
+    let (half_width, half_depth) = (lot.width / 2.0, depth / 2.0);
+
+    let corner = |along: f64, across: f64| {
+        XZPoint::new(
+            (cx + ux * along + vx * across).round() as i32,
+            (cz + uz * along + vz * across).round() as i32,
+        )
+    };
+    vec![
+        corner(-half_width, -half_depth),
+        corner(half_width, -half_depth),
+        corner(half_width, half_depth),
+        corner(-half_width, half_depth),
+        corner(-half_width, -half_depth),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(x: i32, z: i32, housenumber: Option<&str>) -> ProcessedNode {
+        ProcessedNode {
+            id: 0,
+            tags: housenumber
+                .map(|number| HashMap::from([("addr:housenumber".to_string(), number.to_string())]))
+                .unwrap_or_default(),
+            x,
+            z,
+        }
+    }
+
+    #[test]
+    fn test_interpolate() {
+        // Numbers 1 to 9 along 80 blocks: 3, 5 and 7 are interpolated every 20 blocks
+        let nodes = [
+            node(0, 0, Some("1")),
+            node(40, 0, None),
+            node(80, 0, Some("9")),
+        ];
+        let lots = interpolate(&nodes, step_of("odd").unwrap(), 1.0);
+
+        let numbers: Vec<&str> = lots.iter().map(|lot| lot.housenumber.as_str()).collect();
+        assert_eq!(numbers, vec!["3", "5", "7"]);
+        assert_eq!(lots[0].center, (20.0, 0.0));
+        assert_eq!(lots[2].center, (60.0, 0.0));
+        assert_eq!(lots[0].width, HOUSE_WIDTH);
+    }
+
+    #[test]
+    fn test_add_address_lots() {
+        let address = ProcessedElement::Node(node(100, 100, Some("4")));
+        let shop = ProcessedElement::Node(ProcessedNode {
+            tags: HashMap::from([
+                ("addr:housenumber".to_string(), "6".to_string()),
+                ("shop".to_string(), "bakery".to_string()),
+            ]),
+            ..node(200, 200, None)
+        });
+        let mut elements = vec![address, shop];
+
+        assert_eq!(add_address_lots(&mut elements, 1.0), 1);
+        let ProcessedElement::Way(house) = &elements[2] else {
+            panic!("expected a way");
+        };
+        assert_eq!(house.tags["building"], "house");
+        assert_eq!(house.nodes.len(), 5);
+
+        // A second run finds the house already there
+        assert_eq!(add_address_lots(&mut elements, 1.0), 0);
+    }
+}
@@ -0,0 +1,290 @@
+//! Central scheduler for all requests to external APIs (Overpass, Nominatim, elevation tiles).
+//!
+//! Requests to the same host are spaced out according to the usage policy of that service,
+//! queued in the order they were issued (also across threads), and a `Retry-After` sent with
+//! 429/503 responses is honored before the request is retried. All requests identify Arnis
+//! with a proper User-Agent, optionally including contact information of the user. Downloads
+//! that still fail are tried again on other mirrors according to the `RetryPolicy`, unless
+//! they were cancelled.
+
+use once_cell::sync::Lazy;
+use rand::Rng;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{HeaderMap, RETRY_AFTER, USER_AGENT};
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// How often a rate limited request is retried before giving up
+const MAX_RETRIES: u32 = 3;
+/// Longer waits requested by a server are not honored, the request fails instead
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+/// Wait before retrying a rate limited request if the server doesn't say how long
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Minimum time between two requests to the same host
+fn min_interval(host: &str) -> Duration {
+    if host.ends_with("nominatim.openstreetmap.org") {
+        // Nominatim usage policy: an absolute maximum of 1 request per second
+        Duration::from_secs(1)
+    } else if host.contains("overpass") {
+        // Overpass queries are expensive, the public servers only grant a few slots per user
+        Duration::from_secs(2)
+    } else {
+        Duration::from_millis(100)
+    }
+}
+
+struct HostState {
+    interval: Duration,
+    next_slot: Instant,
+}
+
+impl HostState {
+    /// Reserves the next free slot, queueing behind all requests issued before
+    fn reserve(&mut self, now: Instant) -> Instant {
+        let slot = self.next_slot.max(now);
+        self.next_slot = slot + self.interval;
+        slot
+    }
+
+    /// Keeps all further requests back until the server accepts them again
+    fn back_off(&mut self, until: Instant) {
+        self.next_slot = self.next_slot.max(until);
+    }
+}
+
+/// How failed downloads are tried again, e.g. across the mirrors of a service
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first one
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for every further one
+    pub base_delay: Duration,
+    /// Longest wait between two attempts
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub const DEFAULT: RetryPolicy = RetryPolicy {
+        max_attempts: 5,
+        base_delay: Duration::from_secs(5),
+        max_delay: Duration::from_secs(120),
+    };
+
+    /// Wait before the given retry (starting at 1), with jitter so that parallel runs don't
+    /// hit the servers in lockstep
+    pub fn delay(&self, retry: u32, rng: &mut impl Rng) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(1 << retry.saturating_sub(1).min(16))
+            .min(self.max_delay);
+        exponential.mul_f64(rng.gen_range(0.5..=1.0))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static HOSTS: Lazy<Mutex<HashMap<String, HostState>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static CONTACT: RwLock<Option<String>> = RwLock::new(None);
+static RETRY_POLICY: RwLock<RetryPolicy> = RwLock::new(RetryPolicy::DEFAULT);
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Sets contact information (e.g. an email address) that is sent along with all requests,
+/// as requested by the usage policies of the public OSM services
+pub fn set_contact(contact: Option<&str>) {
+    *CONTACT.write().unwrap() = contact.map(str::to_string);
+}
+
+/// Sets how failed downloads are tried again
+pub fn set_retry_policy(policy: RetryPolicy) {
+    *RETRY_POLICY.write().unwrap() = policy;
+}
+
+/// How failed downloads are tried again
+pub fn retry_policy() -> RetryPolicy {
+    *RETRY_POLICY.read().unwrap()
+}
+
+/// Stops the running downloads at the next chunk of data they receive, or lets downloads
+/// run again
+pub fn set_cancelled(cancelled: bool) {
+    CANCELLED.store(cancelled, Ordering::SeqCst);
+}
+
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// The User-Agent sent with all requests
+pub fn user_agent() -> String {
+    let base = format!(
+        "arnis/{} (+{})",
+        env!("CARGO_PKG_VERSION"),
+        env!("CARGO_PKG_REPOSITORY")
+    );
+    match CONTACT.read().unwrap().as_deref() {
+        Some(contact) => format!("{base} contact: {contact}"),
+        None => base,
+    }
+}
+
+fn host_of(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Blocks until the next request to the host of `url` may be sent.
+/// Used directly for downloads that don't go through `get`, e.g. with curl or wget.
+pub fn wait_for_slot(url: &str) {
+    let host = host_of(url);
+    let slot = HOSTS
+        .lock()
+        .unwrap()
+        .entry(host.clone())
+        .or_insert_with(|| HostState {
+            interval: min_interval(&host),
+            next_slot: Instant::now(),
+        })
+        .reserve(Instant::now());
+
+    let wait = slot.saturating_duration_since(Instant::now());
+    if !wait.is_zero() {
+        std::thread::sleep(wait);
+    }
+}
+
+fn back_off(url: &str, wait: Duration) {
+    let host = host_of(url);
+    if let Some(state) = HOSTS.lock().unwrap().get_mut(&host) {
+        state.back_off(Instant::now() + wait);
+    }
+}
+
+/// Sends a GET request once the host is free, retrying when the server asks to slow down
+pub fn get(client: &Client, url: &str, query: &[(&str, &str)]) -> reqwest::Result<Response> {
+    get_with_headers(client, url, query, HeaderMap::new())
+}
+
+/// Like `get`, with additional request headers (e.g. `Range` to resume a download)
+pub fn get_with_headers(
+    client: &Client,
+    url: &str,
+    query: &[(&str, &str)],
+    headers: HeaderMap,
+) -> reqwest::Result<Response> {
+    let mut retries = 0;
+    loop {
+        wait_for_slot(url);
+        let response = client
+            .get(url)
+            .query(query)
+            .headers(headers.clone())
+            .header(USER_AGENT, user_agent())
+            .send()?;
+
+        let rate_limited = matches!(
+            response.status(),
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+        );
+        if !rate_limited || retries >= MAX_RETRIES {
+            return Ok(response);
+        }
+
+        let wait = retry_after(response.headers()).unwrap_or(DEFAULT_RETRY_AFTER);
+        if wait > MAX_RETRY_AFTER {
+            return Ok(response);
+        }
+
+        println!(
+            "Rate limited by {}, retrying in {} seconds...",
+            host_of(url),
+            wait.as_secs()
+        );
+        back_off(url, wait);
+        retries += 1;
+    }
+}
+
+/// Parses the `Retry-After` header, which we only support in its delay-seconds form
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_reserve_spaces_out_requests() {
+        let now = Instant::now();
+        let mut state = HostState {
+            interval: Duration::from_secs(1),
+            next_slot: now,
+        };
+
+        assert_eq!(state.reserve(now), now);
+        assert_eq!(state.reserve(now), now + Duration::from_secs(1));
+        assert_eq!(state.reserve(now), now + Duration::from_secs(2));
+
+        // After a quiet period, requests can go out right away
+        let later = now + Duration::from_secs(10);
+        assert_eq!(state.reserve(later), later);
+
+        state.back_off(later + Duration::from_secs(30));
+        assert_eq!(state.reserve(later), later + Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_min_interval() {
+        assert_eq!(
+            min_interval("nominatim.openstreetmap.org"),
+            Duration::from_secs(1)
+        );
+        assert_eq!(min_interval("lz4.overpass-api.de"), Duration::from_secs(2));
+        assert_eq!(min_interval("s3.amazonaws.com"), Duration::from_millis(100));
+        assert_eq!(
+            host_of("https://z.overpass-api.de/api/interpreter"),
+            "z.overpass-api.de"
+        );
+    }
+
+    #[test]
+    fn test_retry_policy_delay() {
+        let policy = RetryPolicy::DEFAULT;
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let first = policy.delay(1, &mut rng);
+            assert!(first >= Duration::from_millis(2500) && first <= Duration::from_secs(5));
+            let third = policy.delay(3, &mut rng);
+            assert!(third >= Duration::from_secs(10) && third <= Duration::from_secs(20));
+            // Capped, also for attempts far beyond the shift width
+            assert!(policy.delay(40, &mut rng) <= policy.max_delay);
+        }
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers), None);
+    }
+}
@@ -1,44 +1,239 @@
-use crate::coordinate_system::geographic::LLBBox;
+use crate::categories::Category;
+use crate::chunking::ChunkPreset;
+use crate::coordinate_system::geographic::{LLBBox, LLPoint};
+use crate::data_processing::{MAX_Y, MIN_Y};
+use crate::edge_clip::EdgeClip;
+use crate::elevation_provider::ElevationSource;
+use crate::geojson_export::GeoJsonLayer;
+use crate::height_cap::HeightCap;
+use crate::lod::DetailLevel;
+use crate::merge::OverlapStrategy;
+use crate::schematic::OutputFormat;
+use crate::season::Season;
+use crate::stats_overlay::StatsMetric;
+use crate::world_edge::WorldEdge;
 use clap::Parser;
 use std::path::PathBuf;
 use std::time::Duration;
 
 /// Command-line arguments parser
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(author, version, about)]
 pub struct Args {
-    /// Bounding box of the area (min_lat,min_lng,max_lat,max_lng) (required)
-    #[arg(long, allow_hyphen_values = true, value_parser = LLBBox::from_str)]
-    pub bbox: LLBBox,
+    /// Bounding box of the area (min_lat,min_lng,max_lat,max_lng) (required unless --gpx is used)
+    #[arg(
+        long,
+        allow_hyphen_values = true,
+        value_parser = LLBBox::from_str,
+        required_unless_present = "gpx"
+    )]
+    pub bbox: Option<LLBBox>,
 
-    /// JSON file containing OSM data (optional)
+    /// Additional bounding box merged into the same world, can be repeated (optional)
+    #[arg(
+        long,
+        allow_hyphen_values = true,
+        value_parser = LLBBox::from_str,
+        conflicts_with = "gpx"
+    )]
+    pub extra_bbox: Vec<LLBBox>,
+
+    /// Generate plain ground between the bounding boxes instead of leaving void (optional)
+    #[arg(long, requires = "extra_bbox")]
+    pub fill_gaps: bool,
+
+    /// GPX track to generate a corridor around instead of a bounding box (optional)
+    #[arg(long, value_name = "PATH")]
+    pub gpx: Option<PathBuf>,
+
+    /// Width of the generated corridor around the GPX track, in meters
+    #[arg(long, default_value_t = 200.0)]
+    pub corridor_width: f64,
+
+    /// JSON, .osm or .osm.pbf file containing OSM data (optional)
     #[arg(long, group = "location")]
     pub file: Option<String>,
 
-    /// JSON file to save OSM data to (optional)
+    /// Vector tile server to read the data from instead of Overpass, with {z}/{x}/{y} placeholders,
+    /// e.g. http://tiles.example.org/data/v3/{z}/{x}/{y}.pbf (optional)
+    #[arg(long, value_name = "URL", conflicts_with = "file")]
+    pub tile_url: Option<String>,
+
+    /// Zoom level of the vector tiles to read
+    #[arg(long, default_value_t = crate::vector_tiles::DEFAULT_ZOOM)]
+    pub tile_zoom: u8,
+
+    /// Overture Maps GeoParquet files or URLs (buildings, transportation segments, places) to
+    /// read the data from instead of Overpass (optional)
+    #[arg(
+        long,
+        value_name = "FILE_OR_URL",
+        num_args = 1..,
+        conflicts_with_all = ["file", "tile_url"]
+    )]
+    pub overture: Vec<String>,
+
+    /// GeoJSON building footprints (e.g. Microsoft or Google open buildings) to add where OSM
+    /// has no building (optional)
+    #[arg(long, value_name = "FILE", num_args = 1..)]
+    pub footprints: Vec<PathBuf>,
+
+    /// Add houses for addresses and address interpolations without a mapped building (optional)
+    #[arg(long)]
+    pub address_lots: bool,
+
+    /// JSON file to save OSM data to, gzip-compressed if the name ends in .gz (optional)
     #[arg(long, group = "location")]
     pub save_json_file: Option<String>,
 
+    /// Keep downloaded OSM data for a week and reuse it for areas inside a downloaded one (optional)
+    #[arg(long)]
+    pub osm_cache: bool,
+
+    /// Also write what was parsed as GeoJSON next to the saved JSON file, optionally only some
+    /// layers (buildings,roads,railways,water,landuse,pois) (optional)
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..,
+        value_delimiter = ',',
+        requires = "save_json_file"
+    )]
+    pub export_geojson: Option<Vec<GeoJsonLayer>>,
+
+    /// Generate the area as it was mapped at this date (YYYY-MM-DD, not before 2012-09-12) (optional)
+    #[arg(long, value_parser = parse_date)]
+    pub date: Option<String>,
+
+    /// File with a custom Overpass query, {{bbox}} is replaced with the bounding box (optional)
+    #[arg(long, value_name = "PATH")]
+    pub query_template: Option<PathBuf>,
+
+    /// Highlight the changes since this date: added (lime), removed (red), changed (yellow) (optional)
+    #[arg(long, value_parser = parse_date, conflicts_with = "compare_file")]
+    pub compare_date: Option<String>,
+
+    /// JSON file with an older snapshot of the OSM data to highlight the changes against (optional)
+    #[arg(long)]
+    pub compare_file: Option<String>,
+
+    /// Update the world at --path to the current data: only the chunks of features that changed
+    /// since it was generated are regenerated, the rest stays as it is (optional)
+    #[arg(
+        long,
+        conflicts_with_all = ["compare_date", "compare_file", "low_memory", "second_palette", "stats"]
+    )]
+    pub update: bool,
+
+    /// Keep the OSM data in the world, so it can be updated with --update later (optional)
+    #[arg(long, conflicts_with = "low_memory")]
+    pub track_updates: bool,
+
     /// Path to the Minecraft world (required)
     #[arg(long, value_parser = validate_minecraft_world_path)]
     pub path: PathBuf,
 
+    /// Also write the area as a schematic next to the world: schematic (Sponge .schem for
+    /// WorldEdit) or litematic (Litematica) (optional)
+    #[arg(long, value_enum, default_value_t = OutputFormat::World)]
+    pub format: OutputFormat,
+
+    /// Categories whose empty space is written as structure void instead of air in the
+    /// schematic, so pasting keeps the blocks already there; columns without any feature count
+    /// as terrain, e.g. `terrain,vegetation` to paste only roads and buildings (optional)
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "CATEGORIES",
+        conflicts_with = "low_memory"
+    )]
+    pub structure_void: Vec<Category>,
+
+    /// Existing world to also write the generated area into, e.g. a server world; its chunks
+    /// outside of the area are left untouched (optional)
+    #[arg(long, value_name = "PATH", value_parser = validate_minecraft_world_path)]
+    pub into_world: Option<PathBuf>,
+
+    /// Blocks (x,z) the area is moved by in --into-world, rounded to whole chunks (optional)
+    #[arg(
+        long,
+        value_name = "X,Z",
+        allow_hyphen_values = true,
+        value_parser = parse_offset,
+        requires = "into_world"
+    )]
+    pub offset: Option<(i32, i32)>,
+
+    /// Contact information (e.g. email) sent with API requests, recommended for batch runs (optional)
+    #[arg(long, env = "ARNIS_CONTACT")]
+    pub contact: Option<String>,
+
+    /// Send anonymous run statistics (hardware class, area size, durations) to this endpoint (optional)
+    #[arg(long, env = "ARNIS_TELEMETRY_URL", value_name = "URL")]
+    pub telemetry_url: Option<String>,
+
     /// Downloader method (requests/curl/wget) (optional)
     #[arg(long, default_value = "requests")]
     pub downloader: String,
 
+    /// How often a failed Overpass download is tried, rotating through the servers with
+    /// growing waits in between
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    pub download_attempts: u32,
+
     /// World scale to use, in blocks per meter
     #[arg(long, default_value_t = 1.0)]
     pub scale: f64,
 
-    /// Ground level to use in the Minecraft world
-    #[arg(long, default_value_t = -62)]
+    /// Keep all detail below scale 1 instead of simplifying outlines, merging small buildings
+    /// and dropping tiny features (optional)
+    #[arg(long)]
+    pub no_simplify: bool,
+
+    /// Y of the ground (with terrain, of the lowest ground); moves everything up or down, e.g.
+    /// to leave room for deep basements or to line up with an existing world
+    #[arg(
+        long,
+        default_value_t = -62,
+        allow_negative_numbers = true,
+        value_parser = parse_ground_level
+    )]
     pub ground_level: i32,
 
     /// Enable terrain (optional)
     #[arg(long)]
     pub terrain: bool,
 
+    /// Elevation data for --terrain: terrarium (AWS terrain tiles), srtm or copernicus
+    #[arg(long, value_enum, default_value_t = ElevationSource::Terrarium)]
+    pub elevation_source: ElevationSource,
+
+    /// Elevation from a local GeoTIFF in WGS84 instead of a download, for regions with poor
+    /// coverage (optional)
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "terrain",
+        conflicts_with = "elevation_source"
+    )]
+    pub dem_file: Option<PathBuf>,
+
+    /// Factor on the height differences of the terrain, e.g. 2 to make gentle hills stand
+    /// out; the terrain still has to fit below the build height
+    #[arg(long, default_value_t = 1.0, value_parser = parse_exaggeration)]
+    pub terrain_exaggeration: f64,
+
+    /// Smoothing radius of the terrain in blocks, 0 to keep every bump of the elevation data
+    /// (optional, chosen from the size of the area by default)
+    #[arg(
+        long,
+        value_name = "BLOCKS",
+        requires = "terrain",
+        value_parser = parse_smoothing
+    )]
+    pub terrain_smoothing: Option<f64>,
+
     /// Enable interior generation (optional)
     #[arg(long, default_value_t = true, action = clap::ArgAction::SetTrue)]
     pub interior: bool,
@@ -47,10 +242,241 @@ pub struct Args {
     #[arg(long, default_value_t = true, action = clap::ArgAction::SetTrue)]
     pub roof: bool,
 
+    /// Weather masonry with cracked and mossy blocks and worn edges, following --seed (optional)
+    #[arg(long)]
+    pub weathering: bool,
+
+    /// Season of the world: autumn colors grass and canopies, winter adds snow and ice
+    #[arg(long, value_enum, default_value_t = Season::Summer)]
+    pub season: Season,
+
+    /// Light the city for night-time shots: lit windows, shop fronts and street lamps (optional)
+    #[arg(long)]
+    pub night: bool,
+
     /// Enable filling ground (optional)
     #[arg(long, default_value_t = false, action = clap::ArgAction::SetFalse)]
     pub fillground: bool,
 
+    /// Tallest building height in blocks; taller buildings are compressed or cut to fit,
+    /// see --height-cap (optional)
+    #[arg(
+        long,
+        value_name = "BLOCKS",
+        value_parser = clap::value_parser!(i32).range(8..=(MAX_Y - MIN_Y) as i64)
+    )]
+    pub max_building_height: Option<i32>,
+
+    /// How --max-building-height fits taller buildings: compress (keeps them taller than
+    /// lower ones) or clip
+    #[arg(long, value_enum, default_value_t = HeightCap::Compress)]
+    pub height_cap: HeightCap,
+
+    /// Height in blocks of a single building given as OSM_ID=BLOCKS, exempt from
+    /// --max-building-height; can be repeated (optional)
+    #[arg(long, value_name = "OSM_ID=BLOCKS", value_parser = parse_height_override)]
+    pub height_override: Vec<(u64, i32)>,
+
+    /// Only generate a flat, color-coded map of roads, buildings, water and landuse (optional)
+    #[arg(long)]
+    pub map_mode: bool,
+
+    /// Generate a bar chart of element density or building height per grid cell instead of
+    /// the real world (optional)
+    #[arg(long, value_enum, value_name = "METRIC")]
+    pub stats: Option<StatsMetric>,
+
+    /// Side length in blocks of the grid cells of --stats
+    #[arg(long, value_name = "BLOCKS", default_value_t = 32, value_parser = clap::value_parser!(i32).range(4..))]
+    pub stats_cell_size: i32,
+
+    /// Handling of features crossing the edge of the area: hard (cut), whole (keep
+    /// buildings whole) or fade (lower features towards the edge) (optional)
+    #[arg(long, value_enum, default_value_t = EdgeClip::Hard)]
+    pub edge_clip: EdgeClip,
+
+    /// Treatment of the world edge: void, ocean, wall (bedrock and barrier) or feather
+    /// (blend terrain to flat) (optional)
+    #[arg(long, value_enum, default_value_t = WorldEdge::Void)]
+    pub world_edge: WorldEdge,
+
+    /// Categories to leave out, comma-separated: buildings, roads, rail, water, vegetation,
+    /// barriers, amenities, terrain; they aren't downloaded either (optional)
+    #[arg(
+        long,
+        alias = "exclude",
+        value_enum,
+        value_delimiter = ',',
+        value_name = "CATEGORIES"
+    )]
+    pub skip: Vec<Category>,
+
+    /// Only generate and download these categories, comma-separated, as for --skip (optional)
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "CATEGORIES")]
+    pub include: Vec<Category>,
+
+    /// Detail level of the whole area (full/simplified/footprints) (optional)
+    #[arg(long, value_enum, default_value_t = DetailLevel::Full, conflicts_with = "lod_focus")]
+    pub lod: DetailLevel,
+
+    /// Focal point (lat,lng) around which detail decreases with distance (optional)
+    #[arg(long, allow_hyphen_values = true, value_parser = parse_llpoint)]
+    pub lod_focus: Option<LLPoint>,
+
+    /// Distance from the focal point up to which everything is generated, in meters
+    #[arg(long, default_value_t = 1000.0)]
+    pub lod_full_radius: f64,
+
+    /// Distance from the focal point up to which simplified buildings are generated, in meters
+    #[arg(long, default_value_t = 3000.0)]
+    pub lod_simplified_radius: f64,
+
+    /// Keep the current world spawn instead of moving it to a walkable spot near the center (optional)
+    #[arg(long)]
+    pub keep_spawn: bool,
+
+    /// Build a spawn platform with a chest containing maps and a book about the area (optional)
+    #[arg(long, conflicts_with = "keep_spawn")]
+    pub starter_kit: bool,
+
+    /// Put written books listing the named places of the area and their coordinates at spawn (optional)
+    #[arg(long, conflicts_with = "keep_spawn")]
+    pub gazetteer: bool,
+
+    /// Mark elements with fixme/note tags or broken geometry with glowing pillars and signs (optional)
+    #[arg(long)]
+    pub qa_markers: bool,
+
+    /// Make rail lines rideable by minecart, with powered rails and stops with buttons (optional)
+    #[arg(long)]
+    pub rideable_rails: bool,
+
+    /// Build glowing rings to fly through with an elytra between the main sights of the area (optional)
+    #[arg(long)]
+    pub elytra_tour: bool,
+
+    /// GPX file with the route of the elytra tour, instead of the sights (optional)
+    #[arg(long, value_name = "PATH", requires = "elytra_tour")]
+    pub elytra_route: Option<PathBuf>,
+
+    /// Write a resource pack with readable textures for the generated signs into the world (optional)
+    #[arg(long)]
+    pub resource_pack: bool,
+
+    /// Continue an interrupted run for the same area from its last checkpoint (optional)
+    #[arg(long)]
+    pub resume: bool,
+
+    /// Seed for random details like tree types and window colors, for reproducible worlds (optional)
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Print a hash of the generated world, or check it against the given hash (requires --seed)
+    #[arg(long, value_name = "HASH", num_args = 0..=1, requires = "seed")]
+    pub verify: Option<Option<String>>,
+
+    /// Maximum memory in GB to use for generation, larger areas are refused (optional)
+    #[arg(long, env = "ARNIS_MAX_RAM_GB", value_parser = parse_ram_gb)]
+    pub max_ram_gb: Option<f64>,
+
+    /// Maximum number of threads to use (optional)
+    #[arg(
+        long,
+        env = "ARNIS_THREADS",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub threads: Option<usize>,
+
+    /// Pause while the CPU is too hot and slow down on battery, for long runs on laptops (optional)
+    #[arg(long)]
+    pub power_saving: bool,
+
+    /// Run at idle priority so the machine stays usable during generation (optional)
+    #[arg(long)]
+    pub background: bool,
+
+    /// Generate the area part by part and merge the parts, so large areas fit into memory (optional)
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "file",
+            "gpx",
+            "extra_bbox",
+            "second_palette",
+            "stats",
+            "resume",
+            "unknown_tags_out",
+        ]
+    )]
+    pub low_memory: bool,
+
+    /// With --low-memory, merge the parts done so far into the world after every part, so it
+    /// can be explored while the rest is generated (optional)
+    #[arg(long, requires = "low_memory")]
+    pub preview_parts: bool,
+
+    /// Size of the parts with --low-memory: small (a quarter of the free memory per part),
+    /// balanced (half) or large (most of it, fewer seams)
+    #[arg(long, value_enum, default_value_t = ChunkPreset::Balanced)]
+    pub chunking: ChunkPreset,
+
+    /// How the overlapping edges of the parts with --low-memory are merged: largest (the part
+    /// with the most content), first, newest or majority (see `arnis merge --help`)
+    #[arg(long, value_enum, default_value_t = OverlapStrategy::Largest)]
+    pub overlap: OverlapStrategy,
+
+    /// Directory for temporary data like downloaded elevation tiles (optional)
+    #[arg(long, value_name = "PATH", env = "ARNIS_TEMP_DIR")]
+    pub temp_dir: Option<PathBuf>,
+
+    /// Maximum write speed in MB/s while saving the world, e.g. to keep HDDs responsive (optional)
+    #[arg(long, value_name = "MB/S", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_write_speed: Option<u64>,
+
+    /// Experimental: aerial imagery tile URL ({z}/{x}/{y}, or {bbox} for WMS) used to pick
+    /// ground blocks where OSM has no landuse (optional)
+    #[arg(long, value_name = "URL")]
+    pub imagery_url: Option<String>,
+
+    /// GeoJSON land polygons; ground outside of them becomes sea, for coasts whose water
+    /// OSM only maps with coastlines (optional)
+    #[arg(long, value_name = "PATH")]
+    pub land_polygons: Option<PathBuf>,
+
+    /// GeoJSON polygons whose interior is left empty, e.g. to build it by hand (optional)
+    #[arg(long, value_name = "PATH")]
+    pub exclude_polygons: Option<PathBuf>,
+
+    /// Write the tags that no processor handled, with counts, to this JSON file (optional)
+    #[arg(long, value_name = "PATH")]
+    pub unknown_tags_out: Option<PathBuf>,
+
+    /// Fail on the first malformed OSM element instead of repairing or skipping it (optional)
+    #[arg(long)]
+    pub strict: bool,
+
+    /// JSON file replacing vanilla blocks with (modded) blocks when saving (optional)
+    #[arg(long, value_name = "PATH")]
+    pub block_palette: Option<PathBuf>,
+
+    /// Write the vanilla fallbacks of the block palette instead of modded blocks (optional)
+    #[arg(long, requires = "block_palette")]
+    pub vanilla_fallback: bool,
+
+    /// Also generate the area with this block palette into `--second-path`, to compare two
+    /// palettes side by side (optional)
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "second_path",
+        conflicts_with = "stats"
+    )]
+    pub second_palette: Option<PathBuf>,
+
+    /// Minecraft world for the area generated with `--second-palette`
+    #[arg(long, value_parser = validate_minecraft_world_path, requires = "second_palette")]
+    pub second_path: Option<PathBuf>,
+
     /// Enable debug mode (optional)
     #[arg(long)]
     pub debug: bool,
@@ -69,6 +495,15 @@ pub struct Args {
     pub spawn_point: Option<(f64, f64)>,
 }
 
+impl Args {
+    /// Bounding box of the area. With `--gpx` it is set from the route corridor before
+    /// anything is generated.
+    pub fn bbox(&self) -> LLBBox {
+        self.bbox
+            .expect("the bounding box is set from --bbox or the GPX route")
+    }
+}
+
 fn validate_minecraft_world_path(path: &str) -> Result<PathBuf, String> {
     let mc_world_path = PathBuf::from(path);
     if !mc_world_path.exists() {
@@ -84,6 +519,130 @@ fn validate_minecraft_world_path(path: &str) -> Result<PathBuf, String> {
     Ok(mc_world_path)
 }
 
+fn parse_offset(arg: &str) -> Result<(i32, i32), String> {
+    let (x, z) = arg
+        .split_once(',')
+        .ok_or_else(|| format!("Expected x,z but got: {arg}"))?;
+    let x: i32 = x
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid x offset: {e}"))?;
+    let z: i32 = z
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid z offset: {e}"))?;
+    Ok((x, z))
+}
+
+fn parse_llpoint(arg: &str) -> Result<LLPoint, String> {
+    let (lat, lng) = arg
+        .split_once(',')
+        .ok_or_else(|| format!("Expected lat,lng but got: {arg}"))?;
+    let lat: f64 = lat
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid latitude: {e}"))?;
+    let lng: f64 = lng
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid longitude: {e}"))?;
+    LLPoint::new(lat, lng)
+}
+
+/// Validates a YYYY-MM-DD date and turns it into the timestamp format used by Overpass
+fn parse_date(arg: &str) -> Result<String, String> {
+    let parts: Vec<&str> = arg.split('-').collect();
+    let [year, month, day] = parts[..] else {
+        return Err(format!("Expected a date like 2015-06-01 but got: {arg}"));
+    };
+
+    let valid_digits =
+        |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+    if !valid_digits(year, 4) || !valid_digits(month, 2) || !valid_digits(day, 2) {
+        return Err(format!("Expected a date like 2015-06-01 but got: {arg}"));
+    }
+
+    let month_num: u32 = month.parse().unwrap();
+    let day_num: u32 = day.parse().unwrap();
+    if !(1..=12).contains(&month_num) || !(1..=31).contains(&day_num) {
+        return Err(format!("Invalid date: {arg}"));
+    }
+
+    // Overpass only keeps the history since the OSM license change
+    if arg < "2012-09-12" {
+        return Err("Historical data is only available from 2012-09-12 onwards".to_string());
+    }
+
+    Ok(format!("{arg}T00:00:00Z"))
+}
+
+/// Checks that the ground leaves room for the dirt below it and stays below the build limit
+pub fn check_ground_level(ground_level: i32) -> Result<(), String> {
+    let (min, max) = (MIN_Y + 2, MAX_Y - 1);
+    if !(min..=max).contains(&ground_level) {
+        return Err(format!(
+            "Ground level {ground_level} is outside the world, it must be between {min} and {max}"
+        ));
+    }
+    Ok(())
+}
+
+fn parse_ground_level(arg: &str) -> Result<i32, String> {
+    let ground_level: i32 = arg
+        .parse()
+        .map_err(|e| format!("Invalid ground level: {e}"))?;
+    check_ground_level(ground_level)?;
+    Ok(ground_level)
+}
+
+fn parse_height_override(arg: &str) -> Result<(u64, i32), String> {
+    let (id, height) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Expected OSM_ID=BLOCKS but got: {arg}"))?;
+    let id: u64 = id
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid OSM id: {e}"))?;
+    let height: i32 = height
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid height: {e}"))?;
+    if !(1..=MAX_Y - MIN_Y).contains(&height) {
+        return Err(format!("Height {height} does not fit into the world"));
+    }
+    Ok((id, height))
+}
+
+fn parse_exaggeration(arg: &str) -> Result<f64, String> {
+    let factor: f64 = arg
+        .parse()
+        .map_err(|e| format!("Invalid terrain exaggeration: {e}"))?;
+    if !factor.is_finite() || factor <= 0.0 {
+        return Err("The terrain exaggeration must be above 0".to_string());
+    }
+    Ok(factor)
+}
+
+fn parse_smoothing(arg: &str) -> Result<f64, String> {
+    let radius: f64 = arg
+        .parse()
+        .map_err(|e| format!("Invalid smoothing radius: {e}"))?;
+    if !radius.is_finite() || radius < 0.0 {
+        return Err("The smoothing radius can't be negative".to_string());
+    }
+    Ok(radius)
+}
+
+fn parse_ram_gb(arg: &str) -> Result<f64, String> {
+    let gb: f64 = arg
+        .parse()
+        .map_err(|e| format!("Invalid amount of memory: {e}"))?;
+    if !gb.is_finite() || gb < 0.5 {
+        return Err("At least 0.5 GB of memory are required".to_string());
+    }
+    Ok(gb)
+}
+
 fn parse_duration(arg: &str) -> Result<std::time::Duration, std::num::ParseIntError> {
     let seconds = arg.parse()?;
     Ok(std::time::Duration::from_secs(seconds))
@@ -139,8 +698,498 @@ mod tests {
         let cmd = ["arnis", "--path", tmp_path, "--file", ""];
         assert!(Args::try_parse_from(cmd.iter()).is_err());
 
+        // The bounding box of a route is derived from it later
+        let cmd = ["arnis", "--path", tmp_path, "--gpx", "route.gpx"];
+        assert_eq!(Args::try_parse_from(cmd.iter()).unwrap().bbox, None);
+
         // The --gui flag isn't used here, ugh. TODO clean up main.rs and its argparse usage.
         // let cmd = ["arnis", "--gui"];
         // assert!(Args::try_parse_from(cmd.iter()).is_ok());
     }
+
+    #[test]
+    fn test_extra_bboxes() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+
+        let cmd = [
+            "arnis",
+            "--path",
+            tmp_path,
+            "--bbox",
+            "1,2,3,4",
+            "--extra-bbox",
+            "5,6,7,8",
+            "--extra-bbox",
+            "-4,-3,-2,-1",
+            "--fill-gaps",
+        ];
+        let args = Args::parse_from(cmd.iter());
+        assert_eq!(args.extra_bbox.len(), 2);
+        assert_eq!(args.extra_bbox[1], LLBBox::new(-4., -3., -2., -1.).unwrap());
+        assert!(args.fill_gaps);
+
+        let cmd = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+        assert!(Args::parse_from(cmd.iter()).extra_bbox.is_empty());
+
+        let cmd = [
+            "arnis",
+            "--path",
+            tmp_path,
+            "--bbox",
+            "1,2,3,4",
+            "--fill-gaps",
+        ];
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("2015-06-01").unwrap(), "2015-06-01T00:00:00Z");
+        assert!(parse_date("2012-09-12").is_ok());
+        assert!(parse_date("2012-09-11").is_err());
+        assert!(parse_date("2015-6-1").is_err());
+        assert!(parse_date("2015-13-01").is_err());
+        assert!(parse_date("01.06.2015").is_err());
+        assert!(parse_date("2015-06-01T12:00:00Z").is_err());
+    }
+
+    #[test]
+    fn test_compare_options() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+
+        let cmd = [
+            "arnis",
+            "--path",
+            tmp_path,
+            "--bbox",
+            "1,2,3,4",
+            "--compare-date",
+            "2016-01-01",
+        ];
+        let args = Args::parse_from(cmd.iter());
+        assert_eq!(args.compare_date.as_deref(), Some("2016-01-01T00:00:00Z"));
+        assert!(args.compare_file.is_none());
+
+        // Only one older snapshot can be compared against
+        let cmd = [
+            "arnis",
+            "--path",
+            tmp_path,
+            "--bbox",
+            "1,2,3,4",
+            "--compare-date",
+            "2016-01-01",
+            "--compare-file",
+            "old.json",
+        ];
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+    }
+
+    #[test]
+    fn test_export_geojson() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+        let base = [
+            "arnis",
+            "--path",
+            tmp_path,
+            "--bbox",
+            "1,2,3,4",
+            "--save-json-file",
+            "data.json",
+        ];
+
+        let args = Args::parse_from(base.iter());
+        assert_eq!(args.export_geojson, None);
+
+        let cmd = [&base[..], &["--export-geojson"][..]].concat();
+        assert_eq!(Args::parse_from(cmd.iter()).export_geojson, Some(vec![]));
+
+        let cmd = [&base[..], &["--export-geojson", "buildings,roads"][..]].concat();
+        assert_eq!(
+            Args::parse_from(cmd.iter()).export_geojson,
+            Some(vec![GeoJsonLayer::Buildings, GeoJsonLayer::Roads])
+        );
+
+        // Only available together with the saved data
+        let cmd = [
+            "arnis",
+            "--path",
+            tmp_path,
+            "--bbox",
+            "1,2,3,4",
+            "--export-geojson",
+        ];
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+    }
+
+    #[test]
+    fn test_verify_options() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+        let base = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+
+        let args = Args::parse_from(base.iter());
+        assert_eq!(args.seed, None);
+        assert_eq!(args.verify, None);
+
+        let cmd = [&base[..], &["--seed", "42", "--verify"][..]].concat();
+        let args = Args::parse_from(cmd.iter());
+        assert_eq!(args.seed, Some(42));
+        assert_eq!(args.verify, Some(None));
+
+        let cmd = [
+            &base[..],
+            &["--seed", "42", "--verify", "00ff00ff00ff00ff"][..],
+        ]
+        .concat();
+        assert_eq!(
+            Args::parse_from(cmd.iter()).verify,
+            Some(Some("00ff00ff00ff00ff".to_string()))
+        );
+
+        // Without a seed, the world is different every time
+        let cmd = [&base[..], &["--verify"][..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+        let base = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+
+        let cmd = [&base[..], &["--max-ram-gb", "6.5", "--threads", "4"][..]].concat();
+        let args = Args::parse_from(cmd.iter());
+        assert_eq!(args.max_ram_gb, Some(6.5));
+        assert_eq!(args.threads, Some(4));
+
+        for invalid in [
+            ["--max-ram-gb", "0.1"],
+            ["--max-ram-gb", "lots"],
+            ["--threads", "0"],
+        ] {
+            let cmd = [&base[..], &invalid[..]].concat();
+            assert!(Args::try_parse_from(cmd.iter()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_ground_level() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+        let base = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+
+        let args = Args::parse_from(base.iter());
+        assert_eq!(args.ground_level, -62);
+
+        let cmd = [&base[..], &["--ground-level", "-40"][..]].concat();
+        assert_eq!(Args::parse_from(cmd.iter()).ground_level, -40);
+
+        for invalid in ["-63", "319", "low"] {
+            let cmd = [&base[..], &["--ground-level", invalid][..]].concat();
+            assert!(Args::try_parse_from(cmd.iter()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_building_heights() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+        let base = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+
+        let cmd = [
+            &base[..],
+            &["--max-building-height", "120", "--height-cap", "clip"][..],
+            &[
+                "--height-override",
+                "123=200",
+                "--height-override",
+                "456=40",
+            ][..],
+        ]
+        .concat();
+        let args = Args::parse_from(cmd.iter());
+        assert_eq!(args.max_building_height, Some(120));
+        assert_eq!(args.height_cap, HeightCap::Clip);
+        assert_eq!(args.height_override, vec![(123, 200), (456, 40)]);
+
+        for invalid in ["123", "123=0", "abc=20", "123=400"] {
+            let cmd = [&base[..], &["--height-override", invalid][..]].concat();
+            assert!(Args::try_parse_from(cmd.iter()).is_err());
+        }
+        let cmd = [&base[..], &["--max-building-height", "1000"][..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+    }
+
+    #[test]
+    fn test_season() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+        let base = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+
+        assert_eq!(Args::parse_from(base.iter()).season, Season::Summer);
+        let cmd = [&base[..], &["--season", "winter"][..]].concat();
+        assert_eq!(Args::parse_from(cmd.iter()).season, Season::Winter);
+        let cmd = [&base[..], &["--season", "spring"][..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+        let base = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+
+        assert_eq!(Args::parse_from(base.iter()).format, OutputFormat::World);
+        let cmd = [&base[..], &["--format", "litematic"][..]].concat();
+        assert_eq!(Args::parse_from(cmd.iter()).format, OutputFormat::Litematic);
+        let cmd = [&base[..], &["--format", "mcstructure"][..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+    }
+
+    #[test]
+    fn test_into_world() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+        let base = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+
+        let cmd = [
+            &base[..],
+            &["--into-world", tmp_path, "--offset", "1000,-4000"][..],
+        ]
+        .concat();
+        let args = Args::parse_from(cmd.iter());
+        assert_eq!(args.into_world.as_deref(), Some(tmpdir.path()));
+        assert_eq!(args.offset, Some((1000, -4000)));
+
+        // The offset only applies to --into-world
+        let cmd = [&base[..], &["--offset", "16,16"][..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+        let cmd = [&base[..], &["--into-world", tmp_path, "--offset", "16"][..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+    }
+
+    #[test]
+    fn test_update() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+        let base = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+
+        assert!(!Args::parse_from(base.iter()).update);
+        let cmd = [&base[..], &["--update"][..]].concat();
+        assert!(Args::parse_from(cmd.iter()).update);
+        let cmd = [&base[..], &["--update", "--compare-file", "old.json"][..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+
+        assert!(!Args::parse_from(base.iter()).track_updates);
+        let cmd = [&base[..], &["--track-updates", "--low-memory"][..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+    }
+
+    #[test]
+    fn test_elevation_options() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+        let base = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+
+        let args = Args::parse_from(base.iter());
+        assert_eq!(args.elevation_source, ElevationSource::Terrarium);
+
+        let cmd = [
+            &base[..],
+            &["--terrain", "--elevation-source", "copernicus"][..],
+        ]
+        .concat();
+        let args = Args::parse_from(cmd.iter());
+        assert_eq!(args.elevation_source, ElevationSource::Copernicus);
+
+        let cmd = [&base[..], &["--terrain", "--dem-file", "dem.tif"][..]].concat();
+        assert_eq!(
+            Args::parse_from(cmd.iter()).dem_file,
+            Some(PathBuf::from("dem.tif"))
+        );
+
+        // A DEM only makes sense with terrain, and replaces the download
+        let cmd = [&base[..], &["--dem-file", "dem.tif"][..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+        let cmd = [
+            &base[..],
+            &[
+                "--terrain",
+                "--dem-file",
+                "dem.tif",
+                "--elevation-source",
+                "srtm",
+            ][..],
+        ]
+        .concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+
+        assert_eq!(args.terrain_exaggeration, 1.0);
+        assert_eq!(args.terrain_smoothing, None);
+        let cmd = [
+            &base[..],
+            &[
+                "--terrain",
+                "--terrain-exaggeration",
+                "1.5",
+                "--terrain-smoothing",
+                "0",
+            ][..],
+        ]
+        .concat();
+        let args = Args::parse_from(cmd.iter());
+        assert_eq!(args.terrain_exaggeration, 1.5);
+        assert_eq!(args.terrain_smoothing, Some(0.0));
+        for invalid in [
+            &["--terrain-exaggeration", "0"][..],
+            &["--terrain", "--terrain-smoothing", "-1"][..],
+            &["--terrain-smoothing", "4"][..],
+        ] {
+            let cmd = [&base[..], invalid].concat();
+            assert!(Args::try_parse_from(cmd.iter()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_low_memory() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+        let base = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+
+        assert!(!Args::parse_from(base.iter()).low_memory);
+        let cmd = [&base[..], &["--low-memory"][..]].concat();
+        assert!(Args::parse_from(cmd.iter()).low_memory);
+
+        // Parts are fetched by bounding box, so an area from elsewhere can't be split
+        let cmd = [&base[..], &["--low-memory", "--file", "data.json"][..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+        let cmd = [&base[..], &["--low-memory", "--extra-bbox", "5,6,7,8"][..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+        let cmd = [
+            "arnis",
+            "--path",
+            tmp_path,
+            "--low-memory",
+            "--gpx",
+            "route.gpx",
+        ];
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+
+        // Every part would overwrite the same report
+        let cmd = [
+            &base[..],
+            &["--low-memory", "--unknown-tags-out", "tags.csv"][..],
+        ]
+        .concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+
+        let cmd = [&base[..], &["--low-memory", "--preview-parts"][..]].concat();
+        assert!(Args::parse_from(cmd.iter()).preview_parts);
+        let cmd = [&base[..], &["--preview-parts"][..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+
+        assert_eq!(
+            Args::parse_from(base.iter()).chunking,
+            ChunkPreset::Balanced
+        );
+        let cmd = [&base[..], &["--low-memory", "--chunking", "small"][..]].concat();
+        assert_eq!(Args::parse_from(cmd.iter()).chunking, ChunkPreset::Small);
+
+        let cmd = [&base[..], &["--low-memory", "--overlap", "majority"][..]].concat();
+        assert_eq!(
+            Args::parse_from(cmd.iter()).overlap,
+            OverlapStrategy::Majority
+        );
+    }
+
+    #[test]
+    fn test_second_palette() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+        let second_dir = minecraft_tmpdir();
+        let second_path = second_dir.path().to_str().unwrap();
+        let base = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+
+        let options = ["--second-palette", "b.json", "--second-path", second_path];
+        let cmd = [&base[..], &options[..]].concat();
+        let args = Args::parse_from(cmd.iter());
+        assert_eq!(args.second_path.as_deref(), Some(second_dir.path()));
+
+        // Each option needs the other
+        let cmd = [&base[..], &options[..2]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+        let cmd = [&base[..], &options[2..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+    }
+
+    #[test]
+    fn test_skip_categories() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+        let base = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+
+        let args = Args::parse_from(base.iter());
+        assert!(args.skip.is_empty());
+
+        let cmd = [&base[..], &["--skip", "buildings,rail"][..]].concat();
+        let args = Args::parse_from(cmd.iter());
+        assert_eq!(args.skip, vec![Category::Buildings, Category::Rail]);
+
+        let cmd = [&base[..], &["--exclude", "water", "--include", "roads"][..]].concat();
+        let args = Args::parse_from(cmd.iter());
+        assert_eq!(args.skip, vec![Category::Water]);
+        assert_eq!(args.include, vec![Category::Roads]);
+
+        let cmd = [&base[..], &["--skip", "houses"][..]].concat();
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+    }
+
+    #[test]
+    fn test_lod_options() {
+        let tmpdir = minecraft_tmpdir();
+        let tmp_path = tmpdir.path().to_str().unwrap();
+
+        let cmd = ["arnis", "--path", tmp_path, "--bbox", "1,2,3,4"];
+        let args = Args::parse_from(cmd.iter());
+        assert_eq!(args.lod, DetailLevel::Full);
+        assert!(args.lod_focus.is_none());
+
+        let cmd = [
+            "arnis",
+            "--path",
+            tmp_path,
+            "--bbox",
+            "1,2,3,4",
+            "--lod",
+            "footprints",
+        ];
+        assert_eq!(Args::parse_from(cmd.iter()).lod, DetailLevel::Footprints);
+
+        let cmd = [
+            "arnis",
+            "--path",
+            tmp_path,
+            "--bbox",
+            "1,2,3,4",
+            "--lod-focus",
+            "2.5,-3",
+        ];
+        let args = Args::parse_from(cmd.iter());
+        assert_eq!(args.lod_focus, Some(LLPoint::new(2.5, -3.0).unwrap()));
+
+        let cmd = [
+            "arnis",
+            "--path",
+            tmp_path,
+            "--bbox",
+            "1,2,3,4",
+            "--lod-focus",
+            "2.5",
+        ];
+        assert!(Args::try_parse_from(cmd.iter()).is_err());
+    }
 }
@@ -6,6 +6,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 use crate::colors::RGBTuple;
+use crate::seeded_rng;
 
 // Enums for stair properties
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -91,13 +92,28 @@ impl Block {
         self.id
     }
 
+    /// The block with the given `id()`, e.g. when reading a world snapshot
+    pub(crate) fn from_id(id: u8) -> Result<Self, String> {
+        match Self::name_of(id) {
+            Some(_) => Ok(Self::new(id)),
+            None => Err(format!("Invalid block id {id}")),
+        }
+    }
+
     #[inline(always)]
     pub fn namespace(&self) -> &str {
         "minecraft"
     }
 
     pub fn name(&self) -> &str {
-        match self.id {
+        match Self::name_of(self.id) {
+            Some(name) => name,
+            None => panic!("Invalid id"),
+        }
+    }
+
+    fn name_of(id: u8) -> Option<&'static str> {
+        Some(match id {
             0 => "acacia_planks",
             1 => "air",
             2 => "andesite",
@@ -266,8 +282,18 @@ impl Block {
             185 => "quartz_stairs",
             186 => "polished_andesite_stairs",
             187 => "nether_brick_stairs",
-            _ => panic!("Invalid id"),
-        }
+            188 => "lime_wool",
+            189 => "barrier",
+            190..=193 => "powered_rail",
+            194 => "redstone_block",
+            195 => "stone_button",
+            196 => "mossy_stone_bricks",
+            197 => "cracked_deepslate_bricks",
+            198 => "cave_air",
+            199..=202 => "powered_rail",
+            203 | 204 => "spruce_log",
+            _ => return None,
+        })
     }
 
     pub fn properties(&self) -> Option<Value> {
@@ -463,6 +489,44 @@ impl Block {
                 map.insert("half".to_string(), Value::String("top".to_string()));
                 map
             })),
+            190..=193 => Some(Value::Compound({
+                let mut map = HashMap::new();
+                // Use manual % check since is_multiple_of() is unstable on stable Rust
+                #[allow(clippy::manual_is_multiple_of)]
+                let shape = if self.id % 2 == 0 {
+                    "north_south"
+                } else {
+                    "east_west"
+                };
+                let powered = if self.id < 192 { "true" } else { "false" };
+                map.insert("shape".to_string(), Value::String(shape.to_string()));
+                map.insert("powered".to_string(), Value::String(powered.to_string()));
+                map
+            })),
+            195 => Some(Value::Compound({
+                let mut map = HashMap::new();
+                map.insert("face".to_string(), Value::String("floor".to_string()));
+                map.insert("facing".to_string(), Value::String("north".to_string()));
+                map
+            })),
+            199..=202 => Some(Value::Compound({
+                let mut map = HashMap::new();
+                let shape = match self.id {
+                    199 => "ascending_east",
+                    200 => "ascending_west",
+                    201 => "ascending_north",
+                    _ => "ascending_south",
+                };
+                map.insert("shape".to_string(), Value::String(shape.to_string()));
+                map.insert("powered".to_string(), Value::String("true".to_string()));
+                map
+            })),
+            203 | 204 => Some(Value::Compound({
+                let mut map = HashMap::new();
+                let axis = if self.id == 203 { "x" } else { "z" };
+                map.insert("axis".to_string(), Value::String(axis.to_string()));
+                map
+            })),
             _ => None,
         }
     }
@@ -697,6 +761,25 @@ pub const SMOOTH_SANDSTONE_STAIRS: Block = Block::new(184);
 pub const QUARTZ_STAIRS: Block = Block::new(185);
 pub const POLISHED_ANDESITE_STAIRS: Block = Block::new(186);
 pub const NETHER_BRICK_STAIRS: Block = Block::new(187);
+pub const LIME_WOOL: Block = Block::new(188);
+pub const BARRIER: Block = Block::new(189);
+pub const POWERED_RAIL_NORTH_SOUTH: Block = Block::new(190);
+pub const POWERED_RAIL_EAST_WEST: Block = Block::new(191);
+pub const UNPOWERED_RAIL_NORTH_SOUTH: Block = Block::new(192);
+pub const UNPOWERED_RAIL_EAST_WEST: Block = Block::new(193);
+pub const REDSTONE_BLOCK: Block = Block::new(194);
+pub const STONE_BUTTON: Block = Block::new(195);
+pub const MOSSY_STONE_BRICKS: Block = Block::new(196);
+pub const CRACKED_DEEPSLATE_BRICKS: Block = Block::new(197);
+/// Air that is kept when the ground is generated, for rooms dug into it
+pub const CAVE_AIR: Block = Block::new(198);
+pub const POWERED_RAIL_ASCENDING_EAST: Block = Block::new(199);
+pub const POWERED_RAIL_ASCENDING_WEST: Block = Block::new(200);
+pub const POWERED_RAIL_ASCENDING_NORTH: Block = Block::new(201);
+pub const POWERED_RAIL_ASCENDING_SOUTH: Block = Block::new(202);
+/// Spruce logs lying along the x and z axis, for railway sleepers
+pub const SPRUCE_LOG_EAST_WEST: Block = Block::new(203);
+pub const SPRUCE_LOG_NORTH_SOUTH: Block = Block::new(204);
 
 /// Maps a block to its corresponding stair variant
 #[inline]
@@ -751,7 +834,7 @@ pub static WINDOW_VARIATIONS: [Block; 7] = [
 // Window types for different building styles
 pub fn get_window_block_for_building_type(building_type: &str) -> Block {
     use rand::Rng;
-    let mut rng = rand::thread_rng();
+    let mut rng = seeded_rng::rng();
 
     match building_type {
         "residential" | "house" | "apartment" => {
@@ -787,7 +870,7 @@ pub fn get_window_block_for_building_type(building_type: &str) -> Block {
 // Random floor block selection
 pub fn get_random_floor_block() -> Block {
     use rand::Rng;
-    let mut rng = rand::thread_rng();
+    let mut rng = seeded_rng::rng();
 
     let floor_options = [
         WHITE_CONCRETE,
@@ -934,7 +1017,7 @@ static DEFINED_COLORS: &[ColorBlockMapping] = &[
 // Function to randomly select building wall block with alternatives
 pub fn get_building_wall_block_for_color(color: RGBTuple) -> Block {
     use rand::Rng;
-    let mut rng = rand::thread_rng();
+    let mut rng = seeded_rng::rng();
 
     // Find the closest color match
     let closest_color = DEFINED_COLORS
@@ -952,7 +1035,7 @@ pub fn get_building_wall_block_for_color(color: RGBTuple) -> Block {
 // Function to get a random fallback building block when no color attribute is specified
 pub fn get_fallback_building_block() -> Block {
     use rand::Rng;
-    let mut rng = rand::thread_rng();
+    let mut rng = seeded_rng::rng();
 
     let fallback_options = [
         BLACKSTONE,
@@ -989,7 +1072,7 @@ pub fn get_fallback_building_block() -> Block {
 // Function to get a random castle wall block
 pub fn get_castle_wall_block() -> Block {
     use rand::Rng;
-    let mut rng = rand::thread_rng();
+    let mut rng = seeded_rng::rng();
 
     let castle_wall_options = [
         STONE_BRICKS,
@@ -0,0 +1,145 @@
+//! Block palettes (`--block-palette`): replace vanilla blocks with blocks from mods when
+//! the world is saved, e.g. Create or Chisel blocks, with vanilla fallbacks for players
+//! without the mods. A palette is a JSON file like
+//!
+//! ```json
+//! {
+//!   "stone_bricks": { "block": "chisel:stonebrick/tiles", "fallback": "cracked_stone_bricks" },
+//!   "polished_andesite": "create:andesite_casing"
+//! }
+//! ```
+
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::RwLock;
+
+static ACTIVE: RwLock<Option<ActivePalette>> = RwLock::new(None);
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+enum Replacement {
+    Block(String),
+    WithFallback {
+        block: String,
+        fallback: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BlockPalette {
+    replacements: HashMap<String, Replacement>,
+}
+
+struct ActivePalette {
+    palette: BlockPalette,
+    use_fallbacks: bool,
+}
+
+impl BlockPalette {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        Self::parse(&contents).map_err(|e| format!("Invalid palette {}: {e}", path.display()))
+    }
+
+    fn parse(contents: &str) -> Result<Self, String> {
+        let replacements: HashMap<String, Replacement> =
+            serde_json::from_str(contents).map_err(|e| e.to_string())?;
+
+        // Keys are vanilla names, with or without namespace
+        let replacements = replacements
+            .into_iter()
+            .map(|(name, replacement)| {
+                let name = name.strip_prefix("minecraft:").unwrap_or(&name).to_string();
+                (name, replacement)
+            })
+            .collect();
+        Ok(BlockPalette { replacements })
+    }
+
+    /// Name written to the world for a vanilla block, `None` keeps the block
+    fn replace(&self, name: &str, use_fallbacks: bool) -> Option<&str> {
+        match self.replacements.get(name)? {
+            Replacement::Block(block) if !use_fallbacks => Some(block),
+            Replacement::WithFallback { block, .. } if !use_fallbacks => Some(block),
+            Replacement::WithFallback {
+                fallback: Some(fallback),
+                ..
+            } => Some(fallback),
+            _ => None,
+        }
+    }
+
+    /// Namespaces of the blocks the palette writes, other than vanilla ones
+    pub fn required_mods(&self, use_fallbacks: bool) -> BTreeSet<String> {
+        self.replacements
+            .keys()
+            .filter_map(|name| self.replace(name, use_fallbacks))
+            .filter_map(|block| block.split_once(':').map(|(namespace, _)| namespace))
+            .filter(|namespace| *namespace != "minecraft")
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// Uses the palette for all worlds saved from now on
+pub fn set_palette(palette: Option<BlockPalette>, use_fallbacks: bool) {
+    *ACTIVE.write().unwrap() = palette.map(|palette| ActivePalette {
+        palette,
+        use_fallbacks,
+    });
+}
+
+/// Name of a block in the saved world
+pub fn block_name(name: &str) -> String {
+    ACTIVE
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|active| active.palette.replace(name, active.use_fallbacks))
+        .unwrap_or(name)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALETTE: &str = r#"{
+        "minecraft:stone_bricks": { "block": "chisel:stonebrick/tiles", "fallback": "cracked_stone_bricks" },
+        "polished_andesite": "create:andesite_casing",
+        "oak_planks": { "block": "minecraft:spruce_planks" }
+    }"#;
+
+    #[test]
+    fn test_replace() {
+        let palette = BlockPalette::parse(PALETTE).unwrap();
+
+        assert_eq!(
+            palette.replace("stone_bricks", false),
+            Some("chisel:stonebrick/tiles")
+        );
+        assert_eq!(
+            palette.replace("stone_bricks", true),
+            Some("cracked_stone_bricks")
+        );
+        assert_eq!(
+            palette.replace("polished_andesite", false),
+            Some("create:andesite_casing")
+        );
+        // Without a fallback, vanilla worlds keep the original block
+        assert_eq!(palette.replace("polished_andesite", true), None);
+        assert_eq!(palette.replace("dirt", false), None);
+    }
+
+    #[test]
+    fn test_required_mods() {
+        let palette = BlockPalette::parse(PALETTE).unwrap();
+        assert_eq!(
+            palette.required_mods(false).into_iter().collect::<Vec<_>>(),
+            vec!["chisel", "create"]
+        );
+        assert!(palette.required_mods(true).is_empty());
+    }
+}
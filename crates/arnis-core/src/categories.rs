@@ -0,0 +1,199 @@
+//! Categories of features that can be left out of generation with `--skip`, e.g. to
+//! generate only roads and terrain for a racing map without the cost of the buildings.
+//! `--include` generates only the given categories instead. Left out categories aren't
+//! downloaded either.
+
+use crate::args::Args;
+use crate::osm_parser::ProcessedElement;
+use clap::ValueEnum;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum Category {
+    Buildings,
+    Roads,
+    Rail,
+    Water,
+    /// Landuse, natural areas, parks and trees
+    Vegetation,
+    Barriers,
+    /// Amenities, tourism and man-made structures
+    Amenities,
+    /// Elevation data, the ground stays flat
+    Terrain,
+}
+
+impl Category {
+    /// Category of an element, in the same order of precedence as the generation of ways
+    pub fn of(element: &ProcessedElement) -> Option<Self> {
+        let tags = element.tags();
+        let has = |key: &str| tags.contains_key(key);
+        let is = |key: &str, value: &str| tags.get(key).is_some_and(|v| v == value);
+
+        if has("building") || has("building:part") || has("door") || has("entrance") {
+            Some(Category::Buildings)
+        } else if has("highway") {
+            Some(Category::Roads)
+        } else if has("landuse") {
+            Some(Category::Vegetation)
+        } else if has("water") || is("natural", "water") || is("natural", "bay") {
+            Some(Category::Water)
+        } else if has("natural") {
+            Some(Category::Vegetation)
+        } else if has("amenity") {
+            Some(Category::Amenities)
+        } else if has("leisure") {
+            Some(Category::Vegetation)
+        } else if has("barrier") {
+            Some(Category::Barriers)
+        } else if has("waterway") {
+            Some(Category::Water)
+        } else if has("railway") || has("roller_coaster") {
+            Some(Category::Rail)
+        } else if has("aeroway") || has("area:aeroway") {
+            Some(Category::Roads)
+        } else if is("service", "siding") {
+            Some(Category::Rail)
+        } else if has("tourism") || has("man_made") {
+            Some(Category::Amenities)
+        } else {
+            None
+        }
+    }
+
+    /// Bit of the category in a set of categories stored as `u8`
+    pub fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// Keys of the Overpass query that fetch the features of this category
+    fn overpass_keys(self) -> &'static [&'static str] {
+        match self {
+            Category::Buildings => &["building", "entrance", "door"],
+            Category::Roads => &["highway", "bridge"],
+            Category::Rail => &["railway"],
+            Category::Water => &["natural", "water", "waterway"],
+            Category::Vegetation => &["landuse", "natural", "leisure"],
+            Category::Barriers => &["barrier"],
+            Category::Amenities => &["amenity", "tourism"],
+            Category::Terrain => &[],
+        }
+    }
+}
+
+/// Categories that are downloaded and generated
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeatureFilter {
+    /// Only these categories, or all if empty
+    pub include: Vec<Category>,
+    pub exclude: Vec<Category>,
+}
+
+impl FeatureFilter {
+    pub fn from_args(args: &Args) -> Self {
+        FeatureFilter {
+            include: args.include.clone(),
+            exclude: args.skip.clone(),
+        }
+    }
+
+    /// Whether elements of the category are generated. Elements of no category are only
+    /// generated without `--include`.
+    pub fn allows(&self, category: Option<Category>) -> bool {
+        match category {
+            Some(category) => {
+                (self.include.is_empty() || self.include.contains(&category))
+                    && !self.exclude.contains(&category)
+            }
+            None => self.include.is_empty(),
+        }
+    }
+
+    /// Keys the Overpass query asks for, or `None` if nothing is left out and the query asks
+    /// for all ways as well
+    pub fn overpass_keys(&self) -> Option<Vec<&'static str>> {
+        let data_categories = Category::value_variants()
+            .iter()
+            .filter(|&&category| category != Category::Terrain);
+        if self.include.is_empty() && data_categories.clone().all(|&c| self.allows(Some(c))) {
+            return None;
+        }
+
+        let mut keys: Vec<&str> = Vec::new();
+        for &category in data_categories.filter(|&&c| self.allows(Some(c))) {
+            for key in category.overpass_keys() {
+                if !keys.contains(key) {
+                    keys.push(key);
+                }
+            }
+        }
+        Some(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm_parser::ProcessedNode;
+
+    fn node(tags: &[(&str, &str)]) -> ProcessedElement {
+        ProcessedElement::Node(ProcessedNode {
+            id: 1,
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            x: 0,
+            z: 0,
+        })
+    }
+
+    #[test]
+    fn test_category_of() {
+        assert_eq!(
+            Category::of(&node(&[("building", "yes"), ("amenity", "school")])),
+            Some(Category::Buildings)
+        );
+        assert_eq!(
+            Category::of(&node(&[("highway", "primary")])),
+            Some(Category::Roads)
+        );
+        assert_eq!(
+            Category::of(&node(&[("natural", "water")])),
+            Some(Category::Water)
+        );
+        assert_eq!(
+            Category::of(&node(&[("natural", "tree")])),
+            Some(Category::Vegetation)
+        );
+        assert_eq!(
+            Category::of(&node(&[("railway", "rail")])),
+            Some(Category::Rail)
+        );
+        assert_eq!(Category::of(&node(&[("name", "Nothing")])), None);
+    }
+
+    #[test]
+    fn test_feature_filter() {
+        let all = FeatureFilter::default();
+        assert!(all.allows(Some(Category::Rail)) && all.allows(None));
+        assert_eq!(all.overpass_keys(), None);
+
+        let skip_rail = FeatureFilter {
+            include: Vec::new(),
+            exclude: vec![Category::Rail],
+        };
+        assert!(!skip_rail.allows(Some(Category::Rail)) && skip_rail.allows(None));
+        assert!(!skip_rail.overpass_keys().unwrap().contains(&"railway"));
+
+        // Water and vegetation share the natural key
+        let water = FeatureFilter {
+            include: vec![Category::Water, Category::Buildings],
+            exclude: Vec::new(),
+        };
+        assert!(!water.allows(None) && !water.allows(Some(Category::Roads)));
+        assert_eq!(
+            water.overpass_keys().unwrap(),
+            ["building", "entrance", "door", "natural", "water", "waterway"]
+        );
+    }
+}
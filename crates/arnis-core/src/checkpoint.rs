@@ -0,0 +1,260 @@
+//! Checkpoints of the generation, so `--resume` can continue an interrupted run instead of
+//! starting over. While elements are processed, the world built so far is written to the
+//! temporary directory every few minutes, together with the index of the next element and
+//! the random seed. Short runs finish before the first checkpoint and never write one.
+
+use crate::args::Args;
+use crate::disk_io;
+use crate::error::ArnisError;
+use crate::osm_parser::ProcessedElement;
+use crate::seeded_rng;
+use crate::world_editor::WorldEditor;
+use fnv::FnvHasher;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::hash::Hasher;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const STATE_FILE: &str = "state.json";
+const SNAPSHOT_FILE: &str = "world.nbt.gz";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct CheckpointState {
+    bbox: [f64; 4],
+    scale: f64,
+    seed: u64,
+    element_count: usize,
+    /// Elements before this index are part of the snapshot
+    next_element: usize,
+    /// ID of the last element in the snapshot, to detect changed data
+    last_element_id: Option<u64>,
+}
+
+impl CheckpointState {
+    /// Whether the checkpoint was written for the same elements
+    fn matches(&self, other: &CheckpointState, elements: &[ProcessedElement]) -> bool {
+        self.bbox == other.bbox
+            && self.scale == other.scale
+            && self.element_count == other.element_count
+            && self.next_element <= elements.len()
+            && self.last_element_id
+                == self
+                    .next_element
+                    .checked_sub(1)
+                    .map(|index| elements[index].id())
+    }
+}
+
+pub struct Checkpointer {
+    dir: PathBuf,
+    state: CheckpointState,
+    last_saved: Instant,
+}
+
+impl Checkpointer {
+    pub fn new(args: &Args, elements: &[ProcessedElement]) -> Self {
+        let bbox = [
+            args.bbox().min().lat(),
+            args.bbox().min().lng(),
+            args.bbox().max().lat(),
+            args.bbox().max().lng(),
+        ];
+        Checkpointer {
+            dir: checkpoint_dir(args, &bbox),
+            state: CheckpointState {
+                bbox,
+                scale: args.scale,
+                seed: seeded_rng::seed().unwrap_or(0),
+                element_count: elements.len(),
+                next_element: 0,
+                last_element_id: None,
+            },
+            last_saved: Instant::now(),
+        }
+    }
+
+    /// Restores the world of an interrupted run, returns the index of the first element that
+    /// still has to be processed
+    pub fn resume(&mut self, editor: &mut WorldEditor, elements: &[ProcessedElement]) -> usize {
+        let saved: Option<CheckpointState> = fs::read_to_string(self.dir.join(STATE_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok());
+        let Some(saved) = saved else {
+            println!("No interrupted run found for this area, starting from the beginning");
+            return 0;
+        };
+        if !saved.matches(&self.state, elements) {
+            println!("The data changed since the interrupted run, starting from the beginning");
+            return 0;
+        }
+        if let Err(e) = editor.load_snapshot(&self.dir.join(SNAPSHOT_FILE)) {
+            eprintln!("Warning: Failed to restore the interrupted run, starting over: {e}");
+            return 0;
+        }
+
+        // Random details of the remaining elements match those of the interrupted run
+        seeded_rng::set_seed(saved.seed);
+        println!(
+            "Resuming the interrupted run at element {} of {}",
+            saved.next_element, saved.element_count
+        );
+        self.state = saved;
+        self.state.next_element
+    }
+
+    /// Writes a checkpoint if the last one is long enough ago
+    pub fn save_if_due(
+        &mut self,
+        editor: &WorldEditor,
+        next_element: usize,
+        elements: &[ProcessedElement],
+    ) {
+        if self.last_saved.elapsed() < CHECKPOINT_INTERVAL {
+            return;
+        }
+        self.save(editor, next_element, elements);
+    }
+
+    /// Writes a checkpoint of the elements before `next_element`
+    pub fn save(
+        &mut self,
+        editor: &WorldEditor,
+        next_element: usize,
+        elements: &[ProcessedElement],
+    ) {
+        // Without a seed, random details could not be repeated after resuming
+        if seeded_rng::seed().is_none() {
+            self.state.seed = rand::random();
+            seeded_rng::set_seed(self.state.seed);
+        }
+
+        self.state.next_element = next_element;
+        self.state.last_element_id = next_element
+            .checked_sub(1)
+            .map(|index| elements[index].id());
+        if let Err(e) = self.write(editor) {
+            eprintln!("Warning: Failed to write checkpoint: {e}");
+        }
+        self.last_saved = Instant::now();
+    }
+
+    fn write(&self, editor: &WorldEditor) -> Result<(), ArnisError> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| ArnisError::Io(format!("Failed to create {}: {e}", self.dir.display())))?;
+        editor.write_snapshot(&self.dir.join(SNAPSHOT_FILE))?;
+        // The state is written last, so it never points to an older snapshot
+        let json = serde_json::to_string(&self.state)
+            .map_err(|e| ArnisError::Io(format!("Failed to serialize checkpoint: {e}")))?;
+        fs::write(self.dir.join(STATE_FILE), json)
+            .map_err(|e| ArnisError::Io(format!("Failed to write checkpoint: {e}")))
+    }
+
+    /// Removes the checkpoint once the world is saved
+    pub fn clear(&self) {
+        if self.dir.exists() {
+            if let Err(e) = fs::remove_dir_all(&self.dir) {
+                eprintln!("Warning: Failed to remove checkpoint: {e}");
+            }
+        }
+    }
+}
+
+/// Directory of the checkpoints of runs for the same area and world
+fn checkpoint_dir(args: &Args, bbox: &[f64; 4]) -> PathBuf {
+    let mut hasher = FnvHasher::default();
+    for value in bbox.iter().chain(std::iter::once(&args.scale)) {
+        hasher.write_u64(value.to_bits());
+    }
+    hasher.write(args.path.to_string_lossy().as_bytes());
+    disk_io::temp_dir()
+        .join("arnis-checkpoints")
+        .join(format!("{:016x}", hasher.finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_definitions::{DIRT, STONE};
+    use crate::coordinate_system::cartesian::XZBBox;
+    use crate::test_utilities::get_llbbox_arnis;
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let xzbbox = XZBBox::rect_from_xz_lengths(100.0, 100.0).unwrap();
+        let path = dir.path().join(SNAPSHOT_FILE);
+
+        let mut editor = WorldEditor::new(dir.path().to_path_buf(), &xzbbox, get_llbbox_arnis());
+        editor.set_block_absolute(STONE, 40, 10, 70, None, None);
+        editor.write_snapshot(&path).unwrap();
+
+        let mut restored = WorldEditor::new(dir.path().to_path_buf(), &xzbbox, get_llbbox_arnis());
+        restored.load_snapshot(&path).unwrap();
+        assert!(restored.check_for_block_absolute(40, 10, 70, Some(&[STONE]), None));
+        assert!(!restored.block_at_absolute(40, 11, 70));
+    }
+
+    #[test]
+    fn test_checkpoint_after_pause() {
+        let dir = tempfile::tempdir().unwrap();
+        let xzbbox = XZBBox::rect_from_xz_lengths(100.0, 100.0).unwrap();
+        let checkpointer = Checkpointer {
+            dir: dir.path().join("checkpoint"),
+            state: CheckpointState {
+                bbox: [54.6, 9.9, 54.7, 10.0],
+                scale: 1.0,
+                seed: 7,
+                element_count: 0,
+                next_element: 0,
+                last_element_id: None,
+            },
+            last_saved: Instant::now(),
+        };
+
+        // Pausing moves all regions to disk, later checkpoints still include them
+        let mut editor = WorldEditor::new(dir.path().to_path_buf(), &xzbbox, get_llbbox_arnis());
+        editor.set_spill_dir(dir.path().join("spill"));
+        editor.set_block_absolute(STONE, 40, 10, 70, None, None);
+        checkpointer.write(&editor).unwrap();
+        editor.release_memory();
+        fs::remove_dir_all(&checkpointer.dir).unwrap();
+        checkpointer.write(&editor).unwrap();
+        assert!(checkpointer.dir.join(STATE_FILE).exists());
+
+        let mut restored = WorldEditor::new(dir.path().to_path_buf(), &xzbbox, get_llbbox_arnis());
+        restored.set_spill_dir(dir.path().join("restored"));
+        restored
+            .load_snapshot(&checkpointer.dir.join(SNAPSHOT_FILE))
+            .unwrap();
+        restored.set_block_absolute(DIRT, 40, 11, 70, None, None);
+        assert!(restored.check_for_block_absolute(40, 10, 70, Some(&[STONE]), None));
+    }
+
+    #[test]
+    fn test_state_matches() {
+        let state = CheckpointState {
+            bbox: [54.6, 9.9, 54.7, 10.0],
+            scale: 1.0,
+            seed: 7,
+            element_count: 0,
+            next_element: 0,
+            last_element_id: None,
+        };
+        assert!(state.matches(&state, &[]));
+
+        let other_scale = CheckpointState {
+            scale: 2.0,
+            ..state.clone()
+        };
+        assert!(!state.matches(&other_scale, &[]));
+
+        // More processed elements than there are now
+        let ahead = CheckpointState {
+            next_element: 1,
+            ..state.clone()
+        };
+        assert!(!ahead.matches(&state, &[]));
+    }
+}
@@ -0,0 +1,299 @@
+//! Splitting of large areas into chunks that are processed one after another.
+//!
+//! The chunk size is not fixed but derived from the memory available on this machine and
+//! the density of the data, so low-RAM machines automatically get smaller chunks.
+
+use crate::coordinate_system::geographic::LLBBox;
+use crate::coordinate_system::transformation::geo_distance;
+use crate::cpu_info::PlatformInfo;
+use crate::perf_config::estimate_generation_bytes;
+use clap::ValueEnum;
+
+/// Meters per degree of latitude
+const METERS_PER_DEGREE: f64 = 111_320.0;
+/// Bounds of the chunk size, in degrees of latitude
+const MIN_CHUNK_SIZE_DEG: f64 = 0.005;
+const MAX_CHUNK_SIZE_DEG: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChunkConfig {
+    /// Edge length of a chunk in degrees of latitude; longitude is widened to keep chunks square
+    pub chunk_size_deg: f64,
+}
+
+/// How large chunks are for the memory of this machine (`--chunking`). Larger chunks mean
+/// fewer seams and less work repeated in the overlaps, smaller ones leave more memory to
+/// other programs and survive unexpectedly dense data.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ChunkPreset {
+    /// A quarter of the available memory per chunk, for machines in use while generating
+    Small,
+    /// Half of the available memory per chunk
+    #[default]
+    Balanced,
+    /// Most of the available memory per chunk, for machines doing nothing else
+    Large,
+}
+
+impl ChunkPreset {
+    /// Share of the available memory a single chunk may use
+    pub fn memory_share(self) -> f64 {
+        match self {
+            ChunkPreset::Small => 0.25,
+            ChunkPreset::Balanced => 0.5,
+            ChunkPreset::Large => 0.8,
+        }
+    }
+
+    /// Blocks every chunk extends into its neighbors, so that features along a seam are
+    /// complete in one of them; larger chunks afford a wider overlap
+    pub fn overlap_blocks(self) -> f64 {
+        match self {
+            ChunkPreset::Small => 32.0,
+            ChunkPreset::Balanced => 48.0,
+            ChunkPreset::Large => 64.0,
+        }
+    }
+}
+
+impl Default for ChunkConfig {
+    /// Chunk size for the memory of this machine and an average city center
+    fn default() -> Self {
+        Self::auto(&PlatformInfo::detect(), 2000.0, 1.0)
+    }
+}
+
+impl ChunkConfig {
+    /// Largest chunk size whose estimated memory use fits into the memory available on the
+    /// platform, given the density of the data (elements per km²) and the world scale
+    pub fn auto(platform: &PlatformInfo, elements_per_km2: f64, scale: f64) -> Self {
+        Self::with_preset(platform, elements_per_km2, scale, ChunkPreset::default())
+    }
+
+    /// Like `auto`, with the share of the memory given by `preset`
+    pub fn with_preset(
+        platform: &PlatformInfo,
+        elements_per_km2: f64,
+        scale: f64,
+        preset: ChunkPreset,
+    ) -> Self {
+        let budget_bytes =
+            platform.available_memory_gb * preset.memory_share() * 1024.0 * 1024.0 * 1024.0;
+        let bytes_per_km2 = estimate_generation_bytes(
+            (1_000_000.0 * scale * scale) as u64,
+            elements_per_km2.max(0.0) as usize,
+            false,
+        )
+        .max(1) as f64;
+
+        let edge_m = (budget_bytes / bytes_per_km2).max(0.0).sqrt() * 1000.0;
+        let chunk_size_deg =
+            (edge_m / METERS_PER_DEGREE).clamp(MIN_CHUNK_SIZE_DEG, MAX_CHUNK_SIZE_DEG);
+
+        ChunkConfig { chunk_size_deg }
+    }
+}
+
+/// Splits a bounding box into a grid of chunks no larger than the configured size.
+/// The chunks cover the whole bounding box without overlapping.
+pub fn split_into_chunks(bbox: &LLBBox, config: &ChunkConfig) -> Vec<LLBBox> {
+    let (min, max) = (bbox.min(), bbox.max());
+    let center_lat = (min.lat() + max.lat()) / 2.0;
+    let lat_step = config.chunk_size_deg;
+    let lng_step = config.chunk_size_deg / center_lat.to_radians().cos().max(0.01);
+
+    let rows = ((max.lat() - min.lat()) / lat_step).ceil().max(1.0) as usize;
+    let cols = ((max.lng() - min.lng()) / lng_step).ceil().max(1.0) as usize;
+    let lat_size = (max.lat() - min.lat()) / rows as f64;
+    let lng_size = (max.lng() - min.lng()) / cols as f64;
+
+    let mut chunks = Vec::with_capacity(rows * cols);
+    for row in 0..rows {
+        for col in 0..cols {
+            // The last row and column end exactly at the edge, without rounding errors
+            let max_lat = if row + 1 == rows {
+                max.lat()
+            } else {
+                min.lat() + (row + 1) as f64 * lat_size
+            };
+            let max_lng = if col + 1 == cols {
+                max.lng()
+            } else {
+                min.lng() + (col + 1) as f64 * lng_size
+            };
+            if let Ok(chunk) = LLBBox::new(
+                min.lat() + row as f64 * lat_size,
+                min.lng() + col as f64 * lng_size,
+                max_lat,
+                max_lng,
+            ) {
+                chunks.push(chunk);
+            }
+        }
+    }
+    chunks
+}
+
+/// Checks the chunks against the memory budget before they are processed. Chunks that are
+/// estimated to need more are split further until they fit; a chunk that is still too dense
+/// at the minimum chunk size is refused with its area, instead of running out of memory.
+pub fn preflight_chunks(
+    chunks: Vec<LLBBox>,
+    element_count: impl Fn(&LLBBox) -> usize,
+    scale: f64,
+    fillground: bool,
+    budget_bytes: u64,
+) -> Result<Vec<LLBBox>, String> {
+    let mut accepted = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        preflight_chunk(
+            chunk,
+            &element_count,
+            scale,
+            fillground,
+            budget_bytes,
+            &mut accepted,
+        )?;
+    }
+    Ok(accepted)
+}
+
+fn preflight_chunk(
+    chunk: LLBBox,
+    element_count: &impl Fn(&LLBBox) -> usize,
+    scale: f64,
+    fillground: bool,
+    budget_bytes: u64,
+    accepted: &mut Vec<LLBBox>,
+) -> Result<(), String> {
+    let elements = element_count(&chunk);
+    let (height_m, width_m) = geo_distance(chunk.min(), chunk.max());
+    let blocks = (height_m * scale).ceil() as u64 * (width_m * scale).ceil() as u64;
+    let estimated_bytes = estimate_generation_bytes(blocks, elements, fillground);
+    if estimated_bytes <= budget_bytes {
+        accepted.push(chunk);
+        return Ok(());
+    }
+
+    let size_deg = chunk.max().lat() - chunk.min().lat();
+    if size_deg <= MIN_CHUNK_SIZE_DEG {
+        let gb = |bytes: u64| bytes as f64 / (1024.0 * 1024.0 * 1024.0);
+        return Err(format!(
+            "The area {:.5},{:.5},{:.5},{:.5} is too dense to generate: its {elements} elements \
+             need about {:.1} GB of memory, but only {:.1} GB are available. \
+             Exclude it, lower the scale or raise --max-ram-gb.",
+            chunk.min().lat(),
+            chunk.min().lng(),
+            chunk.max().lat(),
+            chunk.max().lng(),
+            gb(estimated_bytes),
+            gb(budget_bytes)
+        ));
+    }
+
+    let config = ChunkConfig {
+        chunk_size_deg: (size_deg / 2.0).max(MIN_CHUNK_SIZE_DEG),
+    };
+    for part in split_into_chunks(&chunk, &config) {
+        preflight_chunk(
+            part,
+            element_count,
+            scale,
+            fillground,
+            budget_bytes,
+            accepted,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn platform(available_memory_gb: f64) -> PlatformInfo {
+        PlatformInfo {
+            available_memory_gb,
+            ..PlatformInfo::detect()
+        }
+    }
+
+    #[test]
+    fn test_auto_chunk_size() {
+        let large = ChunkConfig::auto(&platform(32.0), 2000.0, 1.0);
+        let small = ChunkConfig::auto(&platform(2.0), 2000.0, 1.0);
+        assert!(small.chunk_size_deg < large.chunk_size_deg);
+
+        // Denser data and larger scales need smaller chunks
+        let dense = ChunkConfig::auto(&platform(2.0), 50_000.0, 1.0);
+        assert!(dense.chunk_size_deg < small.chunk_size_deg);
+        let scaled = ChunkConfig::auto(&platform(2.0), 2000.0, 2.0);
+        assert!(scaled.chunk_size_deg < small.chunk_size_deg);
+
+        let tiny = ChunkConfig::auto(&platform(0.0), 2000.0, 1.0);
+        assert_eq!(tiny.chunk_size_deg, MIN_CHUNK_SIZE_DEG);
+    }
+
+    #[test]
+    fn test_chunk_presets() {
+        let size = |preset| ChunkConfig::with_preset(&platform(8.0), 2000.0, 1.0, preset);
+        assert_eq!(
+            size(ChunkPreset::Balanced),
+            ChunkConfig::auto(&platform(8.0), 2000.0, 1.0)
+        );
+        assert!(
+            size(ChunkPreset::Small).chunk_size_deg < size(ChunkPreset::Balanced).chunk_size_deg
+        );
+        assert!(
+            size(ChunkPreset::Large).chunk_size_deg > size(ChunkPreset::Balanced).chunk_size_deg
+        );
+        assert!(ChunkPreset::Small.overlap_blocks() < ChunkPreset::Large.overlap_blocks());
+    }
+
+    #[test]
+    fn test_split_into_chunks() {
+        let bbox = LLBBox::new(0.0, 10.0, 0.25, 10.1).unwrap();
+        let config = ChunkConfig {
+            chunk_size_deg: 0.1,
+        };
+        let chunks = split_into_chunks(&bbox, &config);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].min().lat(), 0.0);
+        assert_eq!(chunks[2].max().lat(), 0.25);
+        assert!(chunks.iter().all(|c| c.max().lng() == 10.1));
+
+        // Small areas stay in one piece
+        let bbox = LLBBox::new(0.0, 10.0, 0.01, 10.01).unwrap();
+        assert_eq!(split_into_chunks(&bbox, &config), vec![bbox]);
+    }
+
+    #[test]
+    fn test_preflight_chunks() {
+        let bbox = LLBBox::new(54.0, 9.0, 54.02, 9.02).unwrap();
+        // Most elements are in the south-west corner
+        let dense = LLBBox::new(54.0, 9.0, 54.005, 9.005).unwrap();
+        let count = |chunk: &LLBBox| {
+            if chunk.max().lat() <= dense.max().lat() + 1e-9
+                && chunk.max().lng() <= dense.max().lng() + 1e-9
+            {
+                20_000
+            } else if chunk.contains(&dense.min()) {
+                200_000
+            } else {
+                100
+            }
+        };
+
+        // With enough memory the chunk stays whole
+        let chunks = preflight_chunks(vec![bbox], count, 1.0, false, 4 << 30).unwrap();
+        assert_eq!(chunks, vec![bbox]);
+
+        // Otherwise the dense part is split until it fits
+        let chunks = preflight_chunks(vec![bbox], count, 1.0, false, 512 << 20).unwrap();
+        assert!(chunks.len() > 1);
+
+        // Areas that never fit are refused with their coordinates
+        let err = preflight_chunks(vec![bbox], count, 1.0, false, 64 << 20).unwrap_err();
+        assert!(err.contains("54.00000,9.00000"));
+    }
+}
@@ -0,0 +1,181 @@
+use super::rectangle::XZBBoxRect;
+use crate::coordinate_system::cartesian::{XZPoint, XZVector};
+use fnv::FnvHashMap;
+use std::fmt;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+/// Side length of the lookup cells used to find nearby route segments quickly
+const CELL_SIZE_SHIFT: i32 = 4;
+
+/// An underlying shape of XZBBox enum: every block within `half_width` of a polyline.
+#[derive(Clone, Debug)]
+pub struct XZBBoxCorridor {
+    /// Vertices of the route polyline
+    points: Vec<XZPoint>,
+
+    /// Maximum distance from the polyline that is still covered
+    half_width: f64,
+
+    /// Circumscribed rectangle, clipped to the rectangle the corridor was built in
+    rect: XZBBoxRect,
+
+    /// Segment indices (into `points`) that pass near each 16x16 cell
+    cells: FnvHashMap<(i32, i32), Vec<usize>>,
+}
+
+impl XZBBoxCorridor {
+    pub fn new(bounds: XZBBoxRect, points: Vec<XZPoint>, half_width: f64) -> Result<Self, String> {
+        if points.is_empty() {
+            return Err("Invalid XZBBox::Corridor: route has no points".to_string());
+        }
+
+        if half_width.is_nan() || half_width < 0.0 {
+            return Err(format!(
+                "Invalid XZBBox::Corridor: half width should >= 0, but encountered {half_width}"
+            ));
+        }
+
+        let reach = half_width.ceil() as i32;
+        let min_x = points.iter().map(|p| p.x).min().unwrap() - reach;
+        let max_x = points.iter().map(|p| p.x).max().unwrap() + reach;
+        let min_z = points.iter().map(|p| p.z).min().unwrap() - reach;
+        let max_z = points.iter().map(|p| p.z).max().unwrap() + reach;
+
+        let rect = XZBBoxRect::new(
+            XZPoint::new(min_x.max(bounds.min().x), min_z.max(bounds.min().z)),
+            XZPoint::new(max_x.min(bounds.max().x), max_z.min(bounds.max().z)),
+        )
+        .map_err(|e| format!("Invalid XZBBox::Corridor: route lies outside the world\n{e}"))?;
+
+        let mut cells: FnvHashMap<(i32, i32), Vec<usize>> = FnvHashMap::default();
+        // Half diagonal of a cell, so that a cell is kept if any of its blocks may be covered
+        let cell_reach =
+            half_width + (1 << CELL_SIZE_SHIFT) as f64 * std::f64::consts::FRAC_1_SQRT_2;
+
+        for i in 0..points.len().max(2) - 1 {
+            let a = points[i];
+            let b = points[(i + 1).min(points.len() - 1)];
+
+            let cell_min_x = (a.x.min(b.x) - reach) >> CELL_SIZE_SHIFT;
+            let cell_max_x = (a.x.max(b.x) + reach) >> CELL_SIZE_SHIFT;
+            let cell_min_z = (a.z.min(b.z) - reach) >> CELL_SIZE_SHIFT;
+            let cell_max_z = (a.z.max(b.z) + reach) >> CELL_SIZE_SHIFT;
+
+            for cell_x in cell_min_x..=cell_max_x {
+                for cell_z in cell_min_z..=cell_max_z {
+                    let center = (
+                        ((cell_x << CELL_SIZE_SHIFT) as f64) + 8.0,
+                        ((cell_z << CELL_SIZE_SHIFT) as f64) + 8.0,
+                    );
+                    if distance_sq_to_segment(center, a, b) <= cell_reach * cell_reach {
+                        cells.entry((cell_x, cell_z)).or_default().push(i);
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            points,
+            half_width,
+            rect,
+            cells,
+        })
+    }
+
+    /// The circumscribed rectangle of the corridor
+    pub fn rect(&self) -> XZBBoxRect {
+        self.rect
+    }
+
+    /// Check whether an XZPoint is covered
+    pub fn contains(&self, xzpoint: &XZPoint) -> bool {
+        if !self.rect.contains(xzpoint) {
+            return false;
+        }
+
+        let Some(segments) = self
+            .cells
+            .get(&(xzpoint.x >> CELL_SIZE_SHIFT, xzpoint.z >> CELL_SIZE_SHIFT))
+        else {
+            return false;
+        };
+
+        let point = (xzpoint.x as f64, xzpoint.z as f64);
+        segments.iter().any(|&i| {
+            let a = self.points[i];
+            let b = self.points[(i + 1).min(self.points.len() - 1)];
+            distance_sq_to_segment(point, a, b) <= self.half_width * self.half_width
+        })
+    }
+
+    fn translated(&self, vector: XZVector) -> Self {
+        let points = self.points.iter().map(|p| *p + vector).collect();
+        Self::new(self.rect + vector, points, self.half_width)
+            .expect("Translating a valid corridor keeps it valid")
+    }
+}
+
+/// Squared distance from a point to the segment a-b
+fn distance_sq_to_segment(point: (f64, f64), a: XZPoint, b: XZPoint) -> f64 {
+    let (px, pz) = point;
+    let (ax, az) = (a.x as f64, a.z as f64);
+    let (bx, bz) = (b.x as f64, b.z as f64);
+
+    let (dx, dz) = (bx - ax, bz - az);
+    let length_sq = dx * dx + dz * dz;
+    let t = if length_sq == 0.0 {
+        0.0
+    } else {
+        (((px - ax) * dx + (pz - az) * dz) / length_sq).clamp(0.0, 1.0)
+    };
+
+    let (cx, cz) = (ax + t * dx, az + t * dz);
+    (px - cx) * (px - cx) + (pz - cz) * (pz - cz)
+}
+
+impl fmt::Display for XZBBoxCorridor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Corridor({} points, half width {}, within {})",
+            self.points.len(),
+            self.half_width,
+            self.rect
+        )
+    }
+}
+
+// below are associated +- operators
+impl Add<XZVector> for XZBBoxCorridor {
+    type Output = XZBBoxCorridor;
+
+    fn add(self, other: XZVector) -> Self {
+        self.translated(other)
+    }
+}
+
+impl AddAssign<XZVector> for XZBBoxCorridor {
+    fn add_assign(&mut self, other: XZVector) {
+        *self = self.translated(other);
+    }
+}
+
+impl Sub<XZVector> for XZBBoxCorridor {
+    type Output = XZBBoxCorridor;
+
+    fn sub(self, other: XZVector) -> Self {
+        self.translated(XZVector {
+            dx: -other.dx,
+            dz: -other.dz,
+        })
+    }
+}
+
+impl SubAssign<XZVector> for XZBBoxCorridor {
+    fn sub_assign(&mut self, other: XZVector) {
+        *self = self.translated(XZVector {
+            dx: -other.dx,
+            dz: -other.dz,
+        });
+    }
+}
@@ -1,4 +1,6 @@
+mod corridor;
 mod rectangle;
+mod union;
 mod xzbbox_enum;
 
 pub use xzbbox_enum::XZBBox;
@@ -0,0 +1,99 @@
+use super::rectangle::XZBBoxRect;
+use super::xzbbox_enum::XZBBox;
+use crate::coordinate_system::cartesian::{XZPoint, XZVector};
+use std::fmt;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+/// An underlying shape of XZBBox enum: every block covered by at least one of several shapes.
+#[derive(Clone, Debug)]
+pub struct XZBBoxUnion {
+    /// The merged shapes, which may be disjoint or overlapping
+    parts: Vec<XZBBox>,
+
+    /// Circumscribed rectangle of all parts
+    rect: XZBBoxRect,
+}
+
+impl XZBBoxUnion {
+    pub fn new(parts: Vec<XZBBox>) -> Result<Self, String> {
+        let Some(first) = parts.first() else {
+            return Err("Invalid XZBBox::Union: no shapes to merge".to_string());
+        };
+
+        let (mut min, mut max) = (first.bounding_rect().min(), first.bounding_rect().max());
+        for part in &parts[1..] {
+            let rect = part.bounding_rect();
+            min = XZPoint::new(min.x.min(rect.min().x), min.z.min(rect.min().z));
+            max = XZPoint::new(max.x.max(rect.max().x), max.z.max(rect.max().z));
+        }
+
+        let rect = XZBBoxRect::new(min, max)
+            .map_err(|e| format!("Invalid XZBBox::Union: circumscribed rectangle\n{e}"))?;
+
+        Ok(Self { parts, rect })
+    }
+
+    /// The circumscribed rectangle of all parts
+    pub fn rect(&self) -> XZBBoxRect {
+        self.rect
+    }
+
+    /// Check whether an XZPoint is covered by any part
+    pub fn contains(&self, xzpoint: &XZPoint) -> bool {
+        self.rect.contains(xzpoint) && self.parts.iter().any(|part| part.contains(xzpoint))
+    }
+}
+
+impl fmt::Display for XZBBoxUnion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Union(")?;
+        for (i, part) in self.parts.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{part}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+// below are associated +- operators
+impl Add<XZVector> for XZBBoxUnion {
+    type Output = XZBBoxUnion;
+
+    fn add(self, other: XZVector) -> Self {
+        Self {
+            parts: self.parts.into_iter().map(|part| part + other).collect(),
+            rect: self.rect + other,
+        }
+    }
+}
+
+impl AddAssign<XZVector> for XZBBoxUnion {
+    fn add_assign(&mut self, other: XZVector) {
+        for part in &mut self.parts {
+            *part += other;
+        }
+        self.rect += other;
+    }
+}
+
+impl Sub<XZVector> for XZBBoxUnion {
+    type Output = XZBBoxUnion;
+
+    fn sub(self, other: XZVector) -> Self {
+        Self {
+            parts: self.parts.into_iter().map(|part| part - other).collect(),
+            rect: self.rect - other,
+        }
+    }
+}
+
+impl SubAssign<XZVector> for XZBBoxUnion {
+    fn sub_assign(&mut self, other: XZVector) {
+        for part in &mut self.parts {
+            *part -= other;
+        }
+        self.rect -= other;
+    }
+}
@@ -1,4 +1,6 @@
+use super::corridor::XZBBoxCorridor;
 use super::rectangle::XZBBoxRect;
+use super::union::XZBBoxUnion;
 use crate::coordinate_system::cartesian::{XZPoint, XZVector};
 use std::fmt;
 use std::ops::{Add, AddAssign, Sub, SubAssign};
@@ -7,6 +9,8 @@ use std::ops::{Add, AddAssign, Sub, SubAssign};
 #[derive(Clone, Debug)]
 pub enum XZBBox {
     Rect(XZBBoxRect),
+    Corridor(XZBBoxCorridor),
+    Union(XZBBoxUnion),
 }
 
 impl XZBBox {
@@ -50,10 +54,27 @@ impl XZBBox {
         )?))
     }
 
+    /// Construct a corridor shape bbox covering every block within `half_width` of the route,
+    /// clipped to the circumscribed rectangle of `self`
+    pub fn corridor_within(&self, points: Vec<XZPoint>, half_width: f64) -> Result<Self, String> {
+        Ok(Self::Corridor(XZBBoxCorridor::new(
+            self.bounding_rect(),
+            points,
+            half_width,
+        )?))
+    }
+
+    /// Construct a shape covering every block covered by at least one of `parts`
+    pub fn union(parts: Vec<XZBBox>) -> Result<Self, String> {
+        Ok(Self::Union(XZBBoxUnion::new(parts)?))
+    }
+
     /// Check whether an XZPoint is covered
     pub fn contains(&self, xzpoint: &XZPoint) -> bool {
         match self {
             Self::Rect(r) => r.contains(xzpoint),
+            Self::Corridor(c) => c.contains(xzpoint),
+            Self::Union(u) => u.contains(xzpoint),
         }
     }
 
@@ -61,6 +82,8 @@ impl XZBBox {
     pub fn bounding_rect(&self) -> XZBBoxRect {
         match self {
             Self::Rect(r) => *r,
+            Self::Corridor(c) => c.rect(),
+            Self::Union(u) => u.rect(),
         }
     }
 
@@ -89,6 +112,8 @@ impl fmt::Display for XZBBox {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Rect(r) => write!(f, "XZBBox::{r}"),
+            Self::Corridor(c) => write!(f, "XZBBox::{c}"),
+            Self::Union(u) => write!(f, "XZBBox::{u}"),
         }
     }
 }
@@ -100,6 +125,8 @@ impl Add<XZVector> for XZBBox {
     fn add(self, other: XZVector) -> XZBBox {
         match self {
             Self::Rect(r) => Self::Rect(r + other),
+            Self::Corridor(c) => Self::Corridor(c + other),
+            Self::Union(u) => Self::Union(u + other),
         }
     }
 }
@@ -108,6 +135,8 @@ impl AddAssign<XZVector> for XZBBox {
     fn add_assign(&mut self, other: XZVector) {
         match self {
             Self::Rect(r) => *r += other,
+            Self::Corridor(c) => *c += other,
+            Self::Union(u) => *u += other,
         }
     }
 }
@@ -118,6 +147,8 @@ impl Sub<XZVector> for XZBBox {
     fn sub(self, other: XZVector) -> XZBBox {
         match self {
             Self::Rect(r) => Self::Rect(r - other),
+            Self::Corridor(c) => Self::Corridor(c - other),
+            Self::Union(u) => Self::Union(u - other),
         }
     }
 }
@@ -126,6 +157,8 @@ impl SubAssign<XZVector> for XZBBox {
     fn sub_assign(&mut self, other: XZVector) {
         match self {
             Self::Rect(r) => *r -= other,
+            Self::Corridor(c) => *c -= other,
+            Self::Union(u) => *u -= other,
         }
     }
 }
@@ -186,6 +219,74 @@ mod test {
         assert_eq!(obj.max_z(), 322);
     }
 
+    #[test]
+    fn test_corridor() {
+        let rect = XZBBox::rect_from_xz_lengths(200.0, 200.0).unwrap();
+        let route = vec![
+            XZPoint::new(20, 20),
+            XZPoint::new(120, 20),
+            XZPoint::new(120, 150),
+        ];
+        let obj = rect.corridor_within(route, 5.0).unwrap();
+
+        // along and next to the route
+        assert!(obj.contains(&XZPoint::new(20, 20)));
+        assert!(obj.contains(&XZPoint::new(70, 25)));
+        assert!(obj.contains(&XZPoint::new(124, 100)));
+        assert!(obj.contains(&XZPoint::new(120, 155)));
+
+        // too far from the route, but inside the circumscribed rectangle
+        assert!(!obj.contains(&XZPoint::new(70, 26)));
+        assert!(!obj.contains(&XZPoint::new(60, 100)));
+        assert!(!obj.contains(&XZPoint::new(120, 156)));
+
+        assert_eq!(obj.min_x(), 15);
+        assert_eq!(obj.max_x(), 125);
+        assert_eq!(obj.min_z(), 15);
+        assert_eq!(obj.max_z(), 155);
+
+        // translation moves the covered area along
+        let moved = obj + XZVector { dx: 10, dz: -10 };
+        assert!(moved.contains(&XZPoint::new(80, 15)));
+        assert!(!moved.contains(&XZPoint::new(70, 25)));
+
+        // an empty route or a route outside the world is rejected
+        assert!(rect.corridor_within(vec![], 5.0).is_err());
+        assert!(rect
+            .corridor_within(vec![XZPoint::new(500, 500)], 5.0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_union() {
+        let town = XZBBox::rect_from_xz_lengths(10.0, 10.0).unwrap();
+        let friends_town =
+            XZBBox::rect_from_xz_lengths(5.0, 5.0).unwrap() + XZVector { dx: 30, dz: 20 };
+        let overlapping =
+            XZBBox::rect_from_xz_lengths(4.0, 4.0).unwrap() + XZVector { dx: 8, dz: 8 };
+        let obj = XZBBox::union(vec![town, friends_town, overlapping]).unwrap();
+
+        assert!(obj.contains(&XZPoint::new(0, 0)));
+        assert!(obj.contains(&XZPoint::new(12, 12)));
+        assert!(obj.contains(&XZPoint::new(35, 25)));
+
+        // gap between the parts
+        assert!(!obj.contains(&XZPoint::new(20, 15)));
+        assert!(!obj.contains(&XZPoint::new(0, 25)));
+
+        assert_eq!(obj.min_x(), 0);
+        assert_eq!(obj.max_x(), 35);
+        assert_eq!(obj.min_z(), 0);
+        assert_eq!(obj.max_z(), 25);
+
+        let moved = obj + XZVector { dx: -30, dz: -20 };
+        assert!(moved.contains(&XZPoint::new(5, 5)));
+        assert!(!moved.contains(&XZPoint::new(-10, -5)));
+        assert_eq!(moved.min_x(), -30);
+
+        assert!(XZBBox::union(vec![]).is_err());
+    }
+
     #[test]
     #[allow(clippy::excessive_precision)]
     fn test_invalid_inputs() {
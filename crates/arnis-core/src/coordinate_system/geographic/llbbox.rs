@@ -57,6 +57,30 @@ impl LLBBox {
             && llpoint.lng() >= self.min().lng()
             && llpoint.lng() <= self.max().lng()
     }
+
+    /// The smallest bbox enclosing both `self` and `other`
+    pub fn union(&self, other: &LLBBox) -> Result<LLBBox, String> {
+        Self::new(
+            self.min.lat().min(other.min.lat()),
+            self.min.lng().min(other.min.lng()),
+            self.max.lat().max(other.max.lat()),
+            self.max.lng().max(other.max.lng()),
+        )
+    }
+}
+
+impl std::fmt::Display for LLBBox {
+    /// Formats the bbox the way `from_str` reads it
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.min.lat(),
+            self.min.lng(),
+            self.max.lat(),
+            self.max.lng()
+        )
+    }
 }
 
 #[cfg(test)]
@@ -98,6 +122,10 @@ mod tests {
         };
 
         assert_eq!(bbox_result.unwrap(), arnis_correct);
+        assert_eq!(
+            LLBBox::from_str(&arnis_correct.to_string()).unwrap(),
+            arnis_correct
+        );
     }
 
     #[test]
@@ -115,6 +143,17 @@ mod tests {
         assert_eq!(bbox_result.unwrap(), arnis_correct);
     }
 
+    #[test]
+    fn test_union() {
+        let town = LLBBox::new(54.62, 9.92, 54.63, 9.93).unwrap();
+        let friends_town = LLBBox::new(54.60, 9.95, 54.61, 9.97).unwrap();
+
+        let union = town.union(&friends_town).unwrap();
+        assert_eq!(union, LLBBox::new(54.60, 9.92, 54.63, 9.97).unwrap());
+        assert_eq!(union, friends_town.union(&town).unwrap());
+        assert_eq!(town.union(&town).unwrap(), town);
+    }
+
     #[test]
     fn test_out_of_order() {
         // Violates values in vals_in_order
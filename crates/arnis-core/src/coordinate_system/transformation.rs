@@ -1,4 +1,4 @@
-use super::cartesian::{XZBBox, XZPoint};
+use super::cartesian::{XZBBox, XZPoint, XZVector};
 use super::geographic::{LLBBox, LLPoint};
 
 /// Transform geographic space (within llbbox) to a local tangential cartesian space (within xzbbox)
@@ -61,6 +61,32 @@ impl CoordTransformer {
 
         XZPoint::new(x, z)
     }
+
+    /// Geographic position of the center of a block, the inverse of `transform_point`
+    pub fn inverse_transform_point(&self, xzpoint: XZPoint) -> Result<LLPoint, String> {
+        let rel_x: f64 = (xzpoint.x as f64 + 0.5) / self.scale_factor_x;
+        let rel_z: f64 = (xzpoint.z as f64 + 0.5) / self.scale_factor_z;
+
+        LLPoint::new(
+            self.min_lat + (1.0 - rel_z) * self.len_lat,
+            self.min_lng + rel_x * self.len_lng,
+        )
+    }
+
+    /// Transform a sub-area of the llbbox this transformer was built for into a rectangle
+    pub fn transform_llbbox(&self, llbbox: &LLBBox) -> Result<XZBBox, String> {
+        // Latitude grows northwards while z grows southwards
+        let min = self.transform_point(LLPoint::new(llbbox.max().lat(), llbbox.min().lng())?);
+        let max = self.transform_point(LLPoint::new(llbbox.min().lat(), llbbox.max().lng())?);
+
+        Ok(
+            XZBBox::rect_from_xz_lengths((max.x - min.x) as f64, (max.z - min.z) as f64)?
+                + XZVector {
+                    dx: min.x,
+                    dz: min.z,
+                },
+        )
+    }
 }
 
 // (lat meters, lon meters)
@@ -172,6 +198,48 @@ mod test {
         test_llxztransform_one_scale_one_factor(0.1, 0.2, 0.7);
     }
 
+    // this ensures that sub-areas land inside the world rectangle
+    #[test]
+    pub fn test_transform_llbbox() {
+        let llbbox = get_llbbox_arnis();
+        let (transformer, xzbbox) = CoordTransformer::llbbox_to_xzbbox(&llbbox, 1.0).unwrap();
+
+        let whole = transformer.transform_llbbox(&llbbox).unwrap();
+        assert_eq!(whole.bounding_rect(), xzbbox.bounding_rect());
+
+        let center_lat = (llbbox.min().lat() + llbbox.max().lat()) / 2.0;
+        let center_lng = (llbbox.min().lng() + llbbox.max().lng()) / 2.0;
+        let south_east = LLBBox::new(
+            llbbox.min().lat(),
+            center_lng,
+            center_lat,
+            llbbox.max().lng(),
+        )
+        .unwrap();
+        let part = transformer.transform_llbbox(&south_east).unwrap();
+        assert_eq!(part.max_x(), xzbbox.max_x());
+        assert_eq!(part.max_z(), xzbbox.max_z());
+        assert!(part.min_x() > xzbbox.min_x());
+        assert!(part.min_z() > xzbbox.min_z());
+    }
+
+    // this ensures that transforming back and forth lands on the same block
+    #[test]
+    pub fn test_inverse_transform_point() {
+        let llbbox = get_llbbox_arnis();
+        for scale in [0.5, 1.0, 3.0] {
+            let (transformer, xzbbox) = CoordTransformer::llbbox_to_xzbbox(&llbbox, scale).unwrap();
+            for xzpoint in [
+                XZPoint::new(0, 0),
+                XZPoint::new(xzbbox.max_x() / 3, xzbbox.max_z() / 2),
+                XZPoint::new(xzbbox.max_x() - 1, xzbbox.max_z() - 1),
+            ] {
+                let llpoint = transformer.inverse_transform_point(xzpoint).unwrap();
+                assert_eq!(transformer.transform_point(llpoint), xzpoint);
+            }
+        }
+    }
+
     // this ensures that invalid inputs can be handled correctly
     #[test]
     pub fn test_invalid_construct() {
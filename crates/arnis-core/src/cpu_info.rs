@@ -13,6 +13,8 @@ pub enum SimdFeatures {
 #[derive(Debug, Clone)]
 pub struct PlatformInfo {
     pub total_ram_bytes: u64,
+    /// Memory not in use by other programs at the time of detection
+    pub available_memory_gb: f64,
     pub logical_cpus: usize,
     pub physical_cpus: usize,
     pub simd: SimdFeatures,
@@ -35,11 +37,12 @@ impl PlatformInfo {
     pub fn detect() -> Self {
         let logical_cpus = num_cpus::get();
         let physical_cpus = num_cpus::get_physical();
-        let total_ram_bytes = get_total_ram_bytes();
+        let (total_ram_bytes, available_ram_bytes) = get_ram_bytes();
         let arch = std::env::consts::ARCH;
         let simd = detect_simd_features();
         PlatformInfo {
             total_ram_bytes,
+            available_memory_gb: available_ram_bytes as f64 / (1024.0 * 1024.0 * 1024.0),
             logical_cpus,
             physical_cpus,
             simd,
@@ -48,11 +51,12 @@ impl PlatformInfo {
     }
 }
 
-fn get_total_ram_bytes() -> u64 {
+/// Total and available RAM
+fn get_ram_bytes() -> (u64, u64) {
     use sysinfo::System;
     let mut sys = System::new();
     sys.refresh_memory();
-    sys.total_memory() * 1024
+    (sys.total_memory() * 1024, sys.available_memory() * 1024)
 }
 
 fn detect_simd_features() -> SimdFeatures {
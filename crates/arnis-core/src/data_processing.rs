@@ -1,30 +1,79 @@
 use crate::args::Args;
-use crate::block_definitions::{BEDROCK, DIRT, GRASS_BLOCK, STONE};
-use crate::coordinate_system::cartesian::XZBBox;
+use crate::block_definitions::{BEDROCK, DIRT, GRASS_BLOCK, STONE, WATER};
+use crate::categories::{Category, FeatureFilter};
+use crate::checkpoint::Checkpointer;
+use crate::coordinate_system::cartesian::{XZBBox, XZPoint};
 use crate::coordinate_system::geographic::LLBBox;
+use crate::diff::{self, ElementChange};
 use crate::element_processing::*;
+use crate::elytra_tour;
+use crate::error::ArnisError;
 use crate::ground::Ground;
+use crate::imagery::GroundImagery;
+use crate::lod::{DetailLevel, LodSettings};
 use crate::osm_parser::ProcessedElement;
+use crate::pause;
+use crate::perf_config::PerformanceConfig;
+use crate::polygon_mask::PolygonMask;
 use crate::progress::emit_gui_progress_update;
+use crate::resource_pack::{self, ResourcePack};
+use crate::schematic::OutputFormat;
+use crate::seeded_rng;
+use crate::spawn;
+use crate::throttle::Throttle;
+use crate::world_edge::{self, WorldEdge};
 use crate::world_editor::WorldEditor;
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
 
 pub const MIN_Y: i32 = -64;
+/// Build height limit
+pub const MAX_Y: i32 = 319;
 
 pub fn generate_world(
     elements: Vec<ProcessedElement>,
     xzbbox: XZBBox,
     llbbox: LLBBox,
     ground: Ground,
+    changes: &[ElementChange],
     args: &Args,
-) -> Result<(), String> {
+) -> Result<(), ArnisError> {
     let mut editor: WorldEditor = WorldEditor::new(args.path.clone(), &xzbbox, llbbox);
 
     println!("{} Processing data...", "[4/7]".bold());
 
+    let mut ground = ground;
+    if args.world_edge == WorldEdge::Feather {
+        ground.feather_edges(world_edge::FEATHER_WIDTH);
+    }
+
+    // Roads cut into hills and are filled up in dips instead of following every bump
+    let filter = FeatureFilter::from_args(args);
+    let earthworks = if filter.allows(Some(Category::Roads)) {
+        road_grading::grade_roads(&mut ground, &elements, editor.get_min_coords(), args.scale)
+    } else {
+        Vec::new()
+    };
+
     // Set ground reference in the editor to enable elevation-aware block placement
     editor.set_ground(&ground);
+    editor.set_edge_clip(args.edge_clip);
+    if let Some(perf) = PerformanceConfig::try_get() {
+        editor.set_memory_budget(perf.effective_max_ram_bytes);
+    }
+    if args.weathering {
+        editor.set_weathering(seeded_rng::seed().unwrap_or_else(rand::random));
+    }
+    editor.set_season(args.season);
+    if args.format != OutputFormat::World && !args.structure_void.is_empty() {
+        editor.track_categories();
+    }
+    if let Some(path) = &args.exclude_polygons {
+        match PolygonMask::load(path, &llbbox, &xzbbox, args.scale) {
+            Ok(mask) => editor.set_exclusion(mask),
+            Err(e) => eprintln!("Warning: Exclusion polygons unavailable, nothing excluded: {e}"),
+        }
+    }
 
     println!("{} Processing terrain...", "[5/7]".bold());
     emit_gui_progress_update(25.0, "Processing terrain...");
@@ -37,12 +86,41 @@ pub fn generate_world(
         .unwrap()
         .progress_chars("█▓░"));
 
+    let lod: LodSettings = LodSettings::from_args(args, &llbbox).map_err(ArnisError::Geometry)?;
+    let simplified_args: Args = Args {
+        interior: false,
+        roof: false,
+        ..args.clone()
+    };
+
+    let mut marked_issues: usize = 0;
+    let outlines_with_parts = buildings::outlines_with_parts(&elements);
+    let mut throttle = Throttle::new(args.power_saving);
+
     let progress_increment_prcs: f64 = 45.0 / elements_count as f64;
     let mut current_progress_prcs: f64 = 25.0;
     let mut last_emitted_progress: f64 = current_progress_prcs;
 
-    for element in &elements {
+    let mut checkpoint = Checkpointer::new(args, &elements);
+    let first_element: usize = if args.resume {
+        checkpoint.resume(&mut editor, &elements)
+    } else {
+        0
+    };
+    process_pb.inc(first_element as u64);
+    road_grading::build_earthworks(&mut editor, &earthworks);
+
+    for (index, element) in elements.iter().enumerate().skip(first_element) {
+        checkpoint.save_if_due(&editor, index, &elements);
+        editor.enforce_memory_budget();
         process_pb.inc(1);
+        throttle.pause_if_needed();
+        if pause::is_paused() {
+            // A paused run can be closed and resumed later, and leaves its memory to others
+            checkpoint.save(&editor, index, &elements);
+            editor.release_memory();
+        }
+        pause::wait_while_paused();
         current_progress_prcs += progress_increment_prcs;
         if (current_progress_prcs - last_emitted_progress).abs() > 0.25 {
             emit_gui_progress_update(current_progress_prcs, "");
@@ -59,10 +137,46 @@ pub fn generate_world(
             process_pb.set_message("");
         }
 
+        // Random details only depend on the seed and the element itself
+        seeded_rng::reseed_for_element(element.id());
+
+        let category = Category::of(element);
+        editor.set_category(category);
+        if args.qa_markers && qa_markers::mark_issues(&mut editor, element) {
+            marked_issues += 1;
+        }
+
+        if !filter.allows(category) {
+            continue;
+        }
+        editor.set_allow_overhang(category == Some(Category::Buildings));
+
+        // Reduce the detail depending on the LOD tier of the element
+        let args: &Args = match lod.level_of(element) {
+            DetailLevel::Full => args,
+            DetailLevel::Simplified => {
+                // Small details are left out entirely
+                if matches!(element, ProcessedElement::Node(_))
+                    || element.tags().contains_key("barrier")
+                {
+                    continue;
+                }
+                &simplified_args
+            }
+            DetailLevel::Footprints => {
+                map_mode::generate_map_surface(&mut editor, element, args);
+                continue;
+            }
+        };
+
+        // Keep `tag_coverage::is_handled` in sync when adding processors
         match element {
             ProcessedElement::Way(way) => {
                 if way.tags.contains_key("building") || way.tags.contains_key("building:part") {
-                    buildings::generate_buildings(&mut editor, way, args, None);
+                    // Buildings mapped as 3D parts are generated from their parts only
+                    if !outlines_with_parts.contains(&way.id) {
+                        buildings::generate_buildings(&mut editor, way, args, None);
+                    }
                 } else if way.tags.contains_key("highway") {
                     highways::generate_highways(&mut editor, element, args, &elements);
                 } else if way.tags.contains_key("landuse") {
@@ -82,10 +196,10 @@ pub fn generate_world(
                     } else {
                         waterways::generate_waterways(&mut editor, way);
                     }
+                } else if way.tags.contains_key("railway") {
+                    railways::generate_railways(&mut editor, way, args, &elements);
                 } else if way.tags.contains_key("bridge") {
                     //bridges::generate_bridges(&mut editor, way, ground_level); // TODO FIX
-                } else if way.tags.contains_key("railway") {
-                    railways::generate_railways(&mut editor, way);
                 } else if way.tags.contains_key("roller_coaster") {
                     railways::generate_roller_coaster(&mut editor, way);
                 } else if way.tags.contains_key("aeroway") || way.tags.contains_key("area:aeroway")
@@ -98,7 +212,9 @@ pub fn generate_world(
                 }
             }
             ProcessedElement::Node(node) => {
-                if node.tags.contains_key("door") || node.tags.contains_key("entrance") {
+                if node.tags.get("railway") == Some(&"subway_entrance".to_string()) {
+                    subways::generate_subway_entrance(&mut editor, node, &elements);
+                } else if node.tags.contains_key("door") || node.tags.contains_key("entrance") {
                     doors::generate_doors(&mut editor, node);
                 } else if node.tags.contains_key("natural")
                     && node.tags.get("natural") == Some(&"tree".to_string())
@@ -145,6 +261,16 @@ pub fn generate_world(
     }
 
     process_pb.finish();
+    editor.set_allow_overhang(false);
+    editor.set_category(None);
+    checkpoint.save_if_due(&editor, elements.len(), &elements);
+
+    if args.qa_markers {
+        println!("Marked {marked_issues} elements with fixme/note tags or broken geometry");
+    }
+
+    // In diff worlds, the footprints of changed features are drawn on top
+    diff::highlight_changes(&mut editor, changes, args);
 
     // Generate ground layer
     let total_blocks: u64 = xzbbox.bounding_rect().total_blocks();
@@ -170,39 +296,74 @@ pub fn generate_world(
     let progress_increment_grnd: f64 = 20.0 / total_iterations_grnd;
 
     let groundlayer_block = GRASS_BLOCK;
+    let imagery: Option<GroundImagery> = args.imagery_url.as_deref().and_then(|url| {
+        println!("Fetching aerial imagery for ground cover...");
+        GroundImagery::fetch(url, &llbbox, &xzbbox, args.scale)
+            .inspect_err(|e| eprintln!("Warning: Imagery unavailable, using grass: {e}"))
+            .ok()
+    });
+    let land_mask: Option<PolygonMask> = args.land_polygons.as_deref().and_then(|path| {
+        PolygonMask::load(path, &llbbox, &xzbbox, args.scale)
+            .inspect_err(|e| eprintln!("Warning: Land polygons unavailable, no sea added: {e}"))
+            .ok()
+    });
 
     for x in xzbbox.min_x()..=xzbbox.max_x() {
+        editor.enforce_memory_budget();
         for z in xzbbox.min_z()..=xzbbox.max_z() {
-            // Add default dirt and grass layer if there isn't a stone layer already
-            if !editor.check_for_block(x, 0, z, Some(&[STONE])) {
-                editor.set_block(groundlayer_block, x, 0, z, None, None);
-                editor.set_block(DIRT, x, -1, z, None, None);
-                editor.set_block(DIRT, x, -2, z, None, None);
-            }
+            // Non-rectangular areas (e.g. route corridors) only get ground inside their shape
+            if xzbbox.contains(&XZPoint::new(x, z)) {
+                // Add default dirt and grass layer if there isn't a stone layer already
+                if !editor.check_for_block(x, 0, z, Some(&[STONE])) {
+                    let surface = if land_mask.as_ref().is_some_and(|mask| !mask.contains(x, z)) {
+                        WATER
+                    } else {
+                        imagery
+                            .as_ref()
+                            .and_then(|imagery| imagery.block_at(x, z))
+                            .unwrap_or(groundlayer_block)
+                    };
+                    editor.set_block(surface, x, 0, z, None, None);
+                    // Map mode keeps the surface a single block thick
+                    if !args.map_mode {
+                        editor.set_block(DIRT, x, -1, z, None, None);
+                        editor.set_block(DIRT, x, -2, z, None, None);
+                    }
+                }
 
-            // Fill underground with stone
-            if args.fillground {
-                // Fill from bedrock+1 to 3 blocks below ground with stone
-                editor.fill_blocks_absolute(
-                    STONE,
-                    x,
-                    MIN_Y + 1,
-                    z,
-                    x,
-                    editor.get_absolute_y(x, -3, z),
-                    z,
-                    None,
-                    None,
-                );
+                // Fill underground with stone
+                if args.fillground && !args.map_mode {
+                    // Fill from bedrock+1 to 3 blocks below ground with stone
+                    editor.fill_blocks_absolute(
+                        STONE,
+                        x,
+                        MIN_Y + 1,
+                        z,
+                        x,
+                        editor.get_absolute_y(x, -3, z),
+                        z,
+                        None,
+                        None,
+                    );
+                }
+                // Generate a bedrock level at MIN_Y
+                if !args.map_mode {
+                    editor.set_block_absolute(BEDROCK, x, MIN_Y, z, None, Some(&[BEDROCK]));
+                }
             }
-            // Generate a bedrock level at MIN_Y
-            editor.set_block_absolute(BEDROCK, x, MIN_Y, z, None, Some(&[BEDROCK]));
 
             block_counter += 1;
             // Use manual % check since is_multiple_of() is unstable on stable Rust
             #[allow(clippy::manual_is_multiple_of)]
             if block_counter % batch_size == 0 {
                 ground_pb.inc(batch_size);
+                throttle.pause_if_needed();
+                if pause::is_paused() {
+                    // The checkpoint after the elements stays, ground is generated again
+                    // when resuming
+                    editor.release_memory();
+                }
+                pause::wait_while_paused();
             }
 
             gui_progress_grnd += progress_increment_grnd;
@@ -228,8 +389,39 @@ pub fn generate_world(
     ground_pb.inc(block_counter % batch_size);
     ground_pb.finish();
 
+    world_edge::build_world_edge(&mut editor, &xzbbox, args.world_edge);
+
+    if args.elytra_tour {
+        elytra_tour::build_elytra_tour(&mut editor, &elements, &xzbbox, &llbbox, args)?;
+    }
+
+    // Move the spawn to a walkable spot, unless it was picked in the GUI
+    let spawn: Option<XZPoint> = if args.spawn_point.is_none() && !args.keep_spawn {
+        let spawn = spawn::find_spawn_point(&editor, &elements, &xzbbox);
+        spawn::build_spawn_extras(&mut editor, spawn, args, &elements, &xzbbox);
+        Some(spawn)
+    } else {
+        None
+    };
+
     // Save world
-    editor.save();
+    editor.save()?;
+    checkpoint.clear();
+
+    if args.resource_pack {
+        let pack_dir = args.path.join(resource_pack::PACK_DIR);
+        match ResourcePack::signage().write(&pack_dir) {
+            Ok(()) => println!("Resource pack written to {}", pack_dir.display()),
+            Err(e) => eprintln!("Warning: Failed to write the resource pack: {e}"),
+        }
+    }
+
+    if let Some(spawn) = spawn {
+        let spawn_y = editor.get_absolute_y(spawn.x, 1, spawn.z);
+        if let Err(e) = spawn::set_world_spawn(&args.path, spawn.x, spawn_y, spawn.z) {
+            eprintln!("Warning: Failed to set spawn point: {e}");
+        }
+    }
 
     // Update player spawn Y coordinate based on terrain height after generation
     #[cfg(feature = "gui")]
@@ -237,10 +429,10 @@ pub fn generate_world(
         use crate::gui::update_player_spawn_y_after_generation;
         let bbox_string = format!(
             "{},{},{},{}",
-            args.bbox.min().lng(),
-            args.bbox.min().lat(),
-            args.bbox.max().lng(),
-            args.bbox.max().lat()
+            args.bbox().min().lng(),
+            args.bbox().min().lat(),
+            args.bbox().max().lng(),
+            args.bbox().max().lat()
         );
 
         if let Err(e) = update_player_spawn_y_after_generation(
@@ -0,0 +1,172 @@
+//! Diff worlds: compares two OSM snapshots of the same area and highlights the features
+//! that were added, removed or changed in between.
+
+use crate::args::Args;
+use crate::block_definitions::*;
+use crate::element_processing::map_mode;
+use crate::osm_parser::ProcessedElement;
+use crate::world_editor::WorldEditor;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl ChangeKind {
+    /// Color the footprint of a changed feature is drawn in
+    pub fn block(&self) -> Block {
+        match self {
+            ChangeKind::Added => LIME_WOOL,
+            ChangeKind::Removed => RED_WOOL,
+            ChangeKind::Changed => YELLOW_WOOL,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ElementChange {
+    pub kind: ChangeKind,
+    /// The new version of the element, or the old one if it was removed
+    pub element: ProcessedElement,
+}
+
+/// Compares the elements of an older snapshot with the current ones
+pub fn diff_elements(
+    old_elements: &[ProcessedElement],
+    new_elements: &[ProcessedElement],
+) -> Vec<ElementChange> {
+    let old_by_key: HashMap<(&str, u64), &ProcessedElement> = old_elements
+        .iter()
+        .map(|element| ((element.kind(), element.id()), element))
+        .collect();
+    let new_by_key: HashMap<(&str, u64), &ProcessedElement> = new_elements
+        .iter()
+        .map(|element| ((element.kind(), element.id()), element))
+        .collect();
+
+    let mut changes = Vec::new();
+
+    for element in new_elements {
+        let kind = match old_by_key.get(&(element.kind(), element.id())) {
+            None => ChangeKind::Added,
+            Some(old) if !same_element(old, element) => ChangeKind::Changed,
+            Some(_) => continue,
+        };
+        changes.push(ElementChange {
+            kind,
+            element: element.clone(),
+        });
+    }
+
+    for element in old_elements {
+        if !new_by_key.contains_key(&(element.kind(), element.id())) {
+            changes.push(ElementChange {
+                kind: ChangeKind::Removed,
+                element: element.clone(),
+            });
+        }
+    }
+
+    changes
+}
+
+/// Whether two versions of an element have the same tags and geometry
+fn same_element(a: &ProcessedElement, b: &ProcessedElement) -> bool {
+    match (a, b) {
+        (ProcessedElement::Node(a), ProcessedElement::Node(b)) => a == b,
+        (ProcessedElement::Way(a), ProcessedElement::Way(b)) => a == b,
+        (ProcessedElement::Relation(a), ProcessedElement::Relation(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Draws the footprints of all changes in their color at ground level
+pub fn highlight_changes(editor: &mut WorldEditor, changes: &[ElementChange], args: &Args) {
+    // Removed features first, so that a replacement at the same spot stays visible
+    for kind in [ChangeKind::Removed, ChangeKind::Changed, ChangeKind::Added] {
+        for change in changes.iter().filter(|change| change.kind == kind) {
+            map_mode::draw_footprint(editor, &change.element, kind.block(), args);
+        }
+    }
+}
+
+/// Number of (added, removed, changed) elements
+pub fn summarize(changes: &[ElementChange]) -> (usize, usize, usize) {
+    let count = |kind: ChangeKind| changes.iter().filter(|c| c.kind == kind).count();
+    (
+        count(ChangeKind::Added),
+        count(ChangeKind::Removed),
+        count(ChangeKind::Changed),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm_parser::{ProcessedNode, ProcessedWay};
+
+    fn node(id: u64, x: i32, z: i32) -> ProcessedNode {
+        ProcessedNode {
+            id,
+            tags: HashMap::new(),
+            x,
+            z,
+        }
+    }
+
+    fn building(id: u64, levels: &str) -> ProcessedElement {
+        ProcessedElement::Way(ProcessedWay {
+            id,
+            nodes: vec![
+                node(1, 0, 0),
+                node(2, 10, 0),
+                node(3, 10, 10),
+                node(1, 0, 0),
+            ],
+            tags: HashMap::from([
+                ("building".to_string(), "yes".to_string()),
+                ("building:levels".to_string(), levels.to_string()),
+            ]),
+        })
+    }
+
+    #[test]
+    fn test_diff_elements() {
+        let old = vec![building(1, "2"), building(2, "3"), building(3, "1")];
+        let new = vec![
+            building(1, "2"),
+            building(2, "5"),
+            building(4, "1"),
+            // Same id, but a node and not a way
+            ProcessedElement::Node(node(3, 5, 5)),
+        ];
+
+        let changes = diff_elements(&old, &new);
+        let kinds: Vec<(ChangeKind, &str, u64)> = changes
+            .iter()
+            .map(|c| (c.kind, c.element.kind(), c.element.id()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (ChangeKind::Changed, "way", 2),
+                (ChangeKind::Added, "way", 4),
+                (ChangeKind::Added, "node", 3),
+                (ChangeKind::Removed, "way", 3),
+            ]
+        );
+        assert_eq!(summarize(&changes), (2, 1, 1));
+    }
+
+    #[test]
+    fn test_moved_node_is_changed() {
+        let old = vec![ProcessedElement::Node(node(1, 0, 0))];
+        let new = vec![ProcessedElement::Node(node(1, 0, 1))];
+        let changes = diff_elements(&old, &new);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, ChangeKind::Changed);
+    }
+}
@@ -0,0 +1,92 @@
+//! Disk usage settings: where temporary data (like downloaded elevation tiles) is stored,
+//! and an optional cap on the write throughput while the world is saved.
+//!
+//! Capping writes keeps HDD-based systems responsive during the save. Writes from all
+//! threads share the same budget and are spaced out in the order they were issued.
+
+use once_cell::sync::Lazy;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+static TEMP_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+static WRITE_LIMIT: Lazy<Mutex<Option<WriteLimit>>> = Lazy::new(|| Mutex::new(None));
+
+/// Sets the directory for temporary data, defaults to the working directory
+pub fn set_temp_dir(dir: Option<PathBuf>) {
+    *TEMP_DIR.write().unwrap() = dir;
+}
+
+/// Directory for temporary data
+pub fn temp_dir() -> PathBuf {
+    TEMP_DIR
+        .read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Caps the write throughput in bytes per second, `None` writes as fast as possible
+pub fn set_write_limit(bytes_per_second: Option<u64>) {
+    *WRITE_LIMIT.lock().unwrap() =
+        bytes_per_second
+            .filter(|limit| *limit > 0)
+            .map(|bytes_per_second| WriteLimit {
+                bytes_per_second,
+                next_slot: Instant::now(),
+            });
+}
+
+/// Called after `bytes` were written, blocks as long as needed to stay within the limit
+pub fn throttle_write(bytes: usize) {
+    let until = match WRITE_LIMIT.lock().unwrap().as_mut() {
+        Some(limit) => limit.reserve(bytes as u64, Instant::now()),
+        None => return,
+    };
+
+    let wait = until.saturating_duration_since(Instant::now());
+    if !wait.is_zero() {
+        std::thread::sleep(wait);
+    }
+}
+
+struct WriteLimit {
+    bytes_per_second: u64,
+    next_slot: Instant,
+}
+
+impl WriteLimit {
+    /// Reserves the time the write takes at the limit, queueing behind earlier writes.
+    /// Returns when the reserved time is over.
+    fn reserve(&mut self, bytes: u64, now: Instant) -> Instant {
+        let start = self.next_slot.max(now);
+        self.next_slot =
+            start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+        self.next_slot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_spaces_out_writes() {
+        let now = Instant::now();
+        let mut limit = WriteLimit {
+            bytes_per_second: 1000,
+            next_slot: now,
+        };
+
+        assert_eq!(limit.reserve(500, now), now + Duration::from_millis(500));
+        assert_eq!(limit.reserve(500, now), now + Duration::from_secs(1));
+        assert_eq!(limit.reserve(2000, now), now + Duration::from_secs(3));
+
+        // After a quiet period, earlier writes don't count anymore
+        let later = now + Duration::from_secs(10);
+        assert_eq!(
+            limit.reserve(100, later),
+            later + Duration::from_millis(100)
+        );
+    }
+}
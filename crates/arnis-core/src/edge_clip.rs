@@ -0,0 +1,73 @@
+//! Handling of features that cross the edge of the selected area (`--edge-clip`). Cutting
+//! them at the edge slices buildings in half, so they can also be generated whole, or
+//! everything above the ground can be faded out towards the edge.
+
+use crate::coordinate_system::cartesian::{XZBBox, XZPoint};
+use clap::ValueEnum;
+
+/// How far buildings may extend beyond the edge with `EdgeClip::Whole`
+pub const MAX_OVERHANG: i32 = 64;
+/// Width of the zone along the edge in which `EdgeClip::Fade` lowers features
+pub const FADE_WIDTH: i32 = 32;
+/// Blocks above the ground allowed per block of distance from the edge
+const FADE_SLOPE: i32 = 2;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum EdgeClip {
+    /// Cut everything at the edge
+    #[default]
+    Hard,
+    /// Generate buildings crossing the edge whole (up to 64 blocks beyond it)
+    Whole,
+    /// Lower everything above the ground towards the edge, within 32 blocks of it
+    Fade,
+}
+
+/// Whether a building block at this position is within reach of the edge
+pub fn in_overhang(xzbbox: &XZBBox, point: XZPoint) -> bool {
+    let rect = xzbbox.bounding_rect();
+    point.x >= rect.min().x - MAX_OVERHANG
+        && point.x <= rect.max().x + MAX_OVERHANG
+        && point.z >= rect.min().z - MAX_OVERHANG
+        && point.z <= rect.max().z + MAX_OVERHANG
+}
+
+/// Whether a block this high above the ground stays at this position in the fade zone
+pub fn fade_allows(xzbbox: &XZBBox, point: XZPoint, height_above_ground: i32) -> bool {
+    if height_above_ground <= 0 {
+        return true;
+    }
+
+    let rect = xzbbox.bounding_rect();
+    let distance = (point.x - rect.min().x)
+        .min(rect.max().x - point.x)
+        .min(point.z - rect.min().z)
+        .min(rect.max().z - point.z);
+    distance >= FADE_WIDTH || height_above_ground <= distance * FADE_SLOPE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_overhang() {
+        let xzbbox = XZBBox::rect_from_xz_lengths(100.0, 100.0).unwrap();
+        assert!(in_overhang(&xzbbox, XZPoint::new(-MAX_OVERHANG, 50)));
+        assert!(in_overhang(&xzbbox, XZPoint::new(100 + MAX_OVERHANG, 50)));
+        assert!(!in_overhang(&xzbbox, XZPoint::new(50, -MAX_OVERHANG - 1)));
+    }
+
+    #[test]
+    fn test_fade_allows() {
+        let xzbbox = XZBBox::rect_from_xz_lengths(200.0, 200.0).unwrap();
+        // Far from the edge everything stays
+        assert!(fade_allows(&xzbbox, XZPoint::new(100, 100), 200));
+        // Ground is never removed
+        assert!(fade_allows(&xzbbox, XZPoint::new(0, 100), 0));
+        // At the edge nothing above the ground, and more the farther inside
+        assert!(!fade_allows(&xzbbox, XZPoint::new(0, 100), 1));
+        assert!(fade_allows(&xzbbox, XZPoint::new(195, 100), 10));
+        assert!(!fade_allows(&xzbbox, XZPoint::new(195, 100), 11));
+    }
+}
@@ -4,7 +4,9 @@ use crate::bresenham::bresenham_line;
 use crate::coordinate_system::cartesian::XZPoint;
 use crate::floodfill::flood_fill_area;
 use crate::osm_parser::ProcessedElement;
+use crate::seeded_rng;
 use crate::world_editor::WorldEditor;
+use rand::Rng;
 
 pub fn generate_amenities(editor: &mut WorldEditor, element: &ProcessedElement, args: &Args) {
     // Skip if 'layer' or 'level' is negative in the tags
@@ -81,7 +83,7 @@ pub fn generate_amenities(editor: &mut WorldEditor, element: &ProcessedElement,
                 // Place a bench
                 if let Some(pt) = first_node {
                     // 50% chance to 90 degrees rotate the bench using if
-                    if rand::random::<bool>() {
+                    if seeded_rng::rng().gen::<bool>() {
                         editor.set_block(SMOOTH_STONE, pt.x, 1, pt.z, None, None);
                         editor.set_block(OAK_LOG, pt.x + 1, 1, pt.z, None, None);
                         editor.set_block(OAK_LOG, pt.x - 1, 1, pt.z, None, None);
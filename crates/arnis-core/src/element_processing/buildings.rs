@@ -5,10 +5,14 @@ use crate::colors::color_text_to_rgb_tuple;
 use crate::coordinate_system::cartesian::XZPoint;
 use crate::element_processing::subprocessor::buildings_interior::generate_building_interior;
 use crate::floodfill::flood_fill_area;
-use crate::osm_parser::{ProcessedMemberRole, ProcessedRelation, ProcessedWay};
+use crate::height_cap;
+use crate::night;
+use crate::osm_parser::{ProcessedElement, ProcessedMemberRole, ProcessedRelation, ProcessedWay};
+use crate::seeded_rng;
 use crate::world_editor::WorldEditor;
+use geo::{Contains, LineString, Point, Polygon};
 use rand::Rng;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 
 /// Enum representing different roof types
@@ -19,9 +23,131 @@ enum RoofType {
     Skillion, // Single sloping surface
     Pyramidal, // All sides come to a point at the top
     Dome,   // Rounded, hemispherical structure
+    Onion,  // Bulb swelling out beyond the walls and drawing in to a point
     Flat,   // Default flat roof
 }
 
+/// What the 3D tags of a building say about its roof, overriding the defaults of the shape
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct RoofTags {
+    /// Height of the roof in blocks (`roof:height`)
+    height: Option<i32>,
+    /// Ridge across the longer side of the building instead of along it (`roof:orientation`)
+    across: bool,
+    /// Block of the roof surface (`roof:colour`)
+    block: Option<Block>,
+}
+
+impl RoofTags {
+    fn from_tags(tags: &std::collections::HashMap<String, String>, scale_factor: f64) -> Self {
+        RoofTags {
+            height: tags
+                .get("roof:height")
+                .and_then(|height| parse_meters(height))
+                .map(|height| ((height * scale_factor).round() as i32).max(1)),
+            across: tags.get("roof:orientation").map(String::as_str) == Some("across"),
+            block: tags
+                .get("roof:colour")
+                .and_then(|colour| color_text_to_rgb_tuple(colour))
+                .map(get_building_wall_block_for_color),
+        }
+    }
+
+    /// The tagged roof block, or by chance the accent or the wall block
+    fn roof_block(&self, accent_block: Block, wall_block: Block) -> Block {
+        self.block.unwrap_or_else(|| {
+            if seeded_rng::rng().gen_bool(0.5) {
+                accent_block
+            } else {
+                wall_block
+            }
+        })
+    }
+}
+
+/// A length tag in meters, like `height=12` or `min_height=4.5 m`
+fn parse_meters(value: &str) -> Option<f64> {
+    value.trim_end_matches('m').trim().parse::<f64>().ok()
+}
+
+/// Radius of an onion dome at `t` (0 at its base, 1 at the tip) relative to the radius of
+/// the base: swelling out to a bulb first, then drawing in to a point
+fn onion_profile(t: f64) -> f64 {
+    const BULB_END: f64 = 0.55;
+    if t < BULB_END {
+        1.0 + 0.25 * (std::f64::consts::PI * t / BULB_END).sin()
+    } else {
+        (1.0 - (t - BULB_END) / (1.0 - BULB_END)).max(0.0).powf(1.5)
+    }
+}
+
+/// Ids of the building outlines with `building:part`s mapped inside them. The parts carry the
+/// 3D shape of such buildings, so they are generated instead of the outline.
+pub fn outlines_with_parts(elements: &[ProcessedElement]) -> HashSet<u64> {
+    const CELL_SIZE: i32 = 64;
+
+    // Centers of all parts, bucketed into a coarse grid
+    let mut part_centers: HashMap<(i32, i32), Vec<(f64, f64)>> = HashMap::new();
+    for element in elements {
+        let ProcessedElement::Way(way) = element else {
+            continue;
+        };
+        if !way.tags.contains_key("building:part") || way.nodes.is_empty() {
+            continue;
+        }
+        let count = way.nodes.len() as f64;
+        let x = way.nodes.iter().map(|n| n.x as f64).sum::<f64>() / count;
+        let z = way.nodes.iter().map(|n| n.z as f64).sum::<f64>() / count;
+        let cell = (
+            (x as i32).div_euclid(CELL_SIZE),
+            (z as i32).div_euclid(CELL_SIZE),
+        );
+        part_centers.entry(cell).or_default().push((x, z));
+    }
+    if part_centers.is_empty() {
+        return HashSet::new();
+    }
+
+    elements
+        .iter()
+        .filter_map(|element| match element {
+            ProcessedElement::Way(way)
+                if way.tags.contains_key("building")
+                    && !way.tags.contains_key("building:part")
+                    && way.nodes.len() >= 3 =>
+            {
+                Some(way)
+            }
+            _ => None,
+        })
+        .filter(|way| {
+            let polygon = Polygon::new(
+                LineString::from(
+                    way.nodes
+                        .iter()
+                        .map(|n| (n.x as f64, n.z as f64))
+                        .collect::<Vec<_>>(),
+                ),
+                vec![],
+            );
+            let min_x = way.nodes.iter().map(|n| n.x).min().unwrap_or(0);
+            let max_x = way.nodes.iter().map(|n| n.x).max().unwrap_or(0);
+            let min_z = way.nodes.iter().map(|n| n.z).min().unwrap_or(0);
+            let max_z = way.nodes.iter().map(|n| n.z).max().unwrap_or(0);
+            (min_x.div_euclid(CELL_SIZE)..=max_x.div_euclid(CELL_SIZE)).any(|cell_x| {
+                (min_z.div_euclid(CELL_SIZE)..=max_z.div_euclid(CELL_SIZE)).any(|cell_z| {
+                    part_centers.get(&(cell_x, cell_z)).is_some_and(|centers| {
+                        centers
+                            .iter()
+                            .any(|&(x, z)| polygon.contains(&Point::new(x, z)))
+                    })
+                })
+            })
+        })
+        .map(|way| way.id)
+        .collect()
+}
+
 #[inline]
 pub fn generate_buildings(
     editor: &mut WorldEditor,
@@ -39,9 +165,13 @@ pub fn generate_buildings(
     // Calculate y-offset for non-terrain mode for absolute positioning
     let abs_terrain_offset = if !args.terrain { args.ground_level } else { 0 };
 
-    // Calculate starting y-offset from min_level
+    // Calculate starting y-offset from min_height, or else from min_level
     let scale_factor = args.scale;
-    let min_level_offset = multiply_scale(min_level * 4, scale_factor);
+    let min_height = element.tags.get("min_height").and_then(|h| parse_meters(h));
+    let min_level_offset = match min_height {
+        Some(min_height) => (min_height * scale_factor) as i32,
+        None => multiply_scale(min_level * 4, scale_factor),
+    };
 
     // Cache floodfill result: compute once and reuse throughout
     let polygon_coords: Vec<(i32, i32)> = element.nodes.iter().map(|n| (n.x, n.z)).collect();
@@ -121,7 +251,7 @@ pub fn generate_buildings(
     let mut processed_points: HashSet<(i32, i32)> = HashSet::new();
     let mut building_height: i32 = ((6.0 * scale_factor) as i32).max(3); // Default building height with scale and minimum
     let mut is_tall_building = false;
-    let mut rng = rand::thread_rng();
+    let mut rng = seeded_rng::rng();
     let use_vertical_windows = rng.gen_bool(0.7);
     let use_accent_roof_line = rng.gen_bool(0.25);
 
@@ -155,7 +285,7 @@ pub fn generate_buildings(
             let lev = levels - min_level;
 
             if lev >= 1 {
-                building_height = multiply_scale(levels * 4 + 2, scale_factor);
+                building_height = multiply_scale(lev * 4 + 2, scale_factor);
                 building_height = building_height.max(3);
 
                 // Mark as tall building if more than 7 stories
@@ -166,9 +296,10 @@ pub fn generate_buildings(
         }
     }
 
+    // `height` is measured from the ground, also for parts starting higher up
     if let Some(height_str) = element.tags.get("height") {
-        if let Ok(height) = height_str.trim_end_matches("m").trim().parse::<f64>() {
-            building_height = (height * scale_factor) as i32;
+        if let Some(height) = parse_meters(height_str) {
+            building_height = (height * scale_factor) as i32 - min_level_offset;
             building_height = building_height.max(3);
 
             // Mark as tall building if height suggests more than 7 stories
@@ -178,6 +309,18 @@ pub fn generate_buildings(
         }
     }
 
+    // The tagged height includes the roof, which is generated on top of the walls
+    let roof_tags = RoofTags::from_tags(&element.tags, scale_factor);
+    let has_roof_shape = element
+        .tags
+        .get("roof:shape")
+        .is_some_and(|shape| shape != "flat");
+    if args.roof && has_roof_shape && element.tags.contains_key("height") {
+        if let Some(roof_height) = roof_tags.height {
+            building_height = (building_height - roof_height).max(3);
+        }
+    }
+
     if let Some(levels) = relation_levels {
         building_height = multiply_scale(levels * 4 + 2, scale_factor);
         building_height = building_height.max(3);
@@ -391,6 +534,33 @@ pub fn generate_buildings(
         }
     }
 
+    // Fit tall buildings under the height cap, unless their height is given by the user
+    if let Some(&(_, height)) = args
+        .height_override
+        .iter()
+        .find(|(id, _)| *id == element.id)
+    {
+        building_height = height;
+    } else if let Some(max_height) = args.max_building_height {
+        building_height = height_cap::apply(building_height, max_height, args.height_cap);
+    }
+
+    // In night mode, some floors have their lights on and shops a lit fascia
+    let lit_floors: Vec<bool> = if args.night {
+        night::lit_floors(&mut rng, building_height)
+    } else {
+        Vec::new()
+    };
+    let lit_fascia = args.night && night::is_shop(&element.tags);
+    let window_at = |h: i32| {
+        let floor = h.div_euclid(4) - start_y_offset.div_euclid(4);
+        if lit_floors.get(floor as usize) == Some(&true) {
+            GLOWSTONE
+        } else {
+            window_block
+        }
+    };
+
     // Process nodes to create walls and corners
     for node in &element.nodes {
         let x: i32 = node.x;
@@ -403,7 +573,7 @@ pub fn generate_buildings(
             for (bx, _, bz) in bresenham_points {
                 // Create foundation pillars from ground up to building base if needed
                 // Only create foundations for buildings without min_level (elevated buildings shouldn't have foundations)
-                if args.terrain && min_level == 0 {
+                if args.terrain && min_level_offset == 0 {
                     // Calculate actual ground level at this position
                     let local_ground_level = if let Some(ground) = editor.get_ground() {
                         ground.level(XZPoint::new(
@@ -430,11 +600,21 @@ pub fn generate_buildings(
                 for h in (start_y_offset + 1)..=(start_y_offset + building_height) {
                     // Add windows to the walls at intervals
                     // Use different window patterns for tall buildings
-                    if is_tall_building && use_vertical_windows {
+                    if lit_fascia && h > start_y_offset + 1 && h <= start_y_offset + 5 && h % 4 == 0
+                    {
+                        editor.set_block_absolute(
+                            GLOWSTONE,
+                            bx,
+                            h + abs_terrain_offset,
+                            bz,
+                            None,
+                            None,
+                        );
+                    } else if is_tall_building && use_vertical_windows {
                         // Tall building pattern - narrower windows with continuous vertical strips
                         if h > start_y_offset + 1 && (bx + bz) % 3 == 0 {
                             editor.set_block_absolute(
-                                window_block,
+                                window_at(h),
                                 bx,
                                 h + abs_terrain_offset,
                                 bz,
@@ -455,7 +635,7 @@ pub fn generate_buildings(
                         // Original pattern for regular buildings (non-vertical windows)
                         if h > start_y_offset + 1 && h % 4 != 0 && (bx + bz) % 6 < 3 {
                             editor.set_block_absolute(
-                                window_block,
+                                window_at(h),
                                 bx,
                                 h + abs_terrain_offset,
                                 bz,
@@ -659,7 +839,8 @@ pub fn generate_buildings(
                 "hipped" | "half-hipped" | "gambrel" | "mansard" | "round" => RoofType::Hipped,
                 "skillion" => RoofType::Skillion,
                 "pyramidal" => RoofType::Pyramidal,
-                "dome" | "onion" | "cone" => RoofType::Dome,
+                "dome" | "cone" => RoofType::Dome,
+                "onion" => RoofType::Onion,
                 _ => RoofType::Flat,
             };
 
@@ -672,6 +853,7 @@ pub fn generate_buildings(
                 wall_block,
                 accent_block,
                 roof_type,
+                roof_tags,
                 &cached_floor_area,
                 abs_terrain_offset,
             );
@@ -695,7 +877,7 @@ pub fn generate_buildings(
                 // Maximum footprint size threshold for gabled roofs
                 let max_footprint_for_gabled = 800;
 
-                let mut rng = rand::thread_rng();
+                let mut rng = seeded_rng::rng();
                 if footprint_size <= max_footprint_for_gabled && rng.gen_bool(0.9) {
                     generate_roof(
                         editor,
@@ -706,6 +888,7 @@ pub fn generate_buildings(
                         wall_block,
                         accent_block,
                         RoofType::Gabled,
+                        roof_tags,
                         &cached_floor_area,
                         abs_terrain_offset,
                     );
@@ -745,6 +928,7 @@ fn generate_roof(
     wall_block: Block,
     accent_block: Block,
     roof_type: RoofType,
+    roof_tags: RoofTags,
     cached_floor_area: &[(i32, i32)],
     abs_terrain_offset: i32,
 ) {
@@ -792,24 +976,21 @@ fn generate_roof(
             let building_size = width.max(length);
 
             // Enhanced logarithmic scaling with increased base values for taller roofs
-            let roof_height_boost = (3.0 + (building_size as f64 * 0.15).ln().max(1.0)) as i32;
+            let roof_height_boost = roof_tags
+                .height
+                .unwrap_or((3.0 + (building_size as f64 * 0.15).ln().max(1.0)) as i32);
             let roof_peak_height = base_height + roof_height_boost;
 
-            // Pre-determine orientation and material
-            let is_wider_than_long = width > length;
+            // Pre-determine orientation and material, the ridge along the longer side unless tagged
+            let is_wider_than_long = (width > length) != roof_tags.across;
             let max_distance = if is_wider_than_long {
                 length >> 1
             } else {
                 width >> 1
             };
 
-            // 50% accent block, otherwise wall block for roof
-            let mut rng = rand::thread_rng();
-            let roof_block = if rng.gen_bool(0.5) {
-                accent_block
-            } else {
-                wall_block
-            };
+            // The tagged colour, otherwise 50% accent block, 50% wall block
+            let roof_block = roof_tags.roof_block(accent_block, wall_block);
 
             // Pre-allocate with capacity hint for better performance
             let mut roof_heights = Vec::with_capacity(floor_area.len());
@@ -917,18 +1098,16 @@ fn generate_roof(
             // Determine if building is significantly rectangular or more square-shaped
             let is_rectangular =
                 (width as f64 / length as f64 > 1.3) || (length as f64 / width as f64 > 1.3);
-            let long_axis_is_x = width > length;
+            let long_axis_is_x = (width > length) != roof_tags.across;
 
             // Make roof taller and more pointy
-            let roof_peak_height = base_height + if width.max(length) > 20 { 7 } else { 5 };
+            let roof_peak_height = base_height
+                + roof_tags
+                    .height
+                    .unwrap_or(if width.max(length) > 20 { 7 } else { 5 });
 
-            // 50% accent block, otherwise wall block for roof
-            let mut rng = rand::thread_rng();
-            let roof_block = if rng.gen_bool(0.5) {
-                accent_block
-            } else {
-                wall_block
-            };
+            // The tagged colour, otherwise 50% accent block, 50% wall block
+            let roof_block = roof_tags.roof_block(accent_block, wall_block);
 
             // Find the building's approximate center line along the long axis
             if is_rectangular {
@@ -1185,15 +1364,10 @@ fn generate_roof(
             let building_size = (max_x - min_x).max(max_z - min_z);
 
             // Scale roof height based on building size (4-10 blocks)
-            let max_roof_height = (building_size / 3).clamp(4, 10);
+            let max_roof_height = roof_tags.height.unwrap_or((building_size / 3).clamp(4, 10));
 
-            // 50% accent block, otherwise wall block for roof
-            let mut rng = rand::thread_rng();
-            let roof_block = if rng.gen_bool(0.5) {
-                accent_block
-            } else {
-                wall_block
-            };
+            // The tagged colour, otherwise 50% accent block, 50% wall block
+            let roof_block = roof_tags.roof_block(accent_block, wall_block);
 
             // First pass: calculate all roof heights
             let mut roof_heights = std::collections::HashMap::new();
@@ -1266,15 +1440,11 @@ fn generate_roof(
             let building_size = (max_x - min_x).max(max_z - min_z);
 
             // Calculate peak height based on building size (taller peak for larger buildings)
-            let peak_height = base_height + (building_size / 3).clamp(3, 8);
+            let peak_height =
+                base_height + roof_tags.height.unwrap_or((building_size / 3).clamp(3, 8));
 
-            // 50% accent block, otherwise wall block for roof
-            let mut rng = rand::thread_rng();
-            let roof_block = if rng.gen_bool(0.5) {
-                accent_block
-            } else {
-                wall_block
-            };
+            // The tagged colour, otherwise 50% accent block, 50% wall block
+            let roof_block = roof_tags.roof_block(accent_block, wall_block);
 
             // First pass: calculate all roof heights
             let mut roof_heights = std::collections::HashMap::new();
@@ -1455,13 +1625,8 @@ fn generate_roof(
             // Dome roof - rounded hemispherical structure
             let radius = ((max_x - min_x).max(max_z - min_z) / 2) as f64;
 
-            // 50% accent block, otherwise wall block for roof
-            let mut rng = rand::thread_rng();
-            let roof_block = if rng.gen_bool(0.5) {
-                accent_block
-            } else {
-                wall_block
-            };
+            // The tagged colour, otherwise 50% accent block, 50% wall block
+            let roof_block = roof_tags.roof_block(accent_block, wall_block);
 
             for &(x, z) in floor_area {
                 let distance_from_center = ((x - center_x).pow(2) + (z - center_z).pow(2)) as f64;
@@ -1469,7 +1634,8 @@ fn generate_roof(
 
                 // Use hemisphere equation to determine the height
                 let height_factor = (1.0 - normalized_distance * normalized_distance).sqrt();
-                let surface_height = base_height + (height_factor * (radius * 0.8)) as i32;
+                let dome_height = roof_tags.height.map_or(radius * 0.8, f64::from);
+                let surface_height = base_height + (height_factor * dome_height) as i32;
 
                 // Fill from the base to the surface
                 for y in base_height..=surface_height {
@@ -1477,6 +1643,32 @@ fn generate_roof(
                 }
             }
         }
+
+        RoofType::Onion => {
+            // Onion dome - solid discs following the profile, overhanging the walls at the bulb
+            let radius = ((max_x - min_x).min(max_z - min_z) / 2).max(1) as f64;
+            let height = roof_tags.height.unwrap_or((radius * 2.5) as i32).max(2);
+            let roof_block = roof_tags.roof_block(accent_block, wall_block);
+
+            for level in 0..=height {
+                let level_radius = radius * onion_profile(level as f64 / height as f64);
+                let reach = level_radius.ceil() as i32;
+                for dx in -reach..=reach {
+                    for dz in -reach..=reach {
+                        if ((dx * dx + dz * dz) as f64).sqrt() <= level_radius.max(0.5) {
+                            editor.set_block_absolute(
+                                roof_block,
+                                center_x + dx,
+                                base_height + level + abs_terrain_offset,
+                                center_z + dz,
+                                None,
+                                None,
+                            );
+                        }
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -1577,3 +1769,79 @@ fn generate_bridge(
         editor.set_block(floor_block, x, bridge_y_offset, z, None, None);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm_parser::ProcessedNode;
+
+    #[test]
+    fn test_roof_tags() {
+        let tags: HashMap<String, String> = [
+            ("roof:height", "3.5 m"),
+            ("roof:orientation", "across"),
+            ("roof:colour", "red"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let roof = RoofTags::from_tags(&tags, 2.0);
+        assert_eq!(roof.height, Some(7));
+        assert!(roof.across);
+        assert!(roof.block.is_some());
+
+        assert_eq!(
+            RoofTags::from_tags(&HashMap::new(), 1.0),
+            RoofTags::default()
+        );
+    }
+
+    fn way(id: u64, tag: &str, corners: &[(i32, i32)]) -> ProcessedElement {
+        ProcessedElement::Way(ProcessedWay {
+            id,
+            nodes: corners
+                .iter()
+                .map(|&(x, z)| ProcessedNode {
+                    id: 0,
+                    tags: HashMap::new(),
+                    x,
+                    z,
+                })
+                .collect(),
+            tags: HashMap::from([(tag.to_string(), "yes".to_string())]),
+        })
+    }
+
+    #[test]
+    fn test_outlines_with_parts() {
+        let square = |x: i32, z: i32, size: i32| {
+            vec![
+                (x, z),
+                (x + size, z),
+                (x + size, z + size),
+                (x, z + size),
+                (x, z),
+            ]
+        };
+        let elements = vec![
+            // Outline with a tower part inside it
+            way(1, "building", &square(0, 0, 100)),
+            way(2, "building:part", &square(60, 60, 20)),
+            // Outline without parts
+            way(3, "building", &square(200, 0, 20)),
+            // Part outside any outline
+            way(4, "building:part", &square(400, 400, 10)),
+        ];
+        assert_eq!(outlines_with_parts(&elements), HashSet::from([1]));
+        assert!(outlines_with_parts(&elements[2..3]).is_empty());
+    }
+
+    #[test]
+    fn test_onion_profile() {
+        // Wider than the base at the bulb, a point at the tip
+        assert_eq!(onion_profile(0.0), 1.0);
+        assert!(onion_profile(0.3) > 1.2);
+        assert!(onion_profile(0.9) < 0.5);
+        assert_eq!(onion_profile(1.0), 0.0);
+    }
+}
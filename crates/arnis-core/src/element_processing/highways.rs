@@ -3,6 +3,7 @@ use crate::block_definitions::*;
 use crate::bresenham::bresenham_line;
 use crate::coordinate_system::cartesian::XZPoint;
 use crate::floodfill::flood_fill_area;
+use crate::night;
 use crate::osm_parser::{ProcessedElement, ProcessedWay};
 use crate::world_editor::WorldEditor;
 use std::collections::HashMap;
@@ -14,40 +15,38 @@ pub fn generate_highways(
     args: &Args,
     all_elements: &[ProcessedElement],
 ) {
-    let highway_connectivity = build_highway_connectivity_map(all_elements);
+    let highway_connectivity = build_connectivity_map(all_elements, "highway");
     generate_highways_internal(editor, element, args, &highway_connectivity);
 }
 
-/// Build a connectivity map for highway endpoints to determine where slopes are needed
-fn build_highway_connectivity_map(elements: &[ProcessedElement]) -> HashMap<(i32, i32), Vec<i32>> {
+/// Each layer is 6 blocks higher/lower
+pub(crate) const LAYER_HEIGHT_STEP: i32 = 6;
+
+/// Build a connectivity map of the nodes of all ways with the tag `key` to the layers of the
+/// ways through them, to determine where slopes are needed. Ways joining the middle of
+/// another way count as well, like the links of an interchange.
+pub(crate) fn build_connectivity_map(
+    elements: &[ProcessedElement],
+    key: &str,
+) -> HashMap<(i32, i32), Vec<i32>> {
     let mut connectivity_map: HashMap<(i32, i32), Vec<i32>> = HashMap::new();
 
     for element in elements {
         if let ProcessedElement::Way(way) = element {
-            if way.tags.contains_key("highway") {
-                let layer_value = way
-                    .tags
-                    .get("layer")
-                    .and_then(|layer| layer.parse::<i32>().ok())
-                    .unwrap_or(0);
-
-                // Treat negative layers as ground level (0) for connectivity
-                let layer_value = if layer_value < 0 { 0 } else { layer_value };
-
-                // Add connectivity for start and end nodes
-                if !way.nodes.is_empty() {
-                    let start_node = &way.nodes[0];
-                    let end_node = &way.nodes[way.nodes.len() - 1];
-
-                    let start_coord = (start_node.x, start_node.z);
-                    let end_coord = (end_node.x, end_node.z);
-
-                    connectivity_map
-                        .entry(start_coord)
-                        .or_default()
-                        .push(layer_value);
+            if way.tags.contains_key(key) {
+                let layer_value = effective_layer(&way.tags);
+
+                // Closed ways start and end at the same node, which counts once
+                let open_nodes = if way.nodes.len() > 1
+                    && way.nodes[0].xz() == way.nodes[way.nodes.len() - 1].xz()
+                {
+                    &way.nodes[1..]
+                } else {
+                    &way.nodes[..]
+                };
+                for node in open_nodes {
                     connectivity_map
-                        .entry(end_coord)
+                        .entry((node.x, node.z))
                         .or_default()
                         .push(layer_value);
                 }
@@ -58,6 +57,15 @@ fn build_highway_connectivity_map(elements: &[ProcessedElement]) -> HashMap<(i32
     connectivity_map
 }
 
+/// A lamp on a post, lit with glowstone
+fn street_lamp(editor: &mut WorldEditor, x: i32, z: i32) {
+    editor.set_block(COBBLESTONE_WALL, x, 1, z, None, None);
+    for dy in 2..=4 {
+        editor.set_block(OAK_FENCE, x, dy, z, None, None);
+    }
+    editor.set_block(GLOWSTONE, x, 5, z, None, None);
+}
+
 /// Internal function that generates highways with connectivity context for elevation handling
 fn generate_highways_internal(
     editor: &mut WorldEditor,
@@ -69,13 +77,7 @@ fn generate_highways_internal(
         if highway_type == "street_lamp" {
             // Handle street lamps
             if let ProcessedElement::Node(first_node) = element {
-                let x: i32 = first_node.x;
-                let z: i32 = first_node.z;
-                editor.set_block(COBBLESTONE_WALL, x, 1, z, None, None);
-                for dy in 2..=4 {
-                    editor.set_block(OAK_FENCE, x, dy, z, None, None);
-                }
-                editor.set_block(GLOWSTONE, x, 5, z, None, None);
+                street_lamp(editor, first_node.x, first_node.z);
             }
         } else if highway_type == "crossing" {
             // Handle traffic signals for crossings
@@ -156,14 +158,8 @@ fn generate_highways_internal(
             let scale_factor = args.scale;
 
             // Parse the layer value for elevation calculation
-            let layer_value = element
-                .tags()
-                .get("layer")
-                .and_then(|layer| layer.parse::<i32>().ok())
-                .unwrap_or(0);
-
-            // Treat negative layers as ground level (0)
-            let layer_value = if layer_value < 0 { 0 } else { layer_value };
+            let layer_value = effective_layer(element.tags());
+            let bridge = is_bridge(element.tags());
 
             // Skip if 'level' is negative in the tags (indoor mapping)
             if let Some(level) = element.tags().get("level") {
@@ -240,7 +236,6 @@ fn generate_highways_internal(
             }
 
             // Calculate elevation based on layer
-            const LAYER_HEIGHT_STEP: i32 = 6; // Each layer is 6 blocks higher/lower
             let base_elevation = layer_value * LAYER_HEIGHT_STEP;
 
             // Check if we need slopes at start and end
@@ -256,12 +251,35 @@ fn generate_highways_internal(
             let total_way_length = calculate_way_length(way);
 
             // Check if this is a short isolated elevated segment - if so, treat as ground level
-            let is_short_isolated_elevated =
-                needs_start_slope && needs_end_slope && layer_value > 0 && total_way_length <= 35;
+            let is_short_isolated_elevated = !bridge
+                && needs_start_slope
+                && needs_end_slope
+                && layer_value > 0
+                && total_way_length <= 35;
+
+            // Links ramp all along between the levels of the roads they join
+            let link_levels = if highway_type.ends_with("_link") {
+                let start = joined_layer(&way.nodes[0], layer_value, highway_connectivity);
+                let end = joined_layer(
+                    &way.nodes[way.nodes.len() - 1],
+                    layer_value,
+                    highway_connectivity,
+                );
+                (start != end).then_some((start * LAYER_HEIGHT_STEP, end * LAYER_HEIGHT_STEP))
+            } else {
+                None
+            };
 
             // Override elevation and slopes for short isolated segments
             let (effective_elevation, effective_start_slope, effective_end_slope) =
-                if is_short_isolated_elevated {
+                if let Some((start, end)) = link_levels {
+                    let highest = if start.max(end) > 0 {
+                        start.max(end)
+                    } else {
+                        start.min(end)
+                    };
+                    (highest, false, false)
+                } else if is_short_isolated_elevated {
                     (0, false, false) // Treat as ground level
                 } else {
                     (base_elevation, needs_start_slope, needs_end_slope)
@@ -269,6 +287,37 @@ fn generate_highways_internal(
 
             let slope_length = (total_way_length as f32 * 0.35).clamp(15.0, 50.0) as usize; // 35% of way length, max 50 blocks, min 15 blocks
 
+            // In night mode, roads at ground level get street lamps along one side
+            let lit_road = args.night
+                && effective_elevation == 0
+                && !matches!(
+                    highway_type.as_str(),
+                    "footway" | "pedestrian" | "path" | "track" | "steps" | "escape"
+                );
+            let lamp_distance = block_range + 2 + i32::from(add_outline);
+            let mut points_since_lamp = 0;
+
+            // Tunnels run straight between their portals instead of following the terrain above
+            let first_node = &way.nodes[0];
+            let last_node = &way.nodes[way.nodes.len() - 1];
+            let portal_levels = (
+                editor.get_absolute_y(first_node.x, 0, first_node.z),
+                editor.get_absolute_y(last_node.x, 0, last_node.z),
+            );
+            let total_points: usize = way
+                .nodes
+                .windows(2)
+                .map(|pair| bresenham_line(pair[0].x, 0, pair[0].z, pair[1].x, 0, pair[1].z).len())
+                .sum();
+            let mut points_along: usize = 0;
+            let half_width = block_range + i32::from(add_outline);
+            let road_blocks = [
+                block_type,
+                BLACK_CONCRETE,
+                WHITE_CONCRETE,
+                LIGHT_GRAY_CONCRETE,
+            ];
+
             // Iterate over nodes to create the highway
             let mut segment_index = 0;
             let total_segments = way.nodes.len() - 1;
@@ -293,16 +342,28 @@ fn generate_highways_internal(
 
                     for (point_index, (x, _, z)) in bresenham_points.iter().enumerate() {
                         // Calculate Y elevation for this point based on slopes and layer
-                        let current_y = calculate_point_elevation(
-                            segment_index,
-                            point_index,
-                            segment_length,
-                            total_segments,
-                            effective_elevation,
-                            effective_start_slope,
-                            effective_end_slope,
-                            slope_length,
-                        );
+                        let progress = points_along as f64 / total_points.max(1) as f64;
+                        let current_y = if let Some((start, end)) = link_levels {
+                            start + ((end - start) as f64 * progress).round() as i32
+                        } else {
+                            calculate_point_elevation(
+                                segment_index,
+                                point_index,
+                                segment_length,
+                                total_segments,
+                                effective_elevation,
+                                effective_start_slope,
+                                effective_end_slope,
+                                slope_length,
+                            )
+                        };
+                        let current_y = if effective_elevation < 0 {
+                            current_y + tunnel_depth(editor, (*x, *z), portal_levels, progress)
+                        } else {
+                            current_y
+                        };
+                        points_along += 1;
+                        let horizontal = (x2 - x1).abs() >= (z2 - z1).abs();
 
                         // Draw the road surface for the entire width
                         for dx in -block_range..=block_range {
@@ -394,6 +455,31 @@ fn generate_highways_internal(
                             }
                         }
 
+                        if effective_elevation < 0 {
+                            // Use manual % check since is_multiple_of() is unstable on stable Rust
+                            #[allow(clippy::manual_is_multiple_of)]
+                            build_tunnel_section(
+                                editor,
+                                (*x, *z),
+                                current_y,
+                                half_width,
+                                horizontal,
+                                points_along % TUNNEL_LIGHT_SPACING == 0,
+                                &road_blocks,
+                            );
+                        } else if bridge && current_y > 0 {
+                            add_bridge_railing(editor, (*x, *z), current_y, half_width, horizontal);
+                        }
+
+                        if lit_road {
+                            if points_since_lamp % night::LAMP_SPACING == 0 {
+                                let (lamp_x, lamp_z) =
+                                    night::lamp_position(prev, (x2, z2), (*x, *z), lamp_distance);
+                                street_lamp(editor, lamp_x, lamp_z);
+                            }
+                            points_since_lamp += 1;
+                        }
+
                         // Add light gray concrete outline for multi-lane roads
                         if add_outline {
                             // Left outline
@@ -455,8 +541,140 @@ fn generate_highways_internal(
     }
 }
 
+/// Height of the room inside a tunnel
+const TUNNEL_CLEARANCE: i32 = 3;
+/// Blocks between the lights in the ceiling of a tunnel
+pub(crate) const TUNNEL_LIGHT_SPACING: usize = 8;
+
+fn is_bridge(tags: &HashMap<String, String>) -> bool {
+    tags.get("bridge").is_some_and(|bridge| bridge != "no")
+}
+
+/// Building passages and culverts stay at the surface
+fn is_tunnel(tags: &HashMap<String, String>) -> bool {
+    tags.get("tunnel").is_some_and(|tunnel| tunnel == "yes")
+}
+
+/// Layer a highway is built at: bridges at least one layer up, tunnels and subways below
+/// ground at least one down and other ways with negative layers at ground level
+pub(crate) fn effective_layer(tags: &HashMap<String, String>) -> i32 {
+    let layer = tags
+        .get("layer")
+        .and_then(|layer| layer.parse::<i32>().ok())
+        .unwrap_or(0);
+    let subway = tags
+        .get("railway")
+        .is_some_and(|railway| railway == "subway");
+    if is_bridge(tags) {
+        layer.max(1)
+    } else if is_tunnel(tags) || (subway && layer < 0) {
+        layer.min(-1)
+    } else {
+        layer.max(0)
+    }
+}
+
+/// How far below the ground at `(x, z)` the straight line between the tunnel portals runs,
+/// `progress` along the tunnel from 0 to 1
+pub(crate) fn tunnel_depth(
+    editor: &WorldEditor,
+    (x, z): (i32, i32),
+    portal_levels: (i32, i32),
+    progress: f64,
+) -> i32 {
+    let bore =
+        portal_levels.0 + ((portal_levels.1 - portal_levels.0) as f64 * progress).round() as i32;
+    let ground = editor.get_absolute_y(x, 0, z);
+    bore.min(ground) - ground
+}
+
+/// Digs the room of a tunnel above the road at `y` and lines it with walls, and with a lit
+/// ceiling where it is deep enough to stay covered. Near the portals it is an open cut.
+pub(crate) fn build_tunnel_section(
+    editor: &mut WorldEditor,
+    (x, z): (i32, i32),
+    y: i32,
+    half_width: i32,
+    horizontal: bool,
+    lit: bool,
+    road_blocks: &[Block],
+) {
+    let top = (y + TUNNEL_CLEARANCE).min(0);
+    let ceiling = y + TUNNEL_CLEARANCE + 1;
+    let walls_blacklist: Vec<Block> = road_blocks.iter().copied().chain([CAVE_AIR]).collect();
+
+    for offset in -half_width - 1..=half_width + 1 {
+        let (cell_x, cell_z) = if horizontal {
+            (x, z + offset)
+        } else {
+            (x + offset, z)
+        };
+        let wall = offset.abs() > half_width;
+        for cell_y in y + 1..=top {
+            if wall {
+                editor.set_block(
+                    STONE_BRICKS,
+                    cell_x,
+                    cell_y,
+                    cell_z,
+                    None,
+                    Some(&walls_blacklist),
+                );
+            } else {
+                editor.set_block(CAVE_AIR, cell_x, cell_y, cell_z, None, Some(road_blocks));
+            }
+        }
+        if ceiling < 0 {
+            let block = if lit && offset == 0 {
+                GLOWSTONE
+            } else {
+                STONE_BRICKS
+            };
+            editor.set_block(block, cell_x, ceiling, cell_z, None, Some(road_blocks));
+        }
+    }
+}
+
+/// Railings along both sides of a bridge deck at `y`
+fn add_bridge_railing(
+    editor: &mut WorldEditor,
+    (x, z): (i32, i32),
+    y: i32,
+    half_width: i32,
+    horizontal: bool,
+) {
+    for offset in [-half_width - 1, half_width + 1] {
+        let (cell_x, cell_z) = if horizontal {
+            (x, z + offset)
+        } else {
+            (x + offset, z)
+        };
+        editor.set_block(STONE_BRICK_WALL, cell_x, y + 1, cell_z, None, None);
+    }
+}
+
+/// Layer of the ways a link joins at `node`: its own layer if another way continues at its
+/// level there, else the layer furthest from it
+fn joined_layer(
+    node: &crate::osm_parser::ProcessedNode,
+    own_layer: i32,
+    connectivity: &HashMap<(i32, i32), Vec<i32>>,
+) -> i32 {
+    let Some(layers) = connectivity.get(&(node.x, node.z)) else {
+        return own_layer;
+    };
+    if layers.iter().filter(|&&layer| layer == own_layer).count() > 1 {
+        return own_layer;
+    }
+    layers
+        .iter()
+        .copied()
+        .max_by_key(|layer| (layer - own_layer).abs())
+        .unwrap_or(own_layer)
+}
+
 /// Helper function to determine if a slope should be added at a specific node
-fn should_add_slope_at_node(
+pub(crate) fn should_add_slope_at_node(
     node: &crate::osm_parser::ProcessedNode,
     current_layer: i32,
     highway_connectivity: &HashMap<(i32, i32), Vec<i32>>,
@@ -509,7 +727,7 @@ fn calculate_way_length(way: &ProcessedWay) -> usize {
 
 /// Calculate the Y elevation for a specific point along the highway
 #[allow(clippy::too_many_arguments)]
-fn calculate_point_elevation(
+pub(crate) fn calculate_point_elevation(
     segment_index: usize,
     point_index: usize,
     segment_length: usize,
@@ -555,7 +773,7 @@ fn calculate_point_elevation(
 }
 
 /// Add support pillars for elevated highways
-fn add_highway_support_pillar(
+pub(crate) fn add_highway_support_pillar(
     editor: &mut WorldEditor,
     x: i32,
     highway_y: i32,
@@ -566,9 +784,9 @@ fn add_highway_support_pillar(
 ) {
     // Only add pillars at specific intervals and positions
     if dx == 0 && dz == 0 && (x + z) % 8 == 0 {
-        // Add pillar from ground to highway level
-        for y in 1..highway_y {
-            editor.set_block(STONE_BRICKS, x, y, z, None, None);
+        // Add pillar from below the ground (or through water) to highway level
+        for y in -2..highway_y {
+            editor.set_block(STONE_BRICKS, x, y, z, Some(&[WATER]), None);
         }
 
         // Add pillar base
@@ -636,3 +854,81 @@ pub fn generate_aeroway(editor: &mut WorldEditor, way: &ProcessedWay, args: &Arg
         previous_node = Some((node.x, node.z));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_effective_layer() {
+        assert_eq!(effective_layer(&tags(&[])), 0);
+        assert_eq!(effective_layer(&tags(&[("layer", "-1")])), 0);
+        assert_eq!(effective_layer(&tags(&[("bridge", "yes")])), 1);
+        assert_eq!(
+            effective_layer(&tags(&[("bridge", "viaduct"), ("layer", "2")])),
+            2
+        );
+        assert_eq!(
+            effective_layer(&tags(&[("bridge", "no"), ("layer", "-1")])),
+            0
+        );
+        assert_eq!(effective_layer(&tags(&[("tunnel", "yes")])), -1);
+        assert_eq!(
+            effective_layer(&tags(&[("tunnel", "yes"), ("layer", "-2")])),
+            -2
+        );
+        assert_eq!(effective_layer(&tags(&[("tunnel", "building_passage")])), 0);
+        // Subways below ground run in tunnels even without the tunnel tag
+        assert_eq!(
+            effective_layer(&tags(&[("railway", "subway"), ("layer", "-2")])),
+            -2
+        );
+        assert_eq!(effective_layer(&tags(&[("railway", "subway")])), 0);
+    }
+
+    fn way(layer: &str, nodes: &[(i32, i32)]) -> ProcessedElement {
+        ProcessedElement::Way(ProcessedWay {
+            id: 0,
+            nodes: nodes
+                .iter()
+                .map(|&(x, z)| crate::osm_parser::ProcessedNode {
+                    id: 0,
+                    tags: HashMap::new(),
+                    x,
+                    z,
+                })
+                .collect(),
+            tags: tags(&[("highway", "primary"), ("layer", layer)]),
+        })
+    }
+
+    #[test]
+    fn test_joined_layer() {
+        // A flyover, a road below it and a link joining the middle of the flyover
+        let elements = vec![
+            way("1", &[(0, 0), (50, 0), (100, 0)]),
+            way("0", &[(0, 20), (100, 20)]),
+            way("0", &[(100, 20), (50, 0)]),
+        ];
+        let connectivity = build_connectivity_map(&elements, "highway");
+        assert_eq!(connectivity[&(50, 0)], vec![1, 0]);
+
+        let node = |x, z| crate::osm_parser::ProcessedNode {
+            id: 0,
+            tags: HashMap::new(),
+            x,
+            z,
+        };
+        // The link climbs to the flyover, but stays level with the road it leaves
+        assert_eq!(joined_layer(&node(50, 0), 0, &connectivity), 1);
+        assert_eq!(joined_layer(&node(100, 20), 0, &connectivity), 0);
+        assert_eq!(joined_layer(&node(7, 7), 0, &connectivity), 0);
+    }
+}
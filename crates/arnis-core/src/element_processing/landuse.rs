@@ -3,6 +3,7 @@ use crate::block_definitions::*;
 use crate::element_processing::tree::Tree;
 use crate::floodfill::flood_fill_area;
 use crate::osm_parser::{ProcessedMemberRole, ProcessedRelation, ProcessedWay};
+use crate::seeded_rng::{self, SeededRng};
 use crate::world_editor::WorldEditor;
 use rand::Rng;
 
@@ -48,7 +49,7 @@ pub fn generate_landuse(editor: &mut WorldEditor, element: &ProcessedWay, args:
     let polygon_coords: Vec<(i32, i32)> = element.nodes.iter().map(|n| (n.x, n.z)).collect();
     let floor_area: Vec<(i32, i32)> = flood_fill_area(&polygon_coords, args.timeout.as_ref());
 
-    let mut rng: rand::prelude::ThreadRng = rand::thread_rng();
+    let mut rng: SeededRng = seeded_rng::rng();
 
     for (x, z) in floor_area {
         if landuse_tag == "traffic_island" {
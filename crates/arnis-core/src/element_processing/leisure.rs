@@ -4,6 +4,7 @@ use crate::bresenham::bresenham_line;
 use crate::element_processing::tree::Tree;
 use crate::floodfill::flood_fill_area;
 use crate::osm_parser::{ProcessedMemberRole, ProcessedRelation, ProcessedWay};
+use crate::seeded_rng::{self, SeededRng};
 use crate::world_editor::WorldEditor;
 use rand::Rng;
 
@@ -91,7 +92,7 @@ pub fn generate_leisure(editor: &mut WorldEditor, element: &ProcessedWay, args:
                 if matches!(leisure_type.as_str(), "park" | "garden" | "nature_reserve")
                     && editor.check_for_block(x, 0, z, Some(&[GRASS_BLOCK]))
                 {
-                    let mut rng: rand::prelude::ThreadRng = rand::thread_rng();
+                    let mut rng: SeededRng = seeded_rng::rng();
                     let random_choice: i32 = rng.gen_range(0..1000);
 
                     match random_choice {
@@ -123,7 +124,7 @@ pub fn generate_leisure(editor: &mut WorldEditor, element: &ProcessedWay, args:
 
                 // Add playground or recreation ground features
                 if matches!(leisure_type.as_str(), "playground" | "recreation_ground") {
-                    let mut rng: rand::prelude::ThreadRng = rand::thread_rng();
+                    let mut rng: SeededRng = seeded_rng::rng();
                     let random_choice: i32 = rng.gen_range(0..5000);
 
                     match random_choice {
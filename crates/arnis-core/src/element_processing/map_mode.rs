@@ -0,0 +1,241 @@
+//! Surface-only "map mode": instead of full 3D structures, every supported feature is drawn
+//! as a single layer of color-coded blocks at ground level, like a giant floor map.
+
+use crate::args::Args;
+use crate::block_definitions::*;
+use crate::bresenham::bresenham_line;
+use crate::floodfill::flood_fill_area;
+use crate::osm_parser::{ProcessedElement, ProcessedMemberRole, ProcessedWay};
+use crate::world_editor::WorldEditor;
+use std::collections::{HashMap, HashSet};
+
+/// How a feature is drawn on the map
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MapFeature {
+    /// Filled polygon
+    Area(Block),
+    /// Line with the given half width in blocks (before scaling)
+    Line(Block, i32),
+}
+
+pub fn generate_map_surface(editor: &mut WorldEditor, element: &ProcessedElement, args: &Args) {
+    // Single points are too small to show up on an overview map
+    if matches!(element, ProcessedElement::Node(_)) {
+        return;
+    }
+
+    if let Some(feature) = classify(element.tags()) {
+        draw_feature(editor, element, feature, args, None);
+    }
+}
+
+/// Draws the footprint of any element in a single color, overwriting what is on the ground.
+/// Used to highlight elements, e.g. changes in diff worlds.
+pub fn draw_footprint(
+    editor: &mut WorldEditor,
+    element: &ProcessedElement,
+    block: Block,
+    args: &Args,
+) {
+    let feature = match (element, classify(element.tags())) {
+        (ProcessedElement::Node(node), _) => {
+            for dx in -1..=1 {
+                for dz in -1..=1 {
+                    editor.set_block(block, node.x + dx, 0, node.z + dz, None, Some(&[]));
+                }
+            }
+            return;
+        }
+        (_, Some(MapFeature::Area(_))) => MapFeature::Area(block),
+        (_, Some(MapFeature::Line(_, half_width))) => MapFeature::Line(block, half_width),
+        (ProcessedElement::Way(way), None) if way.nodes.first() != way.nodes.last() => {
+            MapFeature::Line(block, 0)
+        }
+        (_, None) => MapFeature::Area(block),
+    };
+
+    draw_feature(editor, element, feature, args, Some(&[]));
+}
+
+fn draw_feature(
+    editor: &mut WorldEditor,
+    element: &ProcessedElement,
+    feature: MapFeature,
+    args: &Args,
+    override_blacklist: Option<&[Block]>,
+) {
+    match (element, feature) {
+        (ProcessedElement::Node(_), _) => {}
+        (ProcessedElement::Way(way), MapFeature::Area(block)) => {
+            for (x, z) in way_area(way, args) {
+                editor.set_block(block, x, 0, z, None, override_blacklist);
+            }
+        }
+        (ProcessedElement::Way(way), MapFeature::Line(block, half_width)) => {
+            let half_width = ((half_width as f64) * args.scale).floor() as i32;
+            draw_line(editor, way, block, half_width, override_blacklist);
+        }
+        (ProcessedElement::Relation(rel), MapFeature::Area(block)) => {
+            // Holes of multipolygons (e.g. islands in a lake) keep the default ground
+            let holes: HashSet<(i32, i32)> = rel
+                .members
+                .iter()
+                .filter(|member| member.role == ProcessedMemberRole::Inner)
+                .flat_map(|member| way_area(&member.way, args))
+                .collect();
+
+            for member in &rel.members {
+                if member.role != ProcessedMemberRole::Outer {
+                    continue;
+                }
+                for (x, z) in way_area(&member.way, args) {
+                    if !holes.contains(&(x, z)) {
+                        editor.set_block(block, x, 0, z, None, override_blacklist);
+                    }
+                }
+            }
+        }
+        (ProcessedElement::Relation(_), MapFeature::Line(..)) => {}
+    }
+}
+
+fn way_area(way: &ProcessedWay, args: &Args) -> Vec<(i32, i32)> {
+    let polygon_coords: Vec<(i32, i32)> = way.nodes.iter().map(|n| (n.x, n.z)).collect();
+    flood_fill_area(&polygon_coords, args.timeout.as_ref())
+}
+
+fn draw_line(
+    editor: &mut WorldEditor,
+    way: &ProcessedWay,
+    block: Block,
+    half_width: i32,
+    override_blacklist: Option<&[Block]>,
+) {
+    for pair in way.nodes.windows(2) {
+        let points = bresenham_line(pair[0].x, 0, pair[0].z, pair[1].x, 0, pair[1].z);
+        for (x, _, z) in points {
+            for dx in -half_width..=half_width {
+                for dz in -half_width..=half_width {
+                    editor.set_block(block, x + dx, 0, z + dz, None, override_blacklist);
+                }
+            }
+        }
+    }
+}
+
+/// Picks the map color of a feature from its tags, or None if it is not drawn
+fn classify(tags: &HashMap<String, String>) -> Option<MapFeature> {
+    let tag = |key: &str| tags.get(key).map(String::as_str);
+
+    if tag("building").is_some() || tag("building:part").is_some() {
+        return Some(MapFeature::Area(LIGHT_GRAY_CONCRETE));
+    }
+
+    if let Some(highway) = tag("highway") {
+        if tag("area") == Some("yes") {
+            return Some(MapFeature::Area(GRAY_CONCRETE));
+        }
+        return Some(match highway {
+            "motorway" | "motorway_link" | "trunk" | "trunk_link" => {
+                MapFeature::Line(ORANGE_TERRACOTTA, 3)
+            }
+            "primary" | "primary_link" | "secondary" | "secondary_link" => {
+                MapFeature::Line(YELLOW_CONCRETE, 2)
+            }
+            "footway" | "path" | "cycleway" | "steps" | "track" | "bridleway" | "pedestrian" => {
+                MapFeature::Line(BROWN_TERRACOTTA, 0)
+            }
+            _ => MapFeature::Line(WHITE_CONCRETE, 1),
+        });
+    }
+
+    if tag("water").is_some() || matches!(tag("natural"), Some("water" | "bay")) {
+        return Some(MapFeature::Area(WATER));
+    }
+
+    if let Some(waterway) = tag("waterway") {
+        return Some(match waterway {
+            "dock" | "riverbank" => MapFeature::Area(WATER),
+            "river" | "canal" => MapFeature::Line(WATER, 2),
+            _ => MapFeature::Line(WATER, 0),
+        });
+    }
+
+    if let Some(railway) = tag("railway") {
+        return match railway {
+            "rail" | "light_rail" | "narrow_gauge" | "subway" | "tram" | "monorail" => {
+                Some(MapFeature::Line(BLACK_TERRACOTTA, 0))
+            }
+            _ => None,
+        };
+    }
+
+    if let Some(aeroway) = tag("aeroway") {
+        return match aeroway {
+            "runway" | "taxiway" => Some(MapFeature::Line(GRAY_CONCRETE, 2)),
+            "apron" => Some(MapFeature::Area(GRAY_CONCRETE)),
+            _ => None,
+        };
+    }
+
+    if tag("amenity") == Some("parking") {
+        return Some(MapFeature::Area(GRAY_CONCRETE));
+    }
+
+    let landcover = tag("landuse").or(tag("leisure")).or(tag("natural"))?;
+    let block = match landcover {
+        "grass" | "meadow" | "greenfield" | "village_green" | "recreation_ground" | "park"
+        | "garden" | "pitch" | "playground" | "golf_course" | "grassland" => LIME_CONCRETE,
+        "forest" | "wood" | "scrub" | "heath" => GREEN_WOOL,
+        "farmland" | "farmyard" | "orchard" | "vineyard" | "allotments" => YELLOW_TERRACOTTA,
+        "residential" => WHITE_TERRACOTTA,
+        "commercial" | "retail" => RED_TERRACOTTA,
+        "industrial" | "railway" | "construction" | "quarry" | "landfill" => GRAY_TERRACOTTA,
+        "cemetery" => GREEN_STAINED_HARDENED_CLAY,
+        "beach" | "sand" => SAND,
+        "bare_rock" | "scree" => STONE,
+        "wetland" => MOSS_BLOCK,
+        _ => return None,
+    };
+
+    Some(MapFeature::Area(block))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            classify(&tags(&[("building", "yes"), ("landuse", "residential")])),
+            Some(MapFeature::Area(LIGHT_GRAY_CONCRETE))
+        );
+        assert_eq!(
+            classify(&tags(&[("highway", "primary")])),
+            Some(MapFeature::Line(YELLOW_CONCRETE, 2))
+        );
+        assert_eq!(
+            classify(&tags(&[("highway", "pedestrian"), ("area", "yes")])),
+            Some(MapFeature::Area(GRAY_CONCRETE))
+        );
+        assert_eq!(
+            classify(&tags(&[("natural", "water")])),
+            Some(MapFeature::Area(WATER))
+        );
+        assert_eq!(
+            classify(&tags(&[("leisure", "park")])),
+            Some(MapFeature::Area(LIME_CONCRETE))
+        );
+        assert_eq!(classify(&tags(&[("railway", "platform")])), None);
+        assert_eq!(classify(&tags(&[("natural", "tree")])), None);
+        assert_eq!(classify(&tags(&[("barrier", "fence")])), None);
+    }
+}
@@ -7,9 +7,13 @@ pub mod highways;
 pub mod landuse;
 pub mod leisure;
 pub mod man_made;
+pub mod map_mode;
 pub mod natural;
+pub mod qa_markers;
 pub mod railways;
+pub mod road_grading;
 pub mod subprocessor;
+pub mod subways;
 pub mod tourisms;
 pub mod tree;
 pub mod water_areas;
@@ -4,6 +4,7 @@ use crate::bresenham::bresenham_line;
 use crate::element_processing::tree::Tree;
 use crate::floodfill::flood_fill_area;
 use crate::osm_parser::{ProcessedElement, ProcessedMemberRole, ProcessedRelation, ProcessedWay};
+use crate::seeded_rng::{self, SeededRng};
 use crate::world_editor::WorldEditor;
 use rand::Rng;
 
@@ -79,7 +80,7 @@ pub fn generate_natural(editor: &mut WorldEditor, element: &ProcessedElement, ar
                 let filled_area: Vec<(i32, i32)> =
                     flood_fill_area(&polygon_coords, args.timeout.as_ref());
 
-                let mut rng: rand::prelude::ThreadRng = rand::thread_rng();
+                let mut rng: SeededRng = seeded_rng::rng();
 
                 for (x, z) in filled_area {
                     editor.set_block(block_type, x, 0, z, None, None);
@@ -276,8 +277,8 @@ pub fn generate_natural(editor: &mut WorldEditor, element: &ProcessedElement, ar
                                 let cluster_size = rng.gen_range(5..=10);
 
                                 // Create cluster around current position
-                                for dx in -(cluster_size as i32)..=(cluster_size as i32) {
-                                    for dz in -(cluster_size as i32)..=(cluster_size as i32) {
+                                for dx in -cluster_size..=cluster_size {
+                                    for dz in -cluster_size..=cluster_size {
                                         let cluster_x = x + dx;
                                         let cluster_z = z + dz;
 
@@ -0,0 +1,189 @@
+//! QA mode for mappers: elements with `fixme`/`note` tags or obviously broken geometry
+//! get a glowing marker pillar topped with a sign quoting the problem.
+
+use crate::block_definitions::*;
+use crate::osm_parser::{ProcessedElement, ProcessedMemberRole, ProcessedWay};
+use crate::world_editor::WorldEditor;
+
+/// Height of the glowing pillar below the sign
+const MARKER_HEIGHT: i32 = 3;
+/// Characters that fit on one line of a sign
+const SIGN_LINE_WIDTH: usize = 15;
+
+/// Places a marker if the element has any issues. Returns whether a marker was placed.
+pub fn mark_issues(editor: &mut WorldEditor, element: &ProcessedElement) -> bool {
+    let issues = find_issues(element);
+    if issues.is_empty() {
+        return false;
+    }
+
+    let Some(center) = element.center() else {
+        return false;
+    };
+
+    for y in 1..=MARKER_HEIGHT {
+        editor.set_block(GLOWSTONE, center.x, y, center.z, None, Some(&[]));
+    }
+
+    let [line1, line2, line3, line4] = sign_lines(&issues);
+    editor.set_sign(
+        line1,
+        line2,
+        line3,
+        line4,
+        center.x,
+        MARKER_HEIGHT + 1,
+        center.z,
+        0,
+    );
+
+    true
+}
+
+/// Issues of an element as (label, details) pairs
+fn find_issues(element: &ProcessedElement) -> Vec<(&'static str, String)> {
+    let mut issues = Vec::new();
+    let tags = element.tags();
+
+    for (key, label) in [("fixme", "FIXME"), ("FIXME", "FIXME"), ("note", "NOTE")] {
+        if let Some(value) = tags.get(key) {
+            issues.push((label, value.clone()));
+        }
+    }
+
+    match element {
+        ProcessedElement::Node(_) => {}
+        ProcessedElement::Way(way) => {
+            if way.nodes.len() < 2 {
+                issues.push(("BROKEN WAY", "too few nodes".to_string()));
+            } else if is_area(way) {
+                if way.nodes.first().map(|n| n.id) != way.nodes.last().map(|n| n.id) {
+                    issues.push(("BROKEN AREA", "not closed".to_string()));
+                } else if way.nodes.len() < 4 {
+                    issues.push(("BROKEN AREA", "less than 3 nodes".to_string()));
+                }
+            }
+        }
+        ProcessedElement::Relation(rel) => {
+            if !rel
+                .members
+                .iter()
+                .any(|member| member.role == ProcessedMemberRole::Outer)
+            {
+                issues.push(("BROKEN RELATION", "no outer ring".to_string()));
+            }
+        }
+    }
+
+    issues
+}
+
+/// Ways that describe an area and therefore have to be closed
+fn is_area(way: &ProcessedWay) -> bool {
+    let tags = &way.tags;
+    tags.get("area").map(String::as_str) == Some("yes")
+        || ["building", "building:part", "landuse", "leisure"]
+            .iter()
+            .any(|key| tags.contains_key(*key))
+        || matches!(
+            tags.get("natural").map(String::as_str),
+            Some("water" | "wood" | "scrub" | "beach" | "wetland" | "grassland")
+        )
+}
+
+fn sign_lines(issues: &[(&'static str, String)]) -> [String; 4] {
+    let mut lines: Vec<String> = Vec::new();
+
+    for (label, details) in issues {
+        lines.push(label.to_string());
+
+        // Quotes would break the JSON text of the sign
+        let details: String = details
+            .chars()
+            .filter(|c| *c != '"' && *c != '\\')
+            .collect();
+        let chars: Vec<char> = details.chars().collect();
+        lines.extend(
+            chars
+                .chunks(SIGN_LINE_WIDTH)
+                .map(|chunk| chunk.iter().collect::<String>()),
+        );
+    }
+
+    lines.resize(4, String::new());
+    [
+        lines[0].clone(),
+        lines[1].clone(),
+        lines[2].clone(),
+        lines[3].clone(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm_parser::ProcessedNode;
+    use std::collections::HashMap;
+
+    fn node(id: u64, x: i32, z: i32) -> ProcessedNode {
+        ProcessedNode {
+            id,
+            tags: HashMap::new(),
+            x,
+            z,
+        }
+    }
+
+    fn way(node_ids: &[u64], tags: &[(&str, &str)]) -> ProcessedElement {
+        ProcessedElement::Way(ProcessedWay {
+            id: 1,
+            nodes: node_ids
+                .iter()
+                .map(|&id| node(id, id as i32, id as i32))
+                .collect(),
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn test_find_issues() {
+        let fine = way(&[1, 2, 3, 1], &[("building", "yes")]);
+        assert!(find_issues(&fine).is_empty());
+
+        let fixme = way(&[1, 2], &[("highway", "path"), ("fixme", "check route")]);
+        assert_eq!(
+            find_issues(&fixme),
+            vec![("FIXME", "check route".to_string())]
+        );
+
+        let unclosed = way(&[1, 2, 3], &[("landuse", "grass")]);
+        assert_eq!(
+            find_issues(&unclosed),
+            vec![("BROKEN AREA", "not closed".to_string())]
+        );
+
+        // open ways are fine if they are not areas
+        let road = way(&[1, 2, 3], &[("highway", "residential")]);
+        assert!(find_issues(&road).is_empty());
+
+        let single = way(&[1], &[("highway", "residential")]);
+        assert_eq!(find_issues(&single).len(), 1);
+    }
+
+    #[test]
+    fn test_sign_lines() {
+        let lines = sign_lines(&[("NOTE", "Entrance \"A\" is on the back side".to_string())]);
+        assert_eq!(
+            lines,
+            [
+                "NOTE".to_string(),
+                "Entrance A is o".to_string(),
+                "n the back side".to_string(),
+                String::new(),
+            ]
+        );
+    }
+}
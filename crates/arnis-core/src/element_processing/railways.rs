@@ -1,71 +1,413 @@
+use crate::args::Args;
 use crate::block_definitions::*;
 use crate::bresenham::bresenham_line;
-use crate::osm_parser::ProcessedWay;
+use crate::element_processing::highways::{
+    add_highway_support_pillar, build_connectivity_map, build_tunnel_section,
+    calculate_point_elevation, effective_layer, should_add_slope_at_node, tunnel_depth,
+    LAYER_HEIGHT_STEP, TUNNEL_LIGHT_SPACING,
+};
+use crate::element_processing::subways;
+use crate::floodfill::flood_fill_area;
+use crate::osm_parser::{ProcessedElement, ProcessedWay};
 use crate::world_editor::WorldEditor;
-
-pub fn generate_railways(editor: &mut WorldEditor, element: &ProcessedWay) {
-    if let Some(railway_type) = element.tags.get("railway") {
-        if [
-            "proposed",
-            "abandoned",
-            "subway",
-            "construction",
-            "razed",
-            "turntable",
-        ]
-        .contains(&railway_type.as_str())
-        {
-            return;
+use std::collections::{HashMap, HashSet};
+
+/// Distance between powered rails on rideable lines
+const BOOSTER_SPACING: usize = 16;
+/// Powered rails can't connect sideways, so they keep this distance from junctions
+const JUNCTION_CLEARANCE: i32 = 2;
+/// Blocks between the sleepers laid across the ballast bed
+const SLEEPER_SPACING: usize = 2;
+/// Height of the contact wire of electrified lines above the rails' bed
+const CONTACT_WIRE_HEIGHT: i32 = 4;
+/// Blocks between the masts holding the contact wire
+const MAST_SPACING: usize = 12;
+/// Blocks of the ballast bed, which the bed of another track may replace
+const BALLAST: &[Block] = &[GRAVEL, SPRUCE_LOG_EAST_WEST, SPRUCE_LOG_NORTH_SOUTH];
+/// Blocks on top of the bed, which digging a tunnel next to them leaves in place
+const RAILS: &[Block] = &[
+    RAIL_NORTH_SOUTH,
+    RAIL_EAST_WEST,
+    RAIL_NORTH_EAST,
+    RAIL_NORTH_WEST,
+    RAIL_SOUTH_EAST,
+    RAIL_SOUTH_WEST,
+    POWERED_RAIL_NORTH_SOUTH,
+    POWERED_RAIL_EAST_WEST,
+    UNPOWERED_RAIL_NORTH_SOUTH,
+    UNPOWERED_RAIL_EAST_WEST,
+    POWERED_RAIL_ASCENDING_EAST,
+    POWERED_RAIL_ASCENDING_WEST,
+    POWERED_RAIL_ASCENDING_NORTH,
+    POWERED_RAIL_ASCENDING_SOUTH,
+    STONE_BUTTON,
+    IRON_BARS,
+];
+/// Half width of the hall around underground track near a subway station, platforms included
+const STATION_HALF_WIDTH: i32 = 3;
+
+pub fn generate_railways(
+    editor: &mut WorldEditor,
+    element: &ProcessedWay,
+    args: &Args,
+    all_elements: &[ProcessedElement],
+) {
+    if element.tags.get("railway").map(String::as_str) == Some("platform") {
+        generate_platform(editor, element, args);
+        return;
+    }
+    if !builds_track(&element.tags) {
+        return;
+    }
+    let electrified = element.tags.get("electrified").map(String::as_str) == Some("contact_line");
+
+    let junctions = if args.rideable_rails {
+        junction_points(all_elements)
+    } else {
+        HashSet::new()
+    };
+    let mut placed: usize = 0;
+    let mut pending_stop = false;
+
+    // Railways on bridges and upper layers are raised like highways, tunnels and subways run
+    // below ground, and both ramp to the surface at the ends where no other railway continues
+    // at their level
+    let layer = effective_layer(&element.tags);
+    let (start_slope, end_slope) = if layer != 0 {
+        let connectivity = build_connectivity_map(all_elements, "railway");
+        (
+            should_add_slope_at_node(&element.nodes[0], layer, &connectivity),
+            should_add_slope_at_node(
+                &element.nodes[element.nodes.len() - 1],
+                layer,
+                &connectivity,
+            ),
+        )
+    } else {
+        (false, false)
+    };
+    let segments: Vec<Vec<(i32, i32, i32)>> = element
+        .nodes
+        .windows(2)
+        .map(|pair| {
+            let points = bresenham_line(pair[0].x, 0, pair[0].z, pair[1].x, 0, pair[1].z);
+            smooth_diagonal_rails(&points)
+        })
+        .collect();
+    let total_points: usize = segments.iter().map(Vec::len).sum();
+    let slope_length = (total_points as f32 * 0.35).clamp(15.0, 50.0) as usize;
+    let first = &element.nodes[0];
+    let last = &element.nodes[element.nodes.len() - 1];
+    let portal_levels = (
+        editor.get_absolute_y(first.x, 0, first.z),
+        editor.get_absolute_y(last.x, 0, last.z),
+    );
+    // Tunnels run on a straight line between their portals where the ground rises above it
+    let elevation = |editor: &WorldEditor, distance: usize, (x, z): (i32, i32)| {
+        let distance = distance.min(total_points);
+        let y = calculate_point_elevation(
+            0,
+            distance,
+            total_points,
+            1,
+            layer * LAYER_HEIGHT_STEP,
+            start_slope,
+            end_slope,
+            slope_length,
+        );
+        if layer < 0 {
+            let progress = distance as f64 / total_points.max(1) as f64;
+            y + tunnel_depth(editor, (x, z), portal_levels, progress)
+        } else {
+            y
         }
+    };
+    let stations = if layer < 0 {
+        subways::subway_stations(all_elements)
+    } else {
+        Vec::new()
+    };
+    let mut distance: usize = 0;
+
+    for (i, smoothed_points) in segments.iter().enumerate() {
+        pending_stop |= args.rideable_rails && is_stop(&element.nodes[i].tags);
+        let (from, to) = (&element.nodes[i], &element.nodes[i + 1]);
+        let horizontal = (to.x - from.x).abs() >= (to.z - from.z).abs();
+
+        for j in 0..smoothed_points.len() {
+            let (bx, _, bz) = smoothed_points[j];
+            let y = elevation(editor, distance, (bx, bz));
+            distance += 1;
+
+            let prev = if j > 0 {
+                Some(smoothed_points[j - 1])
+            } else {
+                None
+            };
+            let next = if j < smoothed_points.len() - 1 {
+                Some(smoothed_points[j + 1])
+            } else {
+                None
+            };
 
-        if let Some(subway) = element.tags.get("subway") {
-            if subway == "yes" {
-                return;
-            }
-        }
+            let rail_block = determine_rail_direction(
+                (bx, bz),
+                prev.map(|(x, _, z)| (x, z)),
+                next.map(|(x, _, z)| (x, z)),
+            );
+            let straight = rail_block == RAIL_NORTH_SOUTH || rail_block == RAIL_EAST_WEST;
+            let north_south = rail_block == RAIL_NORTH_SOUTH;
+            // The ballast bed is three blocks wide where the track runs straight
+            let sides: &[(i32, i32)] = match (straight, north_south) {
+                (false, _) => &[],
+                (true, true) => &[(-1, 0), (1, 0)],
+                (true, false) => &[(0, -1), (0, 1)],
+            };
 
-        if let Some(tunnel) = element.tags.get("tunnel") {
-            if tunnel == "yes" {
-                return;
+            if y > 0 {
+                // Deck and piers under raised track
+                editor.set_block(STONE_BRICKS, bx, y - 1, bz, None, None);
+                for (dx, dz) in sides {
+                    editor.set_block(STONE_BRICKS, bx + dx, y - 1, bz + dz, None, None);
+                }
+                add_highway_support_pillar(editor, bx, y, bz, 0, 0, 0);
             }
-        }
-
-        for i in 1..element.nodes.len() {
-            let prev_node = element.nodes[i - 1].xz();
-            let cur_node = element.nodes[i].xz();
 
-            let points = bresenham_line(prev_node.x, 0, prev_node.z, cur_node.x, 0, cur_node.z);
-            let smoothed_points = smooth_diagonal_rails(&points);
+            // Use manual % check since is_multiple_of() is unstable on stable Rust
+            #[allow(clippy::manual_is_multiple_of)]
+            let bed = match (straight && distance % SLEEPER_SPACING == 0, north_south) {
+                (false, _) => GRAVEL,
+                (true, true) => SPRUCE_LOG_EAST_WEST,
+                (true, false) => SPRUCE_LOG_NORTH_SOUTH,
+            };
+            for (dx, dz) in sides {
+                editor.set_block(bed, bx + dx, y, bz + dz, None, None);
+            }
 
-            for j in 0..smoothed_points.len() {
-                let (bx, _, bz) = smoothed_points[j];
+            if y < 0 {
+                let at_station = subways::near_station(&stations, bx, bz);
+                let half_width = if at_station { STATION_HALF_WIDTH } else { 1 };
+                // Use manual % check since is_multiple_of() is unstable on stable Rust
+                #[allow(clippy::manual_is_multiple_of)]
+                build_tunnel_section(
+                    editor,
+                    (bx, bz),
+                    y,
+                    half_width,
+                    horizontal,
+                    distance % TUNNEL_LIGHT_SPACING == 0,
+                    RAILS,
+                );
+                if at_station {
+                    subways::build_platforms(editor, (bx, bz), y, horizontal);
+                }
+            }
 
-                editor.set_block(GRAVEL, bx, 0, bz, None, None);
+            // `distance` already points at the next block
+            let slope = [
+                next.map(|(x, _, z)| (x, elevation(editor, distance, (x, z)), z)),
+                prev.map(|(x, _, z)| {
+                    let y = elevation(editor, distance.saturating_sub(2), (x, z));
+                    (x, y, z)
+                }),
+            ]
+            .into_iter()
+            .flatten()
+            .find_map(|neighbor| ascending_rail(editor, (bx, y, bz), neighbor, rail_block));
+            if args.rideable_rails {
+                placed += 1;
+            }
 
-                let prev = if j > 0 {
-                    Some(smoothed_points[j - 1])
+            // Use manual % check since is_multiple_of() is unstable on stable Rust
+            #[allow(clippy::manual_is_multiple_of)]
+            let (rail, base) = if let Some(ascending) = slope {
+                // Powered rails pull carts up the gradient, powered by the block under them
+                (ascending, REDSTONE_BLOCK)
+            } else if !args.rideable_rails {
+                (rail_block, bed)
+            } else if pending_stop && straight {
+                // The cart halts on the unpowered rail until the button next to it is pressed
+                let stop_rail = if north_south {
+                    UNPOWERED_RAIL_NORTH_SOUTH
                 } else {
-                    None
+                    UNPOWERED_RAIL_EAST_WEST
                 };
-                let next = if j < smoothed_points.len() - 1 {
-                    Some(smoothed_points[j + 1])
+                let (side_x, side_z) = if north_south {
+                    (bx + 1, bz)
                 } else {
-                    None
+                    (bx, bz + 1)
                 };
+                editor.set_block(STONE_BUTTON, side_x, y + 1, side_z, Some(&[CAVE_AIR]), None);
+                pending_stop = false;
+                (stop_rail, bed)
+            } else if straight
+                && placed % BOOSTER_SPACING == 0
+                && !near_junction(&junctions, bx, bz)
+            {
+                let booster = if north_south {
+                    POWERED_RAIL_NORTH_SOUTH
+                } else {
+                    POWERED_RAIL_EAST_WEST
+                };
+                (booster, REDSTONE_BLOCK)
+            } else {
+                (rail_block, bed)
+            };
 
-                let rail_block = determine_rail_direction(
-                    (bx, bz),
-                    prev.map(|(x, _, z)| (x, z)),
-                    next.map(|(x, _, z)| (x, z)),
-                );
-
-                editor.set_block(rail_block, bx, 1, bz, None, None);
+            // The bed under the rail replaces the shoulders of crossing tracks, while at
+            // junctions the rail of the first line stays, as a switchable curve. In tunnels
+            // the rails go into the dug out room.
+            editor.set_block(base, bx, y, bz, Some(BALLAST), None);
+            editor.set_block(rail, bx, y + 1, bz, Some(&[CAVE_AIR]), None);
+
+            if electrified {
+                // Contact wire above the track, hung from a mast beside it
+                editor.set_block(IRON_BARS, bx, y + CONTACT_WIRE_HEIGHT, bz, None, None);
+                // Use manual % check since is_multiple_of() is unstable on stable Rust
+                #[allow(clippy::manual_is_multiple_of)]
+                if distance % MAST_SPACING == 0 {
+                    if let Some((dx, dz)) = sides.last() {
+                        for mast_y in y + 1..=y + CONTACT_WIRE_HEIGHT {
+                            editor.set_block(
+                                IRON_BARS,
+                                bx + dx,
+                                mast_y,
+                                bz + dz,
+                                Some(&[CAVE_AIR]),
+                                None,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
 
-                if bx % 4 == 0 {
-                    editor.set_block(OAK_LOG, bx, 0, bz, None, None);
+/// Station platforms: areas or lines of raised paving beside the track
+fn generate_platform(editor: &mut WorldEditor, element: &ProcessedWay, args: &Args) {
+    let base = effective_layer(&element.tags).max(0) * LAYER_HEIGHT_STEP;
+    let outline: Vec<(i32, i32)> = element.nodes.iter().map(|node| (node.x, node.z)).collect();
+    let closed = element.nodes.len() > 3
+        && element.nodes.first().map(|node| node.id) == element.nodes.last().map(|node| node.id);
+
+    let area: Vec<(i32, i32)> = if closed {
+        flood_fill_area(&outline, args.timeout.as_ref())
+    } else {
+        // Platforms mapped as a line get a width of three blocks
+        let mut area: HashSet<(i32, i32)> = HashSet::new();
+        for pair in outline.windows(2) {
+            for (x, _, z) in bresenham_line(pair[0].0, 0, pair[0].1, pair[1].0, 0, pair[1].1) {
+                for dx in -1..=1 {
+                    for dz in -1..=1 {
+                        area.insert((x + dx, z + dz));
+                    }
                 }
             }
         }
+        area.into_iter().collect()
+    };
+
+    for &(x, z) in &area {
+        editor.set_block(STONE_BRICKS, x, base, z, None, None);
+        editor.set_block(SMOOTH_STONE, x, base + 1, z, None, None);
+        if base > 0 {
+            add_highway_support_pillar(editor, x, base, z, 0, 0, 0);
+        }
+    }
+    if closed {
+        // Safety line along the edges
+        for pair in outline.windows(2) {
+            for (x, _, z) in bresenham_line(pair[0].0, 0, pair[0].1, pair[1].0, 0, pair[1].1) {
+                editor.set_block(YELLOW_CONCRETE, x, base + 1, z, Some(&[SMOOTH_STONE]), None);
+            }
+        }
+    }
+}
+
+/// Whether rails are built for a railway way, abandoned lines and platforms are left out
+pub(crate) fn builds_track(tags: &HashMap<String, String>) -> bool {
+    let Some(railway_type) = tags.get("railway") else {
+        return false;
+    };
+    ![
+        "proposed",
+        "abandoned",
+        "construction",
+        "razed",
+        "turntable",
+        "platform",
+        "platform_edge",
+    ]
+    .contains(&railway_type.as_str())
+}
+
+/// Whether trains stop at a node of a railway way
+fn is_stop(tags: &HashMap<String, String>) -> bool {
+    matches!(
+        tags.get("railway").map(String::as_str),
+        Some("stop" | "halt" | "station")
+    ) || tags
+        .get("public_transport")
+        .is_some_and(|v| v == "stop_position")
+}
+
+/// Positions where three or more tracks meet
+fn junction_points(elements: &[ProcessedElement]) -> HashSet<(i32, i32)> {
+    let mut branches: HashMap<u64, (usize, (i32, i32))> = HashMap::new();
+
+    for element in elements {
+        let ProcessedElement::Way(way) = element else {
+            continue;
+        };
+        if !builds_track(&way.tags) {
+            continue;
+        }
+        for (i, node) in way.nodes.iter().enumerate() {
+            // Inner nodes continue the track in two directions, end nodes in one
+            let count = if i == 0 || i == way.nodes.len() - 1 {
+                1
+            } else {
+                2
+            };
+            let entry = branches.entry(node.id).or_insert((0, (node.x, node.z)));
+            entry.0 += count;
+        }
+    }
+
+    branches
+        .into_values()
+        .filter(|(count, _)| *count >= 3)
+        .map(|(_, point)| point)
+        .collect()
+}
+
+fn near_junction(junctions: &HashSet<(i32, i32)>, x: i32, z: i32) -> bool {
+    (-JUNCTION_CLEARANCE..=JUNCTION_CLEARANCE).any(|dx| {
+        (-JUNCTION_CLEARANCE..=JUNCTION_CLEARANCE).any(|dz| junctions.contains(&(x + dx, z + dz)))
+    })
+}
+
+/// Powered ascending rail towards a neighbor one block higher in line with the track
+fn ascending_rail(
+    editor: &WorldEditor,
+    (x, y, z): (i32, i32, i32),
+    (nx, ny, nz): (i32, i32, i32),
+    rail_block: Block,
+) -> Option<Block> {
+    if editor.get_absolute_y(nx, ny, nz) != editor.get_absolute_y(x, y, z) + 1 {
+        return None;
+    }
+    ascending_shape(nx - x, nz - z, rail_block)
+}
+
+fn ascending_shape(dx: i32, dz: i32, rail_block: Block) -> Option<Block> {
+    match (dx, dz) {
+        (1, 0) if rail_block == RAIL_EAST_WEST => Some(POWERED_RAIL_ASCENDING_EAST),
+        (-1, 0) if rail_block == RAIL_EAST_WEST => Some(POWERED_RAIL_ASCENDING_WEST),
+        (0, -1) if rail_block == RAIL_NORTH_SOUTH => Some(POWERED_RAIL_ASCENDING_NORTH),
+        (0, 1) if rail_block == RAIL_NORTH_SOUTH => Some(POWERED_RAIL_ASCENDING_SOUTH),
+        _ => None,
     }
 }
 
@@ -242,3 +584,74 @@ pub fn generate_roller_coaster(editor: &mut WorldEditor, element: &ProcessedWay)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm_parser::ProcessedNode;
+
+    fn track(id: u64, nodes: &[(u64, i32, i32)]) -> ProcessedElement {
+        ProcessedElement::Way(ProcessedWay {
+            id,
+            nodes: nodes
+                .iter()
+                .map(|&(id, x, z)| ProcessedNode {
+                    id,
+                    tags: HashMap::new(),
+                    x,
+                    z,
+                })
+                .collect(),
+            tags: HashMap::from([("railway".to_string(), "rail".to_string())]),
+        })
+    }
+
+    #[test]
+    fn test_junction_points() {
+        let elements = vec![
+            // A line split into two ways, with a branch off its middle
+            track(1, &[(1, 0, 0), (2, 10, 0)]),
+            track(2, &[(2, 10, 0), (3, 20, 0)]),
+            track(3, &[(4, 0, 10), (1, 0, 0)]),
+            track(4, &[(2, 10, 0), (5, 10, 10)]),
+        ];
+        assert_eq!(junction_points(&elements), HashSet::from([(10, 0)]));
+    }
+
+    #[test]
+    fn test_builds_track() {
+        let tags = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|&(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        assert!(builds_track(&tags(&[("railway", "rail")])));
+        assert!(builds_track(&tags(&[
+            ("railway", "rail"),
+            ("electrified", "contact_line")
+        ])));
+        // Platforms are paved beside the track instead
+        assert!(!builds_track(&tags(&[("railway", "platform")])));
+        // Tunnels and subways are dug out below the ground
+        assert!(builds_track(&tags(&[
+            ("railway", "rail"),
+            ("tunnel", "yes")
+        ])));
+        assert!(builds_track(&tags(&[("railway", "subway")])));
+    }
+
+    #[test]
+    fn test_ascending_shape() {
+        assert_eq!(
+            ascending_shape(1, 0, RAIL_EAST_WEST),
+            Some(POWERED_RAIL_ASCENDING_EAST)
+        );
+        assert_eq!(
+            ascending_shape(0, -1, RAIL_NORTH_SOUTH),
+            Some(POWERED_RAIL_ASCENDING_NORTH)
+        );
+        // Curves can't slope
+        assert_eq!(ascending_shape(1, 0, RAIL_NORTH_EAST), None);
+    }
+}
@@ -0,0 +1,222 @@
+//! Grading of roads on sloped terrain: the ground under a road follows a smoothed profile
+//! along its centerline, cutting into hills and filling up dips across the whole road width,
+//! so roads neither staircase nor clip into the terrain.
+
+use crate::block_definitions::*;
+use crate::bresenham::bresenham_line;
+use crate::coordinate_system::cartesian::XZPoint;
+use crate::ground::Ground;
+use crate::osm_parser::{ProcessedElement, ProcessedWay};
+use crate::world_editor::WorldEditor;
+use std::collections::HashMap;
+
+/// Blocks before and after a point of the centerline that its height is averaged over
+const PROFILE_RADIUS: f64 = 8.0;
+/// Cuts at least this deep get a retaining wall along the road
+const RETAINING_WALL_HEIGHT: i32 = 2;
+
+/// Centerline of a graded road with the height of every point and the half width
+type GradedRoad = (Vec<(i32, i32)>, Vec<i32>, i32);
+
+/// A column of blocks from `bottom` to `top` (absolute Y) left by grading a road
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Earthwork {
+    pub x: i32,
+    pub z: i32,
+    pub bottom: i32,
+    pub top: i32,
+    pub block: Block,
+}
+
+/// Grades all roads at ground level into the terrain and returns the retaining walls and
+/// embankments to build. `min_coords` are the minimum world coordinates of the area.
+pub fn grade_roads(
+    ground: &mut Ground,
+    elements: &[ProcessedElement],
+    min_coords: (i32, i32),
+    scale: f64,
+) -> Vec<Earthwork> {
+    if !ground.elevation_enabled {
+        return Vec::new();
+    }
+
+    let mut roads: Vec<(&ProcessedWay, i32)> = elements
+        .iter()
+        .filter_map(|element| match element {
+            ProcessedElement::Way(way) if is_graded(way) => {
+                Some((way, road_half_width(way, scale)))
+            }
+            _ => None,
+        })
+        .collect();
+    if roads.is_empty() {
+        return Vec::new();
+    }
+    // Wider roads are graded last and win where roads meet
+    roads.sort_by_key(|(_, half_width)| *half_width);
+
+    let original = ground.clone();
+    let point = |x: i32, z: i32| XZPoint::new(x - min_coords.0, z - min_coords.1);
+    let radius = ((PROFILE_RADIUS * scale).round() as usize).max(2);
+
+    let mut graded: Vec<GradedRoad> = Vec::with_capacity(roads.len());
+    for (way, half_width) in roads {
+        let centerline = centerline(way);
+        let levels: Vec<i32> = centerline
+            .iter()
+            .map(|&(x, z)| original.level(point(x, z)))
+            .collect();
+        let profile = smooth_profile(&levels, radius);
+        for (&(x, z), &y) in centerline.iter().zip(&profile) {
+            for dx in -half_width..=half_width {
+                for dz in -half_width..=half_width {
+                    ground.set_level(point(x + dx, z + dz), y);
+                }
+            }
+        }
+        graded.push((centerline, profile, half_width));
+    }
+
+    // Walls where the road cuts into the hill beside it, earth where it was filled up
+    let mut earthworks: HashMap<(i32, i32), Earthwork> = HashMap::new();
+    for (centerline, profile, half_width) in &graded {
+        let edge = half_width + 1;
+        for (&(x, z), &y) in centerline.iter().zip(profile) {
+            for dx in -edge..=edge {
+                for dz in -edge..=edge {
+                    let (cell_x, cell_z) = (x + dx, z + dz);
+                    let earthwork = if dx.abs() == edge || dz.abs() == edge {
+                        let beside = ground.level(point(cell_x, cell_z));
+                        (beside - y >= RETAINING_WALL_HEIGHT).then_some(Earthwork {
+                            x: cell_x,
+                            z: cell_z,
+                            bottom: y + 1,
+                            top: beside,
+                            block: STONE_BRICKS,
+                        })
+                    } else {
+                        // The ground pass lays three blocks under the surface itself
+                        let below = original.level(point(cell_x, cell_z));
+                        (y - 3 > below).then_some(Earthwork {
+                            x: cell_x,
+                            z: cell_z,
+                            bottom: below + 1,
+                            top: y - 3,
+                            block: DIRT,
+                        })
+                    };
+                    if let Some(earthwork) = earthwork {
+                        earthworks
+                            .entry((cell_x, cell_z))
+                            .and_modify(|existing| {
+                                if earthwork.top - earthwork.bottom > existing.top - existing.bottom
+                                {
+                                    *existing = earthwork;
+                                }
+                            })
+                            .or_insert(earthwork);
+                    }
+                }
+            }
+        }
+    }
+    earthworks.into_values().collect()
+}
+
+/// Builds the retaining walls and embankments of graded roads
+pub fn build_earthworks(editor: &mut WorldEditor, earthworks: &[Earthwork]) {
+    for earthwork in earthworks {
+        for y in earthwork.bottom..=earthwork.top {
+            editor.set_block_absolute(earthwork.block, earthwork.x, y, earthwork.z, None, None);
+        }
+    }
+}
+
+/// Roads at ground level; bridges, tunnels, raised layers and areas keep the terrain
+fn is_graded(way: &ProcessedWay) -> bool {
+    let tag = |key: &str| way.tags.get(key).map(String::as_str);
+    way.nodes.len() >= 2
+        && tag("highway").is_some()
+        && tag("area") != Some("yes")
+        && matches!(tag("bridge"), None | Some("no"))
+        && matches!(tag("tunnel"), None | Some("no"))
+        && tag("layer")
+            .and_then(|layer| layer.parse::<i32>().ok())
+            .unwrap_or(0)
+            <= 0
+}
+
+/// Half width of a road as drawn by `highways`, including its outline
+fn road_half_width(way: &ProcessedWay, scale: f64) -> i32 {
+    let lanes = way.tags.get("lanes").map(String::as_str);
+    let (half_width, outline) = match way.tags.get("highway").map(String::as_str) {
+        Some("motorway" | "primary" | "trunk") => (5, false),
+        Some("secondary") => (4, false),
+        Some("tertiary") => (2, false),
+        Some(
+            "footway" | "pedestrian" | "path" | "track" | "secondary_link" | "tertiary_link"
+            | "escape" | "steps",
+        ) => (1, false),
+        Some("service") => (2, false),
+        _ => match lanes {
+            Some("2") => (3, true),
+            Some("1") | None => (2, false),
+            Some(_) => (4, true),
+        },
+    };
+    let half_width = if scale < 1.0 {
+        (half_width as f64 * scale).floor() as i32
+    } else {
+        half_width
+    };
+    half_width + i32::from(outline)
+}
+
+/// The blocks along a way, without repeating the nodes shared by two segments
+fn centerline(way: &ProcessedWay) -> Vec<(i32, i32)> {
+    let mut points: Vec<(i32, i32)> = Vec::new();
+    for pair in way.nodes.windows(2) {
+        for (x, _, z) in bresenham_line(pair[0].x, 0, pair[0].z, pair[1].x, 0, pair[1].z) {
+            if points.last() != Some(&(x, z)) {
+                points.push((x, z));
+            }
+        }
+    }
+    points
+}
+
+/// Moving average of the terrain levels along a road. Towards both ends it blends back into
+/// the terrain, so roads meeting at a node agree on its height.
+fn smooth_profile(levels: &[i32], radius: usize) -> Vec<i32> {
+    let last = levels.len().saturating_sub(1);
+    (0..levels.len())
+        .map(|i| {
+            let window = &levels[i.saturating_sub(radius)..=(i + radius).min(last)];
+            let average = window.iter().sum::<i32>() as f64 / window.len() as f64;
+            let distance_to_end = i.min(last - i);
+            let weight = (distance_to_end as f64 / radius as f64).min(1.0);
+            (levels[i] as f64 * (1.0 - weight) + average * weight).round() as i32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smooth_profile() {
+        // A bump in an otherwise even slope is flattened out
+        let mut levels: Vec<i32> = (0..40).map(|i| i / 4).collect();
+        levels[20] += 6;
+        let profile = smooth_profile(&levels, 8);
+
+        assert_eq!(profile[0], levels[0]);
+        assert_eq!(profile[39], levels[39]);
+        assert!(profile[20] < levels[20] - 3);
+        // No steps of more than a block between neighbors
+        assert!(profile
+            .windows(2)
+            .all(|pair| (pair[1] - pair[0]).abs() <= 1));
+    }
+}
@@ -1,6 +1,87 @@
 use crate::block_definitions::*;
 use crate::world_editor::WorldEditor;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+
+/// Furnishing of a floor, by what the building is used for
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InteriorStyle {
+    Residential,
+    Office,
+    Retail,
+    School,
+}
+
+impl InteriorStyle {
+    /// Style of the given floor. Shops in residential buildings take the ground floor.
+    fn for_floor(tags: &HashMap<String, String>, floor_index: usize) -> Self {
+        let building = tags.get("building").map(String::as_str).unwrap_or("yes");
+        let amenity = tags.get("amenity").map(String::as_str).unwrap_or("");
+        if matches!(
+            building,
+            "school" | "university" | "college" | "kindergarten"
+        ) || matches!(
+            amenity,
+            "school" | "university" | "college" | "kindergarten"
+        ) {
+            InteriorStyle::School
+        } else if matches!(building, "retail" | "supermarket" | "kiosk" | "shop")
+            || (tags.contains_key("shop") && floor_index == 0)
+        {
+            InteriorStyle::Retail
+        } else if matches!(
+            building,
+            "office" | "commercial" | "government" | "civic" | "public"
+        ) || tags.contains_key("office")
+        {
+            InteriorStyle::Office
+        } else {
+            InteriorStyle::Residential
+        }
+    }
+
+    /// Blocks of both layers at the position within the repeating layout
+    fn cells(self, floor_index: usize, x: i32, z: i32) -> (char, char) {
+        match self {
+            InteriorStyle::Residential if floor_index == 0 => {
+                layout_cells(&INTERIOR1_LAYER1, &INTERIOR1_LAYER2, x, z)
+            }
+            InteriorStyle::Residential => layout_cells(&INTERIOR2_LAYER1, &INTERIOR2_LAYER2, x, z),
+            InteriorStyle::Office => layout_cells(&OFFICE_LAYER1, &OFFICE_LAYER2, x, z),
+            InteriorStyle::Retail => layout_cells(&RETAIL_LAYER1, &RETAIL_LAYER2, x, z),
+            InteriorStyle::School => layout_cells(&SCHOOL_LAYER1, &SCHOOL_LAYER2, x, z),
+        }
+    }
+}
+
+/// Cells of a layout tiled seamlessly across the floor
+fn layout_cells<const N: usize>(
+    layer1: &[[char; N]; N],
+    layer2: &[[char; N]; N],
+    x: i32,
+    z: i32,
+) -> (char, char) {
+    let (pattern_x, pattern_z) = (
+        x.rem_euclid(N as i32) as usize,
+        z.rem_euclid(N as i32) as usize,
+    );
+    (layer1[pattern_z][pattern_x], layer2[pattern_z][pattern_x])
+}
+
+/// Position of the ladder between the floors, with the floor to the south of it for the wall
+/// the ladder hangs on and to the north of it for the space to climb in
+fn find_stairwell(
+    floor_area: &HashSet<(i32, i32)>,
+    (min_x, min_z): (i32, i32),
+    (max_x, max_z): (i32, i32),
+) -> Option<(i32, i32)> {
+    (min_z..=max_z)
+        .flat_map(|z| (min_x..=max_x).map(move |x| (x, z)))
+        .find(|&(x, z)| {
+            floor_area.contains(&(x, z))
+                && floor_area.contains(&(x, z + 1))
+                && floor_area.contains(&(x, z - 1))
+        })
+}
 
 /// Interior layout for building ground floors (1st layer above floor)
 #[rustfmt::skip]
@@ -114,6 +195,108 @@ const INTERIOR2_LAYER2: [[char; 23]; 23] = [
     ['P', 'P', ' ', ' ', ' ', 'E', 'B', 'B', 'B', ' ', ' ', 'W', 'B', 'B', 'B', 'B', 'B', 'B', 'B', ' ', 'B', ' ', 'D',],
 ];
 
+/// Interior layout for offices: desks with chairs, filing and a printer (1st layer above floor)
+#[rustfmt::skip]
+const OFFICE_LAYER1: [[char; 12]; 12] = [
+    ['W', 'W', 'W', 'W', 'W', 'D', 'D', 'W', 'W', 'W', 'W', 'W',],
+    ['W', 'B', 'B', ' ', ' ', ' ', ' ', ' ', ' ', 'F', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', 'O', 'O', ' ', ' ', 'O', 'O', ' ', ' ', 'O', 'O',],
+    ['W', ' ', 'S', 'S', ' ', ' ', 'S', 'S', ' ', ' ', 'S', 'S',],
+    ['D', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['D', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', 'O', 'O', ' ', ' ', 'O', 'O', ' ', ' ', 'O', 'O',],
+    ['W', ' ', 'S', 'S', ' ', ' ', 'S', 'S', ' ', ' ', 'S', 'S',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', 'L', ' ', ' ', ' ', ' ', ' ', ' ', ' ', 'C', 'H', ' ',],
+    ['W', 'B', 'B', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+];
+
+/// Interior layout for offices (2nd layer above floor)
+#[rustfmt::skip]
+const OFFICE_LAYER2: [[char; 12]; 12] = [
+    ['W', 'W', 'W', 'W', 'W', 'D', 'D', 'W', 'W', 'W', 'W', 'W',],
+    ['W', 'B', 'B', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['D', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['D', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', 'E', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', 'B', 'B', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+];
+
+/// Interior layout for shops: shelves, display chests and a till (1st layer above floor)
+#[rustfmt::skip]
+const RETAIL_LAYER1: [[char; 12]; 12] = [
+    ['W', 'W', 'W', 'W', 'W', 'D', 'D', 'W', 'W', 'W', 'W', 'W',],
+    ['W', 'B', 'B', 'B', 'B', ' ', ' ', 'B', 'B', 'B', 'B', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', 'B', 'B', 'B', ' ', ' ', 'B', 'B', 'B', ' ', ' ',],
+    ['W', ' ', 'H', 'H', 'H', ' ', ' ', 'H', 'H', 'H', ' ', ' ',],
+    ['D', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['D', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', 'B', 'B', 'B', ' ', ' ', 'B', 'B', 'B', ' ', ' ',],
+    ['W', ' ', 'H', 'H', 'H', ' ', ' ', 'H', 'H', 'H', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', 'O', 'O', 'O', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', 'C', 'S', 'O', ' ', ' ', ' ', ' ', ' ', ' ', 'L', ' ',],
+];
+
+/// Interior layout for shops (2nd layer above floor)
+#[rustfmt::skip]
+const RETAIL_LAYER2: [[char; 12]; 12] = [
+    ['W', 'W', 'W', 'W', 'W', 'D', 'D', 'W', 'W', 'W', 'W', 'W',],
+    ['W', 'B', 'B', 'B', 'B', ' ', ' ', 'B', 'B', 'B', 'B', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', 'B', 'B', 'B', ' ', ' ', 'B', 'B', 'B', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['D', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['D', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', 'B', 'B', 'B', ' ', ' ', 'B', 'B', 'B', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', 'E', ' ',],
+];
+
+/// Interior layout for schools: classrooms with rows of desks (1st layer above floor)
+#[rustfmt::skip]
+const SCHOOL_LAYER1: [[char; 12]; 12] = [
+    ['W', 'W', 'W', 'W', 'W', 'W', 'D', 'D', 'W', 'W', 'W', 'W',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', 'O', 'C', 'O', ' ', ' ', ' ', ' ', ' ', 'B', ' ',],
+    ['W', ' ', ' ', 'S', ' ', ' ', ' ', ' ', ' ', ' ', 'B', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', 'O', 'O', ' ', 'O', 'O', ' ', 'O', 'O', ' ', ' ',],
+    ['W', ' ', 'S', 'S', ' ', 'S', 'S', ' ', 'S', 'S', ' ', ' ',],
+    ['D', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['D', ' ', 'O', 'O', ' ', 'O', 'O', ' ', 'O', 'O', ' ', ' ',],
+    ['W', ' ', 'S', 'S', ' ', 'S', 'S', ' ', 'S', 'S', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', 'B', 'B', 'L', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+];
+
+/// Interior layout for schools (2nd layer above floor)
+#[rustfmt::skip]
+const SCHOOL_LAYER2: [[char; 12]; 12] = [
+    ['W', 'W', 'W', 'W', 'W', 'W', 'D', 'D', 'W', 'W', 'W', 'W',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', 'B', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', 'B', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['D', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['D', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+    ['W', 'B', 'B', 'E', ' ', ' ', ' ', ' ', ' ', ' ', ' ', ' ',],
+];
+
 /// Maps interior layout characters to actual block types for different floor layers
 #[inline(always)]
 pub fn get_interior_block(c: char, is_layer2: bool, wall_block: Block) -> Option<Block> {
@@ -133,7 +316,8 @@ pub fn get_interior_block(c: char, is_layer2: bool, wall_block: Block) -> Option
         '6' => Some(RED_BED_SOUTH_FOOT), // Bed South Foot
         '7' => Some(RED_BED_WEST_HEAD),  // Bed West Head
         '8' => Some(RED_BED_WEST_FOOT),  // Bed West Foot
-        // 'H' => Some(CHEST),           // Chest
+        'H' => Some(CHEST),              // Chest
+        'O' => Some(OAK_SLAB),           // Oak Slab (desks and counters)
         'L' => Some(CAULDRON),           // Cauldron
         'A' => Some(ANVIL),              // Anvil
         'P' => Some(OAK_PRESSURE_PLATE), // Pressure Plate
@@ -154,7 +338,8 @@ pub fn get_interior_block(c: char, is_layer2: bool, wall_block: Block) -> Option
     }
 }
 
-/// Generates interior layouts inside buildings at each floor level
+/// Generates interior layouts inside buildings at each floor level, furnished by the use of
+/// the building, with a ladder leading through all floors
 #[allow(clippy::too_many_arguments)]
 pub fn generate_building_interior(
     editor: &mut WorldEditor,
@@ -189,6 +374,20 @@ pub fn generate_building_interior(
     let interior_max_x = max_x - buffer;
     let interior_max_z = max_z - buffer;
 
+    // The ladder and the space in front of it stay free of furniture
+    let stairwell = if floor_levels.len() > 1 {
+        find_stairwell(
+            &floor_area_set,
+            (interior_min_x, interior_min_z),
+            (interior_max_x, interior_max_z),
+        )
+    } else {
+        None
+    };
+    let reserved: HashSet<(i32, i32)> = stairwell
+        .map(|(x, z)| HashSet::from([(x, z), (x, z - 1), (x, z + 1)]))
+        .unwrap_or_default();
+
     // Generate interiors for each floor
     for (floor_index, &floor_y) in floor_levels.iter().enumerate() {
         // Store wall and door positions for this floor to extend them to the ceiling
@@ -213,43 +412,36 @@ pub fn generate_building_interior(
             }
         };
 
-        // Choose the appropriate interior pattern based on floor number
-        let (layer1, layer2) = if floor_index == 0 {
-            // Ground floor uses INTERIOR1 patterns
-            (&INTERIOR1_LAYER1, &INTERIOR1_LAYER2)
-        } else {
-            // Upper floors use INTERIOR2 patterns
-            (&INTERIOR2_LAYER1, &INTERIOR2_LAYER2)
-        };
-
-        // Get dimensions for the selected pattern
-        let pattern_height = layer1.len() as i32;
-        let pattern_width = layer1[0].len() as i32;
+        // Choose the interior pattern based on the use of the building and the floor number
+        let style = InteriorStyle::for_floor(&element.tags, floor_index);
 
         // Calculate Y offset - place interior 1 block above floor level consistently
         let y_offset = 1;
 
+        // Ladder up through the floor above, on a wall column to its south
+        if let (Some((x, z)), Some(&next_floor_y)) = (stairwell, floor_levels.get(floor_index + 1))
+        {
+            for y in (floor_y + y_offset)..=next_floor_y {
+                editor.set_block_absolute(LADDER, x, y + abs_terrain_offset, z, None, Some(&[]));
+                editor.set_block_absolute(wall_block, x, y + abs_terrain_offset, z + 1, None, None);
+            }
+        }
+
         // Create a seamless repeating pattern across the interior of this floor
         for z in interior_min_z..=interior_max_z {
             for x in interior_min_x..=interior_max_x {
                 // Skip if outside the building's floor area
-                if !floor_area_set.contains(&(x, z)) {
+                if !floor_area_set.contains(&(x, z)) || reserved.contains(&(x, z)) {
                     continue;
                 }
 
-                // Map the world coordinates to pattern coordinates using modulo
-                // This creates a seamless tiling effect across the entire building
-                // Add floor_index offset to create variation between floors
-                let pattern_x = ((x - interior_min_x + floor_index as i32) % pattern_width
-                    + pattern_width)
-                    % pattern_width;
-                let pattern_z = ((z - interior_min_z + floor_index as i32) % pattern_height
-                    + pattern_height)
-                    % pattern_height;
-
-                // Access the pattern arrays safely
-                let cell1 = layer1[pattern_z as usize][pattern_x as usize];
-                let cell2 = layer2[pattern_z as usize][pattern_x as usize];
+                // Map the world coordinates to pattern coordinates, tiling the pattern across
+                // the building, offset by the floor index to create variation between floors
+                let (cell1, cell2) = style.cells(
+                    floor_index,
+                    x - interior_min_x + floor_index as i32,
+                    z - interior_min_z + floor_index as i32,
+                );
 
                 // Place first layer blocks
                 if let Some(block) = get_interior_block(cell1, false, wall_block) {
@@ -301,3 +493,40 @@ pub fn generate_building_interior(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_interior_style() {
+        let style = InteriorStyle::for_floor;
+        assert_eq!(
+            style(&tags(&[("building", "yes"), ("amenity", "school")]), 1),
+            InteriorStyle::School
+        );
+        assert_eq!(
+            style(&tags(&[("building", "office")]), 0),
+            InteriorStyle::Office
+        );
+        // Shops take the ground floor of apartment buildings, the flats are above
+        let shop = tags(&[("building", "apartments"), ("shop", "bakery")]);
+        assert_eq!(style(&shop, 0), InteriorStyle::Retail);
+        assert_eq!(style(&shop, 1), InteriorStyle::Residential);
+    }
+
+    #[test]
+    fn test_find_stairwell() {
+        let area: HashSet<(i32, i32)> = (0..5).flat_map(|x| (0..5).map(move |z| (x, z))).collect();
+        // Needs floor on both sides along z, so not on the first row
+        assert_eq!(find_stairwell(&area, (0, 0), (4, 4)), Some((0, 1)));
+        assert_eq!(find_stairwell(&area, (0, 4), (4, 4)), None);
+    }
+}
@@ -0,0 +1,217 @@
+//! Underground railway stations: halls with platforms around the track near subway stations,
+//! and stairwells from the entrances at the surface down to the nearest underground track.
+
+use crate::block_definitions::*;
+use crate::bresenham::bresenham_line;
+use crate::element_processing::highways::{
+    build_tunnel_section, effective_layer, LAYER_HEIGHT_STEP, TUNNEL_LIGHT_SPACING,
+};
+use crate::element_processing::railways::builds_track;
+use crate::osm_parser::{ProcessedElement, ProcessedNode};
+use crate::world_editor::WorldEditor;
+use std::collections::HashMap;
+
+/// Underground track this close to a subway station runs through its hall
+const STATION_RADIUS: i32 = 20;
+/// Entrances further from any underground track than this get no stairwell
+const ENTRANCE_REACH: i32 = 64;
+
+/// Positions of subway stations, mapped as nodes or areas
+pub fn subway_stations(elements: &[ProcessedElement]) -> Vec<(i32, i32)> {
+    elements
+        .iter()
+        .filter(|element| is_subway_station(element.tags()))
+        .filter_map(ProcessedElement::center)
+        .map(|center| (center.x, center.z))
+        .collect()
+}
+
+fn is_subway_station(tags: &HashMap<String, String>) -> bool {
+    let is = |key: &str, value: &str| tags.get(key).is_some_and(|v| v == value);
+    (is("railway", "station") || is("public_transport", "station"))
+        && (is("station", "subway") || is("subway", "yes"))
+}
+
+pub fn near_station(stations: &[(i32, i32)], x: i32, z: i32) -> bool {
+    stations.iter().any(|&(station_x, station_z)| {
+        let (dx, dz) = (x - station_x, z - station_z);
+        dx * dx + dz * dz <= STATION_RADIUS * STATION_RADIUS
+    })
+}
+
+/// Platforms on both sides of underground track at `y` in a station hall, with a safety line
+/// along their edges
+pub fn build_platforms(editor: &mut WorldEditor, (x, z): (i32, i32), y: i32, horizontal: bool) {
+    for side in [-1, 1] {
+        for offset in 2..=3 {
+            let (cell_x, cell_z) = if horizontal {
+                (x, z + side * offset)
+            } else {
+                (x + side * offset, z)
+            };
+            let surface = if offset == 2 {
+                YELLOW_CONCRETE
+            } else {
+                SMOOTH_STONE
+            };
+            editor.set_block(STONE_BRICKS, cell_x, y, cell_z, None, None);
+            editor.set_block(surface, cell_x, y + 1, cell_z, Some(&[CAVE_AIR]), None);
+        }
+    }
+}
+
+/// A stairwell from a subway entrance down to the nearest underground track
+pub fn generate_subway_entrance(
+    editor: &mut WorldEditor,
+    node: &ProcessedNode,
+    elements: &[ProcessedElement],
+) {
+    let Some(((track_x, track_z), track_y)) = nearest_underground_track(node, elements) else {
+        return;
+    };
+
+    // The stairwell ends level with the platforms of a station hall, one block above the bed
+    let bottom = track_y + 1;
+    let (dx, dz) = (node.x - track_x, node.z - track_z);
+    let length = dx.abs().max(dz.abs()).max(1);
+    // The stairs start further out where the track is too close to get down to it
+    let needed = 1 - bottom + 4;
+    let stretch = (needed as f64 / length as f64).max(1.0);
+    let (start_x, start_z) = (
+        track_x + (dx as f64 * stretch).round() as i32,
+        track_z + (dz as f64 * stretch).round() as i32,
+    );
+
+    let horizontal = dx.abs() >= dz.abs();
+    // Stairs face up towards the entrance
+    let facing = match (horizontal, dx > 0, dz > 0) {
+        (true, true, _) => StairFacing::East,
+        (true, false, _) => StairFacing::West,
+        (false, _, true) => StairFacing::South,
+        (false, _, false) => StairFacing::North,
+    };
+    let stairs = create_stair_with_properties(STONE_BRICK_STAIRS, facing, StairShape::Straight);
+
+    let path = bresenham_line(start_x, 0, start_z, track_x, 0, track_z);
+    for (i, &(x, _, z)) in path.iter().enumerate().skip(1) {
+        // The stairwell opens into the tunnel beside the track
+        if (x - track_x).abs().max((z - track_z).abs()) <= 1 {
+            break;
+        }
+        // One step down per block, from the ground at the entrance to the bottom
+        let step = 1 - i as i32;
+        let y = step.max(bottom);
+        build_tunnel_section(
+            editor,
+            (x, z),
+            y,
+            1,
+            horizontal,
+            i % TUNNEL_LIGHT_SPACING == 0,
+            &[],
+        );
+        if step >= bottom {
+            let absolute_y = editor.get_absolute_y(x, y, z);
+            editor.set_block_with_properties_absolute(
+                stairs.clone(),
+                x,
+                absolute_y,
+                z,
+                Some(&[CAVE_AIR]),
+                None,
+            );
+        } else {
+            editor.set_block(STONE_BRICKS, x, y, z, Some(&[CAVE_AIR]), None);
+        }
+    }
+}
+
+/// The point of underground track closest to `node` within reach, with the height of its
+/// bed below the ground
+fn nearest_underground_track(
+    node: &ProcessedNode,
+    elements: &[ProcessedElement],
+) -> Option<((i32, i32), i32)> {
+    let mut nearest: Option<((i32, i32), i32, i32)> = None;
+    for element in elements {
+        let ProcessedElement::Way(way) = element else {
+            continue;
+        };
+        let layer = effective_layer(&way.tags);
+        if layer >= 0 || !builds_track(&way.tags) {
+            continue;
+        }
+        for pair in way.nodes.windows(2) {
+            for (x, _, z) in bresenham_line(pair[0].x, 0, pair[0].z, pair[1].x, 0, pair[1].z) {
+                // Euclidean, so the stairs lead straight to the track, squared to stay exact
+                let distance = (x - node.x).pow(2) + (z - node.z).pow(2);
+                let closer = match nearest {
+                    Some((_, _, best)) => distance < best,
+                    None => distance <= ENTRANCE_REACH.pow(2),
+                };
+                if closer {
+                    nearest = Some(((x, z), layer * LAYER_HEIGHT_STEP, distance));
+                }
+            }
+        }
+    }
+    nearest.map(|(point, y, _)| (point, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm_parser::ProcessedWay;
+
+    fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn node(x: i32, z: i32, pairs: &[(&str, &str)]) -> ProcessedNode {
+        ProcessedNode {
+            id: 0,
+            tags: tags(pairs),
+            x,
+            z,
+        }
+    }
+
+    #[test]
+    fn test_subway_stations() {
+        let elements = vec![
+            ProcessedElement::Node(node(
+                10,
+                20,
+                &[("railway", "station"), ("station", "subway")],
+            )),
+            ProcessedElement::Node(node(50, 50, &[("railway", "station")])),
+        ];
+        let stations = subway_stations(&elements);
+        assert_eq!(stations, vec![(10, 20)]);
+        assert!(near_station(&stations, 20, 30));
+        assert!(!near_station(&stations, 40, 20));
+    }
+
+    #[test]
+    fn test_nearest_underground_track() {
+        let track = |layer: &str, z: i32| {
+            ProcessedElement::Way(ProcessedWay {
+                id: 0,
+                nodes: vec![node(0, z, &[]), node(100, z, &[])],
+                tags: tags(&[("railway", "subway"), ("tunnel", "yes"), ("layer", layer)]),
+            })
+        };
+        let elements = vec![track("-1", 30), track("-2", 50)];
+
+        let entrance = node(40, 45, &[("railway", "subway_entrance")]);
+        assert_eq!(
+            nearest_underground_track(&entrance, &elements),
+            Some(((40, 50), -2 * LAYER_HEIGHT_STEP))
+        );
+        let far = node(40, 200, &[("railway", "subway_entrance")]);
+        assert_eq!(nearest_underground_track(&far, &elements), None);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::block_definitions::*;
+use crate::seeded_rng;
 use crate::world_editor::WorldEditor;
 use rand::Rng;
 
@@ -115,7 +116,7 @@ impl Tree<'_> {
         blacklist.extend(Self::get_functional_blocks());
         blacklist.push(WATER);
 
-        let mut rng = rand::thread_rng();
+        let mut rng = seeded_rng::rng();
 
         let tree = Self::get_tree(match rng.gen_range(1..=3) {
             1 => TreeType::Oak,
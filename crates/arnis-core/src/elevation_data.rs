@@ -1,14 +1,25 @@
+use crate::api_scheduler;
 use crate::coordinate_system::{geographic::LLBBox, transformation::geo_distance};
-use image::Rgb;
-use std::path::Path;
+use crate::disk_io;
+use crate::elevation_provider::ElevationProvider;
+use fnv::{FnvHashMap, FnvHasher};
+use image::{Rgb, RgbImage};
+use reqwest::header::{HeaderMap, HeaderValue, RANGE};
+use reqwest::StatusCode;
+use std::fs::{File, OpenOptions};
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
 /// Maximum Y coordinate in Minecraft (build height limit)
 const MAX_Y: i32 = 319;
 /// Scale factor for converting real elevation to Minecraft heights
 const BASE_HEIGHT_SCALE: f64 = 0.7;
-/// AWS S3 Terrarium tiles endpoint (no API key required)
-const AWS_TERRARIUM_URL: &str =
-    "https://s3.amazonaws.com/elevation-tiles-prod/terrarium/{z}/{x}/{y}.png";
+/// AWS S3 Terrarium tiles endpoints (no API key required), tried in this order
+const TERRARIUM_MIRRORS: [&str; 2] = [
+    "https://s3.amazonaws.com/elevation-tiles-prod/terrarium/{z}/{x}/{y}.png",
+    "https://elevation-tiles-prod.s3.amazonaws.com/terrarium/{z}/{x}/{y}.png",
+];
 /// Terrarium format offset for height decoding
 const TERRARIUM_OFFSET: f64 = 32768.0;
 /// Minimum zoom level for terrain tiles
@@ -44,175 +55,356 @@ fn lat_lng_to_tile(lat: f64, lng: f64, zoom: u8) -> (u32, u32) {
     (x, y)
 }
 
-/// Downloads a tile from AWS Terrain Tiles service
-fn download_tile(
+/// Loads a tile from the cache, or downloads it from the first mirror that has it
+fn load_tile(
     client: &reqwest::blocking::Client,
     tile_x: u32,
     tile_y: u32,
     zoom: u8,
+    cache_dir: &Path,
+) -> Result<RgbImage, String> {
+    let tile_path = cache_dir.join(format!("z{zoom}_x{tile_x}_y{tile_y}.png"));
+    if let Some(img) = load_cached_tile(&tile_path) {
+        println!(
+            "Loading cached tile x={tile_x},y={tile_y},z={zoom} from {}",
+            tile_path.display()
+        );
+        return Ok(img);
+    }
+
+    for mirror in TERRARIUM_MIRRORS {
+        let url: String = mirror
+            .replace("{z}", &zoom.to_string())
+            .replace("{x}", &tile_x.to_string())
+            .replace("{y}", &tile_y.to_string());
+        println!("Fetching tile x={tile_x},y={tile_y},z={zoom} from {url}");
+
+        match download_tile(client, &url, &tile_path) {
+            Ok(img) => return Ok(img),
+            Err(e) => eprintln!("Warning: Failed to fetch tile from {url}: {e}"),
+        }
+    }
+    Err(format!(
+        "Tile x={tile_x},y={tile_y},z={zoom} is not available from any mirror"
+    ))
+}
+
+/// A cached tile, unless it is missing or fails verification
+fn load_cached_tile(tile_path: &Path) -> Option<RgbImage> {
+    let bytes = std::fs::read(tile_path).ok()?;
+    let checksum_path = checksum_path(tile_path);
+
+    let verified = match std::fs::read_to_string(&checksum_path) {
+        Ok(expected) => expected.trim() == checksum(&bytes),
+        // Tiles cached before checksums were stored only need to decode
+        Err(_) => true,
+    };
+    if verified {
+        if let Ok(img) = image::load_from_memory(&bytes) {
+            if !checksum_path.exists() {
+                let _ = std::fs::write(&checksum_path, checksum(&bytes));
+            }
+            return Some(img.to_rgb8());
+        }
+    }
+
+    eprintln!(
+        "Warning: Cached tile at {} is corrupted. Re-downloading...",
+        tile_path.display()
+    );
+    let _ = std::fs::remove_file(tile_path);
+    let _ = std::fs::remove_file(&checksum_path);
+    None
+}
+
+/// Downloads a tile into the cache. Interrupted downloads are kept as `.part` file and
+/// resumed by the next attempt, also when it goes to another mirror.
+fn download_tile(
+    client: &reqwest::blocking::Client,
+    url: &str,
     tile_path: &Path,
-) -> Result<image::ImageBuffer<Rgb<u8>, Vec<u8>>, Box<dyn std::error::Error>> {
-    println!("Fetching tile x={tile_x},y={tile_y},z={zoom} from AWS Terrain Tiles");
-    let url: String = AWS_TERRARIUM_URL
-        .replace("{z}", &zoom.to_string())
-        .replace("{x}", &tile_x.to_string())
-        .replace("{y}", &tile_y.to_string());
-
-    let response: reqwest::blocking::Response = client.get(&url).send()?;
-    response.error_for_status_ref()?;
-    let bytes = response.bytes()?;
-    std::fs::write(tile_path, &bytes)?;
-    let img: image::DynamicImage = image::load_from_memory(&bytes)?;
-    Ok(img.to_rgb8())
+) -> Result<RgbImage, String> {
+    let part_path = tile_path.with_extension("png.part");
+    let offset = std::fs::metadata(&part_path).map_or(0, |metadata| metadata.len());
+
+    let mut headers = HeaderMap::new();
+    if offset > 0 {
+        headers.insert(
+            RANGE,
+            HeaderValue::from_str(&format!("bytes={offset}-")).unwrap(),
+        );
+    }
+    let mut response =
+        api_scheduler::get_with_headers(client, url, &[], headers).map_err(|e| e.to_string())?;
+    response.error_for_status_ref().map_err(|e| e.to_string())?;
+
+    // Servers that don't support ranges send the whole tile again
+    let resumed = response.status() == StatusCode::PARTIAL_CONTENT;
+    let expected_len = response.content_length();
+    let mut file = if resumed {
+        OpenOptions::new().append(true).open(&part_path)
+    } else {
+        File::create(&part_path)
+    }
+    .map_err(|e| format!("Failed to open {}: {e}", part_path.display()))?;
+
+    let written = response.copy_to(&mut file).map_err(|e| e.to_string())?;
+    drop(file);
+    if expected_len.is_some_and(|expected| expected != written) {
+        return Err(format!(
+            "Download incomplete ({written} of {} bytes)",
+            expected_len.unwrap_or_default()
+        ));
+    }
+
+    let bytes = std::fs::read(&part_path).map_err(|e| e.to_string())?;
+    let img = match image::load_from_memory(&bytes) {
+        Ok(img) => img.to_rgb8(),
+        Err(e) => {
+            // A broken download can't be resumed, start over next time
+            let _ = std::fs::remove_file(&part_path);
+            return Err(format!("Invalid tile: {e}"));
+        }
+    };
+
+    std::fs::write(checksum_path(tile_path), checksum(&bytes)).map_err(|e| e.to_string())?;
+    std::fs::rename(&part_path, tile_path).map_err(|e| e.to_string())?;
+    Ok(img)
+}
+
+fn checksum_path(tile_path: &Path) -> PathBuf {
+    tile_path.with_extension("png.fnv")
+}
+
+fn checksum(bytes: &[u8]) -> String {
+    let mut hasher = FnvHasher::default();
+    hasher.write(bytes);
+    format!("{:016x}", hasher.finish())
 }
 
+/// Elevation data from the AWS terrain tiles
 pub fn fetch_elevation_data(
     bbox: &LLBBox,
     scale: f64,
     ground_level: i32,
 ) -> Result<ElevationData, Box<dyn std::error::Error>> {
-    let (base_scale_z, base_scale_x) = geo_distance(bbox.min(), bbox.max());
-
-    // Apply same floor() and scale operations as CoordTransformer.llbbox_to_xzbbox()
-    let scale_factor_z: f64 = base_scale_z.floor() * scale;
-    let scale_factor_x: f64 = base_scale_x.floor() * scale;
+    fetch_elevation_data_from(
+        &TerrariumTiles::default(),
+        bbox,
+        scale,
+        ground_level,
+        TerrainShape::default(),
+    )
+}
 
-    // Calculate zoom and tiles
-    let zoom: u8 = calculate_zoom_level(bbox);
-    let tiles: Vec<(u32, u32)> = get_tile_coordinates(bbox, zoom);
+/// How the real elevation is shaped into terrain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainShape {
+    /// Factor on the height differences, 1 for the natural relief
+    pub exaggeration: f64,
+    /// Blur radius in blocks, 0 for none, `None` to choose one from the size of the area
+    pub smoothing: Option<f64>,
+}
 
-    // Match grid dimensions with Minecraft world size
-    let grid_width: usize = scale_factor_x as usize;
-    let grid_height: usize = scale_factor_z as usize;
+impl Default for TerrainShape {
+    fn default() -> Self {
+        TerrainShape {
+            exaggeration: 1.0,
+            smoothing: None,
+        }
+    }
+}
 
-    // Initialize height grid with proper dimensions
-    let mut height_grid: Vec<Vec<f64>> = vec![vec![f64::NAN; grid_width]; grid_height];
-    let mut extreme_values_found = Vec::new(); // Track extreme values for debugging
+/// The AWS terrain tiles in Terrarium format, the default elevation provider. Tiles used by
+/// `get_height` stay in memory, so sampling many points nearby only loads each tile once.
+#[derive(Default)]
+pub struct TerrariumTiles {
+    client: reqwest::blocking::Client,
+    /// Decoded tiles at `MAX_ZOOM`, `None` for tiles that could not be loaded
+    tiles: Mutex<FnvHashMap<(u32, u32), Option<RgbImage>>>,
+}
 
-    let client: reqwest::blocking::Client = reqwest::blocking::Client::new();
+impl TerrariumTiles {
+    /// Height of a pixel at `MAX_ZOOM`, counted from the north-western corner of the world
+    fn pixel_height(&self, x: u32, y: u32) -> Option<f64> {
+        let tile = (x / 256, y / 256);
+        let mut tiles = self.tiles.lock().unwrap();
+        let image = tiles.entry(tile).or_insert_with(|| {
+            let cache_dir = tile_cache_dir().ok()?;
+            load_tile(&self.client, tile.0, tile.1, MAX_ZOOM, &cache_dir)
+                .inspect_err(|e| eprintln!("Warning: {e}"))
+                .ok()
+        });
+        image
+            .as_ref()
+            .map(|image| decode_terrarium(image.get_pixel(x % 256, y % 256)))
+    }
+}
 
-    let tile_cache_dir = Path::new("./arnis-tile-cache");
-    if !tile_cache_dir.exists() {
-        std::fs::create_dir_all(tile_cache_dir)?;
+impl ElevationProvider for TerrariumTiles {
+    fn name(&self) -> &str {
+        "AWS Terrain Tiles"
     }
 
-    // Fetch and process each tile
-    for (tile_x, tile_y) in &tiles {
-        // Check if tile is already cached
-        let tile_path = tile_cache_dir.join(format!("z{zoom}_x{tile_x}_y{tile_y}.png"));
+    /// Interpolated between the four nearest pixels
+    fn get_height(&self, lat: f64, lng: f64) -> Option<f64> {
+        let size = 2.0_f64.powi(MAX_ZOOM as i32) * 256.0;
+        let lat_rad: f64 = lat.to_radians();
+        // Pixel centers are at half coordinates
+        let fx = ((lng + 180.0) / 360.0 * size - 0.5).clamp(0.0, size - 1.0);
+        let fy = ((1.0 - lat_rad.tan().asinh() / std::f64::consts::PI) / 2.0 * size - 0.5)
+            .clamp(0.0, size - 1.0);
+
+        let (x, y) = (fx.floor() as u32, fy.floor() as u32);
+        let (tx, ty) = (fx - fx.floor(), fy - fy.floor());
+        let max = size as u32 - 1;
+        let (x1, y1) = ((x + 1).min(max), (y + 1).min(max));
+
+        let top = self.pixel_height(x, y)? * (1.0 - tx) + self.pixel_height(x1, y)? * tx;
+        let bottom = self.pixel_height(x, y1)? * (1.0 - tx) + self.pixel_height(x1, y1)? * tx;
+        Some(top * (1.0 - ty) + bottom * ty)
+    }
 
-        let rgb_img: image::ImageBuffer<Rgb<u8>, Vec<u8>> = if tile_path.exists() {
-            // Check if the cached file has a reasonable size (PNG files should be at least a few KB)
-            let file_size = match std::fs::metadata(&tile_path) {
-                Ok(metadata) => metadata.len(),
-                Err(_) => 0,
+    fn get_tile(
+        &self,
+        bbox: &LLBBox,
+        grid_width: usize,
+        grid_height: usize,
+    ) -> Result<Vec<Vec<f64>>, String> {
+        // Calculate zoom and tiles
+        let zoom: u8 = calculate_zoom_level(bbox);
+        let tiles: Vec<(u32, u32)> = get_tile_coordinates(bbox, zoom);
+
+        // Initialize height grid with proper dimensions
+        let mut height_grid: Vec<Vec<f64>> = vec![vec![f64::NAN; grid_width]; grid_height];
+        let mut extreme_values_found = Vec::new(); // Track extreme values for debugging
+
+        let tile_cache_dir = tile_cache_dir()?;
+
+        // Fetch and process each tile. Missing tiles are filled in from their neighbors below.
+        let mut failed_tiles: usize = 0;
+        for (tile_x, tile_y) in &tiles {
+            let rgb_img = match load_tile(&self.client, *tile_x, *tile_y, zoom, &tile_cache_dir) {
+                Ok(img) => img,
+                Err(e) => {
+                    eprintln!("Warning: {e}");
+                    failed_tiles += 1;
+                    continue;
+                }
             };
 
-            if file_size < 1000 {
-                eprintln!("Warning: Cached tile at {} appears to be too small ({} bytes). Refetching tile.",
-                         tile_path.display(), file_size);
+            // Only process pixels that fall within the requested bbox
+            for (y, row) in rgb_img.rows().enumerate() {
+                for (x, pixel) in row.enumerate() {
+                    // Convert tile pixel coordinates back to geographic coordinates
+                    let pixel_lng =
+                        ((*tile_x as f64 + x as f64 / 256.0) / (2.0_f64.powi(zoom as i32))) * 360.0
+                            - 180.0;
+                    let pixel_lat_rad = std::f64::consts::PI
+                        * (1.0
+                            - 2.0 * (*tile_y as f64 + y as f64 / 256.0)
+                                / (2.0_f64.powi(zoom as i32)));
+                    let pixel_lat = pixel_lat_rad.sinh().atan().to_degrees();
+
+                    // Skip pixels outside the requested bounding box
+                    if pixel_lat < bbox.min().lat()
+                        || pixel_lat > bbox.max().lat()
+                        || pixel_lng < bbox.min().lng()
+                        || pixel_lng > bbox.max().lng()
+                    {
+                        continue;
+                    }
 
-                // Remove the potentially corrupted file
-                if let Err(remove_err) = std::fs::remove_file(&tile_path) {
-                    eprintln!(
-                        "Warning: Failed to remove corrupted tile file: {}",
-                        remove_err
-                    );
-                }
+                    // Map geographic coordinates to grid coordinates
+                    let rel_x =
+                        (pixel_lng - bbox.min().lng()) / (bbox.max().lng() - bbox.min().lng());
+                    let rel_y = 1.0
+                        - (pixel_lat - bbox.min().lat()) / (bbox.max().lat() - bbox.min().lat());
 
-                // Re-download the tile
-                download_tile(&client, *tile_x, *tile_y, zoom, &tile_path)?
-            } else {
-                println!(
-                    "Loading cached tile x={tile_x},y={tile_y},z={zoom} from {}",
-                    tile_path.display()
-                );
-
-                // Try to load cached tile, but handle corruption gracefully
-                match image::open(&tile_path) {
-                    Ok(img) => img.to_rgb8(),
-                    Err(e) => {
-                        eprintln!("Warning: Cached tile at {} is corrupted or invalid: {}. Re-downloading...", tile_path.display(), e);
-
-                        // Remove the corrupted file
-                        if let Err(remove_err) = std::fs::remove_file(&tile_path) {
-                            eprintln!(
-                                "Warning: Failed to remove corrupted tile file: {}",
-                                remove_err
-                            );
-                        }
+                    let scaled_x = (rel_x * grid_width as f64).round() as usize;
+                    let scaled_y = (rel_y * grid_height as f64).round() as usize;
 
-                        // Re-download the tile
-                        download_tile(&client, *tile_x, *tile_y, zoom, &tile_path)?
+                    if scaled_y >= grid_height || scaled_x >= grid_width {
+                        continue;
                     }
-                }
-            }
-        } else {
-            // Download the tile for the first time
-            download_tile(&client, *tile_x, *tile_y, zoom, &tile_path)?
-        };
 
-        // Only process pixels that fall within the requested bbox
-        for (y, row) in rgb_img.rows().enumerate() {
-            for (x, pixel) in row.enumerate() {
-                // Convert tile pixel coordinates back to geographic coordinates
-                let pixel_lng = ((*tile_x as f64 + x as f64 / 256.0) / (2.0_f64.powi(zoom as i32)))
-                    * 360.0
-                    - 180.0;
-                let pixel_lat_rad = std::f64::consts::PI
-                    * (1.0
-                        - 2.0 * (*tile_y as f64 + y as f64 / 256.0) / (2.0_f64.powi(zoom as i32)));
-                let pixel_lat = pixel_lat_rad.sinh().atan().to_degrees();
-
-                // Skip pixels outside the requested bounding box
-                if pixel_lat < bbox.min().lat()
-                    || pixel_lat > bbox.max().lat()
-                    || pixel_lng < bbox.min().lng()
-                    || pixel_lng > bbox.max().lng()
-                {
-                    continue;
-                }
-
-                // Map geographic coordinates to grid coordinates
-                let rel_x = (pixel_lng - bbox.min().lng()) / (bbox.max().lng() - bbox.min().lng());
-                let rel_y =
-                    1.0 - (pixel_lat - bbox.min().lat()) / (bbox.max().lat() - bbox.min().lat());
+                    let height: f64 = decode_terrarium(pixel);
 
-                let scaled_x = (rel_x * grid_width as f64).round() as usize;
-                let scaled_y = (rel_y * grid_height as f64).round() as usize;
+                    // Track extreme values for debugging
+                    if !(-1000.0..=10000.0).contains(&height) {
+                        extreme_values_found
+                            .push((tile_x, tile_y, x, y, pixel[0], pixel[1], pixel[2], height));
+                        if extreme_values_found.len() <= 5 {
+                            // Only log first 5 extreme values
+                            eprintln!("Extreme value found: tile({tile_x},{tile_y}) pixel({x},{y}) RGB({},{},{}) = {height}m", 
+                                     pixel[0], pixel[1], pixel[2]);
+                        }
+                    }
 
-                if scaled_y >= grid_height || scaled_x >= grid_width {
-                    continue;
+                    height_grid[scaled_y][scaled_x] = height;
                 }
+            }
+        }
 
-                // Decode Terrarium format: (R * 256 + G + B/256) - 32768
-                let height: f64 =
-                    (pixel[0] as f64 * 256.0 + pixel[1] as f64 + pixel[2] as f64 / 256.0)
-                        - TERRARIUM_OFFSET;
-
-                // Track extreme values for debugging
-                if !(-1000.0..=10000.0).contains(&height) {
-                    extreme_values_found
-                        .push((tile_x, tile_y, x, y, pixel[0], pixel[1], pixel[2], height));
-                    if extreme_values_found.len() <= 5 {
-                        // Only log first 5 extreme values
-                        eprintln!("Extreme value found: tile({tile_x},{tile_y}) pixel({x},{y}) RGB({},{},{}) = {height}m", 
-                                 pixel[0], pixel[1], pixel[2]);
-                    }
-                }
+        if failed_tiles == tiles.len() {
+            return Err("No elevation tiles could be fetched".to_string());
+        }
+        if failed_tiles > 0 {
+            eprintln!(
+                "Warning: {failed_tiles} of {} elevation tiles are missing, interpolating",
+                tiles.len()
+            );
+        }
 
-                height_grid[scaled_y][scaled_x] = height;
-            }
+        // Report on extreme values found
+        if !extreme_values_found.is_empty() {
+            eprintln!(
+                "Found {} total extreme elevation values during tile processing",
+                extreme_values_found.len()
+            );
+            eprintln!("This may indicate corrupted tile data or areas with invalid elevation data");
         }
+
+        Ok(height_grid)
     }
+}
 
-    // Report on extreme values found
-    if !extreme_values_found.is_empty() {
-        eprintln!(
-            "Found {} total extreme elevation values during tile processing",
-            extreme_values_found.len()
-        );
-        eprintln!("This may indicate corrupted tile data or areas with invalid elevation data");
+fn tile_cache_dir() -> Result<PathBuf, String> {
+    let tile_cache_dir = disk_io::temp_dir().join("arnis-tile-cache");
+    if !tile_cache_dir.exists() {
+        std::fs::create_dir_all(&tile_cache_dir)
+            .map_err(|e| format!("Failed to create {}: {e}", tile_cache_dir.display()))?;
+    }
+    Ok(tile_cache_dir)
+}
+
+/// Decodes the Terrarium format: (R * 256 + G + B/256) - 32768
+fn decode_terrarium(pixel: &Rgb<u8>) -> f64 {
+    (pixel[0] as f64 * 256.0 + pixel[1] as f64 + pixel[2] as f64 / 256.0) - TERRARIUM_OFFSET
+}
+
+/// Elevation data from any provider, smoothed and scaled to Minecraft heights
+pub fn fetch_elevation_data_from(
+    provider: &dyn ElevationProvider,
+    bbox: &LLBBox,
+    scale: f64,
+    ground_level: i32,
+    shape: TerrainShape,
+) -> Result<ElevationData, Box<dyn std::error::Error>> {
+    let (base_scale_z, base_scale_x) = geo_distance(bbox.min(), bbox.max());
+
+    // Apply same floor() and scale operations as CoordTransformer.llbbox_to_xzbbox()
+    let scale_factor_z: f64 = base_scale_z.floor() * scale;
+    let scale_factor_x: f64 = base_scale_x.floor() * scale;
+
+    // Match grid dimensions with Minecraft world size
+    let grid_width: usize = scale_factor_x as usize;
+    let grid_height: usize = scale_factor_z as usize;
+
+    println!("Fetching elevation data from {}", provider.name());
+    let mut height_grid: Vec<Vec<f64>> = provider.get_tile(bbox, grid_width, grid_height)?;
+    if height_grid.iter().flatten().all(|height| height.is_nan()) {
+        return Err(format!("{} has no elevation data for this area", provider.name()).into());
     }
 
     // Fill in any NaN values by interpolating from nearest valid values
@@ -230,7 +422,9 @@ pub fn fetch_elevation_data(
 
     let grid_size: f64 = (grid_width.min(grid_height) as f64).max(1.0);
 
-    let sigma: f64 = if grid_size <= SMALL_GRID_REF {
+    let sigma: f64 = if let Some(radius) = shape.smoothing {
+        radius
+    } else if grid_size <= SMALL_GRID_REF {
         // Linear scaling for small grids
         SMALL_SIGMA_REF * (grid_size / SMALL_GRID_REF)
     } else {
@@ -248,7 +442,11 @@ pub fn fetch_elevation_data(
     ); */
 
     // Continue with the existing blur and conversion to Minecraft heights...
-    let blurred_heights: Vec<Vec<f64>> = apply_gaussian_blur(&height_grid, sigma);
+    let blurred_heights: Vec<Vec<f64>> = if sigma > 0.0 {
+        apply_gaussian_blur(&height_grid, sigma)
+    } else {
+        height_grid
+    };
 
     let mut mc_heights: Vec<Vec<i32>> = Vec::with_capacity(blurred_heights.len());
 
@@ -287,7 +485,8 @@ pub fn fetch_elevation_data(
 
     let height_range: f64 = max_height - min_height;
     // Apply scale factor to height scaling
-    let mut height_scale: f64 = BASE_HEIGHT_SCALE * scale.sqrt(); // sqrt to make height scaling less extreme
+    // sqrt to make height scaling less extreme
+    let mut height_scale: f64 = BASE_HEIGHT_SCALE * scale.sqrt() * shape.exaggeration;
     let mut scaled_range: f64 = height_range * height_scale;
 
     // Adaptive scaling: ensure we don't exceed reasonable Y range
@@ -311,7 +510,11 @@ pub fn fetch_elevation_data(
             .iter()
             .map(|&h| {
                 // Scale the height differences
-                let relative_height: f64 = (h - min_height) / height_range;
+                let relative_height: f64 = if height_range > 0.0 {
+                    (h - min_height) / height_range
+                } else {
+                    0.0
+                };
                 let scaled_height: f64 = relative_height * scaled_range;
                 // With terrain enabled, ground_level is used as the MIN_Y for terrain
                 ((ground_level as f64 + scaled_height).round() as i32).clamp(ground_level, MAX_Y)
@@ -513,6 +716,103 @@ fn filter_elevation_outliers(height_grid: &mut [Vec<f64>]) {
 mod tests {
     use super::*;
 
+    /// Terrarium color of a height
+    fn encode_terrarium(height: f64) -> Rgb<u8> {
+        let value = height + TERRARIUM_OFFSET;
+        Rgb([
+            (value / 256.0) as u8,
+            (value % 256.0) as u8,
+            ((value.fract()) * 256.0) as u8,
+        ])
+    }
+
+    /// A 100 m high wall, about 15 m thick, running north to south
+    struct Wall;
+
+    impl ElevationProvider for Wall {
+        fn name(&self) -> &str {
+            "wall"
+        }
+
+        fn get_height(&self, _lat: f64, lng: f64) -> Option<f64> {
+            Some(if (lng - 9.928).abs() < 0.0001 {
+                100.0
+            } else {
+                0.0
+            })
+        }
+    }
+
+    #[test]
+    fn test_terrain_shape() {
+        let bbox = LLBBox::new(54.627, 9.927, 54.628, 9.929).unwrap();
+        let peak = |exaggeration: f64, smoothing: Option<f64>| {
+            let shape = TerrainShape {
+                exaggeration,
+                smoothing,
+            };
+            let data = fetch_elevation_data_from(&Wall, &bbox, 1.0, -62, shape).unwrap();
+            data.heights.iter().flatten().max().unwrap() + 62
+        };
+
+        assert_eq!(peak(1.0, Some(0.0)), 70);
+        assert_eq!(peak(2.0, Some(0.0)), 140);
+        // Smoothing spreads the wall out into a lower ridge
+        assert!(peak(1.0, None) < 70);
+        assert!(peak(1.0, Some(2.0)) > peak(1.0, Some(8.0)));
+    }
+
+    #[test]
+    fn test_terrarium_get_height() {
+        let provider = TerrariumTiles::default();
+        // A tile rising by one meter per pixel towards the east
+        let tile = RgbImage::from_fn(256, 256, |x, _| encode_terrarium(100.0 + x as f64));
+        let (tile_x, tile_y) = lat_lng_to_tile(54.627, 9.927, MAX_ZOOM);
+        provider
+            .tiles
+            .lock()
+            .unwrap()
+            .insert((tile_x, tile_y), Some(tile));
+
+        // Halfway between the centers of the pixels 10 and 11 of the tile
+        let size = 2.0_f64.powi(MAX_ZOOM as i32) * 256.0;
+        let lng = (tile_x as f64 * 256.0 + 11.0) / size * 360.0 - 180.0;
+        let n = std::f64::consts::PI * (1.0 - 2.0 * (tile_y as f64 * 256.0 + 100.5) / size);
+        let lat = n.sinh().atan().to_degrees();
+
+        let height = provider.get_height(lat, lng).unwrap();
+        assert!((height - 110.5).abs() < 0.01, "got {height}");
+    }
+
+    #[test]
+    fn test_cached_tile_verification() {
+        let dir = tempfile::tempdir().unwrap();
+        let tile_path = dir.path().join("z10_x1_y2.png");
+        let mut png = std::io::Cursor::new(Vec::new());
+        RgbImage::new(4, 4)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+
+        assert!(load_cached_tile(&tile_path).is_none());
+
+        // Tiles cached without a checksum are accepted and get one
+        std::fs::write(&tile_path, &png).unwrap();
+        assert!(load_cached_tile(&tile_path).is_some());
+        assert_eq!(
+            std::fs::read_to_string(checksum_path(&tile_path)).unwrap(),
+            checksum(&png)
+        );
+
+        // A tile not matching its checksum is removed
+        let mut corrupted = png.clone();
+        *corrupted.last_mut().unwrap() ^= 0xff;
+        std::fs::write(&tile_path, &corrupted).unwrap();
+        assert!(load_cached_tile(&tile_path).is_none());
+        assert!(!tile_path.exists());
+        assert!(!checksum_path(&tile_path).exists());
+    }
+
     #[test]
     fn test_terrarium_height_decoding() {
         // Test known Terrarium RGB values
@@ -544,7 +844,7 @@ mod tests {
 
     #[test]
     fn test_aws_url_generation() {
-        let url = AWS_TERRARIUM_URL
+        let url = TERRARIUM_MIRRORS[0]
             .replace("{z}", "15")
             .replace("{x}", "17436")
             .replace("{y}", "11365");
@@ -0,0 +1,438 @@
+//! Sources of elevation data. The AWS terrain tiles are the default; SRTM, Copernicus, a
+//! local GeoTIFF (`--dem-file`) or a flat surface can be used instead, and other services
+//! can be plugged in by implementing `ElevationProvider`.
+
+use crate::api_scheduler;
+use crate::coordinate_system::geographic::LLBBox;
+use crate::disk_io;
+use crate::elevation_data::TerrariumTiles;
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use fnv::FnvHashMap;
+use reqwest::StatusCode;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::path::Path;
+use std::sync::Mutex;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+
+/// GeoTIFF tags locating the raster on the globe
+const MODEL_PIXEL_SCALE_TAG: Tag = Tag::Unknown(33550);
+const MODEL_TIEPOINT_TAG: Tag = Tag::Unknown(33922);
+/// GDAL's tag for the value of pixels without data
+const GDAL_NODATA_TAG: Tag = Tag::Unknown(42113);
+
+pub trait ElevationProvider: Send + Sync {
+    /// Shown in log output
+    fn name(&self) -> &str;
+
+    /// Height in meters above sea level, `None` where the provider has no data
+    fn get_height(&self, lat: f64, lng: f64) -> Option<f64>;
+
+    /// Heights in meters for a grid of `width` x `height` cells covering the bounding box,
+    /// with the first row at the northern edge. Cells without data are NaN.
+    fn get_tile(
+        &self,
+        bbox: &LLBBox,
+        width: usize,
+        height: usize,
+    ) -> Result<Vec<Vec<f64>>, String> {
+        let lat_step = (bbox.max().lat() - bbox.min().lat()) / height.max(1) as f64;
+        let lng_step = (bbox.max().lng() - bbox.min().lng()) / width.max(1) as f64;
+
+        Ok((0..height)
+            .map(|row| {
+                let lat = bbox.max().lat() - (row as f64 + 0.5) * lat_step;
+                (0..width)
+                    .map(|col| {
+                        let lng = bbox.min().lng() + (col as f64 + 0.5) * lng_step;
+                        self.get_height(lat, lng).unwrap_or(f64::NAN)
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+/// Elevation services to download from, unless a `--dem-file` is given
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ElevationSource {
+    /// AWS terrain tiles, combined from several models
+    #[default]
+    Terrarium,
+    /// SRTM at 1 arc-second (about 30 m), between 60°N and 56°S
+    Srtm,
+    /// Copernicus GLO-30 (30 m), worldwide
+    Copernicus,
+}
+
+impl ElevationSource {
+    pub fn provider(self) -> Box<dyn ElevationProvider> {
+        match self {
+            ElevationSource::Terrarium => Box::<TerrariumTiles>::default(),
+            ElevationSource::Srtm => Box::new(DegreeTiles::srtm()),
+            ElevationSource::Copernicus => Box::new(DegreeTiles::copernicus()),
+        }
+    }
+}
+
+/// The same height everywhere
+pub struct Flat {
+    pub height: f64,
+}
+
+impl ElevationProvider for Flat {
+    fn name(&self) -> &str {
+        "flat"
+    }
+
+    fn get_height(&self, _lat: f64, _lng: f64) -> Option<f64> {
+        Some(self.height)
+    }
+}
+
+/// A single-band elevation raster in WGS84 (longitude/latitude) coordinates
+pub struct GeoTiff {
+    name: String,
+    width: usize,
+    height: usize,
+    /// Longitude and latitude of the outer corner of the north-western pixel
+    origin: (f64, f64),
+    /// Size of a pixel in degrees of longitude and latitude
+    pixel_size: (f64, f64),
+    data: Vec<f32>,
+    nodata: Option<f64>,
+}
+
+impl GeoTiff {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+        Self::read(BufReader::new(file), &path.display().to_string())
+    }
+
+    fn read<R: Read + Seek>(reader: R, name: &str) -> Result<Self, String> {
+        let mut decoder =
+            Decoder::new(reader).map_err(|e| format!("Failed to read {name}: {e}"))?;
+
+        let (width, height) = decoder.dimensions().map_err(|e| e.to_string())?;
+        let scale = decoder
+            .get_tag_f64_vec(MODEL_PIXEL_SCALE_TAG)
+            .map_err(|_| format!("{name} is not georeferenced"))?;
+        let tiepoint = decoder
+            .get_tag_f64_vec(MODEL_TIEPOINT_TAG)
+            .map_err(|_| format!("{name} is not georeferenced"))?;
+        let nodata = decoder
+            .get_tag_ascii_string(GDAL_NODATA_TAG)
+            .ok()
+            .and_then(|value| value.trim_end_matches('\0').trim().parse().ok());
+
+        let (&[scale_x, scale_y, ..], &[pixel_x, pixel_y, _, lng, lat, ..]) =
+            (scale.as_slice(), tiepoint.as_slice())
+        else {
+            return Err(format!("{name} has invalid georeferencing"));
+        };
+        let origin = (lng - pixel_x * scale_x, lat + pixel_y * scale_y);
+        if !(-180.0..=180.0).contains(&origin.0) || !(-90.0..=90.0).contains(&origin.1) {
+            return Err(format!(
+                "{name} must use longitude/latitude coordinates (WGS84)"
+            ));
+        }
+
+        let data: Vec<f32> = match decoder.read_image().map_err(|e| e.to_string())? {
+            DecodingResult::U8(v) => v.into_iter().map(f32::from).collect(),
+            DecodingResult::U16(v) => v.into_iter().map(f32::from).collect(),
+            DecodingResult::U32(v) => v.into_iter().map(|v| v as f32).collect(),
+            DecodingResult::I8(v) => v.into_iter().map(f32::from).collect(),
+            DecodingResult::I16(v) => v.into_iter().map(f32::from).collect(),
+            DecodingResult::I32(v) => v.into_iter().map(|v| v as f32).collect(),
+            DecodingResult::F32(v) => v,
+            DecodingResult::F64(v) => v.into_iter().map(|v| v as f32).collect(),
+            _ => return Err(format!("{name} has an unsupported data type")),
+        };
+        if data.len() != width as usize * height as usize {
+            return Err(format!("{name} must have a single band"));
+        }
+
+        Ok(GeoTiff {
+            name: name.to_string(),
+            width: width as usize,
+            height: height as usize,
+            origin,
+            pixel_size: (scale_x, scale_y),
+            data,
+            nodata,
+        })
+    }
+
+    fn pixel(&self, col: usize, row: usize) -> Option<f64> {
+        let value = f64::from(*self.data.get(row * self.width + col)?);
+        (value.is_finite() && Some(value) != self.nodata).then_some(value)
+    }
+}
+
+impl ElevationProvider for GeoTiff {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Bilinear interpolation between the centers of the surrounding pixels,
+    /// or the nearest pixel next to missing data
+    fn get_height(&self, lat: f64, lng: f64) -> Option<f64> {
+        let x = (lng - self.origin.0) / self.pixel_size.0 - 0.5;
+        let y = (self.origin.1 - lat) / self.pixel_size.1 - 0.5;
+        if x < -0.5 || y < -0.5 || x > self.width as f64 - 0.5 || y > self.height as f64 - 0.5 {
+            return None;
+        }
+
+        let x = x.clamp(0.0, (self.width - 1) as f64);
+        let y = y.clamp(0.0, (self.height - 1) as f64);
+        let (col, row) = (x.floor() as usize, y.floor() as usize);
+        let (next_col, next_row) = (
+            (col + 1).min(self.width - 1),
+            (row + 1).min(self.height - 1),
+        );
+        let (fx, fy) = (x - col as f64, y - row as f64);
+
+        let corners = (
+            self.pixel(col, row),
+            self.pixel(next_col, row),
+            self.pixel(col, next_row),
+            self.pixel(next_col, next_row),
+        );
+        let (Some(top_left), Some(top_right), Some(bottom_left), Some(bottom_right)) = corners
+        else {
+            return self.pixel(x.round() as usize, y.round() as usize);
+        };
+
+        let top = top_left * (1.0 - fx) + top_right * fx;
+        let bottom = bottom_left * (1.0 - fx) + bottom_right * fx;
+        Some(top * (1.0 - fy) + bottom * fy)
+    }
+}
+
+/// Decodes the raster of the cell with the given south-western corner from a downloaded file
+type DecodeCell = fn(&[u8], &str, (i32, i32)) -> Result<GeoTiff, String>;
+/// Loaded cells, `None` for cells not covered, which lie entirely in the sea
+type LoadedCells = FnvHashMap<(i32, i32), Result<Option<GeoTiff>, String>>;
+
+/// An elevation model published as one file per cell of 1° x 1°, downloaded as needed and
+/// kept in the temporary directory
+pub struct DegreeTiles {
+    name: &'static str,
+    /// URL of the cell with the given south-western corner
+    url: fn(i32, i32) -> String,
+    decode: DecodeCell,
+    client: reqwest::blocking::Client,
+    cells: Mutex<LoadedCells>,
+}
+
+impl DegreeTiles {
+    /// SRTM in the HGT format, as mirrored with the AWS terrain tiles
+    pub fn srtm() -> Self {
+        Self::new(
+            "SRTM",
+            |lat, lng| {
+                let (lat, lng) = cell_name(lat, lng);
+                format!(
+                    "https://s3.amazonaws.com/elevation-tiles-prod/skadi/{lat}/{lat}{lng}.hgt.gz"
+                )
+            },
+            decode_hgt,
+        )
+    }
+
+    /// Copernicus GLO-30 as cloud-optimized GeoTIFFs on AWS
+    pub fn copernicus() -> Self {
+        Self::new(
+            "Copernicus GLO-30",
+            |lat, lng| {
+                let (lat, lng) = cell_name(lat, lng);
+                let file = format!("Copernicus_DSM_COG_10_{lat}_00_{lng}_00_DEM");
+                format!("https://copernicus-dem-30m.s3.amazonaws.com/{file}/{file}.tif")
+            },
+            |bytes, name, _| GeoTiff::read(Cursor::new(bytes), name),
+        )
+    }
+
+    fn new(name: &'static str, url: fn(i32, i32) -> String, decode: DecodeCell) -> Self {
+        Self {
+            name,
+            url,
+            decode,
+            client: reqwest::blocking::Client::new(),
+            cells: Mutex::new(FnvHashMap::default()),
+        }
+    }
+
+    fn load(&self, cell: (i32, i32)) -> Result<Option<GeoTiff>, String> {
+        let url = (self.url)(cell.0, cell.1);
+        let file_name = url.rsplit('/').next().unwrap_or_default();
+        let cache_dir = disk_io::temp_dir().join("arnis-dem-cache");
+        let cache_path = cache_dir.join(file_name);
+
+        let bytes = match std::fs::read(&cache_path) {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                println!("Fetching {} cell from {url}", self.name);
+                let response = api_scheduler::get(&self.client, &url, &[])
+                    .map_err(|e| format!("Failed to fetch {url}: {e}"))?;
+                // Cells without land are left out of the datasets
+                if matches!(
+                    response.status(),
+                    StatusCode::NOT_FOUND | StatusCode::FORBIDDEN
+                ) {
+                    return Ok(None);
+                }
+                let bytes = response
+                    .error_for_status()
+                    .and_then(|response| response.bytes())
+                    .map_err(|e| format!("Failed to fetch {url}: {e}"))?
+                    .to_vec();
+                std::fs::create_dir_all(&cache_dir)
+                    .and_then(|()| std::fs::write(&cache_path, &bytes))
+                    .map_err(|e| format!("Failed to write {}: {e}", cache_path.display()))?;
+                bytes
+            }
+        };
+
+        (self.decode)(&bytes, file_name, cell)
+            .map(Some)
+            .inspect_err(|_| {
+                // Don't keep a broken download
+                let _ = std::fs::remove_file(&cache_path);
+            })
+    }
+}
+
+impl ElevationProvider for DegreeTiles {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn get_height(&self, lat: f64, lng: f64) -> Option<f64> {
+        let cell = (lat.floor() as i32, lng.floor() as i32);
+        let mut cells = self.cells.lock().unwrap();
+        let raster = cells
+            .entry(cell)
+            .or_insert_with(|| self.load(cell).inspect_err(|e| eprintln!("Warning: {e}")));
+        match raster {
+            Ok(Some(raster)) => raster.get_height(lat, lng),
+            Ok(None) => Some(0.0),
+            Err(_) => None,
+        }
+    }
+}
+
+/// Latitude and longitude of a cell's south-western corner as in file names, e.g. N54 E009
+fn cell_name(lat: i32, lng: i32) -> (String, String) {
+    (
+        format!("{}{:02}", if lat < 0 { 'S' } else { 'N' }, lat.abs()),
+        format!("{}{:03}", if lng < 0 { 'W' } else { 'E' }, lng.abs()),
+    )
+}
+
+/// A gzipped SRTM HGT file: a square of big-endian 16-bit heights, whose outer rows and
+/// columns lie on the edges of the cell
+fn decode_hgt(bytes: &[u8], name: &str, cell: (i32, i32)) -> Result<GeoTiff, String> {
+    let mut raw = Vec::new();
+    GzDecoder::new(bytes)
+        .read_to_end(&mut raw)
+        .map_err(|e| format!("Failed to unpack {name}: {e}"))?;
+
+    let size = ((raw.len() / 2) as f64).sqrt() as usize;
+    if size < 2 || size * size * 2 != raw.len() {
+        return Err(format!("{name} is not a HGT file"));
+    }
+    let step = 1.0 / (size - 1) as f64;
+
+    Ok(GeoTiff {
+        name: name.to_string(),
+        width: size,
+        height: size,
+        origin: (
+            f64::from(cell.1) - step / 2.0,
+            f64::from(cell.0 + 1) + step / 2.0,
+        ),
+        pixel_size: (step, step),
+        data: raw
+            .chunks_exact(2)
+            .map(|pair| f32::from(i16::from_be_bytes([pair[0], pair[1]])))
+            .collect(),
+        nodata: Some(f64::from(i16::MIN)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raster() -> GeoTiff {
+        // 3x2 pixels of 0.1 degrees, starting at 10°E 50°N
+        GeoTiff {
+            name: "test".to_string(),
+            width: 3,
+            height: 2,
+            origin: (10.0, 50.0),
+            pixel_size: (0.1, 0.1),
+            data: vec![100.0, 200.0, 300.0, 400.0, 500.0, -9999.0],
+            nodata: Some(-9999.0),
+        }
+    }
+
+    fn assert_near(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("no height");
+        assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn test_geotiff_height() {
+        let raster = raster();
+        // Pixel centers
+        assert_near(raster.get_height(49.95, 10.05), 100.0);
+        assert_near(raster.get_height(49.85, 10.15), 500.0);
+        // Halfway between two pixel centers
+        assert_near(raster.get_height(49.95, 10.1), 150.0);
+        // Outside of the raster and next to missing data
+        assert_eq!(raster.get_height(51.0, 10.05), None);
+        assert_eq!(raster.get_height(49.85, 10.25), None);
+    }
+
+    #[test]
+    fn test_get_tile() {
+        let bbox = LLBBox::new(49.8, 10.0, 50.0, 10.2).unwrap();
+        let grid = raster().get_tile(&bbox, 2, 2).unwrap();
+        let expected = [[100.0, 200.0], [400.0, 500.0]];
+        for (row, expected_row) in grid.iter().zip(expected) {
+            for (height, expected) in row.iter().zip(expected_row) {
+                assert_near(Some(*height), expected);
+            }
+        }
+
+        let grid = Flat { height: 12.0 }.get_tile(&bbox, 3, 1).unwrap();
+        assert_eq!(grid, vec![vec![12.0; 3]]);
+    }
+
+    #[test]
+    fn test_decode_hgt() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        // 3x3 samples covering the cell 54°N 9°E, rising towards the east
+        let heights: [i16; 9] = [0, 10, 20, 0, 10, 20, 0, 10, i16::MIN];
+        let raw: Vec<u8> = heights.iter().flat_map(|h| h.to_be_bytes()).collect();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&raw).unwrap();
+        let bytes = encoder.finish().unwrap();
+
+        let raster = decode_hgt(&bytes, "N54E009.hgt.gz", (54, 9)).unwrap();
+        assert_near(raster.get_height(54.5, 9.0), 0.0);
+        assert_near(raster.get_height(55.0, 9.75), 15.0);
+        assert_eq!(raster.get_height(54.0, 10.0), None);
+        assert!(decode_hgt(&bytes[..10], "broken", (54, 9)).is_err());
+
+        assert_eq!(cell_name(54, 9), ("N54".to_string(), "E009".to_string()));
+        assert_eq!(cell_name(-1, -70), ("S01".to_string(), "W070".to_string()));
+    }
+}
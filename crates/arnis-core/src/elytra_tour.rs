@@ -0,0 +1,269 @@
+//! Elytra tours (`--elytra-tour`): a chain of glowing rings to fly through, between the
+//! most notable sights of the area or along a route from a GPX file, and a small datapack
+//! whose `/function arnis:tour/start` hands out an elytra and takes the player to the start.
+
+use crate::args::Args;
+use crate::block_definitions::GLOWSTONE;
+use crate::coordinate_system::cartesian::{XZBBox, XZPoint};
+use crate::coordinate_system::geographic::LLBBox;
+use crate::coordinate_system::transformation::CoordTransformer;
+use crate::error::ArnisError;
+use crate::gpx;
+use crate::osm_parser::ProcessedElement;
+use crate::world_editor::WorldEditor;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// Radius of the rings in blocks
+const RING_RADIUS: i32 = 5;
+/// Distance between rings along the tour
+const RING_SPACING: f64 = 64.0;
+/// Height of the rings above the ground
+const FLIGHT_ALTITUDE: i32 = 40;
+/// Sights visited by a tour without route
+const MAX_SIGHTS: usize = 12;
+/// Datapack format of Minecraft 1.21
+const PACK_FORMAT: u32 = 48;
+
+pub fn build_elytra_tour(
+    editor: &mut WorldEditor,
+    elements: &[ProcessedElement],
+    xzbbox: &XZBBox,
+    llbbox: &LLBBox,
+    args: &Args,
+) -> Result<(), ArnisError> {
+    let waypoints = match &args.elytra_route {
+        Some(path) => route_waypoints(path, llbbox, xzbbox, args.scale)?,
+        None => sight_waypoints(elements, xzbbox),
+    };
+    if waypoints.len() < 2 {
+        println!("Not enough sights or route points for an elytra tour, skipping it");
+        return Ok(());
+    }
+
+    let checkpoints = checkpoints(&waypoints, RING_SPACING);
+    let centers: Vec<(i32, i32, i32)> = checkpoints
+        .iter()
+        .map(|point| {
+            let y = editor.get_absolute_y(point.x, FLIGHT_ALTITUDE, point.z);
+            (point.x, y, point.z)
+        })
+        .collect();
+
+    for (i, &(x, y, z)) in centers.iter().enumerate() {
+        // Rings face the direction of flight
+        let (prev_x, _, prev_z) = centers[i.saturating_sub(1)];
+        let (next_x, _, next_z) = centers[(i + 1).min(centers.len() - 1)];
+        let along_x = (next_x - prev_x).abs() > (next_z - prev_z).abs();
+
+        for (across, dy) in ring_offsets(RING_RADIUS) {
+            let (bx, bz) = if along_x {
+                (x, z + across)
+            } else {
+                (x + across, z)
+            };
+            editor.set_block_absolute(GLOWSTONE, bx, y + dy, bz, None, None);
+        }
+    }
+
+    write_datapack(&args.path, &centers)?;
+    println!(
+        "Built an elytra tour with {} rings, start it with /function arnis:tour/start",
+        centers.len()
+    );
+    Ok(())
+}
+
+/// Points of a GPX route in world coordinates
+fn route_waypoints(
+    path: &Path,
+    llbbox: &LLBBox,
+    xzbbox: &XZBBox,
+    scale: f64,
+) -> Result<Vec<XZPoint>, ArnisError> {
+    let route = gpx::load_route(path).map_err(ArnisError::Parse)?;
+    let (transformer, untranslated) =
+        CoordTransformer::llbbox_to_xzbbox(llbbox, scale).map_err(ArnisError::Geometry)?;
+    // The map may have been moved by a translate operation
+    let offset_x = xzbbox.min_x() - untranslated.min_x();
+    let offset_z = xzbbox.min_z() - untranslated.min_z();
+
+    Ok(route
+        .into_iter()
+        .map(|point| transformer.transform_point(point))
+        .map(|point| XZPoint::new(point.x + offset_x, point.z + offset_z))
+        .filter(|point| xzbbox.contains(point))
+        .collect())
+}
+
+/// The most notable named sights, in the order of a tour starting at the most notable one
+fn sight_waypoints(elements: &[ProcessedElement], xzbbox: &XZBBox) -> Vec<XZPoint> {
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut sights: Vec<(usize, XZPoint)> = elements
+        .iter()
+        .filter(|element| {
+            let tags = element.tags();
+            tags.contains_key("historic")
+                || tags.get("tourism").is_some_and(|tourism| {
+                    matches!(
+                        tourism.as_str(),
+                        "attraction" | "viewpoint" | "museum" | "artwork"
+                    )
+                })
+        })
+        .filter_map(|element| {
+            let tags = element.tags();
+            let name = tags.get("name")?;
+            let center = element.center().filter(|center| xzbbox.contains(center))?;
+            if !seen.insert(name.as_str()) {
+                return None;
+            }
+            // Sights with a Wikipedia article come first, then the best mapped ones
+            let wiki = tags.contains_key("wikipedia") || tags.contains_key("wikidata");
+            Some((usize::from(wiki) * 1000 + tags.len(), center))
+        })
+        .collect();
+    sights.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+    let mut remaining: Vec<XZPoint> = sights
+        .into_iter()
+        .take(MAX_SIGHTS)
+        .map(|(_, center)| center)
+        .collect();
+    if remaining.is_empty() {
+        return remaining;
+    }
+
+    // Visit the nearest remaining sight next
+    let mut tour = vec![remaining.remove(0)];
+    while !remaining.is_empty() {
+        let last = tour[tour.len() - 1];
+        let nearest = (0..remaining.len())
+            .min_by_key(|&i| {
+                let dx = (remaining[i].x - last.x) as i64;
+                let dz = (remaining[i].z - last.z) as i64;
+                dx * dx + dz * dz
+            })
+            .unwrap();
+        tour.push(remaining.remove(nearest));
+    }
+    tour
+}
+
+/// Points every `spacing` blocks along the path, including both of its ends
+fn checkpoints(path: &[XZPoint], spacing: f64) -> Vec<XZPoint> {
+    let mut points = vec![path[0]];
+    let mut since_last = 0.0;
+
+    for pair in path.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        let dx = (to.x - from.x) as f64;
+        let dz = (to.z - from.z) as f64;
+        let length = dx.hypot(dz);
+
+        let mut along = spacing - since_last;
+        while along <= length {
+            let t = along / length;
+            points.push(XZPoint::new(
+                from.x + (dx * t).round() as i32,
+                from.z + (dz * t).round() as i32,
+            ));
+            along += spacing;
+        }
+        since_last = length - (along - spacing);
+    }
+
+    let last = path[path.len() - 1];
+    // Avoid a second ring right behind the last one
+    if since_last > spacing / 4.0 {
+        points.push(last);
+    }
+    points
+}
+
+/// Horizontal and vertical offsets of the blocks of a ring
+fn ring_offsets(radius: i32) -> Vec<(i32, i32)> {
+    let steps = 8 * radius;
+    let mut offsets: Vec<(i32, i32)> = (0..steps)
+        .map(|step| {
+            let angle = step as f64 / steps as f64 * std::f64::consts::TAU;
+            let across = (angle.cos() * radius as f64).round() as i32;
+            let dy = (angle.sin() * radius as f64).round() as i32;
+            (across, dy)
+        })
+        .collect();
+    offsets.sort_unstable();
+    offsets.dedup();
+    offsets
+}
+
+/// Writes the datapack with the function that starts the tour
+fn write_datapack(world_dir: &Path, centers: &[(i32, i32, i32)]) -> Result<(), ArnisError> {
+    let pack_dir = world_dir.join("datapacks").join("arnis_elytra_tour");
+    let function_dir = pack_dir
+        .join("data")
+        .join("arnis")
+        .join("function")
+        .join("tour");
+    fs::create_dir_all(&function_dir)
+        .map_err(|e| ArnisError::Io(format!("Failed to create {}: {e}", function_dir.display())))?;
+
+    let mcmeta = format!(
+        "{{\"pack\":{{\"pack_format\":{PACK_FORMAT},\"description\":\"Arnis elytra tour\"}}}}\n"
+    );
+    fs::write(pack_dir.join("pack.mcmeta"), mcmeta)
+        .map_err(|e| ArnisError::Io(format!("Failed to write pack.mcmeta: {e}")))?;
+
+    // Start a bit in front of and above the first ring, looking at it
+    let (x, y, z) = centers[0];
+    let (next_x, _, next_z) = centers[1];
+    let (dx, dz) = ((next_x - x).signum(), (next_z - z).signum());
+    let start = format!(
+        "give @s minecraft:elytra\n\
+         give @s minecraft:firework_rocket 64\n\
+         tp @s {} {} {} facing {x} {y} {z}\n\
+         tellraw @s {{\"text\":\"Fly through the {} glowing rings!\",\"color\":\"gold\"}}\n",
+        x - dx * 16,
+        y + 8,
+        z - dz * 16,
+        centers.len()
+    );
+    fs::write(function_dir.join("start.mcfunction"), start)
+        .map_err(|e| ArnisError::Io(format!("Failed to write the tour function: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoints() {
+        let path = [
+            XZPoint::new(0, 0),
+            XZPoint::new(100, 0),
+            XZPoint::new(100, 100),
+        ];
+        let points = checkpoints(&path, 64.0);
+        assert_eq!(
+            points,
+            vec![
+                XZPoint::new(0, 0),
+                XZPoint::new(64, 0),
+                XZPoint::new(100, 28),
+                XZPoint::new(100, 92),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ring_offsets() {
+        let offsets = ring_offsets(RING_RADIUS);
+        assert!(offsets.contains(&(RING_RADIUS, 0)));
+        assert!(offsets.contains(&(0, -RING_RADIUS)));
+        for (across, dy) in offsets {
+            let distance = ((across * across + dy * dy) as f64).sqrt();
+            assert!((distance - RING_RADIUS as f64).abs() < 1.0);
+        }
+    }
+}
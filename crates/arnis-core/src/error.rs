@@ -0,0 +1,115 @@
+//! Errors of the generation by category, so the GUI can tell the user what to do about them
+
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum ArnisError {
+    /// The data could not be downloaded
+    Network(String),
+    /// The Overpass API refused the query, being busy or the area too large
+    OverpassQuota(String),
+    /// The downloaded data could not be read
+    Parse(String),
+    /// The selected area or settings do not make up a valid world
+    Geometry(String),
+    /// Reading or writing the world failed
+    Io(String),
+    /// The area would not fit into memory
+    MemoryBudget(String),
+    /// The user cancelled the generation
+    Cancelled,
+}
+
+impl ArnisError {
+    /// Stable identifier of the category for the GUI
+    pub fn code(&self) -> &'static str {
+        match self {
+            ArnisError::Network(_) => "network",
+            ArnisError::OverpassQuota(_) => "overpass_quota",
+            ArnisError::Parse(_) => "parse",
+            ArnisError::Geometry(_) => "geometry",
+            ArnisError::Io(_) => "io",
+            ArnisError::MemoryBudget(_) => "memory_budget",
+            ArnisError::Cancelled => "cancelled",
+        }
+    }
+
+    /// What the user can do about the error
+    pub fn guidance(&self) -> &'static str {
+        match self {
+            ArnisError::Network(_) => "Could not download the map data. Check your internet connection and try again.",
+            ArnisError::OverpassQuota(_) => "The map server is busy or the area is too large. Wait a minute or select a smaller area.",
+            ArnisError::Parse(_) => "The map data could not be read. Try again or select a different area.",
+            ArnisError::Geometry(_) => "The selected area or settings are invalid. Check the selection and the settings.",
+            ArnisError::Io(_) => "Could not write the world. Make sure the world folder is writable and the disk has free space.",
+            ArnisError::MemoryBudget(_) => "Not enough memory. Select a smaller area, lower the scale or enable Low Memory mode.",
+            ArnisError::Cancelled => "Generation cancelled.",
+        }
+    }
+
+    /// The same error with `context` in front of the detail, e.g. which part failed
+    pub fn context(self, context: &str) -> Self {
+        let with_context = |detail: String| format!("{context}: {detail}");
+        match self {
+            ArnisError::Network(detail) => ArnisError::Network(with_context(detail)),
+            ArnisError::OverpassQuota(detail) => ArnisError::OverpassQuota(with_context(detail)),
+            ArnisError::Parse(detail) => ArnisError::Parse(with_context(detail)),
+            ArnisError::Geometry(detail) => ArnisError::Geometry(with_context(detail)),
+            ArnisError::Io(detail) => ArnisError::Io(with_context(detail)),
+            ArnisError::MemoryBudget(detail) => ArnisError::MemoryBudget(with_context(detail)),
+            ArnisError::Cancelled => ArnisError::Cancelled,
+        }
+    }
+}
+
+impl fmt::Display for ArnisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArnisError::Network(detail)
+            | ArnisError::OverpassQuota(detail)
+            | ArnisError::Parse(detail)
+            | ArnisError::Geometry(detail)
+            | ArnisError::Io(detail)
+            | ArnisError::MemoryBudget(detail) => f.write_str(detail),
+            ArnisError::Cancelled => f.write_str("Generation cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for ArnisError {}
+
+impl From<io::Error> for ArnisError {
+    fn from(error: io::Error) -> Self {
+        ArnisError::Io(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boxed_error_keeps_category() {
+        let boxed: Box<dyn std::error::Error> =
+            Box::new(ArnisError::OverpassQuota("Too many requests".to_string()));
+        let error = boxed.downcast_ref::<ArnisError>().unwrap();
+        assert_eq!(error.code(), "overpass_quota");
+        assert_eq!(error.to_string(), "Too many requests");
+    }
+
+    #[test]
+    fn test_from_io_error() {
+        let error = ArnisError::from(io::Error::other("disk full"));
+        assert_eq!(error.code(), "io");
+        assert_eq!(error.to_string(), "disk full");
+    }
+
+    #[test]
+    fn test_context_keeps_category() {
+        let error = ArnisError::MemoryBudget("Area too dense".to_string()).context("Part 2");
+        assert_eq!(error.code(), "memory_budget");
+        assert_eq!(error.to_string(), "Part 2: Area too dense");
+        assert_eq!(ArnisError::Cancelled.context("Part 2").code(), "cancelled");
+    }
+}
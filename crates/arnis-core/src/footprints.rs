@@ -0,0 +1,262 @@
+//! Open building footprints (`--footprints`), e.g. the Microsoft or Google datasets as
+//! GeoJSON, to fill in buildings where OSM coverage is sparse. Footprints touching an OSM
+//! building, or a footprint added before them, are left out, so mapped buildings always win.
+
+use crate::coordinate_system::cartesian::XZPoint;
+use crate::coordinate_system::geographic::{LLBBox, LLPoint};
+use crate::coordinate_system::transformation::CoordTransformer;
+use crate::osm_parser::{ProcessedElement, ProcessedNode, ProcessedWay};
+use geo::{BoundingRect, Intersects, LineString, Polygon};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// IDs of footprint ways and nodes start here, far above OSM IDs
+const FOOTPRINT_ID_BASE: u64 = 1 << 62;
+/// Edge length of the cells buildings are indexed by, in blocks
+const CELL_SIZE: f64 = 32.0;
+
+/// A footprint in world coordinates, with its height if the dataset has one
+struct Footprint {
+    outline: Vec<XZPoint>,
+    height: Option<f64>,
+}
+
+/// Adds the footprints of the GeoJSON files inside `bbox` as buildings, returns how many
+pub fn merge_footprints(
+    elements: &mut Vec<ProcessedElement>,
+    paths: &[PathBuf],
+    bbox: &LLBBox,
+    scale: f64,
+) -> Result<usize, String> {
+    let (transformer, _) = CoordTransformer::llbbox_to_xzbbox(bbox, scale)?;
+
+    let mut footprints: Vec<Footprint> = Vec::new();
+    for path in paths {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let data: Value = serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+        footprints.extend(read_footprints(&data, bbox, &transformer));
+    }
+
+    Ok(add_footprints(elements, footprints))
+}
+
+/// Outer rings of the polygons of a GeoJSON feature collection that reach into `bbox`
+fn read_footprints(data: &Value, bbox: &LLBBox, transformer: &CoordTransformer) -> Vec<Footprint> {
+    let Some(features) = data["features"].as_array() else {
+        return Vec::new();
+    };
+
+    let mut footprints = Vec::new();
+    for feature in features {
+        let geometry = &feature["geometry"];
+        let polygons: Vec<&Value> = match geometry["type"].as_str() {
+            Some("Polygon") => vec![&geometry["coordinates"]],
+            Some("MultiPolygon") => geometry["coordinates"]
+                .as_array()
+                .map(|polygons| polygons.iter().collect())
+                .unwrap_or_default(),
+            _ => continue,
+        };
+        // Microsoft marks unknown heights with -1
+        let height = feature["properties"]["height"]
+            .as_f64()
+            .filter(|height| *height > 0.0);
+
+        for polygon in polygons {
+            let points: Vec<LLPoint> = polygon[0]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|position| {
+                    LLPoint::new(position[1].as_f64()?, position[0].as_f64()?).ok()
+                })
+                .collect();
+            if points.len() < 4 || !points.iter().any(|point| bbox.contains(point)) {
+                continue;
+            }
+            footprints.push(Footprint {
+                outline: points
+                    .into_iter()
+                    .map(|point| transformer.transform_point(point))
+                    .collect(),
+                height,
+            });
+        }
+    }
+    footprints
+}
+
+/// Adds the footprints that don't touch an existing building
+fn add_footprints(elements: &mut Vec<ProcessedElement>, footprints: Vec<Footprint>) -> usize {
+    let mut index = BuildingIndex::of_buildings(elements);
+
+    let mut next_id = FOOTPRINT_ID_BASE;
+    let mut added: usize = 0;
+    for footprint in footprints {
+        let outline = polygon_of_points(&footprint.outline);
+        if index.intersects(&outline) {
+            continue;
+        }
+        index.insert(outline);
+
+        let mut nodes: Vec<ProcessedNode> = footprint.outline[..footprint.outline.len() - 1]
+            .iter()
+            .map(|point| {
+                next_id += 1;
+                ProcessedNode {
+                    id: next_id,
+                    tags: HashMap::new(),
+                    x: point.x,
+                    z: point.z,
+                }
+            })
+            .collect();
+        // Close the way with its first node, like OSM does
+        nodes.push(nodes[0].clone());
+
+        let mut tags = HashMap::from([("building".to_string(), "yes".to_string())]);
+        if let Some(height) = footprint.height {
+            tags.insert("height".to_string(), format!("{height:.1}"));
+        }
+        next_id += 1;
+        elements.push(ProcessedElement::Way(ProcessedWay {
+            id: next_id,
+            nodes,
+            tags,
+        }));
+        added += 1;
+    }
+    added
+}
+
+fn polygon(nodes: &[ProcessedNode]) -> Polygon {
+    let points: Vec<XZPoint> = nodes.iter().map(ProcessedNode::xz).collect();
+    polygon_of_points(&points)
+}
+
+pub(crate) fn polygon_of_points(points: &[XZPoint]) -> Polygon {
+    let exterior: LineString = points
+        .iter()
+        .map(|point| (f64::from(point.x), f64::from(point.z)))
+        .collect();
+    Polygon::new(exterior, vec![])
+}
+
+/// Buildings by the grid cells their bounding rectangles cover
+#[derive(Default)]
+pub(crate) struct BuildingIndex {
+    buildings: Vec<Polygon>,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl BuildingIndex {
+    /// Index of the buildings among `elements`
+    pub(crate) fn of_buildings(elements: &[ProcessedElement]) -> Self {
+        let mut index = BuildingIndex::default();
+        for element in elements {
+            if !element.tags().contains_key("building") {
+                continue;
+            }
+            match element {
+                ProcessedElement::Way(way) => index.insert(polygon(&way.nodes)),
+                ProcessedElement::Relation(relation) => {
+                    for member in &relation.members {
+                        index.insert(polygon(&member.way.nodes));
+                    }
+                }
+                ProcessedElement::Node(_) => {}
+            }
+        }
+        index
+    }
+
+    fn cells_of(polygon: &Polygon) -> Vec<(i32, i32)> {
+        let Some(rect) = polygon.bounding_rect() else {
+            return Vec::new();
+        };
+        let cell = |value: f64| (value / CELL_SIZE).floor() as i32;
+        let (min_x, max_x) = (cell(rect.min().x), cell(rect.max().x));
+        let (min_z, max_z) = (cell(rect.min().y), cell(rect.max().y));
+        (min_x..=max_x)
+            .flat_map(|x| (min_z..=max_z).map(move |z| (x, z)))
+            .collect()
+    }
+
+    pub(crate) fn insert(&mut self, polygon: Polygon) {
+        let index = self.buildings.len();
+        for cell in Self::cells_of(&polygon) {
+            self.cells.entry(cell).or_default().push(index);
+        }
+        self.buildings.push(polygon);
+    }
+
+    pub(crate) fn intersects(&self, polygon: &Polygon) -> bool {
+        Self::cells_of(polygon)
+            .iter()
+            .filter_map(|cell| self.cells.get(cell))
+            .flatten()
+            .any(|&index| self.buildings[index].intersects(polygon))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: i32, z: i32, size: i32) -> Vec<XZPoint> {
+        vec![
+            XZPoint::new(x, z),
+            XZPoint::new(x + size, z),
+            XZPoint::new(x + size, z + size),
+            XZPoint::new(x, z + size),
+            XZPoint::new(x, z),
+        ]
+    }
+
+    #[test]
+    fn test_add_footprints_skips_overlaps() {
+        let osm_building = ProcessedWay {
+            id: 1,
+            nodes: square(0, 0, 10)
+                .into_iter()
+                .map(|point| ProcessedNode {
+                    id: 0,
+                    tags: HashMap::new(),
+                    x: point.x,
+                    z: point.z,
+                })
+                .collect(),
+            tags: HashMap::from([("building".to_string(), "house".to_string())]),
+        };
+        let mut elements = vec![ProcessedElement::Way(osm_building)];
+
+        let footprints = vec![
+            // Overlaps the OSM building
+            Footprint {
+                outline: square(5, 5, 10),
+                height: None,
+            },
+            Footprint {
+                outline: square(40, 40, 10),
+                height: Some(7.5),
+            },
+            // Overlaps the footprint before
+            Footprint {
+                outline: square(45, 45, 10),
+                height: None,
+            },
+        ];
+        assert_eq!(add_footprints(&mut elements, footprints), 1);
+
+        let ProcessedElement::Way(added) = &elements[1] else {
+            panic!("expected a way");
+        };
+        assert_eq!(added.tags["height"], "7.5");
+        assert_eq!(added.nodes.len(), 5);
+        assert_eq!(added.nodes[0], added.nodes[4]);
+        assert!(added.id > FOOTPRINT_ID_BASE);
+    }
+}
@@ -0,0 +1,212 @@
+//! In-game gazetteer: written books listing the named features of the generated area
+//! together with their block coordinates.
+
+use crate::coordinate_system::cartesian::XZBBox;
+use crate::osm_parser::ProcessedElement;
+use crate::spawn::written_book;
+use fastnbt::Value;
+use std::collections::{BTreeSet, HashSet};
+
+/// Characters that fit on one line of a book page
+const LINE_WIDTH: usize = 19;
+/// Lines per book, keeping every book well below the page limit of written books
+const LINES_PER_BOOK: usize = 50 * 12;
+
+/// Categories listed in the gazetteer, in order, with the tag key and value(s) that select them
+const CATEGORIES: [(&str, &str, &[&str]); 7] = [
+    (
+        "Places",
+        "place",
+        &["square", "neighbourhood", "quarter", "suburb", "village"],
+    ),
+    ("Transport", "railway", &["station", "halt", "tram_stop"]),
+    ("Sights", "tourism", &[]),
+    ("Historic", "historic", &[]),
+    ("Amenities", "amenity", &[]),
+    ("Leisure", "leisure", &[]),
+    ("Shops", "shop", &[]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct GazetteerEntry {
+    category: usize,
+    name: String,
+    x: i32,
+    z: i32,
+}
+
+/// Builds the gazetteer books for all named features inside the world
+pub fn gazetteer_books(elements: &[ProcessedElement], xzbbox: &XZBBox) -> Vec<Value> {
+    let lines = gazetteer_lines(&collect_entries(elements, xzbbox));
+    let books: Vec<&[String]> = lines.chunks(LINES_PER_BOOK).collect();
+
+    books
+        .iter()
+        .enumerate()
+        .map(|(i, lines)| {
+            let title = if books.len() > 1 {
+                format!("Gazetteer {}/{}", i + 1, books.len())
+            } else {
+                "Gazetteer".to_string()
+            };
+            written_book(&title, lines.to_vec())
+        })
+        .collect()
+}
+
+fn collect_entries(elements: &[ProcessedElement], xzbbox: &XZBBox) -> BTreeSet<GazetteerEntry> {
+    let mut entries = BTreeSet::new();
+    let mut seen: HashSet<(usize, &str)> = HashSet::new();
+
+    for element in elements {
+        let tags = element.tags();
+        let Some(name) = tags.get("name") else {
+            continue;
+        };
+
+        let category = CATEGORIES.iter().position(|(_, key, values)| {
+            tags.get(*key)
+                .is_some_and(|value| values.is_empty() || values.contains(&value.as_str()))
+        });
+        let (Some(category), Some(center)) = (category, element.center()) else {
+            continue;
+        };
+
+        if !xzbbox.contains(&center) {
+            continue;
+        }
+
+        // Long features are split into several elements, list each name once per category
+        if !seen.insert((category, name.as_str())) {
+            continue;
+        }
+
+        entries.insert(GazetteerEntry {
+            category,
+            name: name.clone(),
+            x: center.x,
+            z: center.z,
+        });
+    }
+
+    entries
+}
+
+fn gazetteer_lines(entries: &BTreeSet<GazetteerEntry>) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current_category = None;
+
+    for entry in entries {
+        if current_category != Some(entry.category) {
+            if current_category.is_some() {
+                lines.push(String::new());
+            }
+            lines.push(format!("== {} ==", CATEGORIES[entry.category].0));
+            current_category = Some(entry.category);
+        }
+
+        lines.push(truncate(&entry.name));
+        lines.push(format!("  x {} z {}", entry.x, entry.z));
+    }
+
+    if lines.is_empty() {
+        lines.push("No named places".to_string());
+        lines.push("in this area.".to_string());
+    }
+
+    lines
+}
+
+fn truncate(name: &str) -> String {
+    if name.chars().count() <= LINE_WIDTH {
+        name.to_string()
+    } else {
+        let mut truncated: String = name.chars().take(LINE_WIDTH - 1).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm_parser::ProcessedNode;
+    use std::collections::HashMap;
+
+    fn named_node(id: u64, x: i32, z: i32, tags: &[(&str, &str)]) -> ProcessedElement {
+        ProcessedElement::Node(ProcessedNode {
+            id,
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>(),
+            x,
+            z,
+        })
+    }
+
+    #[test]
+    fn test_collect_entries() {
+        let xzbbox = XZBBox::rect_from_xz_lengths(100.0, 100.0).unwrap();
+        let elements = vec![
+            named_node(1, 10, 20, &[("name", "Town Hall"), ("amenity", "townhall")]),
+            named_node(
+                2,
+                30,
+                40,
+                &[("name", "Old Gate"), ("historic", "city_gate")],
+            ),
+            named_node(3, 50, 50, &[("amenity", "bench")]),
+            named_node(
+                4,
+                60,
+                60,
+                &[("name", "Main Street"), ("highway", "primary")],
+            ),
+            named_node(5, 500, 500, &[("name", "Far Away"), ("tourism", "museum")]),
+            named_node(6, 70, 70, &[("name", "Town Hall"), ("amenity", "townhall")]),
+            named_node(7, 5, 5, &[("name", "Market"), ("place", "square")]),
+            named_node(8, 6, 6, &[("name", "Nowhere"), ("place", "locality")]),
+        ];
+
+        let entries: Vec<GazetteerEntry> =
+            collect_entries(&elements, &xzbbox).into_iter().collect();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["Market", "Old Gate", "Town Hall"]);
+        assert_eq!((entries[2].x, entries[2].z), (10, 20));
+    }
+
+    #[test]
+    fn test_gazetteer_lines() {
+        let entries = BTreeSet::from([
+            GazetteerEntry {
+                category: 4,
+                name: "A very long name of a public library".to_string(),
+                x: 1,
+                z: -2,
+            },
+            GazetteerEntry {
+                category: 2,
+                name: "Museum".to_string(),
+                x: 30,
+                z: 40,
+            },
+        ]);
+
+        let lines = gazetteer_lines(&entries);
+        assert_eq!(
+            lines,
+            vec![
+                "== Sights ==",
+                "Museum",
+                "  x 30 z 40",
+                "",
+                "== Amenities ==",
+                "A very long name o…",
+                "  x 1 z -2",
+            ]
+        );
+
+        assert_eq!(gazetteer_lines(&BTreeSet::new()).len(), 2);
+    }
+}
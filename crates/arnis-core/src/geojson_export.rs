@@ -0,0 +1,327 @@
+//! Exports the parsed elements as GeoJSON, one file per category, so that what Arnis
+//! understood of the OSM data can be inspected in GIS tools like QGIS.
+
+use crate::coordinate_system::cartesian::XZPoint;
+use crate::coordinate_system::geographic::LLBBox;
+use crate::coordinate_system::transformation::CoordTransformer;
+use crate::osm_parser::{ProcessedElement, ProcessedMemberRole, ProcessedNode, ProcessedWay};
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+use std::path::{Path, PathBuf};
+
+/// Category of elements written to its own GeoJSON file
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum GeoJsonLayer {
+    Buildings,
+    Roads,
+    Railways,
+    Water,
+    Landuse,
+    Pois,
+}
+
+impl GeoJsonLayer {
+    fn file_name(&self) -> &'static str {
+        match self {
+            GeoJsonLayer::Buildings => "buildings.geojson",
+            GeoJsonLayer::Roads => "roads.geojson",
+            GeoJsonLayer::Railways => "railways.geojson",
+            GeoJsonLayer::Water => "water.geojson",
+            GeoJsonLayer::Landuse => "landuse.geojson",
+            GeoJsonLayer::Pois => "pois.geojson",
+        }
+    }
+
+    /// Layer an element is exported to, in the same order of precedence as generation
+    fn of(element: &ProcessedElement) -> Option<Self> {
+        let tags = element.tags();
+        let has = |key: &str| tags.contains_key(key);
+
+        if has("building") || has("building:part") {
+            Some(GeoJsonLayer::Buildings)
+        } else if has("highway") || has("aeroway") {
+            Some(GeoJsonLayer::Roads)
+        } else if has("railway") {
+            Some(GeoJsonLayer::Railways)
+        } else if has("water")
+            || has("waterway")
+            || matches!(
+                tags.get("natural").map(String::as_str),
+                Some("water" | "bay")
+            )
+        {
+            Some(GeoJsonLayer::Water)
+        } else if has("landuse") || has("leisure") || has("natural") {
+            Some(GeoJsonLayer::Landuse)
+        } else if matches!(element, ProcessedElement::Node(_)) && !tags.is_empty() {
+            Some(GeoJsonLayer::Pois)
+        } else {
+            None
+        }
+    }
+}
+
+/// Writes one GeoJSON file per layer into `dir`. An empty list of layers exports all of them.
+pub fn export_geojson(
+    elements: &[ProcessedElement],
+    llbbox: &LLBBox,
+    scale: f64,
+    layers: &[GeoJsonLayer],
+    dir: &Path,
+) -> Result<Vec<PathBuf>, String> {
+    let (transformer, _) = CoordTransformer::llbbox_to_xzbbox(llbbox, scale)?;
+    let layers: Vec<GeoJsonLayer> = if layers.is_empty() {
+        GeoJsonLayer::value_variants().to_vec()
+    } else {
+        layers.to_vec()
+    };
+
+    let mut written = Vec::new();
+    for layer in layers {
+        let features: Vec<Value> = elements
+            .iter()
+            .filter(|element| GeoJsonLayer::of(element) == Some(layer))
+            .filter_map(|element| feature(element, &transformer))
+            .collect();
+
+        let collection = json!({
+            "type": "FeatureCollection",
+            "features": features,
+        });
+
+        let path = dir.join(layer.file_name());
+        std::fs::write(&path, collection.to_string())
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+fn feature(element: &ProcessedElement, transformer: &CoordTransformer) -> Option<Value> {
+    let geometry = match element {
+        ProcessedElement::Node(node) => json!({
+            "type": "Point",
+            "coordinates": position(node.xz(), transformer)?,
+        }),
+        ProcessedElement::Way(way) => way_geometry(way, transformer)?,
+        ProcessedElement::Relation(rel) => {
+            let ring = |way: &ProcessedWay| ring(way, transformer);
+            let mut polygons: Vec<(&ProcessedWay, Vec<Value>)> = rel
+                .members
+                .iter()
+                .filter(|member| member.role == ProcessedMemberRole::Outer)
+                .filter_map(|member| Some((&member.way, vec![ring(&member.way)?])))
+                .collect();
+
+            // Holes go into the outer ring they lie in
+            for member in &rel.members {
+                if member.role != ProcessedMemberRole::Inner {
+                    continue;
+                }
+                let (Some(first), Some(hole)) = (member.way.nodes.first(), ring(&member.way))
+                else {
+                    continue;
+                };
+                if let Some((_, rings)) = polygons
+                    .iter_mut()
+                    .find(|(outer, _)| bounds_contain(outer, first))
+                {
+                    rings.push(hole);
+                }
+            }
+
+            if polygons.is_empty() {
+                return None;
+            }
+            json!({
+                "type": "MultiPolygon",
+                "coordinates": polygons.into_iter().map(|(_, rings)| rings).collect::<Vec<_>>(),
+            })
+        }
+    };
+
+    let mut properties: Map<String, Value> = element
+        .tags()
+        .iter()
+        .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+        .collect();
+    properties.insert(
+        "@id".to_string(),
+        Value::String(format!("{}/{}", element.kind(), element.id())),
+    );
+
+    Some(json!({
+        "type": "Feature",
+        "geometry": geometry,
+        "properties": properties,
+    }))
+}
+
+fn way_geometry(way: &ProcessedWay, transformer: &CoordTransformer) -> Option<Value> {
+    if is_closed(way) && way.nodes.len() >= 4 && !way.tags.contains_key("highway") {
+        return Some(json!({
+            "type": "Polygon",
+            "coordinates": [ring(way, transformer)?],
+        }));
+    }
+
+    Some(json!({
+        "type": "LineString",
+        "coordinates": positions(&way.nodes, transformer)?,
+    }))
+}
+
+/// A closed ring of positions; unclosed member ways of relations are closed here
+fn ring(way: &ProcessedWay, transformer: &CoordTransformer) -> Option<Value> {
+    let mut nodes: Vec<ProcessedNode> = way.nodes.clone();
+    if !is_closed(way) {
+        nodes.push(nodes.first()?.clone());
+    }
+    if nodes.len() < 4 {
+        return None;
+    }
+    positions(&nodes, transformer)
+}
+
+fn positions(nodes: &[ProcessedNode], transformer: &CoordTransformer) -> Option<Value> {
+    nodes
+        .iter()
+        .map(|node| position(node.xz(), transformer))
+        .collect::<Option<Vec<Value>>>()
+        .map(Value::Array)
+}
+
+/// GeoJSON position, in (longitude, latitude) order
+fn position(xzpoint: XZPoint, transformer: &CoordTransformer) -> Option<Value> {
+    let llpoint = transformer.inverse_transform_point(xzpoint).ok()?;
+    Some(json!([llpoint.lng(), llpoint.lat()]))
+}
+
+fn is_closed(way: &ProcessedWay) -> bool {
+    way.nodes.len() > 1 && way.nodes.first().map(|n| n.id) == way.nodes.last().map(|n| n.id)
+}
+
+fn bounds_contain(way: &ProcessedWay, node: &ProcessedNode) -> bool {
+    let xs = way.nodes.iter().map(|n| n.x);
+    let zs = way.nodes.iter().map(|n| n.z);
+    let (Some(min_x), Some(max_x)) = (xs.clone().min(), xs.max()) else {
+        return false;
+    };
+    let (Some(min_z), Some(max_z)) = (zs.clone().min(), zs.max()) else {
+        return false;
+    };
+    (min_x..=max_x).contains(&node.x) && (min_z..=max_z).contains(&node.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm_parser::ProcessedMember;
+    use crate::osm_parser::ProcessedRelation;
+    use std::collections::HashMap;
+
+    fn node(id: u64, x: i32, z: i32) -> ProcessedNode {
+        ProcessedNode {
+            id,
+            tags: HashMap::new(),
+            x,
+            z,
+        }
+    }
+
+    fn way(id: u64, nodes: Vec<ProcessedNode>, tags: &[(&str, &str)]) -> ProcessedWay {
+        ProcessedWay {
+            id,
+            nodes,
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    fn square(first_id: u64, min: i32, max: i32) -> Vec<ProcessedNode> {
+        vec![
+            node(first_id, min, min),
+            node(first_id + 1, max, min),
+            node(first_id + 2, max, max),
+            node(first_id + 3, min, max),
+            node(first_id, min, min),
+        ]
+    }
+
+    fn transformer() -> CoordTransformer {
+        let llbbox = LLBBox::new(54.627053, 9.927928, 54.634902, 9.937563).unwrap();
+        CoordTransformer::llbbox_to_xzbbox(&llbbox, 1.0).unwrap().0
+    }
+
+    #[test]
+    fn test_layer_of() {
+        let building = ProcessedElement::Way(way(1, square(1, 0, 10), &[("building", "yes")]));
+        assert_eq!(GeoJsonLayer::of(&building), Some(GeoJsonLayer::Buildings));
+
+        let lake = ProcessedElement::Way(way(2, square(1, 0, 10), &[("natural", "water")]));
+        assert_eq!(GeoJsonLayer::of(&lake), Some(GeoJsonLayer::Water));
+
+        let wood = ProcessedElement::Way(way(3, square(1, 0, 10), &[("natural", "wood")]));
+        assert_eq!(GeoJsonLayer::of(&wood), Some(GeoJsonLayer::Landuse));
+
+        let bare_node = ProcessedElement::Node(node(4, 1, 1));
+        assert_eq!(GeoJsonLayer::of(&bare_node), None);
+    }
+
+    #[test]
+    fn test_way_geometry() {
+        let transformer = transformer();
+
+        let building = way(1, square(1, 0, 10), &[("building", "yes")]);
+        let geometry = way_geometry(&building, &transformer).unwrap();
+        assert_eq!(geometry["type"], "Polygon");
+        assert_eq!(geometry["coordinates"][0].as_array().unwrap().len(), 5);
+
+        // Closed roads (e.g. roundabouts) stay lines
+        let roundabout = way(2, square(1, 0, 10), &[("highway", "primary")]);
+        assert_eq!(
+            way_geometry(&roundabout, &transformer).unwrap()["type"],
+            "LineString"
+        );
+
+        // Positions are (lng, lat) and lie within the bounding box
+        let lng = geometry["coordinates"][0][0][0].as_f64().unwrap();
+        let lat = geometry["coordinates"][0][0][1].as_f64().unwrap();
+        assert!((9.927928..9.937563).contains(&lng));
+        assert!((54.627053..54.634902).contains(&lat));
+    }
+
+    #[test]
+    fn test_multipolygon_holes() {
+        let transformer = transformer();
+        let rel = ProcessedElement::Relation(ProcessedRelation {
+            id: 1,
+            tags: HashMap::from([("natural".to_string(), "water".to_string())]),
+            members: vec![
+                ProcessedMember {
+                    role: ProcessedMemberRole::Outer,
+                    way: way(1, square(1, 0, 100), &[]),
+                },
+                ProcessedMember {
+                    role: ProcessedMemberRole::Outer,
+                    way: way(2, square(10, 200, 300), &[]),
+                },
+                ProcessedMember {
+                    role: ProcessedMemberRole::Inner,
+                    way: way(3, square(20, 220, 250), &[]),
+                },
+            ],
+        });
+
+        let feature = feature(&rel, &transformer).unwrap();
+        assert_eq!(feature["geometry"]["type"], "MultiPolygon");
+        let polygons = feature["geometry"]["coordinates"].as_array().unwrap();
+        assert_eq!(polygons.len(), 2);
+        assert_eq!(polygons[0].as_array().unwrap().len(), 1);
+        assert_eq!(polygons[1].as_array().unwrap().len(), 2);
+        assert_eq!(feature["properties"]["@id"], "relation/1");
+    }
+}
@@ -0,0 +1,217 @@
+//! GPX track loading and route corridor helpers for generating only the area along a route.
+use crate::coordinate_system::cartesian::{XZBBox, XZPoint};
+use crate::coordinate_system::geographic::{LLBBox, LLPoint};
+use crate::coordinate_system::transformation::{geo_distance, CoordTransformer};
+use std::fs;
+use std::path::Path;
+
+/// Maximum length of the route covered by one Overpass request, in meters
+const ROUTE_CHUNK_LENGTH_M: f64 = 2000.0;
+/// Approximate length of one degree of latitude, in meters
+const METERS_PER_DEGREE_LAT: f64 = 111_320.0;
+
+/// Loads the track points (or route points, if there is no track) of a GPX file
+pub fn load_route(path: &Path) -> Result<Vec<LLPoint>, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read GPX file {}: {e}", path.display()))?;
+    parse_gpx(&contents)
+}
+
+/// Extracts the points of all `<trkpt>` elements, falling back to `<rtept>` elements
+pub fn parse_gpx(contents: &str) -> Result<Vec<LLPoint>, String> {
+    let mut points = parse_points(contents, "trkpt")?;
+    if points.is_empty() {
+        points = parse_points(contents, "rtept")?;
+    }
+
+    if points.is_empty() {
+        return Err("GPX file contains no track or route points".to_string());
+    }
+
+    Ok(points)
+}
+
+fn parse_points(contents: &str, tag: &str) -> Result<Vec<LLPoint>, String> {
+    let opening = format!("<{tag}");
+    let mut points = Vec::new();
+
+    for (start, _) in contents.match_indices(&opening) {
+        let rest = &contents[start + opening.len()..];
+        // Skip longer tag names sharing the same prefix
+        if !rest.starts_with(|c: char| c.is_whitespace()) {
+            continue;
+        }
+
+        let end = rest
+            .find('>')
+            .ok_or_else(|| format!("Unterminated <{tag}> element in GPX file"))?;
+        let attributes = &rest[..end];
+
+        let lat = parse_attribute(attributes, "lat")
+            .ok_or_else(|| format!("<{tag}> element without a valid lat attribute"))?;
+        let lon = parse_attribute(attributes, "lon")
+            .ok_or_else(|| format!("<{tag}> element without a valid lon attribute"))?;
+
+        points.push(LLPoint::new(lat, lon)?);
+    }
+
+    Ok(points)
+}
+
+fn parse_attribute(attributes: &str, name: &str) -> Option<f64> {
+    let mut search = attributes;
+    loop {
+        let index = search.find(name)?;
+        let preceded_by_space = matches!(
+            search[..index].chars().next_back(),
+            None | Some(' ' | '\t' | '\r' | '\n')
+        );
+        let rest = search[index + name.len()..].trim_start();
+
+        if preceded_by_space {
+            if let Some(value) = rest.strip_prefix('=') {
+                let value = value.trim_start();
+                let quote = value.chars().next()?;
+                if quote == '"' || quote == '\'' {
+                    let value = &value[1..];
+                    let end = value.find(quote)?;
+                    return value[..end].trim().parse().ok();
+                }
+            }
+        }
+
+        search = &search[index + name.len()..];
+    }
+}
+
+/// Splits a route into consecutive pieces of at most `chunk_length_m` meters.
+/// Neighbouring pieces share their boundary point so no part of the route is lost.
+pub fn split_route(points: &[LLPoint], chunk_length_m: f64) -> Vec<Vec<LLPoint>> {
+    let mut chunks: Vec<Vec<LLPoint>> = Vec::new();
+    let mut current: Vec<LLPoint> = Vec::new();
+    let mut current_length: f64 = 0.0;
+
+    for &point in points {
+        if let Some(&last) = current.last() {
+            let (dz, dx) = geo_distance(last, point);
+            current_length += (dz * dz + dx * dx).sqrt();
+        }
+        current.push(point);
+
+        if current_length >= chunk_length_m {
+            chunks.push(std::mem::replace(&mut current, vec![point]));
+            current_length = 0.0;
+        }
+    }
+
+    if current.len() > 1 || chunks.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+/// Splits a route into the pieces that are each fetched with a single Overpass request
+pub fn split_route_for_download(points: &[LLPoint]) -> Vec<Vec<LLPoint>> {
+    split_route(points, ROUTE_CHUNK_LENGTH_M)
+}
+
+/// Bounding box around the route points, grown by half the corridor width on every side
+pub fn corridor_bbox(points: &[LLPoint], corridor_width_m: f64) -> Result<LLBBox, String> {
+    if points.is_empty() {
+        return Err("Cannot build a corridor around an empty route".to_string());
+    }
+
+    let min_lat = points.iter().map(|p| p.lat()).fold(f64::MAX, f64::min);
+    let max_lat = points.iter().map(|p| p.lat()).fold(f64::MIN, f64::max);
+    let min_lng = points.iter().map(|p| p.lng()).fold(f64::MAX, f64::min);
+    let max_lng = points.iter().map(|p| p.lng()).fold(f64::MIN, f64::max);
+
+    // Keep at least a few meters so single-point routes still form a valid bbox
+    let half_width_m = (corridor_width_m / 2.0).max(5.0);
+    let lat_margin = half_width_m / METERS_PER_DEGREE_LAT;
+    let widest_lat = min_lat.abs().max(max_lat.abs()).min(89.0);
+    let lng_margin = half_width_m / (METERS_PER_DEGREE_LAT * widest_lat.to_radians().cos());
+
+    LLBBox::new(
+        (min_lat - lat_margin).max(-90.0),
+        (min_lng - lng_margin).max(-180.0),
+        (max_lat + lat_margin).min(90.0),
+        (max_lng + lng_margin).min(180.0),
+    )
+}
+
+/// Minecraft-space shape covering the corridor around the route inside `llbbox`
+pub fn corridor_xzbbox(
+    points: &[LLPoint],
+    llbbox: &LLBBox,
+    scale: f64,
+    corridor_width_m: f64,
+) -> Result<XZBBox, String> {
+    let (transformer, xzbbox) = CoordTransformer::llbbox_to_xzbbox(llbbox, scale)?;
+    let xzpoints: Vec<XZPoint> = points
+        .iter()
+        .map(|p| transformer.transform_point(*p))
+        .collect();
+
+    xzbbox.corridor_within(xzpoints, corridor_width_m / 2.0 * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_GPX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="test">
+  <trk><name>Arnis walk</name><trkseg>
+    <trkpt lat="54.6290" lon="9.9300"><ele>12.0</ele></trkpt>
+    <trkpt lon='9.9320' lat='54.6300'/>
+    <trkpt lat="54.6310" lon="9.9350"></trkpt>
+  </trkseg></trk>
+</gpx>"#;
+
+    #[test]
+    fn test_parse_gpx() {
+        let points = parse_gpx(SAMPLE_GPX).unwrap();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0], LLPoint::new(54.6290, 9.9300).unwrap());
+        assert_eq!(points[1], LLPoint::new(54.6300, 9.9320).unwrap());
+
+        let route =
+            r#"<gpx><rte><rtept lat="1.5" lon="2.5"/><rtept lat="1.6" lon="2.6"/></rte></gpx>"#;
+        assert_eq!(parse_gpx(route).unwrap().len(), 2);
+
+        assert!(parse_gpx("<gpx></gpx>").is_err());
+        assert!(parse_gpx(r#"<gpx><trkpt lat="1.0"/></gpx>"#).is_err());
+        assert!(parse_gpx(r#"<gpx><trkpt lat="95.0" lon="1.0"/></gpx>"#).is_err());
+    }
+
+    #[test]
+    fn test_split_route() {
+        let points: Vec<LLPoint> = (0..10)
+            .map(|i| LLPoint::new(54.0 + i as f64 * 0.001, 9.0).unwrap())
+            .collect();
+
+        // ~111m between points, so ~4 points per 300m piece
+        let chunks = split_route(&points, 300.0);
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.first().unwrap()[0], points[0]);
+        assert_eq!(chunks.last().unwrap().last(), points.last());
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[0].last(), pair[1].first());
+        }
+
+        assert_eq!(split_route(&points[..1], 300.0).len(), 1);
+    }
+
+    #[test]
+    fn test_corridor_bbox() {
+        let points = parse_gpx(SAMPLE_GPX).unwrap();
+        let bbox = corridor_bbox(&points, 200.0).unwrap();
+        assert!(bbox.min().lat() < 54.6290 && bbox.max().lat() > 54.6310);
+        assert!(bbox.min().lng() < 9.9300 && bbox.max().lng() > 9.9350);
+
+        assert!(corridor_bbox(&[], 200.0).is_err());
+        assert!(corridor_bbox(&points[..1], 0.0).is_ok());
+    }
+}
@@ -1,6 +1,7 @@
 use crate::args::Args;
 use crate::coordinate_system::{cartesian::XZPoint, geographic::LLBBox};
-use crate::elevation_data::{fetch_elevation_data, ElevationData};
+use crate::elevation_data::{fetch_elevation_data_from, ElevationData, TerrainShape};
+use crate::elevation_provider::{ElevationProvider, GeoTiff};
 use crate::progress::emit_gui_progress_update;
 use colored::Colorize;
 use image::{Rgb, RgbImage};
@@ -22,8 +23,14 @@ impl Ground {
         }
     }
 
-    pub fn new_enabled(bbox: &LLBBox, scale: f64, ground_level: i32) -> Self {
-        match fetch_elevation_data(bbox, scale, ground_level) {
+    pub fn new_enabled(
+        provider: &dyn ElevationProvider,
+        bbox: &LLBBox,
+        scale: f64,
+        ground_level: i32,
+        shape: TerrainShape,
+    ) -> Self {
+        match fetch_elevation_data_from(provider, bbox, scale, ground_level, shape) {
             Ok(elevation_data) => Self {
                 elevation_enabled: true,
                 ground_level,
@@ -88,6 +95,47 @@ impl Ground {
         data.heights[z][x]
     }
 
+    /// Overrides the ground level at the given coordinates, e.g. to grade a road into a slope
+    pub fn set_level(&mut self, coord: XZPoint, level: i32) {
+        if !self.elevation_enabled {
+            return;
+        }
+        let Some(data) = self.elevation_data.as_ref() else {
+            return;
+        };
+        let (x_ratio, z_ratio) = self.get_data_coordinates(coord, data);
+        let x: usize = ((x_ratio * (data.width - 1) as f64).round() as usize).min(data.width - 1);
+        let z: usize = ((z_ratio * (data.height - 1) as f64).round() as usize).min(data.height - 1);
+        if let Some(data) = self.elevation_data.as_mut() {
+            data.heights[z][x] = level;
+        }
+    }
+
+    /// Blends the terrain to the flat ground level within `width` blocks of the edge
+    pub fn feather_edges(&mut self, width: usize) {
+        let ground_level = self.ground_level;
+        let Some(data) = self.elevation_data.as_mut() else {
+            return;
+        };
+        let (grid_width, grid_height) = (data.width, data.height);
+
+        for (z, row) in data.heights.iter_mut().enumerate() {
+            for (x, height) in row.iter_mut().enumerate() {
+                let distance = x
+                    .min(z)
+                    .min(grid_width.saturating_sub(x + 1))
+                    .min(grid_height.saturating_sub(z + 1));
+                if distance < width {
+                    // Smoothstep, so the slope is flat at both ends of the blend
+                    let t = distance as f64 / width as f64;
+                    let weight = t * t * (3.0 - 2.0 * t);
+                    *height =
+                        ground_level + ((*height - ground_level) as f64 * weight).round() as i32;
+                }
+            }
+        }
+    }
+
     fn save_debug_image(&self, filename: &str) {
         let heights = &self
             .elevation_data
@@ -142,7 +190,27 @@ pub fn generate_ground_data(args: &Args) -> Ground {
     if args.terrain {
         println!("{} Fetching elevation...", "[3/7]".bold());
         emit_gui_progress_update(15.0, "Fetching elevation...");
-        let ground = Ground::new_enabled(&args.bbox, args.scale, args.ground_level);
+        let provider: Box<dyn ElevationProvider> = match &args.dem_file {
+            Some(path) => match GeoTiff::open(path) {
+                Ok(dem) => Box::new(dem),
+                Err(e) => {
+                    eprintln!("Warning: {e}, using {:?} instead", args.elevation_source);
+                    args.elevation_source.provider()
+                }
+            },
+            None => args.elevation_source.provider(),
+        };
+        let shape = TerrainShape {
+            exaggeration: args.terrain_exaggeration,
+            smoothing: args.terrain_smoothing,
+        };
+        let ground = Ground::new_enabled(
+            provider.as_ref(),
+            &args.bbox(),
+            args.scale,
+            args.ground_level,
+            shape,
+        );
         if args.debug {
             ground.save_debug_image("elevation_debug");
         }
@@ -150,3 +218,49 @@ pub fn generate_ground_data(args: &Args) -> Ground {
     }
     Ground::new_flat(args.ground_level)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feather_edges() {
+        let mut ground = Ground {
+            elevation_enabled: true,
+            ground_level: -62,
+            elevation_data: Some(ElevationData {
+                heights: vec![vec![38; 9]; 9],
+                width: 9,
+                height: 9,
+            }),
+        };
+        ground.feather_edges(4);
+
+        let heights = &ground.elevation_data.as_ref().unwrap().heights;
+        // Flat at the edge, rising towards the untouched center
+        assert_eq!(heights[0][4], -62);
+        assert_eq!(heights[4][0], -62);
+        assert!(heights[4][1] > -62 && heights[4][1] < heights[4][2]);
+        assert_eq!(heights[4][4], 38);
+    }
+
+    #[test]
+    fn test_set_level() {
+        let mut ground = Ground {
+            elevation_enabled: true,
+            ground_level: -62,
+            elevation_data: Some(ElevationData {
+                heights: vec![vec![10; 8]; 8],
+                width: 8,
+                height: 8,
+            }),
+        };
+        ground.set_level(XZPoint::new(3, 5), 14);
+        assert_eq!(ground.level(XZPoint::new(3, 5)), 14);
+        assert_eq!(ground.level(XZPoint::new(5, 3)), 10);
+
+        let mut flat = Ground::new_flat(-62);
+        flat.set_level(XZPoint::new(3, 5), 14);
+        assert_eq!(flat.level(XZPoint::new(3, 5)), -62);
+    }
+}
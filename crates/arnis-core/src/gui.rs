@@ -1,14 +1,31 @@
+use crate::api_scheduler;
 use crate::args::Args;
+use crate::categories::{Category, FeatureFilter};
+use crate::chunking::ChunkPreset;
 use crate::coordinate_system::cartesian::XZPoint;
 use crate::coordinate_system::geographic::{LLBBox, LLPoint};
 use crate::coordinate_system::transformation::CoordTransformer;
 use crate::data_processing;
+use crate::edge_clip::EdgeClip;
+use crate::elevation_provider::ElevationSource;
+use crate::error::ArnisError;
 use crate::ground::{self, Ground};
+use crate::height_cap::HeightCap;
+use crate::low_memory;
 use crate::map_transformation;
+use crate::merge::OverlapStrategy;
+use crate::osm_cache::{self, CachedArea};
 use crate::osm_parser;
+use crate::perf_config::{self, PerformanceConfig};
 use crate::progress;
 use crate::retrieve_data;
+use crate::schematic::OutputFormat;
+use crate::season::Season;
+use crate::simplify;
+use crate::update;
 use crate::version_check;
+use crate::world_edge::WorldEdge;
+use clap::ValueEnum;
 use fastnbt::Value;
 use flate2::read::GzDecoder;
 use fs2::FileExt;
@@ -63,6 +80,9 @@ pub fn run_gui() {
     // Launch the UI
     println!("Launching UI...");
 
+    // Downloads are kept so areas can be generated again without waiting for Overpass
+    osm_cache::enable();
+
     // Set a custom panic hook to log panic information
     panic::set_hook(Box::new(|panic_info| {
         let message = format!("Application panicked: {panic_info:?}");
@@ -103,7 +123,12 @@ pub fn run_gui() {
             gui_select_world,
             gui_start_generation,
             gui_get_version,
-            gui_check_for_updates
+            gui_check_for_updates,
+            gui_set_paused,
+            gui_cancel_download,
+            gui_list_cached_areas,
+            gui_delete_cached_area,
+            gui_refresh_cached_area
         ])
         .setup(|app| {
             let app_handle = app.handle();
@@ -649,6 +674,33 @@ pub fn update_player_spawn_y_after_generation(
     Ok(())
 }
 
+#[tauri::command]
+fn gui_set_paused(paused: bool) {
+    crate::pause::set_paused(paused);
+}
+
+#[tauri::command]
+fn gui_cancel_download() {
+    api_scheduler::set_cancelled(true);
+}
+
+#[tauri::command]
+fn gui_list_cached_areas() -> Vec<CachedArea> {
+    osm_cache::list_cached_areas()
+}
+
+#[tauri::command]
+fn gui_delete_cached_area(id: String) -> Result<(), String> {
+    osm_cache::delete_cached_area(&id)
+}
+
+#[tauri::command]
+async fn gui_refresh_cached_area(id: String) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || osm_cache::refresh_cached_area(&id))
+        .await
+        .map_err(|e| format!("Failed to refresh cached area: {e}"))?
+}
+
 #[tauri::command]
 fn gui_get_version() -> String {
     env!("CARGO_PKG_VERSION").to_string()
@@ -676,12 +728,22 @@ fn gui_start_generation(
     interior_enabled: bool,
     roof_enabled: bool,
     fillground_enabled: bool,
+    map_mode: bool,
+    power_saving: bool,
+    low_memory: bool,
+    skip_categories: Vec<String>,
     is_new_world: bool,
     spawn_point: Option<(f64, f64)>,
 ) -> Result<(), String> {
     use progress::emit_gui_error;
     use LLBBox;
 
+    if let Err(error_msg) = crate::args::check_ground_level(ground_level) {
+        let error = ArnisError::Geometry(error_msg);
+        emit_gui_error(&error);
+        return Err(error.to_string());
+    }
+
     // If spawn point was chosen and the world is new, check and set the spawn point
     if is_new_world && spawn_point.is_some() {
         // Verify the spawn point is within bounds
@@ -689,10 +751,10 @@ fn gui_start_generation(
             let llbbox = match LLBBox::from_str(&bbox_text) {
                 Ok(bbox) => bbox,
                 Err(e) => {
-                    let error_msg = format!("Failed to parse bounding box: {e}");
-                    eprintln!("{error_msg}");
-                    emit_gui_error(&error_msg);
-                    return Err(error_msg);
+                    let error = ArnisError::Geometry(format!("Failed to parse bounding box: {e}"));
+                    eprintln!("{error}");
+                    emit_gui_error(&error);
+                    return Err(error.to_string());
                 }
             };
 
@@ -712,6 +774,7 @@ fn gui_start_generation(
         }
     }
 
+    api_scheduler::set_cancelled(false);
     tauri::async_runtime::spawn(async move {
         if let Err(e) = tokio::task::spawn_blocking(move || {
             // Acquire session lock for the world directory before starting generation
@@ -719,10 +782,10 @@ fn gui_start_generation(
             let _session_lock = match SessionLock::acquire(&world_path) {
                 Ok(lock) => lock,
                 Err(e) => {
-                    let error_msg = format!("Failed to acquire session lock: {e}");
-                    eprintln!("{error_msg}");
-                    emit_gui_error(&error_msg);
-                    return Err(error_msg);
+                    let error = ArnisError::Io(format!("Failed to acquire session lock: {e}"));
+                    eprintln!("{error}");
+                    emit_gui_error(&error);
+                    return Err(error.to_string());
                 }
             };
 
@@ -730,10 +793,10 @@ fn gui_start_generation(
             let bbox = match LLBBox::from_str(&bbox_text) {
                 Ok(bbox) => bbox,
                 Err(e) => {
-                    let error_msg = format!("Failed to parse bounding box: {e}");
-                    eprintln!("{error_msg}");
-                    emit_gui_error(&error_msg);
-                    return Err(error_msg);
+                    let error = ArnisError::Geometry(format!("Failed to parse bounding box: {e}"));
+                    eprintln!("{error}");
+                    emit_gui_error(&error);
+                    return Err(error.to_string());
                 }
             };
 
@@ -744,99 +807,256 @@ fn gui_start_generation(
                 world_path
             };
 
+            let skip: Vec<Category> = skip_categories
+                .iter()
+                .filter_map(|name| Category::from_str(name, true).ok())
+                .collect();
+
             // Create an Args instance with the chosen bounding box and world directory path
             let args: Args = Args {
-                bbox,
+                bbox: Some(bbox),
+                extra_bbox: Vec::new(),
+                fill_gaps: false,
+                gpx: None,
+                corridor_width: 200.0,
                 file: None,
                 save_json_file: None,
+                osm_cache: false,
                 path: updated_world_path,
                 downloader: "requests".to_string(),
+                download_attempts: 5,
                 scale: world_scale,
+                no_simplify: false,
                 ground_level,
                 terrain: terrain_enabled,
+                elevation_source: ElevationSource::Terrarium,
+                dem_file: None,
+                terrain_exaggeration: 1.0,
+                terrain_smoothing: None,
                 interior: interior_enabled,
                 roof: roof_enabled,
+                weathering: false,
+                season: Season::Summer,
+                format: OutputFormat::World,
+                structure_void: Vec::new(),
+                into_world: None,
+                offset: None,
+                update: false,
+                track_updates: false,
+                night: false,
                 fillground: fillground_enabled,
+                max_building_height: None,
+                height_cap: HeightCap::Compress,
+                height_override: Vec::new(),
+                map_mode,
+                lod: crate::lod::DetailLevel::Full,
+                lod_focus: None,
+                lod_full_radius: 1000.0,
+                lod_simplified_radius: 3000.0,
+                keep_spawn: false,
+                starter_kit: false,
+                gazetteer: false,
+                qa_markers: false,
+                rideable_rails: false,
+                elytra_tour: false,
+                elytra_route: None,
+                resource_pack: false,
+                resume: false,
+                date: None,
+                compare_date: None,
+                compare_file: None,
+                contact: None,
+                tile_url: None,
+                overture: Vec::new(),
+                footprints: Vec::new(),
+                address_lots: false,
+                tile_zoom: crate::vector_tiles::DEFAULT_ZOOM,
+                telemetry_url: None,
+                query_template: None,
+                export_geojson: None,
+                seed: None,
+                verify: None,
+                max_ram_gb: None,
+                threads: None,
+                power_saving,
+                background: false,
+                low_memory,
+                preview_parts: false,
+                chunking: ChunkPreset::Balanced,
+                overlap: OverlapStrategy::Largest,
+                temp_dir: None,
+                max_write_speed: None,
+                imagery_url: None,
+                second_palette: None,
+                second_path: None,
+                land_polygons: None,
+                exclude_polygons: None,
+                unknown_tags_out: None,
+                strict: false,
+                stats: None,
+                stats_cell_size: 32,
+                skip,
+                include: Vec::new(),
+                edge_clip: EdgeClip::Hard,
+                world_edge: WorldEdge::Void,
+                block_palette: None,
+                vanilla_fallback: false,
                 debug: false,
                 timeout: Some(std::time::Duration::from_secs(floodfill_timeout)),
                 spawn_point,
             };
 
-            // If skip_osm_objects is true (terrain-only mode), skip fetching and processing OSM data
-            if skip_osm_objects {
-                // Generate ground data (terrain) for terrain-only mode
-                let ground = ground::generate_ground_data(&args);
-
-                // Create empty parsed_elements and xzbbox for terrain-only mode
-                let parsed_elements = Vec::new();
-                let (_coord_transformer, xzbbox) =
-                    CoordTransformer::llbbox_to_xzbbox(&args.bbox, args.scale)
-                        .map_err(|e| format!("Failed to create coordinate transformer: {}", e))?;
-
-                let _ = data_processing::generate_world(
-                    parsed_elements,
-                    xzbbox,
-                    args.bbox,
-                    ground,
-                    &args,
-                );
-                // Session lock will be automatically released when _session_lock goes out of scope
-                return Ok(());
-            }
-
-            // Run data fetch and world generation (standard mode: objects + terrain, or objects only)
-            match retrieve_data::fetch_data_from_overpass(args.bbox, args.debug, "requests", None) {
-                Ok(raw_data) => {
-                    let (mut parsed_elements, mut xzbbox) =
-                        osm_parser::parse_osm_data(raw_data, args.bbox, args.scale, args.debug);
-                    parsed_elements.sort_by(|el1, el2| {
-                        let (el1_priority, el2_priority) =
-                            (osm_parser::get_priority(el1), osm_parser::get_priority(el2));
-                        match (
-                            el1.tags().contains_key("landuse"),
-                            el2.tags().contains_key("landuse"),
-                        ) {
-                            (true, false) => std::cmp::Ordering::Greater,
-                            (false, true) => std::cmp::Ordering::Less,
-                            _ => el1_priority.cmp(&el2_priority),
-                        }
-                    });
-
-                    let mut ground = ground::generate_ground_data(&args);
-
-                    // Transform map (parsed_elements). Operations are defined in a json file
-                    map_transformation::transform_map(
-                        &mut parsed_elements,
-                        &mut xzbbox,
-                        &mut ground,
-                    );
-
-                    let _ = data_processing::generate_world(
-                        parsed_elements,
-                        xzbbox,
-                        args.bbox,
-                        ground,
-                        &args,
-                    );
-                    // Session lock will be automatically released when _session_lock goes out of scope
-                    Ok(())
-                }
-                Err(e) => {
-                    let error_msg = format!("Failed to fetch data: {e}");
-                    emit_gui_error(&error_msg);
-                    // Session lock will be automatically released when _session_lock goes out of scope
-                    Err(error_msg)
-                }
+            // In low memory mode the parts are generated one after another, each with the
+            // memory of the previous one released
+            if args.low_memory {
+                let perf =
+                    PerformanceConfig::try_get().unwrap_or_else(PerformanceConfig::init_default);
+                // Failed parts are reported by `generate_from_args`, the rest is merging
+                let mut reported = false;
+                low_memory::generate_in_parts(&args, perf, |part| {
+                    generate_from_args(part, skip_osm_objects).map_err(|e| {
+                        reported = true;
+                        ArnisError::Io(e)
+                    })
+                })
+                .map_err(|error| {
+                    if !reported {
+                        emit_gui_error(&error);
+                    }
+                    error.to_string()
+                })
+            } else {
+                generate_from_args(&args, skip_osm_objects)
             }
+            // Session lock will be automatically released when _session_lock goes out of scope
         })
         .await
         {
-            let error_msg = format!("Error in blocking task: {e}");
-            eprintln!("{error_msg}");
-            emit_gui_error(&error_msg);
+            let error = if api_scheduler::is_cancelled() {
+                ArnisError::Cancelled
+            } else {
+                ArnisError::Io(format!("Error in blocking task: {e}"))
+            };
+            eprintln!("{error}");
+            emit_gui_error(&error);
             // Session lock will be automatically released when the task fails
         }
     });
 
     Ok(())
 }
+
+/// Fetches the data of the area of `args` and generates the world, or only the terrain
+/// when `skip_osm_objects` is set
+fn generate_from_args(args: &Args, skip_osm_objects: bool) -> Result<(), String> {
+    use progress::emit_gui_error;
+
+    // If skip_osm_objects is true (terrain-only mode), skip fetching and processing OSM data
+    if skip_osm_objects {
+        // Generate ground data (terrain) for terrain-only mode
+        let ground = ground::generate_ground_data(args);
+
+        // Create empty parsed_elements and xzbbox for terrain-only mode
+        let parsed_elements = Vec::new();
+        let (_coord_transformer, xzbbox) =
+            CoordTransformer::llbbox_to_xzbbox(&args.bbox(), args.scale)
+                .map_err(|e| format!("Failed to create coordinate transformer: {}", e))?;
+
+        if let Err(error) =
+            data_processing::generate_world(parsed_elements, xzbbox, args.bbox(), ground, &[], args)
+        {
+            emit_gui_error(&error);
+            return Err(error.to_string());
+        }
+        return Ok(());
+    }
+
+    // Run data fetch and world generation (standard mode: objects + terrain, or objects only)
+    let query_options = retrieve_data::QueryOptions {
+        filter: FeatureFilter::from_args(args),
+        ..Default::default()
+    };
+    match retrieve_data::fetch_data_from_overpass(
+        args.bbox(),
+        &query_options,
+        args.debug,
+        "requests",
+        None,
+    ) {
+        Ok(raw_data) => {
+            let raw_data = osm_parser::RawOsmData::from(raw_data);
+            if args.track_updates {
+                if let Err(e) = update::save_snapshot(&args.path, &raw_data) {
+                    eprintln!("Warning: {e}");
+                }
+            }
+            let (mut parsed_elements, mut xzbbox) = match osm_parser::parse_osm_data(
+                raw_data,
+                args.bbox(),
+                args.scale,
+                args.debug,
+                args.strict,
+            ) {
+                Ok(parsed) => parsed,
+                Err(error) => {
+                    emit_gui_error(&error);
+                    return Err(format!("Failed to parse data: {error}"));
+                }
+            };
+
+            // Refuse areas that would not fit into memory before spending time on them
+            let perf = PerformanceConfig::try_get().unwrap_or_else(PerformanceConfig::init_default);
+            let estimated_bytes = perf_config::estimate_generation_bytes(
+                xzbbox.bounding_rect().total_blocks(),
+                parsed_elements.len(),
+                args.fillground,
+            );
+            if let Err(error) = perf.check_memory_budget(estimated_bytes) {
+                emit_gui_error(&error);
+                return Err(error.to_string());
+            }
+            if !args.no_simplify {
+                simplify::simplify_for_scale(&mut parsed_elements, args.scale);
+            }
+            parsed_elements.sort_by(|el1, el2| {
+                let (el1_priority, el2_priority) =
+                    (osm_parser::get_priority(el1), osm_parser::get_priority(el2));
+                match (
+                    el1.tags().contains_key("landuse"),
+                    el2.tags().contains_key("landuse"),
+                ) {
+                    (true, false) => std::cmp::Ordering::Greater,
+                    (false, true) => std::cmp::Ordering::Less,
+                    _ => el1_priority.cmp(&el2_priority),
+                }
+            });
+
+            let mut ground = ground::generate_ground_data(args);
+
+            // Transform map (parsed_elements). Operations are defined in a json file
+            map_transformation::transform_map(&mut parsed_elements, &mut xzbbox, &mut ground);
+
+            if let Err(error) = data_processing::generate_world(
+                parsed_elements,
+                xzbbox,
+                args.bbox(),
+                ground,
+                &[],
+                args,
+            ) {
+                emit_gui_error(&error);
+                return Err(error.to_string());
+            }
+            Ok(())
+        }
+        Err(e) => {
+            // Errors of the download keep their category, anything else is a failed download
+            let error = match e.downcast::<ArnisError>() {
+                Ok(error) => *error,
+                Err(e) => ArnisError::Network(format!("Failed to fetch data: {e}")),
+            };
+            emit_gui_error(&error);
+            Err(error.to_string())
+        }
+    }
+}
@@ -562,9 +562,25 @@ $(document).ready(function () {
     window.addEventListener('message', function(event) {
         if (event.data && event.data.type === 'changeTileTheme') {
             changeTileTheme(event.data.theme);
+        } else if (event.data && event.data.type === 'showCachedAreas') {
+            showCachedAreas(event.data.areas);
         }
     });
 
+    // Outlines of the cached downloads, shown while the Cached Areas dialog is open
+    var cachedAreasLayer = L.featureGroup().addTo(map);
+    function showCachedAreas(areas) {
+        cachedAreasLayer.clearLayers();
+        areas.forEach(function (bbox) {
+            L.rectangle([[bbox[0], bbox[1]], [bbox[2], bbox[3]]], {
+                color: '#fecc44',
+                weight: 2,
+                dashArray: '6, 4',
+                fill: false
+            }).addTo(cachedAreasLayer);
+        });
+    }
+
     // Set the dropdown value in parent window if it exists
     if (window.parent && window.parent.document) {
         var dropdown = window.parent.document.getElementById('tile-theme-select');
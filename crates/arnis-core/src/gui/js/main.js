@@ -17,9 +17,12 @@ window.addEventListener("DOMContentLoaded", async () => {
   registerMessageEvent();
   window.selectWorld = selectWorld;
   window.startGeneration = startGeneration;
+  window.togglePause = togglePause;
+  window.cancelDownload = cancelDownload;
   setupProgressListener();
   initSettings();
   initWorldPicker();
+  initCachedAreas();
   handleBboxInput();
   const localization = await getLocalization();
   await applyLocalization(localization);
@@ -82,6 +85,8 @@ async function applyLocalization(localization) {
     "span[id='choose_world']": "choose_world",
     "#selected-world": "no_world_selected",
     "#start-button": "start_generation",
+    "#pause-button": "pause_generation",
+    "#cancel-download-button": "cancel_download",
     "h2[data-localize='progress']": "progress",
     "h2[data-localize='choose_world_modal_title']": "choose_world_modal_title",
     "button[data-localize='select_existing_world']": "select_existing_world",
@@ -90,18 +95,30 @@ async function applyLocalization(localization) {
     "label[data-localize='world_scale']": "world_scale",
     "label[data-localize='custom_bounding_box']": "custom_bounding_box",
     "label[data-localize='floodfill_timeout']": "floodfill_timeout",
-    // DEPRECATED: Ground level localization removed
-    // "label[data-localize='ground_level']": "ground_level",
+    "label[data-localize='ground_level']": "ground_level",
     "label[data-localize='language']": "language",
     "label[data-localize='generation_mode']": "generation_mode",
     "option[data-localize='mode_geo_terrain']": "mode_geo_terrain",
     "option[data-localize='mode_geo_only']": "mode_geo_only",
     "option[data-localize='mode_terrain_only']": "mode_terrain_only",
+    "option[data-localize='mode_map_surface']": "mode_map_surface",
     "label[data-localize='terrain']": "terrain",
     "label[data-localize='interior']": "interior",
     "label[data-localize='roof']": "roof",
     "label[data-localize='fillground']": "fillground",
+    "label[data-localize='power_saving']": "power_saving",
+    "label[data-localize='low_memory']": "low_memory",
+    "label[data-localize='categories']": "categories",
+    "span[data-localize='category_buildings']": "category_buildings",
+    "span[data-localize='category_roads']": "category_roads",
+    "span[data-localize='category_rail']": "category_rail",
+    "span[data-localize='category_water']": "category_water",
+    "span[data-localize='category_vegetation']": "category_vegetation",
+    "span[data-localize='category_barriers']": "category_barriers",
+    "span[data-localize='category_amenities']": "category_amenities",
     "label[data-localize='map_theme']": "map_theme",
+    "button[data-localize='cached_areas']": "cached_areas",
+    "h2[data-localize='cached_areas']": "cached_areas",
     ".footer-link": "footer_text",
     "button[data-localize='license_and_credits']": "license_and_credits",
     "h2[data-localize='license_and_credits']": "license_and_credits",
@@ -109,8 +126,7 @@ async function applyLocalization(localization) {
     // Placeholder strings
     "input[id='bbox-coords']": "placeholder_bbox",
     "input[id='floodfill-timeout']": "placeholder_floodfill",
-    // DEPRECATED: Ground level placeholder removed
-    // "input[id='ground-level']": "placeholder_ground"
+    "input[id='ground-level']": "placeholder_ground"
   };
 
   for (const selector in localizationElements) {
@@ -197,6 +213,10 @@ function setupProgressListener() {
     if (progress != -1) {
       progressBar.style.width = `${progress}%`;
       progressDetail.textContent = `${Math.round(progress)}%`;
+      // Downloads end with the fetch stage at 5%
+      if (progress >= 5) {
+        document.getElementById("cancel-download-button").style.display = "none";
+      }
     }
 
     if (message != "") {
@@ -205,14 +225,22 @@ function setupProgressListener() {
       if (message.startsWith("Error!")) {
         progressMessage.style.color = "#fa7878";
         generationButtonEnabled = true;
+        resetPauseButton();
       } else if (message.startsWith("Done!")) {
         progressMessage.style.color = "#7bd864";
         generationButtonEnabled = true;
+        resetPauseButton();
       } else {
         progressMessage.style.color = "";
+        progressMessage.title = "";
       }
     }
   });
+
+  // The guidance of an error is shown as its message, the details on hover
+  window.__TAURI__.event.listen("generation-error", (event) => {
+    progressMessage.title = event.payload.message;
+  });
 }
 
 function initSettings() {
@@ -329,6 +357,97 @@ function initSettings() {
   window.closeLicense = closeLicense;
 }
 
+function initCachedAreas() {
+  const cachedAreasModal = document.getElementById("cached-areas-modal");
+
+  async function openCachedAreas() {
+    window.closeSettings();
+    cachedAreasModal.style.display = "flex";
+    cachedAreasModal.style.justifyContent = "center";
+    cachedAreasModal.style.alignItems = "center";
+    await renderCachedAreas();
+  }
+
+  function closeCachedAreas() {
+    cachedAreasModal.style.display = "none";
+    showCachedAreasOnMap([]);
+  }
+
+  window.openCachedAreas = openCachedAreas;
+  window.closeCachedAreas = closeCachedAreas;
+}
+
+// Lists the downloaded areas with buttons to generate, download again or delete them
+async function renderCachedAreas() {
+  const list = document.getElementById("cached-areas-list");
+  const areas = (await invoke("gui_list_cached_areas")) || [];
+  list.innerHTML = "";
+  showCachedAreasOnMap(areas.map(area => area.bbox));
+
+  if (areas.length === 0) {
+    const empty = document.createElement("p");
+    localizeElement(window.localization, { element: empty }, "no_cached_areas");
+    list.appendChild(empty);
+    return;
+  }
+
+  for (const area of areas) {
+    const row = document.createElement("div");
+    row.className = "cached-area-row";
+
+    const info = document.createElement("span");
+    const savedAt = new Date(area.saved_at * 1000).toLocaleString();
+    const sizeMb = (area.size_bytes / (1024 * 1024)).toFixed(1);
+    info.textContent = `${area.bbox.map(value => value.toFixed(4)).join(", ")} · ${savedAt} · ${sizeMb} MB`;
+    row.appendChild(info);
+
+    row.appendChild(cachedAreaButton("use_cached_area", () => {
+      // Goes through the custom bounding box input, which also shows the area on the map
+      const inputBox = document.getElementById("bbox-coords");
+      inputBox.value = area.bbox.join(",");
+      inputBox.dispatchEvent(new Event("input"));
+      window.closeCachedAreas();
+    }));
+    if (area.refreshable) {
+      row.appendChild(cachedAreaButton("refresh_cached_area", async (button) => {
+        button.disabled = true;
+        try {
+          await invoke("gui_refresh_cached_area", { id: area.id });
+        } catch (error) {
+          console.error("Failed to refresh cached area: ", error);
+        }
+        await renderCachedAreas();
+      }));
+    }
+    row.appendChild(cachedAreaButton("delete_cached_area", async () => {
+      try {
+        await invoke("gui_delete_cached_area", { id: area.id });
+      } catch (error) {
+        console.error("Failed to delete cached area: ", error);
+      }
+      await renderCachedAreas();
+    }));
+
+    list.appendChild(row);
+  }
+}
+
+function cachedAreaButton(localizedStringKey, onClick) {
+  const button = document.createElement("button");
+  button.type = "button";
+  button.className = "cached-area-button";
+  localizeElement(window.localization, { element: button }, localizedStringKey);
+  button.addEventListener("click", () => onClick(button));
+  return button;
+}
+
+function showCachedAreasOnMap(bboxes) {
+  const mapIframe = document.querySelector('.map-container');
+  if (mapIframe && mapIframe.contentWindow) {
+    mapIframe.contentWindow.postMessage({ type: 'showCachedAreas', areas: bboxes }, '*');
+  }
+}
+
 function initWorldPicker() {
   // World Picker
   const worldPickerModal = document.getElementById("world-modal");
@@ -562,6 +681,29 @@ function handleWorldSelectionError(errorCode) {
 }
 
 let generationButtonEnabled = true;
+let generationPaused = false;
+
+async function togglePause() {
+  generationPaused = !generationPaused;
+  await invoke("gui_set_paused", { paused: generationPaused });
+
+  const pauseButton = document.getElementById("pause-button");
+  const key = generationPaused ? "resume_generation" : "pause_generation";
+  localizeElement(window.localization, { element: pauseButton }, key);
+}
+
+function resetPauseButton() {
+  generationPaused = false;
+  const pauseButton = document.getElementById("pause-button");
+  pauseButton.style.display = "none";
+  localizeElement(window.localization, { element: pauseButton }, "pause_generation");
+  document.getElementById("cancel-download-button").style.display = "none";
+}
+
+async function cancelDownload() {
+  await invoke("gui_cancel_download");
+  document.getElementById("cancel-download-button").style.display = "none";
+}
 /**
  * Initiates the world generation process
  * Validates required inputs and sends generation parameters to the backend
@@ -603,19 +745,23 @@ async function startGeneration() {
     var generationMode = document.getElementById("generation-mode-select").value;
     var terrain = (generationMode === "geo-terrain" || generationMode === "terrain-only");
     var skipOsmObjects = (generationMode === "terrain-only");
+    var mapMode = (generationMode === "map-surface");
 
     var interior = document.getElementById("interior-toggle").checked;
     var roof = document.getElementById("roof-toggle").checked;
     var fill_ground = document.getElementById("fillground-toggle").checked;
+    var power_saving = document.getElementById("power-saving-toggle").checked;
+    var low_memory = document.getElementById("low-memory-toggle").checked;
+    var skip_categories = Array.from(document.querySelectorAll(".category-toggle input"))
+      .filter(input => !input.checked)
+      .map(input => input.value);
     var scale = parseFloat(document.getElementById("scale-value-slider").value);
     var floodfill_timeout = parseInt(document.getElementById("floodfill-timeout").value, 10);
-    // var ground_level = parseInt(document.getElementById("ground-level").value, 10);
-    // DEPRECATED: Ground level input removed from UI
-    var ground_level = -62;
+    var ground_level = parseInt(document.getElementById("ground-level").value, 10);
 
     // Validate floodfill_timeout and ground_level
     floodfill_timeout = isNaN(floodfill_timeout) || floodfill_timeout < 0 ? 20 : floodfill_timeout;
-    ground_level = isNaN(ground_level) || ground_level < -62 ? 20 : ground_level;
+    ground_level = isNaN(ground_level) || ground_level < -62 || ground_level > 318 ? -62 : ground_level;
 
     // Pass the selected options to the Rust backend
     await invoke("gui_start_generation", {
@@ -629,12 +775,18 @@ async function startGeneration() {
         interiorEnabled: interior,
         roofEnabled: roof,
         fillgroundEnabled: fill_ground,
+        mapMode: mapMode,
+        powerSaving: power_saving,
+        lowMemory: low_memory,
+        skipCategories: skip_categories,
         isNewWorld: isNewWorld,
         spawnPoint: spawnPoint
     });
 
     console.log("Generation process started.");
     generationButtonEnabled = false;
+    document.getElementById("pause-button").style.display = "";
+    document.getElementById("cancel-download-button").style.display = "";
   } catch (error) {
     console.error("Error starting generation:", error);
     generationButtonEnabled = true;
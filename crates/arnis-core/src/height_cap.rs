@@ -0,0 +1,52 @@
+//! Fitting tall buildings into the build height (`--max-building-height`). Cutting them at
+//! the cap turns a skyline of towers into one flat roof line, so by default heights above
+//! half the cap are compressed instead, which keeps taller buildings taller.
+
+use clap::ValueEnum;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum HeightCap {
+    /// Compress heights above half the cap, approaching but never reaching it
+    #[default]
+    Compress,
+    /// Cut heights at the cap
+    Clip,
+}
+
+/// Height of a building of `height` blocks under a cap of `max_height` blocks
+pub fn apply(height: i32, max_height: i32, mode: HeightCap) -> i32 {
+    match mode {
+        HeightCap::Clip => height.min(max_height),
+        HeightCap::Compress => {
+            let knee = max_height / 2;
+            if height <= knee {
+                return height;
+            }
+            // Rises with slope 1 at the knee and flattens out towards the cap
+            let range = f64::from(max_height - knee);
+            let excess = f64::from(height - knee);
+            knee + (range * (1.0 - (-excess / range).exp())) as i32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(apply(30, 100, HeightCap::Clip), 30);
+        assert_eq!(apply(300, 100, HeightCap::Clip), 100);
+
+        // Low buildings keep their height
+        assert_eq!(apply(50, 100, HeightCap::Compress), 50);
+        // Taller ones stay below the cap and in order
+        let heights: Vec<i32> = [60, 100, 200, 300]
+            .iter()
+            .map(|&height| apply(height, 100, HeightCap::Compress))
+            .collect();
+        assert!(heights.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(apply(828, 100, HeightCap::Compress) < 100);
+    }
+}
@@ -0,0 +1,328 @@
+//! Experimental: ground cover from aerial imagery where OSM has no landuse.
+//!
+//! Tiles come from a user-supplied endpoint, either an XYZ template with `{z}`, `{x}` and
+//! `{y}` placeholders or a WMS GetMap URL with a `{bbox}` placeholder (filled in as
+//! `min_lng,min_lat,max_lng,max_lat` in EPSG:4326, 256x256 pixels per request). The color
+//! of each patch of ground picks the surface block; OSM landuse placed earlier always wins.
+
+use crate::api_scheduler;
+use crate::block_definitions::*;
+use crate::coordinate_system::cartesian::{XZBBox, XZPoint};
+use crate::coordinate_system::geographic::LLBBox;
+use crate::coordinate_system::transformation::CoordTransformer;
+use crate::disk_io;
+use fnv::{FnvHashMap, FnvHasher};
+use image::RgbImage;
+use std::f64::consts::PI;
+use std::hash::Hasher;
+use std::path::PathBuf;
+
+/// Side length in blocks of the ground patches that share one sampled color
+const CELL_SIZE: i32 = 4;
+/// Zoom level used when the area is small enough
+const MAX_ZOOM: u8 = 18;
+/// The zoom level is lowered until the area fits into this many tiles
+const MAX_TILES: u64 = 1024;
+/// Pixel size of WMS requests
+const WMS_TILE_SIZE: u32 = 256;
+
+/// Surface blocks for the ground of the whole area, in patches of `CELL_SIZE` blocks
+pub struct GroundImagery {
+    min_x: i32,
+    min_z: i32,
+    columns: usize,
+    cells: Vec<Option<Block>>,
+}
+
+impl GroundImagery {
+    pub fn fetch(
+        url_template: &str,
+        llbbox: &LLBBox,
+        xzbbox: &XZBBox,
+        scale: f64,
+    ) -> Result<Self, String> {
+        let source = TileSource::new(url_template)?;
+        let (transformer, untranslated) = CoordTransformer::llbbox_to_xzbbox(llbbox, scale)?;
+        let zoom = zoom_for(llbbox);
+
+        let (min_x, min_z) = (xzbbox.min_x(), xzbbox.min_z());
+        // The map may have been moved by a translate operation
+        let offset_x = min_x - untranslated.min_x();
+        let offset_z = min_z - untranslated.min_z();
+        let columns = ((xzbbox.max_x() - min_x) / CELL_SIZE + 1) as usize;
+        let rows = ((xzbbox.max_z() - min_z) / CELL_SIZE + 1) as usize;
+
+        let client = reqwest::blocking::Client::new();
+        let mut tiles: FnvHashMap<(u32, u32), Option<RgbImage>> = FnvHashMap::default();
+        let mut failed: usize = 0;
+        let mut cells = Vec::with_capacity(columns * rows);
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let center = XZPoint::new(
+                    min_x - offset_x + column as i32 * CELL_SIZE + CELL_SIZE / 2,
+                    min_z - offset_z + row as i32 * CELL_SIZE + CELL_SIZE / 2,
+                );
+                let point = transformer.inverse_transform_point(center)?;
+                let (tile, px, py) = source.locate(point.lat(), point.lng(), zoom);
+
+                let image = tiles.entry(tile).or_insert_with(|| {
+                    source
+                        .load(&client, tile.0, tile.1, zoom)
+                        .inspect_err(|e| {
+                            failed += 1;
+                            eprintln!("Warning: imagery tile {zoom}/{}/{}: {e}", tile.0, tile.1);
+                        })
+                        .ok()
+                });
+
+                cells.push(
+                    image
+                        .as_ref()
+                        .and_then(|image| ground_block_for_color(average_color(image, px, py))),
+                );
+            }
+        }
+
+        if failed > 0 && failed == tiles.len() {
+            return Err("No imagery tiles could be loaded".to_string());
+        }
+
+        Ok(GroundImagery {
+            min_x,
+            min_z,
+            columns,
+            cells,
+        })
+    }
+
+    /// Surface block for the given position, `None` where the imagery gave no clear answer
+    pub fn block_at(&self, x: i32, z: i32) -> Option<Block> {
+        let column = ((x - self.min_x) / CELL_SIZE) as usize;
+        let row = ((z - self.min_z) / CELL_SIZE) as usize;
+        *self.cells.get(row * self.columns + column)?
+    }
+}
+
+enum TileSource {
+    Xyz(String),
+    Wms(String),
+}
+
+impl TileSource {
+    fn new(url_template: &str) -> Result<Self, String> {
+        if url_template.contains("{bbox}") {
+            Ok(TileSource::Wms(url_template.to_string()))
+        } else if ["{z}", "{x}", "{y}"]
+            .iter()
+            .all(|placeholder| url_template.contains(placeholder))
+        {
+            Ok(TileSource::Xyz(url_template.to_string()))
+        } else {
+            Err("Imagery URL needs {z}, {x} and {y} placeholders, or {bbox} for WMS".to_string())
+        }
+    }
+
+    /// Tile containing the point, and the pixel position of the point inside of it
+    fn locate(&self, lat: f64, lng: f64, zoom: u8) -> ((u32, u32), f64, f64) {
+        let n = 2.0_f64.powi(zoom as i32);
+        let (x, y) = tile_position(lat, lng, n);
+        let tile = (x.floor() as u32, y.floor() as u32);
+
+        let fy = match self {
+            TileSource::Xyz(_) => y.fract(),
+            // WMS tiles are requested in plain latitude/longitude, so rows are linear in latitude
+            TileSource::Wms(_) => {
+                let (north, south) = (tile_lat(tile.1, n), tile_lat(tile.1 + 1, n));
+                (north - lat) / (north - south)
+            }
+        };
+        (tile, x.fract(), fy)
+    }
+
+    fn url(&self, x: u32, y: u32, zoom: u8) -> String {
+        match self {
+            TileSource::Xyz(template) => template
+                .replace("{z}", &zoom.to_string())
+                .replace("{x}", &x.to_string())
+                .replace("{y}", &y.to_string()),
+            TileSource::Wms(template) => {
+                let n = 2.0_f64.powi(zoom as i32);
+                let bbox = format!(
+                    "{},{},{},{}",
+                    x as f64 / n * 360.0 - 180.0,
+                    tile_lat(y + 1, n),
+                    (x + 1) as f64 / n * 360.0 - 180.0,
+                    tile_lat(y, n),
+                );
+                template
+                    .replace("{bbox}", &bbox)
+                    .replace("{width}", &WMS_TILE_SIZE.to_string())
+                    .replace("{height}", &WMS_TILE_SIZE.to_string())
+            }
+        }
+    }
+
+    fn load(
+        &self,
+        client: &reqwest::blocking::Client,
+        x: u32,
+        y: u32,
+        zoom: u8,
+    ) -> Result<RgbImage, String> {
+        let (TileSource::Xyz(template) | TileSource::Wms(template)) = self;
+        let tile_path = cache_dir(template)?.join(format!("z{zoom}_x{x}_y{y}.png"));
+        if let Ok(image) = image::open(&tile_path) {
+            return Ok(image.to_rgb8());
+        }
+
+        let response = api_scheduler::get(client, &self.url(x, y, zoom), &[])
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?;
+        let bytes = response.bytes().map_err(|e| e.to_string())?;
+        let image = image::load_from_memory(&bytes)
+            .map_err(|e| format!("Not an image: {e}"))?
+            .to_rgb8();
+        if let Err(e) = image.save(&tile_path) {
+            eprintln!("Warning: Failed to cache imagery tile: {e}");
+        }
+        Ok(image)
+    }
+}
+
+/// Latitude of the northern edge of a tile row
+fn tile_lat(y: u32, n: f64) -> f64 {
+    (PI * (1.0 - 2.0 * y as f64 / n)).sinh().atan().to_degrees()
+}
+
+/// Highest zoom level at which the area fits into `MAX_TILES` tiles
+fn zoom_for(llbbox: &LLBBox) -> u8 {
+    (1..=MAX_ZOOM)
+        .rev()
+        .find(|&zoom| {
+            let n = 2.0_f64.powi(zoom as i32);
+            let (min_x, min_y) = tile_position(llbbox.max().lat(), llbbox.min().lng(), n);
+            let (max_x, max_y) = tile_position(llbbox.min().lat(), llbbox.max().lng(), n);
+            let tiles =
+                (max_x.floor() - min_x.floor() + 1.0) * (max_y.floor() - min_y.floor() + 1.0);
+            tiles as u64 <= MAX_TILES
+        })
+        .unwrap_or(1)
+}
+
+/// Fractional tile coordinates of a point in the web mercator tiling
+fn tile_position(lat: f64, lng: f64, n: f64) -> (f64, f64) {
+    let x = (lng + 180.0) / 360.0 * n;
+    let y = (1.0 - lat.to_radians().tan().asinh() / PI) / 2.0 * n;
+    (x, y)
+}
+
+/// One cache directory per endpoint, so switching imagery doesn't mix up tiles
+fn cache_dir(url_template: &str) -> Result<PathBuf, String> {
+    let mut hasher = FnvHasher::default();
+    hasher.write(url_template.as_bytes());
+
+    let dir = disk_io::temp_dir()
+        .join("arnis-imagery-cache")
+        .join(format!("{:016x}", hasher.finish()));
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Average color of the 3x3 pixels around a relative position inside the image
+fn average_color(image: &RgbImage, fx: f64, fy: f64) -> [u8; 3] {
+    let (width, height) = image.dimensions();
+    let cx = ((fx * width as f64) as u32).min(width - 1);
+    let cy = ((fy * height as f64) as u32).min(height - 1);
+
+    let mut sum = [0u32; 3];
+    let mut count = 0;
+    for x in cx.saturating_sub(1)..=(cx + 1).min(width - 1) {
+        for y in cy.saturating_sub(1)..=(cy + 1).min(height - 1) {
+            let pixel = image.get_pixel(x, y);
+            for (total, channel) in sum.iter_mut().zip(pixel.0) {
+                *total += channel as u32;
+            }
+            count += 1;
+        }
+    }
+    sum.map(|total| (total / count) as u8)
+}
+
+/// Picks a ground block matching the color of a patch of aerial imagery.
+/// Returns `None` for colors that are more likely water, roofs or shadows than ground.
+pub fn ground_block_for_color([r, g, b]: [u8; 3]) -> Option<Block> {
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let brightness = (r + g + b) / 3;
+
+    if max - min < 20 {
+        // Grey
+        return match brightness {
+            0..=45 => None,
+            46..=110 => Some(GRAVEL),
+            111..=200 => Some(STONE),
+            _ => Some(SNOW_BLOCK),
+        };
+    }
+
+    if b >= g && b > r {
+        // Water and glass roofs
+        return None;
+    }
+
+    if g >= r {
+        // Vegetation: dense forest canopy is darker than meadows
+        return Some(match g {
+            0..=70 => PODZOL,
+            71..=100 => MOSS_BLOCK,
+            _ => GRASS_BLOCK,
+        });
+    }
+
+    // Red dominates: bare soil, fields and sand
+    if brightness > 160 && g > b + 20 {
+        Some(SAND)
+    } else if brightness > 90 {
+        Some(COARSE_DIRT)
+    } else {
+        Some(DIRT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ground_block_for_color() {
+        assert_eq!(ground_block_for_color([90, 140, 60]), Some(GRASS_BLOCK));
+        assert_eq!(ground_block_for_color([30, 55, 30]), Some(PODZOL));
+        assert_eq!(ground_block_for_color([220, 200, 150]), Some(SAND));
+        assert_eq!(ground_block_for_color([140, 110, 80]), Some(COARSE_DIRT));
+        assert_eq!(ground_block_for_color([80, 60, 45]), Some(DIRT));
+        assert_eq!(ground_block_for_color([245, 248, 250]), Some(SNOW_BLOCK));
+        assert_eq!(ground_block_for_color([100, 100, 105]), Some(GRAVEL));
+        assert_eq!(ground_block_for_color([40, 80, 140]), None);
+        assert_eq!(ground_block_for_color([15, 15, 20]), None);
+    }
+
+    #[test]
+    fn test_tile_source() {
+        assert!(TileSource::new("https://example.com/tiles.png").is_err());
+
+        let xyz = TileSource::new("https://example.com/{z}/{x}/{y}.jpg").unwrap();
+        assert_eq!(xyz.url(1, 2, 3), "https://example.com/3/1/2.jpg");
+        // The north-western quarter of the world at zoom 1
+        let (tile, fx, fy) = xyz.locate(45.0, -90.0, 1);
+        assert_eq!(tile, (0, 0));
+        assert!((fx - 0.5).abs() < 1e-9 && fy > 0.0 && fy < 1.0);
+
+        let wms = TileSource::new("https://example.com/wms?BBOX={bbox}&WIDTH={width}").unwrap();
+        let url = wms.url(1, 0, 1);
+        assert!(url.starts_with("https://example.com/wms?BBOX=0,0,180,85.05"));
+        assert!(url.ends_with("&WIDTH=256"));
+    }
+}
@@ -0,0 +1,343 @@
+//! `arnis inspect`: loads saved OSM data into the internal model and answers queries about
+//! it, to find out why a specific feature was generated the way it was.
+
+use crate::coordinate_system::geographic::LLBBox;
+use crate::osm_parser::{self, ProcessedElement};
+use crate::retrieve_data;
+use clap::Parser;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+/// Elements listed at most by `find`
+const MAX_LISTED: usize = 25;
+
+const HELP: &str = "\
+Filters are combined with AND: key, key=value, !key, and node/way/relation for the type.
+
+  count <filters>        Number of matching elements, e.g. `count building height`
+  find <filters>         IDs of matching elements, e.g. `find highway=primary !name`
+  values <key> <filters> Values of a tag with their counts, e.g. `values highway`
+  show <type>/<id>       Tags and geometry of one element, e.g. `show way/12345`
+  summary                Number of elements per type and main tag
+  help                   This help
+  quit                   Exit";
+
+/// Inspect saved OSM data (e.g. from --save-json-file) as Arnis understands it
+#[derive(Parser, Debug)]
+#[command(name = "arnis inspect")]
+pub struct InspectArgs {
+    /// JSON file with the saved OSM data
+    #[arg(long, value_name = "PATH")]
+    pub cached: PathBuf,
+
+    /// Bounding box the data was fetched for (defaults to the extent of the data)
+    #[arg(long, allow_hyphen_values = true, value_parser = LLBBox::from_str)]
+    pub bbox: Option<LLBBox>,
+
+    /// Run this query and exit instead of starting the interactive prompt, can be repeated
+    #[arg(long, short = 'c')]
+    pub command: Vec<String>,
+}
+
+pub fn run(args: InspectArgs) -> Result<(), String> {
+    let data = retrieve_data::fetch_data_from_file(&args.cached.to_string_lossy())
+        .map_err(|e| format!("Failed to load {}: {e}", args.cached.display()))?;
+    let bbox = match args.bbox {
+        Some(bbox) => bbox,
+        None => data_bbox(&data)?,
+    };
+    let (elements, _) =
+        osm_parser::parse_osm_data(data, bbox, 1.0, false, false).map_err(|e| e.to_string())?;
+
+    let mut stdout = std::io::stdout();
+    if !args.command.is_empty() {
+        for command in &args.command {
+            writeln!(stdout, "{}", execute(&elements, command)).map_err(|e| e.to_string())?;
+        }
+        return Ok(());
+    }
+
+    println!(
+        "Loaded {} elements. Type `help` for the available queries.",
+        elements.len()
+    );
+    let mut lines = std::io::stdin().lock().lines();
+    loop {
+        print!("> ");
+        stdout.flush().map_err(|e| e.to_string())?;
+
+        let Some(line) = lines.next() else {
+            return Ok(());
+        };
+        let line = line.map_err(|e| e.to_string())?;
+        let line = line.trim();
+        if matches!(line, "quit" | "exit") {
+            return Ok(());
+        }
+        if !line.is_empty() {
+            println!("{}", execute(&elements, line));
+        }
+    }
+}
+
+/// Extent of all nodes in the data, used when the original bounding box is not known
+fn data_bbox(data: &Value) -> Result<LLBBox, String> {
+    let (mut min_lat, mut min_lng) = (f64::MAX, f64::MAX);
+    let (mut max_lat, mut max_lng) = (f64::MIN, f64::MIN);
+
+    for element in data["elements"].as_array().into_iter().flatten() {
+        if let (Some(lat), Some(lng)) = (element["lat"].as_f64(), element["lon"].as_f64()) {
+            min_lat = min_lat.min(lat);
+            min_lng = min_lng.min(lng);
+            max_lat = max_lat.max(lat);
+            max_lng = max_lng.max(lng);
+        }
+    }
+
+    LLBBox::new(min_lat, min_lng, max_lat, max_lng)
+        .map_err(|e| format!("Could not determine the extent of the data, pass --bbox: {e}"))
+}
+
+/// Answers a single query
+pub fn execute(elements: &[ProcessedElement], line: &str) -> String {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    let rest: Vec<&str> = words.collect();
+
+    match command {
+        "count" => {
+            let count = elements.iter().filter(|e| matches_all(e, &rest)).count();
+            format!("{count} elements")
+        }
+        "find" => {
+            let found: Vec<String> = elements
+                .iter()
+                .filter(|e| matches_all(e, &rest))
+                .map(|e| format!("{}/{}", e.kind(), e.id()))
+                .collect();
+            let mut output = found
+                .iter()
+                .take(MAX_LISTED)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("\n");
+            if found.len() > MAX_LISTED {
+                output.push_str(&format!("\n... and {} more", found.len() - MAX_LISTED));
+            }
+            if found.is_empty() {
+                output = "No matching elements".to_string();
+            }
+            output
+        }
+        "values" => {
+            let Some((key, filters)) = rest.split_first() else {
+                return "Usage: values <key> <filters>".to_string();
+            };
+            let mut values: BTreeMap<&str, usize> = BTreeMap::new();
+            for element in elements.iter().filter(|e| matches_all(e, filters)) {
+                if let Some(value) = element.tags().get(*key) {
+                    *values.entry(value.as_str()).or_default() += 1;
+                }
+            }
+            if values.is_empty() {
+                return format!("No element has a {key} tag");
+            }
+            let mut values: Vec<(&str, usize)> = values.into_iter().collect();
+            values.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+            values
+                .iter()
+                .map(|(value, count)| format!("{count:>7}  {value}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        "show" => {
+            let Some((kind, id)) = rest.first().and_then(|arg| arg.split_once('/')) else {
+                return "Usage: show <type>/<id>".to_string();
+            };
+            let Ok(id) = id.parse::<u64>() else {
+                return format!("Invalid id: {id}");
+            };
+            match elements.iter().find(|e| e.kind() == kind && e.id() == id) {
+                Some(element) => describe(element),
+                None => format!("{kind}/{id} is not in the data"),
+            }
+        }
+        "summary" => summary(elements),
+        "help" | "" => HELP.to_string(),
+        _ => format!("Unknown query `{command}`, type `help` for the available queries"),
+    }
+}
+
+fn matches_all(element: &ProcessedElement, filters: &[&str]) -> bool {
+    filters.iter().all(|filter| matches_filter(element, filter))
+}
+
+fn matches_filter(element: &ProcessedElement, filter: &str) -> bool {
+    let tags = element.tags();
+    if let Some(key) = filter.strip_prefix('!') {
+        return !tags.contains_key(key);
+    }
+    if let Some((key, value)) = filter.split_once('=') {
+        return tags.get(key).map(String::as_str) == Some(value);
+    }
+    if matches!(filter, "node" | "way" | "relation") {
+        return element.kind() == filter;
+    }
+    tags.contains_key(filter)
+}
+
+fn describe(element: &ProcessedElement) -> String {
+    let mut lines = vec![format!("{}/{}", element.kind(), element.id())];
+
+    let tags: BTreeMap<&String, &String> = element.tags().iter().collect();
+    for (key, value) in tags {
+        lines.push(format!("  {key}={value}"));
+    }
+
+    match element {
+        ProcessedElement::Node(node) => lines.push(format!("  at x {} z {}", node.x, node.z)),
+        ProcessedElement::Way(way) => {
+            let closed = way.nodes.len() > 1 && way.nodes.first() == way.nodes.last();
+            lines.push(format!(
+                "  {} nodes ({})",
+                way.nodes.len(),
+                if closed { "closed" } else { "open" }
+            ));
+        }
+        ProcessedElement::Relation(rel) => {
+            for member in &rel.members {
+                lines.push(format!(
+                    "  {:?} way/{} with {} nodes",
+                    member.role,
+                    member.way.id,
+                    member.way.nodes.len()
+                ));
+            }
+        }
+    }
+
+    if let Some(center) = element.center() {
+        lines.push(format!("  center at x {} z {}", center.x, center.z));
+    }
+
+    lines.join("\n")
+}
+
+fn summary(elements: &[ProcessedElement]) -> String {
+    let mut per_type: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut per_key: BTreeMap<&str, usize> = BTreeMap::new();
+    let main_keys = [
+        "building", "highway", "landuse", "natural", "leisure", "amenity", "waterway", "railway",
+        "barrier",
+    ];
+
+    for element in elements {
+        *per_type.entry(element.kind()).or_default() += 1;
+        if let Some(key) = main_keys
+            .iter()
+            .find(|key| element.tags().contains_key(**key))
+        {
+            *per_key.entry(*key).or_default() += 1;
+        }
+    }
+
+    per_type
+        .iter()
+        .chain(per_key.iter())
+        .map(|(name, count)| format!("{count:>7}  {name}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm_parser::{ProcessedNode, ProcessedWay};
+    use std::collections::HashMap;
+
+    fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn elements() -> Vec<ProcessedElement> {
+        let node = |id, x, z| ProcessedNode {
+            id,
+            tags: HashMap::new(),
+            x,
+            z,
+        };
+        vec![
+            ProcessedElement::Way(ProcessedWay {
+                id: 10,
+                nodes: vec![node(1, 0, 0), node(2, 5, 0), node(3, 5, 5), node(1, 0, 0)],
+                tags: tags(&[("building", "yes"), ("height", "12")]),
+            }),
+            ProcessedElement::Way(ProcessedWay {
+                id: 11,
+                nodes: vec![node(1, 0, 0), node(2, 5, 0), node(3, 5, 5), node(1, 0, 0)],
+                tags: tags(&[("building", "house")]),
+            }),
+            ProcessedElement::Way(ProcessedWay {
+                id: 12,
+                nodes: vec![node(4, 0, 10), node(5, 20, 10)],
+                tags: tags(&[("highway", "primary"), ("name", "Main Street")]),
+            }),
+            ProcessedElement::Node(ProcessedNode {
+                id: 13,
+                tags: tags(&[("highway", "street_lamp")]),
+                x: 3,
+                z: 3,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_count_and_find() {
+        let elements = elements();
+        assert_eq!(execute(&elements, "count building"), "2 elements");
+        assert_eq!(execute(&elements, "count building height"), "1 elements");
+        assert_eq!(execute(&elements, "count highway !name"), "1 elements");
+        assert_eq!(execute(&elements, "count highway way"), "1 elements");
+        assert_eq!(execute(&elements, "find building=house"), "way/11");
+        assert_eq!(execute(&elements, "find amenity"), "No matching elements");
+    }
+
+    #[test]
+    fn test_values_and_show() {
+        let elements = elements();
+        assert_eq!(
+            execute(&elements, "values highway"),
+            "      1  primary\n      1  street_lamp"
+        );
+        assert_eq!(
+            execute(&elements, "show way/12"),
+            "way/12\n  highway=primary\n  name=Main Street\n  2 nodes (open)\n  center at x 10 z 10"
+        );
+        assert_eq!(
+            execute(&elements, "show way/99"),
+            "way/99 is not in the data"
+        );
+        assert!(execute(&elements, "frobnicate").starts_with("Unknown query"));
+    }
+
+    #[test]
+    fn test_data_bbox() {
+        let data = serde_json::json!({
+            "elements": [
+                { "type": "node", "id": 1, "lat": 54.1, "lon": 9.5 },
+                { "type": "node", "id": 2, "lat": 54.3, "lon": 9.2 },
+                { "type": "way", "id": 3, "nodes": [1, 2] },
+            ]
+        });
+        let bbox = data_bbox(&data).unwrap();
+        assert_eq!((bbox.min().lat(), bbox.min().lng()), (54.1, 9.2));
+        assert_eq!((bbox.max().lat(), bbox.max().lng()), (54.3, 9.5));
+
+        assert!(data_bbox(&serde_json::json!({ "elements": [] })).is_err());
+    }
+}
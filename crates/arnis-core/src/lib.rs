@@ -1,34 +1,80 @@
 #![allow(clippy::module_inception)]
 
+pub mod address_lots;
+pub mod api_scheduler;
 pub mod args;
 pub mod block_definitions;
+pub mod block_palette;
 pub mod bresenham;
+pub mod categories;
+pub mod checkpoint;
+pub mod chunking;
 pub mod colors;
 pub mod coordinate_system;
 pub mod cpu_info;
 pub mod data_processing;
+pub mod diff;
+pub mod disk_io;
+pub mod edge_clip;
 pub mod element_processing;
 pub mod elevation_data;
+pub mod elevation_provider;
+pub mod elytra_tour;
+pub mod error;
 pub mod floodfill;
+pub mod footprints;
+pub mod gazetteer;
+pub mod geojson_export;
+pub mod gpx;
 pub mod ground;
 #[cfg(feature = "gui")]
 pub mod gui;
+pub mod height_cap;
+pub mod imagery;
+pub mod inspect;
+pub mod lod;
+pub mod low_memory;
 pub mod map_transformation;
+pub mod merge;
 #[cfg(feature = "metrics")]
 pub mod metrics;
+pub mod night;
+pub mod osm_cache;
 pub mod osm_parser;
+pub mod osm_xml;
+pub mod overture;
+pub mod pause;
+pub mod pbf;
 pub mod perf_config;
+pub mod polygon_mask;
 #[cfg(feature = "gui")]
 pub mod progress;
+pub mod protobuf;
+pub mod resource_pack;
 pub mod retrieve_data;
+pub mod schematic;
+pub mod season;
+pub mod seeded_rng;
+pub mod simplify;
+pub mod spawn;
+pub mod stats_overlay;
+pub mod tag_coverage;
+pub mod telemetry;
 #[cfg(test)]
 pub mod test_utilities;
+pub mod throttle;
+pub mod trim;
+pub mod update;
+pub mod vector_tiles;
 pub mod version_check;
+pub mod weathering;
+pub mod world_edge;
 pub mod world_editor;
+pub mod world_hash;
 
 #[cfg(not(feature = "gui"))]
 pub mod progress {
-    pub fn emit_gui_error(_message: &str) {}
+    pub fn emit_gui_error(_error: &crate::error::ArnisError) {}
     pub fn emit_gui_progress_update(_progress: f64, _message: &str) {}
     pub fn is_running_with_gui() -> bool {
         false
@@ -36,6 +82,7 @@ pub mod progress {
 }
 
 pub use args::Args;
+pub use error::ArnisError;
 #[cfg(feature = "metrics")]
 pub use metrics::{MetricsRecorder, MetricsSnapshot};
 pub use perf_config::PerformanceConfig;
@@ -0,0 +1,138 @@
+//! Level-of-detail tiers, so that large areas can be generated with less detail (and less
+//! memory) away from the part the player actually cares about.
+
+use crate::args::Args;
+use crate::coordinate_system::cartesian::XZPoint;
+use crate::coordinate_system::geographic::LLBBox;
+use crate::coordinate_system::transformation::CoordTransformer;
+use crate::osm_parser::ProcessedElement;
+use clap::ValueEnum;
+
+/// How much of an element is generated
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum DetailLevel {
+    /// Everything, including interiors, roofs and small details
+    Full,
+    /// Buildings without interiors and roofs, no small details such as trees or benches
+    Simplified,
+    /// Only flat, color-coded footprints like in map mode
+    Footprints,
+}
+
+/// Decides the detail level of every element, either uniformly or by distance from a focal point
+#[derive(Debug, Clone)]
+pub struct LodSettings {
+    focus: Option<XZPoint>,
+    full_radius: f64,
+    simplified_radius: f64,
+    uniform: DetailLevel,
+}
+
+impl LodSettings {
+    /// `full_radius` and `simplified_radius` are given in blocks
+    pub fn new(
+        focus: Option<XZPoint>,
+        full_radius: f64,
+        simplified_radius: f64,
+        uniform: DetailLevel,
+    ) -> Self {
+        Self {
+            focus,
+            full_radius,
+            simplified_radius: simplified_radius.max(full_radius),
+            uniform,
+        }
+    }
+
+    pub fn from_args(args: &Args, llbbox: &LLBBox) -> Result<Self, String> {
+        if args.map_mode {
+            return Ok(Self::new(None, 0.0, 0.0, DetailLevel::Footprints));
+        }
+
+        let focus = match args.lod_focus {
+            Some(llpoint) => {
+                let (transformer, _) = CoordTransformer::llbbox_to_xzbbox(llbbox, args.scale)?;
+                Some(transformer.transform_point(llpoint))
+            }
+            None => None,
+        };
+
+        Ok(Self::new(
+            focus,
+            args.lod_full_radius * args.scale,
+            args.lod_simplified_radius * args.scale,
+            args.lod,
+        ))
+    }
+
+    /// Detail level of the given position
+    pub fn level_at(&self, point: XZPoint) -> DetailLevel {
+        let Some(focus) = self.focus else {
+            return self.uniform;
+        };
+
+        let dx = (point.x - focus.x) as f64;
+        let dz = (point.z - focus.z) as f64;
+        let distance = (dx * dx + dz * dz).sqrt();
+
+        if distance <= self.full_radius {
+            DetailLevel::Full
+        } else if distance <= self.simplified_radius {
+            DetailLevel::Simplified
+        } else {
+            DetailLevel::Footprints
+        }
+    }
+
+    /// Detail level of an element, judged by the center of its nodes
+    pub fn level_of(&self, element: &ProcessedElement) -> DetailLevel {
+        if self.focus.is_none() {
+            return self.uniform;
+        }
+
+        match element.center() {
+            Some(center) => self.level_at(center),
+            None => self.uniform,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uniform_level() {
+        let lod = LodSettings::new(None, 100.0, 300.0, DetailLevel::Simplified);
+        assert_eq!(lod.level_at(XZPoint::new(0, 0)), DetailLevel::Simplified);
+        assert_eq!(
+            lod.level_at(XZPoint::new(5000, 5000)),
+            DetailLevel::Simplified
+        );
+    }
+
+    #[test]
+    fn test_level_by_distance() {
+        let lod = LodSettings::new(
+            Some(XZPoint::new(1000, 1000)),
+            100.0,
+            300.0,
+            DetailLevel::Full,
+        );
+        assert_eq!(lod.level_at(XZPoint::new(1000, 1000)), DetailLevel::Full);
+        assert_eq!(lod.level_at(XZPoint::new(1100, 1000)), DetailLevel::Full);
+        assert_eq!(
+            lod.level_at(XZPoint::new(1000, 1200)),
+            DetailLevel::Simplified
+        );
+        assert_eq!(
+            lod.level_at(XZPoint::new(1300, 1300)),
+            DetailLevel::Footprints
+        );
+
+        // the simplified tier can't be smaller than the full one
+        let lod = LodSettings::new(Some(XZPoint::new(0, 0)), 500.0, 100.0, DetailLevel::Full);
+        assert_eq!(lod.level_at(XZPoint::new(400, 0)), DetailLevel::Full);
+        assert_eq!(lod.level_at(XZPoint::new(600, 0)), DetailLevel::Footprints);
+    }
+}
@@ -0,0 +1,423 @@
+//! Low memory mode (`--low-memory`): instead of generating a large area at once, the area is
+//! split into parts sized for the memory of this machine, the parts are generated one after
+//! another into temporary worlds and finally merged into the target world. Only one part is
+//! held in memory at a time.
+//!
+//! Parts overlap by a few chunks so the chunks along a seam are complete in one of them; the
+//! merge keeps the version with the most content.
+//!
+//! With `--preview-parts`, the parts done so far are merged after every part, and
+//! `missing_areas.json` in the world lists the areas that are still missing.
+
+use crate::args::Args;
+use crate::categories::FeatureFilter;
+use crate::chunking::{preflight_chunks, split_into_chunks, ChunkConfig, ChunkPreset};
+use crate::coordinate_system::geographic::LLBBox;
+use crate::coordinate_system::transformation::geo_distance;
+use crate::disk_io;
+use crate::error::ArnisError;
+use crate::merge::{self, MergeArgs};
+use crate::perf_config::PerformanceConfig;
+use crate::retrieve_data::{self, QueryOptions};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+/// Meters per degree of latitude
+const METERS_PER_DEGREE: f64 = 111_320.0;
+/// Elements per km² assumed when sizing the parts, an average city center
+const PLANNING_DENSITY: f64 = 2000.0;
+/// Areas not generated yet in a preview, in the world directory
+const MISSING_AREAS_FILE: &str = "missing_areas.json";
+
+/// Bounding boxes of the parts of `bbox`. The first part contains the north-west corner, so
+/// the merged world has the same origin as one generated in one go.
+///
+/// Before overlapping them, the parts are checked against the memory budget with the number
+/// of elements in them from `element_count`: dense parts are split further, and an area too
+/// dense for the budget even at the smallest size is refused, see `preflight_chunks`.
+pub fn plan_parts(
+    bbox: &LLBBox,
+    perf: &PerformanceConfig,
+    scale: f64,
+    preset: ChunkPreset,
+    fillground: bool,
+    element_count: impl Fn(&LLBBox) -> usize,
+) -> Result<Vec<LLBBox>, ArnisError> {
+    let mut platform = perf.platform.clone();
+    platform.available_memory_gb = platform
+        .available_memory_gb
+        .min(perf.effective_max_ram_bytes as f64 / (1024.0 * 1024.0 * 1024.0));
+    let config = ChunkConfig::with_preset(&platform, PLANNING_DENSITY, scale, preset);
+    let budget_bytes = (platform.available_memory_gb * 1024.0 * 1024.0 * 1024.0) as u64;
+    let chunks = preflight_chunks(
+        split_into_chunks(bbox, &config),
+        element_count,
+        scale,
+        fillground,
+        budget_bytes,
+    )
+    .map_err(ArnisError::MemoryBudget)?;
+    let mut parts = with_overlap(bbox, &chunks, scale, preset.overlap_blocks());
+
+    let north_west = parts
+        .iter()
+        .position(|part| {
+            part.max().lat() >= bbox.max().lat() && part.min().lng() <= bbox.min().lng()
+        })
+        .unwrap_or(0);
+    parts.swap(0, north_west);
+    Ok(parts)
+}
+
+/// Elements of the data of a part, from Overpass, or estimated from the average density if
+/// they can't be counted
+fn count_elements(args: &Args, part: &LLBBox) -> usize {
+    let query_options = QueryOptions {
+        date: args.date.clone(),
+        template: None,
+        filter: FeatureFilter::from_args(args),
+    };
+    retrieve_data::count_elements(part, &query_options, &args.downloader).unwrap_or_else(|e| {
+        eprintln!(
+            "Warning: Failed to count the elements of {part}, assuming an average density: {e}"
+        );
+        let (height_m, width_m) = geo_distance(part.min(), part.max());
+        (height_m * width_m / 1_000_000.0 * PLANNING_DENSITY) as usize
+    })
+}
+
+/// Extends every part `overlap_blocks` into its neighbors without growing beyond `bbox`
+fn with_overlap(bbox: &LLBBox, parts: &[LLBBox], scale: f64, overlap_blocks: f64) -> Vec<LLBBox> {
+    let (min, max) = (bbox.min(), bbox.max());
+    let lat_margin = overlap_blocks / scale / METERS_PER_DEGREE;
+    let center_lat = (min.lat() + max.lat()) / 2.0;
+    let lng_margin = lat_margin / center_lat.to_radians().cos().max(0.01);
+
+    parts
+        .iter()
+        .map(|part| {
+            LLBBox::new(
+                (part.min().lat() - lat_margin).max(min.lat()),
+                (part.min().lng() - lng_margin).max(min.lng()),
+                (part.max().lat() + lat_margin).min(max.lat()),
+                (part.max().lng() + lng_margin).min(max.lng()),
+            )
+            .unwrap_or(*part)
+        })
+        .collect()
+}
+
+/// Generates the area of `args` part by part with `generate_part` and merges the parts into
+/// the world at `args.path`. `generate_part` gets the arguments of a single part, which
+/// differ from `args` only in the bounding box and the world path.
+pub fn generate_in_parts(
+    args: &Args,
+    perf: &PerformanceConfig,
+    generate_part: impl FnMut(&Args) -> Result<(), ArnisError>,
+) -> Result<(), ArnisError> {
+    println!("Checking the density of the area...");
+    let parts = plan_parts(
+        &args.bbox(),
+        perf,
+        args.scale,
+        args.chunking,
+        args.fillground,
+        |part| count_elements(args, part),
+    )?;
+    generate_parts(args, &parts, generate_part)
+}
+
+/// Generates the planned `parts` of the area of `args` one after another, and merges them
+/// into the world at `args.path` once all of them succeeded
+fn generate_parts(
+    args: &Args,
+    parts: &[LLBBox],
+    mut generate_part: impl FnMut(&Args) -> Result<(), ArnisError>,
+) -> Result<(), ArnisError> {
+    if parts.len() < 2 {
+        println!("The area fits into memory, generating it in one go");
+        return generate_part(&Args {
+            low_memory: false,
+            ..args.clone()
+        });
+    }
+
+    let work_dir = disk_io::temp_dir().join(format!("arnis-low-memory-{}", std::process::id()));
+    let result = (|| {
+        let mut worlds = Vec::with_capacity(parts.len());
+        for (index, part) in parts.iter().enumerate() {
+            println!("Generating part {} of {} ({part})", index + 1, parts.len());
+            let world = work_dir.join(format!("part-{index}"));
+            prepare_world(&args.path, &world)?;
+            generate_part(&Args {
+                bbox: Some(*part),
+                path: world.clone(),
+                low_memory: false,
+                ..args.clone()
+            })
+            .map_err(|e| e.context(&format!("Failed to generate part {}", index + 1)))?;
+            worlds.push(world);
+
+            if args.preview_parts && index + 1 < parts.len() {
+                println!("Merging {} parts for a preview", worlds.len());
+                merge::run(MergeArgs {
+                    output: args.path.clone(),
+                    worlds: worlds.clone(),
+                    overlap: args.overlap,
+                })
+                .map_err(ArnisError::Io)?;
+                write_missing_areas(&args.path, &parts[index + 1..], parts.len())?;
+            }
+        }
+
+        println!(
+            "Merging {} parts into {}",
+            worlds.len(),
+            args.path.display()
+        );
+        merge::run(MergeArgs {
+            output: args.path.clone(),
+            worlds,
+            overlap: args.overlap,
+        })
+        .map_err(ArnisError::Io)?;
+        remove_missing_areas(&args.path);
+        Ok(())
+    })();
+
+    if let Err(e) = std::fs::remove_dir_all(&work_dir) {
+        eprintln!("Failed to remove {}: {e}", work_dir.display());
+    }
+    result
+}
+
+/// Lists the parts of a preview that are not generated yet, by their bounding boxes
+fn write_missing_areas(world: &Path, missing: &[LLBBox], total: usize) -> Result<(), ArnisError> {
+    let manifest = json!({
+        "parts_total": total,
+        "parts_done": total - missing.len(),
+        "missing": missing.iter().map(|part| part.to_string()).collect::<Vec<_>>(),
+    });
+    let path = world.join(MISSING_AREAS_FILE);
+    std::fs::write(&path, manifest.to_string())
+        .map_err(|e| ArnisError::Io(format!("Failed to write {}: {e}", path.display())))
+}
+
+fn remove_missing_areas(world: &Path) {
+    let path = world.join(MISSING_AREAS_FILE);
+    if path.exists() {
+        if let Err(e) = std::fs::remove_file(&path) {
+            eprintln!("Failed to remove {}: {e}", path.display());
+        }
+    }
+}
+
+/// Flags of the whole area that single parts leave out
+const WHOLE_AREA_FLAGS: [&str; 3] = ["--low-memory", "--preview-parts", "--update"];
+/// Options of the whole area that single parts leave out or replace, with their value: the
+/// area and world of the part, options applied to the merged world, and output files every
+/// part would overwrite
+const WHOLE_AREA_OPTIONS: [&str; 10] = [
+    "--bbox",
+    "--path",
+    "--format",
+    "--into-world",
+    "--offset",
+    "--gpx",
+    "--extra-bbox",
+    "--second-path",
+    "--second-palette",
+    "--save-json-file",
+];
+/// Options of the whole area that single parts leave out, whose value is optional
+const WHOLE_AREA_OPTIONAL_VALUE: [&str; 1] = ["--verify"];
+
+/// Command line that generates a single part: the command line of the whole area (without
+/// the program name) with the bounding box and world path of the part, and without the
+/// options of the whole area, see `WHOLE_AREA_FLAGS` and `WHOLE_AREA_OPTIONS`. The merged
+/// world is verified instead of the parts.
+pub fn part_command_line(args: &[String], part: &Args) -> Vec<String> {
+    let mut command_line = Vec::with_capacity(args.len() + 2);
+    let mut rest = args.iter().peekable();
+    while let Some(arg) = rest.next() {
+        let name = arg.split_once('=').map_or(arg.as_str(), |(name, _)| name);
+        if WHOLE_AREA_FLAGS.contains(&name) {
+            continue;
+        }
+        if WHOLE_AREA_OPTIONS.contains(&name) {
+            if name == arg {
+                rest.next();
+            }
+            continue;
+        }
+        if WHOLE_AREA_OPTIONAL_VALUE.contains(&name) {
+            if name == arg && rest.peek().is_some_and(|value| !value.starts_with('-')) {
+                rest.next();
+            }
+            continue;
+        }
+        command_line.push(arg.clone());
+    }
+    command_line.push(format!("--bbox={}", part.bbox()));
+    command_line.push(format!("--path={}", part.path.display()));
+    command_line
+}
+
+/// Creates an empty world for a part, with the settings of the target world
+pub(crate) fn prepare_world(target: &Path, world: &Path) -> Result<(), ArnisError> {
+    std::fs::create_dir_all(world.join("region"))
+        .map_err(|e| ArnisError::Io(format!("Failed to create {}: {e}", world.display())))?;
+    let level: PathBuf = target.join("level.dat");
+    if level.is_file() {
+        std::fs::copy(&level, world.join("level.dat"))
+            .map_err(|e| ArnisError::Io(format!("Failed to copy level.dat: {e}")))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu_info::PlatformInfo;
+    use crate::perf_config::CpuOptMode;
+    use clap::Parser;
+
+    #[test]
+    fn test_with_overlap() {
+        let bbox = LLBBox::from_str("48.0,11.0,48.1,11.2").unwrap();
+        let parts = [
+            LLBBox::from_str("48.0,11.0,48.05,11.1").unwrap(),
+            LLBBox::from_str("48.05,11.1,48.1,11.2").unwrap(),
+        ];
+        let extended = with_overlap(&bbox, &parts, 1.0, 48.0);
+
+        // Parts grow into each other, but not beyond the area
+        assert!(extended[0].max().lat() > 48.05 && extended[0].max().lng() > 11.1);
+        assert!(extended[1].min().lat() < 48.05 && extended[1].min().lng() < 11.1);
+        assert_eq!(extended[0].min(), bbox.min());
+        assert_eq!(extended[1].max(), bbox.max());
+    }
+
+    #[test]
+    fn test_plan_parts_preflight() {
+        let bbox = LLBBox::from_str("48.0,11.0,48.02,11.02").unwrap();
+        let perf = PerformanceConfig {
+            effective_max_ram_bytes: 1 << 30,
+            effective_threads: 1,
+            cpu_opt_mode: CpuOptMode::Auto,
+            platform: PlatformInfo {
+                available_memory_gb: 1.0,
+                ..PlatformInfo::detect()
+            },
+        };
+        let area = |part: &LLBBox| {
+            (part.max().lat() - part.min().lat()) * (part.max().lng() - part.min().lng())
+        };
+        // Elements spread evenly over the area
+        let plan = |elements: usize| {
+            plan_parts(&bbox, &perf, 1.0, ChunkPreset::Balanced, false, |part| {
+                (elements as f64 * area(part) / area(&bbox)) as usize
+            })
+        };
+
+        // Sparse areas are a single part, dense ones are split further or refused
+        assert_eq!(plan(100).unwrap().len(), 1);
+        assert!(plan(400_000).unwrap().len() > 1);
+        let error = plan(50_000_000).unwrap_err();
+        assert_eq!(error.code(), "memory_budget");
+        assert!(error.to_string().contains("too dense"));
+    }
+
+    #[test]
+    fn test_failed_part_aborts() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmpdir.path().join("region")).unwrap();
+        let path = tmpdir.path().to_str().unwrap();
+        let args = Args::parse_from(["arnis", "--path", path, "--bbox", "48.0,11.0,48.1,11.2"]);
+        let parts = [
+            LLBBox::from_str("48.0,11.0,48.05,11.1").unwrap(),
+            LLBBox::from_str("48.05,11.1,48.1,11.2").unwrap(),
+        ];
+
+        let mut generated = 0;
+        let result = generate_parts(&args, &parts, |_| {
+            generated += 1;
+            Err(ArnisError::Io("exit status: 1".to_string()))
+        });
+
+        // Neither the following parts nor the merge run
+        let error = result.unwrap_err();
+        assert!(error.to_string().starts_with("Failed to generate part 1"));
+        assert_eq!(generated, 1);
+        assert_eq!(
+            std::fs::read_dir(tmpdir.path().join("region"))
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_missing_areas() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let missing = [LLBBox::from_str("48.05,11.1,48.1,11.2").unwrap()];
+        write_missing_areas(tmpdir.path(), &missing, 3).unwrap();
+
+        let path = tmpdir.path().join(MISSING_AREAS_FILE);
+        let manifest: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(manifest["parts_done"], 2);
+        assert_eq!(manifest["missing"], json!([missing[0].to_string()]));
+
+        remove_missing_areas(tmpdir.path());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_part_command_line() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmpdir.path().join("region")).unwrap();
+        let path = tmpdir.path().to_str().unwrap();
+        let part = Args::parse_from(["arnis", "--path", path, "--bbox", "-1.5,2,-1,3"]);
+
+        let args: Vec<String> = [
+            "--bbox=1,2,3,4",
+            "--low-memory",
+            "--preview-parts",
+            "--terrain",
+            "--format=schematic",
+            "--offset",
+            "16,16",
+            "--path",
+            "world",
+            "--gpx",
+            "route.gpx",
+            "--extra-bbox=5,6,7,8",
+            "--update",
+            "--seed=42",
+            "--verify",
+            "--second-palette",
+            "palette.json",
+            "--second-path=second",
+            "--save-json-file",
+            "data.json",
+            "--verify",
+            "00ff00ff00ff00ff",
+            "--scale",
+            "2",
+        ]
+        .map(String::from)
+        .to_vec();
+        assert_eq!(
+            part_command_line(&args, &part),
+            [
+                "--terrain".to_string(),
+                "--seed=42".to_string(),
+                "--scale".to_string(),
+                "2".to_string(),
+                "--bbox=-1.5,2,-1,3".to_string(),
+                format!("--path={path}"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,723 @@
+//! `arnis merge`: combines worlds generated separately, e.g. neighboring areas or the parts
+//! of a large area generated one after another, into a single world. The worlds are placed
+//! by the georeference in their `metadata.json`; where they overlap, the chunk with the most
+//! content is kept by default, so generated chunks win over the empty base chunks around them.
+//! `--overlap` picks another strategy: the first world listed, the most recently written one,
+//! or a majority vote per block.
+//!
+//! `--into-world` writes a generated area into an existing world the same way, replacing only
+//! the chunks of the area.
+
+use crate::schematic::{block_state, unpack_section};
+use crate::trim::{open_region, region_coords};
+use crate::world_editor::WorldMetadata;
+use clap::{Parser, ValueEnum};
+use fastanvil::Region;
+use fastnbt::{LongArray, Value};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Merge worlds generated by Arnis into one world
+#[derive(Parser, Debug)]
+#[command(name = "arnis merge")]
+pub struct MergeArgs {
+    /// Directory of the merged world, created if missing
+    #[arg(long, value_name = "PATH")]
+    pub output: PathBuf,
+
+    /// Worlds to merge; the first one defines the coordinates of the merged world
+    #[arg(required = true, num_args = 2..)]
+    pub worlds: Vec<PathBuf>,
+
+    /// Which chunk is kept where worlds overlap
+    #[arg(long, value_enum, default_value_t = OverlapStrategy::Largest)]
+    pub overlap: OverlapStrategy,
+}
+
+/// How a chunk is picked where several worlds overlap. Chunks with the most content suit
+/// terrain and the base chunks around areas, a fixed priority keeps roads along a seam from
+/// one world, and a vote per block smooths out differences between three or more worlds.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OverlapStrategy {
+    /// The chunk with the most content
+    #[default]
+    Largest,
+    /// The chunk of the world listed first
+    First,
+    /// The chunk of the world written last
+    Newest,
+    /// Every block as most worlds have it, on top of the chunk with the most content
+    Majority,
+}
+
+/// A version of a chunk of the merged world in one of the worlds
+struct ChunkSource {
+    world: usize,
+    region: (i32, i32),
+    chunk: (usize, usize),
+    size: usize,
+    modified: Option<SystemTime>,
+}
+
+/// Chunks of the merged world with their versions, by region of the merged world
+type ChunksByRegion = HashMap<(i32, i32), Vec<((i32, i32), Vec<ChunkSource>)>>;
+
+pub fn run(args: MergeArgs) -> Result<(), String> {
+    let metadata: Vec<WorldMetadata> = args
+        .worlds
+        .iter()
+        .map(|world| WorldMetadata::load(world))
+        .collect::<Result<_, _>>()?;
+
+    let offsets: Vec<(i32, i32)> = metadata
+        .iter()
+        .zip(&args.worlds)
+        .map(|(world, path)| {
+            let (offset, error) = chunk_offset(&metadata[0], world);
+            if error > 0 {
+                println!(
+                    "Warning: {} is shifted by {error} blocks to align its chunks",
+                    path.display()
+                );
+            }
+            offset
+        })
+        .collect();
+
+    // All versions of every chunk of the merged world, in the order of the worlds
+    let mut candidates: HashMap<(i32, i32), Vec<ChunkSource>> = HashMap::new();
+    for (world, path) in args.worlds.iter().enumerate() {
+        for ((region_x, region_z), region_path) in region_files(path)? {
+            let modified = std::fs::metadata(&region_path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            let mut region = open_region(&region_path)?;
+            for chunk in region.iter().flatten() {
+                let target = (
+                    region_x * 32 + chunk.x as i32 + offsets[world].0,
+                    region_z * 32 + chunk.z as i32 + offsets[world].1,
+                );
+                candidates.entry(target).or_default().push(ChunkSource {
+                    world,
+                    region: (region_x, region_z),
+                    chunk: (chunk.x, chunk.z),
+                    size: chunk.data.len(),
+                    modified,
+                });
+            }
+        }
+    }
+
+    let mut by_region: ChunksByRegion = HashMap::new();
+    for (target, mut versions) in candidates {
+        let kept = pick_chunk(&versions, args.overlap);
+        // The kept version goes first, the others only matter for the vote
+        versions.swap(0, kept);
+        if args.overlap != OverlapStrategy::Majority {
+            versions.truncate(1);
+        }
+        by_region
+            .entry((target.0.div_euclid(32), target.1.div_euclid(32)))
+            .or_default()
+            .push((target, versions));
+    }
+
+    let region_dir = args.output.join("region");
+    std::fs::create_dir_all(&region_dir)
+        .map_err(|e| format!("Failed to create {}: {e}", region_dir.display()))?;
+
+    let mut written: usize = 0;
+    for ((region_x, region_z), chunks) in by_region {
+        let path = region_dir.join(format!("r.{region_x}.{region_z}.mca"));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+        let mut output = Region::create(file)
+            .map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+
+        let mut sources: HashMap<(usize, (i32, i32)), Region<File>> = HashMap::new();
+        for (target, versions) in chunks {
+            let source = &versions[0];
+            let Some(data) = read_source_chunk(&mut sources, &args.worlds, source)? else {
+                continue;
+            };
+
+            // A tie between two worlds is no majority, so the vote needs at least three
+            let (dx, dz) = offsets[source.world];
+            let data = if versions.len() >= 3 {
+                let mut chunk: Value = fastnbt::from_bytes(&data)
+                    .map_err(|e| format!("Failed to parse chunk: {e}"))?;
+                let mut others: Vec<Value> = Vec::new();
+                for other in &versions[1..] {
+                    if let Some(data) = read_source_chunk(&mut sources, &args.worlds, other)? {
+                        others.push(
+                            fastnbt::from_bytes(&data)
+                                .map_err(|e| format!("Failed to parse chunk: {e}"))?,
+                        );
+                    }
+                }
+                vote_blocks(&mut chunk, &others);
+                shift_chunk(&mut chunk, dx, dz);
+                fastnbt::to_bytes(&chunk).map_err(|e| format!("Failed to encode chunk: {e}"))?
+            } else if (dx, dz) == (0, 0) {
+                data
+            } else {
+                let mut chunk: Value = fastnbt::from_bytes(&data)
+                    .map_err(|e| format!("Failed to parse chunk: {e}"))?;
+                shift_chunk(&mut chunk, dx, dz);
+                fastnbt::to_bytes(&chunk).map_err(|e| format!("Failed to encode chunk: {e}"))?
+            };
+
+            output
+                .write_chunk(
+                    target.0.rem_euclid(32) as usize,
+                    target.1.rem_euclid(32) as usize,
+                    &data,
+                )
+                .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+            written += 1;
+        }
+    }
+
+    write_metadata(&args.output, &metadata, &offsets)?;
+
+    // The merged world uses the settings of the first world
+    let level = args.worlds[0].join("level.dat");
+    if level.is_file() && !args.output.join("level.dat").exists() {
+        std::fs::copy(&level, args.output.join("level.dat"))
+            .map_err(|e| format!("Failed to copy level.dat: {e}"))?;
+    }
+
+    println!(
+        "Merged {} worlds into {} ({written} chunks)",
+        args.worlds.len(),
+        args.output.display()
+    );
+    Ok(())
+}
+
+/// Index of the version of a chunk kept by `strategy`. The vote happens on top of the
+/// version with the most content.
+fn pick_chunk(versions: &[ChunkSource], strategy: OverlapStrategy) -> usize {
+    let mut kept = 0;
+    for (i, version) in versions.iter().enumerate().skip(1) {
+        let better = match strategy {
+            OverlapStrategy::Largest | OverlapStrategy::Majority => {
+                version.size > versions[kept].size
+            }
+            OverlapStrategy::First => version.world < versions[kept].world,
+            // Worlds written at the same time, like the parts of one run, keep the later one
+            OverlapStrategy::Newest => version.modified >= versions[kept].modified,
+        };
+        if better {
+            kept = i;
+        }
+    }
+    kept
+}
+
+/// Reads a version of a chunk, opening its region file on first use
+fn read_source_chunk(
+    sources: &mut HashMap<(usize, (i32, i32)), Region<File>>,
+    worlds: &[PathBuf],
+    source: &ChunkSource,
+) -> Result<Option<Vec<u8>>, String> {
+    let region = match sources.entry((source.world, source.region)) {
+        Entry::Occupied(entry) => entry.into_mut(),
+        Entry::Vacant(entry) => {
+            let (x, z) = source.region;
+            let source_path = worlds[source.world]
+                .join("region")
+                .join(format!("r.{x}.{z}.mca"));
+            entry.insert(open_region(&source_path)?)
+        }
+    };
+    region
+        .read_chunk(source.chunk.0, source.chunk.1)
+        .map_err(|e| format!("Failed to read chunk: {e}"))
+}
+
+/// Sets every block of `chunk` to the state most of `chunk` and `others` have there. Ties
+/// keep the block of `chunk`, as do sections the others don't have.
+fn vote_blocks(chunk: &mut Value, others: &[Value]) {
+    let other_sections: Vec<HashMap<i32, SectionBlocks>> = others
+        .iter()
+        .map(|other| {
+            chunk_sections(other)
+                .into_iter()
+                .flatten()
+                .filter_map(read_section)
+                .collect()
+        })
+        .collect();
+    let Some(sections) = chunk_sections_mut(chunk) else {
+        return;
+    };
+
+    for section in sections {
+        let Some((y, own)) = read_section(section) else {
+            continue;
+        };
+        let voters: Vec<&SectionBlocks> = std::iter::once(&own)
+            .chain(
+                other_sections
+                    .iter()
+                    .filter_map(|sections| sections.get(&y)),
+            )
+            .collect();
+        if voters.len() < 3 {
+            continue;
+        }
+
+        let mut palette: Vec<Value> = Vec::new();
+        let mut lookup: HashMap<&str, usize> = HashMap::new();
+        let blocks: Vec<usize> = (0..4096)
+            .map(|i| {
+                let mut votes: Vec<(&str, &Value, usize)> = Vec::new();
+                for voter in &voters {
+                    let index = voter.blocks[i];
+                    let state = voter.states[index].as_str();
+                    match votes.iter_mut().find(|(voted, _, _)| *voted == state) {
+                        Some((_, _, count)) => *count += 1,
+                        None => votes.push((state, &voter.palette[index], 1)),
+                    }
+                }
+                // The first of the most voted states, which is the own block on a tie
+                let (state, entry, _) =
+                    votes.iter().fold(
+                        votes[0],
+                        |best, vote| if vote.2 > best.2 { *vote } else { best },
+                    );
+                *lookup.entry(state).or_insert_with(|| {
+                    palette.push(entry.clone());
+                    palette.len() - 1
+                })
+            })
+            .collect();
+        write_section(section, palette, &blocks);
+    }
+}
+
+/// Block states of a section: its palette entries, their state strings and the palette
+/// index of each of its 4096 blocks
+struct SectionBlocks {
+    palette: Vec<Value>,
+    states: Vec<String>,
+    blocks: Vec<usize>,
+}
+
+/// Arnis writes the chunk data inside a `Level` compound, Minecraft at the top level
+fn chunk_sections(chunk: &Value) -> Option<&Vec<Value>> {
+    let Value::Compound(map) = chunk else {
+        return None;
+    };
+    match (map.get("Level"), map.get("sections")) {
+        (Some(level), _) => chunk_sections(level),
+        (None, Some(Value::List(sections))) => Some(sections),
+        _ => None,
+    }
+}
+
+fn chunk_sections_mut(chunk: &mut Value) -> Option<&mut Vec<Value>> {
+    let Value::Compound(map) = chunk else {
+        return None;
+    };
+    if map.contains_key("Level") {
+        return chunk_sections_mut(map.get_mut("Level")?);
+    }
+    match map.get_mut("sections") {
+        Some(Value::List(sections)) => Some(sections),
+        _ => None,
+    }
+}
+
+/// Section y and its block states
+fn read_section(section: &Value) -> Option<(i32, SectionBlocks)> {
+    let Value::Compound(section) = section else {
+        return None;
+    };
+    let y = match section.get("Y")? {
+        Value::Byte(y) => i32::from(*y),
+        Value::Int(y) => *y,
+        _ => return None,
+    };
+    let Some(Value::Compound(block_states)) = section.get("block_states") else {
+        return None;
+    };
+    let Some(Value::List(palette)) = block_states.get("palette") else {
+        return None;
+    };
+    if palette.is_empty() {
+        return None;
+    }
+
+    let blocks = match block_states.get("data") {
+        Some(Value::LongArray(data)) if palette.len() > 1 => unpack_section(data, palette.len())
+            .into_iter()
+            .map(|index| if index < palette.len() { index } else { 0 })
+            .collect(),
+        _ => vec![0; 4096],
+    };
+    Some((
+        y,
+        SectionBlocks {
+            states: palette.iter().map(block_state).collect(),
+            palette: palette.clone(),
+            blocks,
+        },
+    ))
+}
+
+/// Replaces the block states of a section
+fn write_section(section: &mut Value, palette: Vec<Value>, blocks: &[usize]) {
+    let Value::Compound(section) = section else {
+        return;
+    };
+    let Some(Value::Compound(block_states)) = section.get_mut("block_states") else {
+        return;
+    };
+    if palette.len() > 1 {
+        block_states.insert(
+            "data".to_string(),
+            Value::LongArray(LongArray::new(pack_section(blocks, palette.len()))),
+        );
+    } else {
+        block_states.remove("data");
+    }
+    block_states.insert("palette".to_string(), Value::List(palette));
+}
+
+/// Packs the palette indices of a section as `unpack_section` reads them
+fn pack_section(blocks: &[usize], palette_len: usize) -> Vec<i64> {
+    let bits = (usize::BITS - (palette_len - 1).leading_zeros()).max(4) as usize;
+    let per_long = 64 / bits;
+    let mut longs = vec![0u64; blocks.len().div_ceil(per_long)];
+    for (i, &block) in blocks.iter().enumerate() {
+        longs[i / per_long] |= (block as u64) << (i % per_long * bits);
+    }
+    longs.into_iter().map(|long| long as i64).collect()
+}
+
+/// Offset in chunks that places `world` in the coordinates of `base`, and by how many
+/// blocks it had to be rounded to whole chunks
+fn chunk_offset(base: &WorldMetadata, world: &WorldMetadata) -> ((i32, i32), i32) {
+    let (x, z) = base.block_position(world.max_geo_lat, world.min_geo_lon);
+    to_chunks((x - world.min_mc_x, z - world.min_mc_z))
+}
+
+/// Offset in blocks rounded to whole chunks, and by how many blocks it was rounded
+fn to_chunks((block_dx, block_dz): (i32, i32)) -> ((i32, i32), i32) {
+    let (dx, dz) = (
+        (block_dx as f64 / 16.0).round() as i32,
+        (block_dz as f64 / 16.0).round() as i32,
+    );
+    let error = (block_dx - dx * 16).abs().max((block_dz - dz * 16).abs());
+    ((dx, dz), error)
+}
+
+/// Writes the chunks of the area generated in `generated` into the existing world `target`,
+/// moved by `offset` blocks rounded to whole chunks. Chunks of `target` outside of the area
+/// are left as they are. Returns how many chunks were written.
+pub fn merge_into(generated: &Path, target: &Path, offset: (i32, i32)) -> Result<usize, String> {
+    if generated.canonicalize().ok() == target.canonicalize().ok() {
+        return Err("The area is generated into this world already".to_string());
+    }
+    let metadata = WorldMetadata::load(generated)?;
+    let ((dx, dz), error) = to_chunks(offset);
+    if error > 0 {
+        println!("Warning: the area is shifted by {error} blocks to align its chunks");
+    }
+    // Only the chunks of the area, not the chunks the world had before the generation
+    let chunks_x = metadata.min_mc_x.div_euclid(16)..=metadata.max_mc_x.div_euclid(16);
+    let chunks_z = metadata.min_mc_z.div_euclid(16)..=metadata.max_mc_z.div_euclid(16);
+    write_chunks(generated, target, (dx, dz), |x, z| {
+        chunks_x.contains(&x) && chunks_z.contains(&z)
+    })
+}
+
+/// Writes the chunks of `source` for which `selected` holds into the existing world
+/// `target`, moved by whole chunks. Returns how many chunks were written.
+pub fn write_chunks(
+    source: &Path,
+    target: &Path,
+    (dx, dz): (i32, i32),
+    selected: impl Fn(i32, i32) -> bool,
+) -> Result<usize, String> {
+    let region_dir = target.join("region");
+    let mut written: usize = 0;
+    for ((region_x, region_z), region_path) in region_files(source)? {
+        let mut region = open_region(&region_path)?;
+        // A region of the source covers up to four regions of the target
+        let mut outputs: HashMap<(i32, i32), Region<File>> = HashMap::new();
+
+        for chunk in region.iter().flatten() {
+            let (x, z) = (
+                region_x * 32 + chunk.x as i32,
+                region_z * 32 + chunk.z as i32,
+            );
+            if !selected(x, z) {
+                continue;
+            }
+
+            let data = if (dx, dz) == (0, 0) {
+                chunk.data
+            } else {
+                let mut data: Value = fastnbt::from_bytes(&chunk.data)
+                    .map_err(|e| format!("Failed to parse chunk: {e}"))?;
+                shift_chunk(&mut data, dx, dz);
+                fastnbt::to_bytes(&data).map_err(|e| format!("Failed to encode chunk: {e}"))?
+            };
+
+            let (target_x, target_z) = (x + dx, z + dz);
+            let key = (target_x.div_euclid(32), target_z.div_euclid(32));
+            let output = match outputs.entry(key) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(open_or_create_region(&region_dir, key)?),
+            };
+            output
+                .write_chunk(
+                    target_x.rem_euclid(32) as usize,
+                    target_z.rem_euclid(32) as usize,
+                    &data,
+                )
+                .map_err(|e| format!("Failed to write chunk into {}: {e}", target.display()))?;
+            written += 1;
+        }
+    }
+
+    Ok(written)
+}
+
+/// Opens a region file of a world, or creates it if the world has none there yet
+fn open_or_create_region(region_dir: &Path, (x, z): (i32, i32)) -> Result<Region<File>, String> {
+    let path = region_dir.join(format!("r.{x}.{z}.mca"));
+    if path.is_file() {
+        return open_region(&path);
+    }
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+    Region::create(file).map_err(|e| format!("Failed to create {}: {e}", path.display()))
+}
+
+/// Moves a chunk by whole chunks, including the positions of its block entities
+fn shift_chunk(chunk: &mut Value, dx: i32, dz: i32) {
+    let Value::Compound(map) = chunk else {
+        return;
+    };
+
+    if let Some(Value::Int(x)) = map.get_mut("xPos") {
+        *x += dx;
+    }
+    if let Some(Value::Int(z)) = map.get_mut("zPos") {
+        *z += dz;
+    }
+    if let Some(Value::List(entities)) = map.get_mut("block_entities") {
+        for entity in entities {
+            if let Value::Compound(entity) = entity {
+                if let Some(Value::Int(x)) = entity.get_mut("x") {
+                    *x += dx * 16;
+                }
+                if let Some(Value::Int(z)) = entity.get_mut("z") {
+                    *z += dz * 16;
+                }
+            }
+        }
+    }
+
+    // Arnis writes the chunk data inside a `Level` compound, Minecraft at the top level
+    if let Some(level) = map.get_mut("Level") {
+        shift_chunk(level, dx, dz);
+    }
+}
+
+/// Georeference of the merged world, covering all worlds
+fn write_metadata(
+    output: &Path,
+    metadata: &[WorldMetadata],
+    offsets: &[(i32, i32)],
+) -> Result<(), String> {
+    let mut merged = metadata[0].clone();
+    for (world, (dx, dz)) in metadata.iter().zip(offsets).skip(1) {
+        merged.min_mc_x = merged.min_mc_x.min(world.min_mc_x + dx * 16);
+        merged.max_mc_x = merged.max_mc_x.max(world.max_mc_x + dx * 16);
+        merged.min_mc_z = merged.min_mc_z.min(world.min_mc_z + dz * 16);
+        merged.max_mc_z = merged.max_mc_z.max(world.max_mc_z + dz * 16);
+        merged.min_geo_lat = merged.min_geo_lat.min(world.min_geo_lat);
+        merged.max_geo_lat = merged.max_geo_lat.max(world.max_geo_lat);
+        merged.min_geo_lon = merged.min_geo_lon.min(world.min_geo_lon);
+        merged.max_geo_lon = merged.max_geo_lon.max(world.max_geo_lon);
+    }
+
+    let json = serde_json::to_string_pretty(&merged)
+        .map_err(|e| format!("Failed to serialize metadata: {e}"))?;
+    std::fs::write(output.join("metadata.json"), json)
+        .map_err(|e| format!("Failed to write metadata.json: {e}"))
+}
+
+/// A region file with its region coordinates
+type RegionFile = ((i32, i32), PathBuf);
+
+/// Region files of a world with their region coordinates
+fn region_files(world: &Path) -> Result<Vec<RegionFile>, String> {
+    let region_dir = world.join("region");
+    let entries = std::fs::read_dir(&region_dir)
+        .map_err(|e| format!("Failed to read {}: {e}", region_dir.display()))?;
+
+    Ok(entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter_map(|path| Some((region_coords(&path)?, path)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(min_geo_lon: f64, max_geo_lon: f64) -> WorldMetadata {
+        WorldMetadata {
+            min_mc_x: 0,
+            max_mc_x: 1000,
+            min_mc_z: 0,
+            max_mc_z: 1000,
+            min_geo_lat: 50.0,
+            max_geo_lat: 50.1,
+            min_geo_lon,
+            max_geo_lon,
+        }
+    }
+
+    #[test]
+    fn test_chunk_offset() {
+        let base = metadata(10.0, 10.1);
+        assert_eq!(chunk_offset(&base, &base), ((0, 0), 0));
+
+        // The neighbor to the east starts 1000 blocks further, rounded to 63 chunks
+        let east = metadata(10.1, 10.2);
+        assert_eq!(chunk_offset(&base, &east), ((63, 0), 8));
+    }
+
+    #[test]
+    fn test_to_chunks() {
+        assert_eq!(to_chunks((1024, -4000)), ((64, -250), 0));
+        assert_eq!(to_chunks((1000, 4004)), ((63, 250), 8));
+    }
+
+    #[test]
+    fn test_pick_chunk() {
+        let version = |world: usize, size: usize, modified: u64| ChunkSource {
+            world,
+            region: (0, 0),
+            chunk: (0, 0),
+            size,
+            modified: Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(modified)),
+        };
+        let versions = [
+            version(1, 500, 20),
+            version(0, 200, 10),
+            version(2, 300, 30),
+        ];
+
+        assert_eq!(pick_chunk(&versions, OverlapStrategy::Largest), 0);
+        assert_eq!(pick_chunk(&versions, OverlapStrategy::Majority), 0);
+        assert_eq!(pick_chunk(&versions, OverlapStrategy::First), 1);
+        assert_eq!(pick_chunk(&versions, OverlapStrategy::Newest), 2);
+    }
+
+    #[test]
+    fn test_vote_blocks() {
+        let block = |name: &str| {
+            Value::Compound(HashMap::from([(
+                "Name".to_string(),
+                Value::String(name.to_string()),
+            )]))
+        };
+        // A section of stone with the given block at index 0
+        let chunk = |first: &str| {
+            let palette = vec![block("minecraft:stone"), block(first)];
+            let mut blocks = vec![0; 4096];
+            blocks[0] = 1;
+            let section = Value::Compound(HashMap::from([
+                ("Y".to_string(), Value::Byte(0)),
+                (
+                    "block_states".to_string(),
+                    Value::Compound(HashMap::from([
+                        ("palette".to_string(), Value::List(palette)),
+                        (
+                            "data".to_string(),
+                            Value::LongArray(LongArray::new(pack_section(&blocks, 2))),
+                        ),
+                    ])),
+                ),
+            ]));
+            Value::Compound(HashMap::from([(
+                "sections".to_string(),
+                Value::List(vec![section]),
+            )]))
+        };
+
+        let mut own = chunk("minecraft:dirt");
+        vote_blocks(
+            &mut own,
+            &[chunk("minecraft:gravel"), chunk("minecraft:gravel")],
+        );
+        let (_, section) = read_section(&chunk_sections(&own).unwrap()[0]).unwrap();
+        assert_eq!(section.states[section.blocks[0]], "minecraft:gravel");
+        assert_eq!(section.states[section.blocks[1]], "minecraft:stone");
+
+        // Without a majority the own block stays
+        let mut own = chunk("minecraft:dirt");
+        vote_blocks(
+            &mut own,
+            &[chunk("minecraft:gravel"), chunk("minecraft:sand")],
+        );
+        let (_, section) = read_section(&chunk_sections(&own).unwrap()[0]).unwrap();
+        assert_eq!(section.states[section.blocks[0]], "minecraft:dirt");
+    }
+
+    #[test]
+    fn test_shift_chunk() {
+        let entity = Value::Compound(HashMap::from([
+            ("x".to_string(), Value::Int(20)),
+            ("z".to_string(), Value::Int(-5)),
+        ]));
+        let mut chunk = Value::Compound(HashMap::from([(
+            "Level".to_string(),
+            Value::Compound(HashMap::from([
+                ("xPos".to_string(), Value::Int(1)),
+                ("zPos".to_string(), Value::Int(-1)),
+                ("block_entities".to_string(), Value::List(vec![entity])),
+            ])),
+        )]));
+
+        shift_chunk(&mut chunk, 2, 3);
+
+        let Value::Compound(root) = chunk else {
+            panic!("expected a compound");
+        };
+        let Some(Value::Compound(level)) = root.get("Level") else {
+            panic!("expected the Level compound");
+        };
+        assert_eq!(level.get("xPos"), Some(&Value::Int(3)));
+        assert_eq!(level.get("zPos"), Some(&Value::Int(2)));
+        let Some(Value::List(entities)) = level.get("block_entities") else {
+            panic!("expected block entities");
+        };
+        let Value::Compound(entity) = &entities[0] else {
+            panic!("expected a compound");
+        };
+        assert_eq!(entity.get("x"), Some(&Value::Int(52)));
+        assert_eq!(entity.get("z"), Some(&Value::Int(43)));
+    }
+}
@@ -0,0 +1,70 @@
+//! Night mode (`--night`): as many light sources as the city plausibly has, for night-time
+//! shots. Some floors of every building get lit windows, shops get a glowing fascia above the
+//! ground floor and roads get street lamps at regular intervals.
+
+use rand::Rng;
+use std::collections::HashMap;
+
+/// Chance of a building floor having its lights on
+const LIT_FLOOR_CHANCE: f64 = 0.4;
+/// Blocks between street lamps along a road
+pub const LAMP_SPACING: usize = 16;
+
+/// For every floor of a building of this height, whether its lights are on
+pub fn lit_floors(rng: &mut impl Rng, building_height: i32) -> Vec<bool> {
+    (0..building_height / 4 + 2)
+        .map(|_| rng.gen_bool(LIT_FLOOR_CHANCE))
+        .collect()
+}
+
+/// Whether the building holds a shop, which gets a lit fascia
+pub fn is_shop(tags: &HashMap<String, String>) -> bool {
+    tags.contains_key("shop")
+        || tags.get("building").is_some_and(|building| {
+            matches!(
+                building.as_str(),
+                "retail" | "commercial" | "supermarket" | "kiosk"
+            )
+        })
+}
+
+/// Position of the street lamp beside the road point `(x, z)`, `distance` blocks from the
+/// center line of the segment from `from` to `to`
+pub fn lamp_position(
+    from: (i32, i32),
+    to: (i32, i32),
+    (x, z): (i32, i32),
+    distance: i32,
+) -> (i32, i32) {
+    // Roads are drawn as squares around the center line, so the side is along the other axis
+    if (to.0 - from.0).abs() >= (to.1 - from.1).abs() {
+        (x, z + distance)
+    } else {
+        (x + distance, z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lamp_position() {
+        // Beside east-west roads to the south, beside north-south roads to the east
+        assert_eq!(lamp_position((0, 0), (10, 2), (5, 1), 4), (5, 5));
+        assert_eq!(lamp_position((0, 0), (-2, 10), (-1, 5), 4), (3, 5));
+    }
+
+    #[test]
+    fn test_is_shop() {
+        let tags = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert!(is_shop(&tags(&[("building", "retail")])));
+        assert!(is_shop(&tags(&[("building", "yes"), ("shop", "bakery")])));
+        assert!(!is_shop(&tags(&[("building", "apartments")])));
+    }
+}
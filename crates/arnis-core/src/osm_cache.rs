@@ -0,0 +1,418 @@
+//! Local cache of Overpass responses (`--osm-cache`). Requesting an area inside one that was
+//! downloaded recently reuses that download, clipped to the requested area, instead of
+//! querying Overpass again. Responses are stored gzip-compressed in the temporary directory,
+//! together with an index of their bounding boxes. The GUI lists them in its "Cached Areas"
+//! dialog, where they can be selected again, downloaded again or deleted.
+
+use crate::coordinate_system::geographic::{LLBBox, LLPoint};
+use crate::disk_io;
+use crate::retrieve_data::{self, QueryOptions};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use fnv::{FnvHashMap, FnvHashSet, FnvHasher};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// OSM data changes, so older downloads are not reused
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const INDEX_FILE: &str = "index.json";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct CacheEntry {
+    /// `min_lat, min_lng, max_lat, max_lng`
+    bbox: [f64; 4],
+    /// Hash of the query options, data of other dates or templates is never reused
+    query: String,
+    file: String,
+    /// Seconds since the Unix epoch
+    saved_at: u64,
+}
+
+/// A cached download, as listed in the GUI
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CachedArea {
+    pub id: String,
+    /// `min_lat, min_lng, max_lat, max_lng`
+    pub bbox: [f64; 4],
+    /// Seconds since the Unix epoch
+    pub saved_at: u64,
+    pub size_bytes: u64,
+    /// Whether it was downloaded with the built-in query, so it can be downloaded again;
+    /// historical or custom queries can't
+    pub refreshable: bool,
+}
+
+impl CacheEntry {
+    fn contains(&self, bbox: &[f64; 4]) -> bool {
+        self.bbox[0] <= bbox[0]
+            && self.bbox[1] <= bbox[1]
+            && self.bbox[2] >= bbox[2]
+            && self.bbox[3] >= bbox[3]
+    }
+
+    fn area(&self) -> f64 {
+        (self.bbox[2] - self.bbox[0]) * (self.bbox[3] - self.bbox[1])
+    }
+}
+
+/// Enables the cache for this run
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// The downloads that can still be reused, newest first
+pub fn list_cached_areas() -> Vec<CachedArea> {
+    let default_query = query_key(&QueryOptions::default());
+    let now = now();
+    let mut areas: Vec<CachedArea> = read_index()
+        .into_iter()
+        .filter(|entry| now.saturating_sub(entry.saved_at) < MAX_AGE.as_secs())
+        .map(|entry| CachedArea {
+            size_bytes: fs::metadata(cache_dir().join(&entry.file)).map_or(0, |m| m.len()),
+            refreshable: entry.query == default_query,
+            id: entry.file,
+            bbox: entry.bbox,
+            saved_at: entry.saved_at,
+        })
+        .collect();
+    areas.sort_by_key(|area| std::cmp::Reverse(area.saved_at));
+    areas
+}
+
+/// Removes a download from the cache
+pub fn delete_cached_area(id: &str) -> Result<(), String> {
+    let mut index = read_index();
+    let count = index.len();
+    index.retain(|entry| entry.file != id);
+    if index.len() == count {
+        return Err(format!("No cached area {id}"));
+    }
+    write_index(&index)?;
+
+    let path = cache_dir().join(id);
+    if path.exists() {
+        fs::remove_file(&path).map_err(|e| format!("Failed to delete {}: {e}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Downloads a cached area again, replacing the old download
+pub fn refresh_cached_area(id: &str) -> Result<(), String> {
+    let area = list_cached_areas()
+        .into_iter()
+        .find(|area| area.id == id)
+        .ok_or_else(|| format!("No cached area {id}"))?;
+    if !area.refreshable {
+        return Err("Areas downloaded with a date or custom query can't be refreshed".to_string());
+    }
+    let [min_lat, min_lng, max_lat, max_lng] = area.bbox;
+    let bbox = LLBBox::new(min_lat, min_lng, max_lat, max_lng)?;
+
+    let options = QueryOptions::default();
+    let response = retrieve_data::download_area(&bbox, &options, "requests")
+        .map_err(|e| format!("Failed to download the area again: {e}"))?;
+    // The same area and query map to the same file, so this replaces the old download
+    write_entry(&bbox, &options, &response)
+}
+
+/// Cached data covering `bbox`, clipped to it
+pub(crate) fn lookup(bbox: &LLBBox, options: &QueryOptions) -> Option<String> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let wanted = bbox_array(bbox);
+    let entry = find_entry(&read_index(), &wanted, &query_key(options), now())?;
+
+    let text = match read_entry(&entry) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Warning: Failed to read cached OSM data: {e}");
+            return None;
+        }
+    };
+    if entry.bbox == wanted {
+        println!("Using cached OSM data of this area");
+        return Some(text);
+    }
+
+    println!("Using cached OSM data of a surrounding area");
+    let data: Value = serde_json::from_str(&text).ok()?;
+    Some(clip(&data, bbox).to_string())
+}
+
+/// Adds a downloaded response to the cache
+pub(crate) fn store(bbox: &LLBBox, options: &QueryOptions, response: &str) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Err(e) = write_entry(bbox, options, response) {
+        eprintln!("Warning: Failed to cache OSM data: {e}");
+    }
+}
+
+/// The smallest recent entry for the same query that contains `wanted`
+fn find_entry(
+    entries: &[CacheEntry],
+    wanted: &[f64; 4],
+    query: &str,
+    now: u64,
+) -> Option<CacheEntry> {
+    entries
+        .iter()
+        .filter(|entry| entry.query == query && entry.contains(wanted))
+        .filter(|entry| now.saturating_sub(entry.saved_at) < MAX_AGE.as_secs())
+        .min_by(|a, b| a.area().total_cmp(&b.area()))
+        .cloned()
+}
+
+/// Elements of an Overpass response that are inside `bbox`: the nodes inside it, the ways
+/// with a node inside it and the relations with such a member, with everything they consist of
+fn clip(data: &Value, bbox: &LLBBox) -> Value {
+    let elements: &[Value] = data["elements"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let key = |element: &Value| {
+        (
+            element["type"].as_str().unwrap_or_default().to_string(),
+            element["id"].as_u64().unwrap_or_default(),
+        )
+    };
+    let ways: FnvHashMap<u64, &Value> = elements
+        .iter()
+        .filter(|element| element["type"] == "way")
+        .filter_map(|element| Some((element["id"].as_u64()?, element)))
+        .collect();
+    let node_ids = |way: &Value| -> Vec<u64> {
+        way["nodes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_u64)
+            .collect()
+    };
+
+    let mut kept: FnvHashSet<(String, u64)> = elements
+        .iter()
+        .filter(|element| element["type"] == "node")
+        .filter(|node| match (node["lat"].as_f64(), node["lon"].as_f64()) {
+            (Some(lat), Some(lon)) => LLPoint::new(lat, lon).is_ok_and(|p| bbox.contains(&p)),
+            _ => false,
+        })
+        .map(key)
+        .collect();
+    for way in ways.values() {
+        if node_ids(way)
+            .into_iter()
+            .any(|id| kept.contains(&("node".to_string(), id)))
+        {
+            kept.insert(key(way));
+        }
+    }
+    for relation in elements.iter().filter(|e| e["type"] == "relation") {
+        let members: Vec<(String, u64)> = relation["members"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|member| {
+                Some((
+                    member["type"].as_str()?.to_string(),
+                    member["ref"].as_u64()?,
+                ))
+            })
+            .collect();
+        if members.iter().any(|member| kept.contains(member)) {
+            // Multipolygons need all their members, also those outside the area
+            kept.insert(key(relation));
+            kept.extend(members);
+        }
+    }
+    let kept_ways: Vec<u64> = kept
+        .iter()
+        .filter(|(kind, _)| kind == "way")
+        .map(|(_, id)| *id)
+        .collect();
+    for id in kept_ways {
+        if let Some(way) = ways.get(&id) {
+            kept.extend(node_ids(way).into_iter().map(|id| ("node".to_string(), id)));
+        }
+    }
+
+    let clipped: Vec<&Value> = elements
+        .iter()
+        .filter(|element| kept.contains(&key(element)))
+        .collect();
+    serde_json::json!({ "elements": clipped })
+}
+
+fn cache_dir() -> PathBuf {
+    disk_io::temp_dir().join("arnis-osm-cache")
+}
+
+fn read_index() -> Vec<CacheEntry> {
+    fs::read_to_string(cache_dir().join(INDEX_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn read_entry(entry: &CacheEntry) -> Result<String, String> {
+    let path = cache_dir().join(&entry.file);
+    let file = File::open(&path).map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+    let mut text = String::new();
+    GzDecoder::new(file)
+        .read_to_string(&mut text)
+        .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    Ok(text)
+}
+
+fn write_entry(bbox: &LLBBox, options: &QueryOptions, response: &str) -> Result<(), String> {
+    // Overpass reports timeouts and other errors in a remark, such data may be incomplete
+    if response.contains("\"remark\"") {
+        return Err("Overpass reported a problem, the data may be incomplete".to_string());
+    }
+    let dir = cache_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+
+    let bbox = bbox_array(bbox);
+    let query = query_key(options);
+    let mut hasher = FnvHasher::default();
+    for value in bbox {
+        hasher.write_u64(value.to_bits());
+    }
+    let entry = CacheEntry {
+        file: format!("{query}_{:016x}.json.gz", hasher.finish()),
+        bbox,
+        query,
+        saved_at: now(),
+    };
+
+    let path = dir.join(&entry.file);
+    let write = || -> std::io::Result<()> {
+        let mut encoder = GzEncoder::new(File::create(&path)?, Compression::default());
+        encoder.write_all(response.as_bytes())?;
+        encoder.finish()?;
+        Ok(())
+    };
+    write().map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+
+    // Replaces an older download of the same area, drops downloads too old to be reused
+    let mut index = read_index();
+    let mut expired: Vec<String> = Vec::new();
+    index.retain(|other| {
+        let recent = entry.saved_at.saturating_sub(other.saved_at) < MAX_AGE.as_secs();
+        if !recent {
+            expired.push(other.file.clone());
+        }
+        recent && other.file != entry.file
+    });
+    index.push(entry);
+    write_index(&index)?;
+
+    for file in expired {
+        let _ = fs::remove_file(dir.join(file));
+    }
+    Ok(())
+}
+
+fn write_index(index: &[CacheEntry]) -> Result<(), String> {
+    let json =
+        serde_json::to_string(index).map_err(|e| format!("Failed to serialize index: {e}"))?;
+    fs::write(cache_dir().join(INDEX_FILE), json).map_err(|e| format!("Failed to write index: {e}"))
+}
+
+fn query_key(options: &QueryOptions) -> String {
+    let mut hasher = FnvHasher::default();
+    hasher.write(options.date.as_deref().unwrap_or_default().as_bytes());
+    hasher.write_u8(0);
+    hasher.write(options.template.as_deref().unwrap_or_default().as_bytes());
+    // Only filtered queries differ, so the keys of earlier downloads stay valid
+    if let Some(keys) = options.filter.overpass_keys() {
+        hasher.write_u8(0);
+        hasher.write(keys.join(",").as_bytes());
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+fn bbox_array(bbox: &LLBBox) -> [f64; 4] {
+    [
+        bbox.min().lat(),
+        bbox.min().lng(),
+        bbox.max().lat(),
+        bbox.max().lng(),
+    ]
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_find_entry() {
+        let entry = |bbox: [f64; 4], saved_at: u64| CacheEntry {
+            bbox,
+            query: "q".to_string(),
+            file: String::new(),
+            saved_at,
+        };
+        let large = entry([54.0, 9.0, 55.0, 10.0], 1000);
+        let small = entry([54.5, 9.5, 54.8, 9.8], 1000);
+        let entries = vec![large.clone(), small.clone()];
+
+        let inside_small = [54.6, 9.6, 54.7, 9.7];
+        assert_eq!(find_entry(&entries, &inside_small, "q", 2000), Some(small));
+        let inside_large = [54.1, 9.1, 54.2, 9.2];
+        assert_eq!(find_entry(&entries, &inside_large, "q", 2000), Some(large));
+
+        assert_eq!(find_entry(&entries, &inside_small, "other", 2000), None);
+        let too_late = 1000 + MAX_AGE.as_secs();
+        assert_eq!(find_entry(&entries, &inside_small, "q", too_late), None);
+        let overlapping = [54.9, 9.9, 55.1, 10.1];
+        assert_eq!(find_entry(&entries, &overlapping, "q", 2000), None);
+    }
+
+    #[test]
+    fn test_clip() {
+        let data = json!({ "elements": [
+            { "type": "relation", "id": 1, "members": [
+                { "type": "way", "ref": 11, "role": "outer" },
+                { "type": "way", "ref": 12, "role": "outer" },
+            ] },
+            { "type": "way", "id": 11, "nodes": [101, 102] },
+            { "type": "way", "id": 12, "nodes": [103, 104] },
+            { "type": "way", "id": 13, "nodes": [104, 105] },
+            { "type": "node", "id": 101, "lat": 54.65, "lon": 9.95 },
+            { "type": "node", "id": 102, "lat": 54.80, "lon": 9.95 },
+            { "type": "node", "id": 103, "lat": 54.90, "lon": 9.95 },
+            { "type": "node", "id": 104, "lat": 54.90, "lon": 9.90 },
+            { "type": "node", "id": 105, "lat": 54.95, "lon": 9.90 },
+        ] });
+        let bbox = LLBBox::new(54.6, 9.9, 54.7, 10.0).unwrap();
+
+        let clipped = clip(&data, &bbox);
+        let ids: Vec<u64> = clipped["elements"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|element| element["id"].as_u64().unwrap())
+            .collect();
+        // Way 12 is outside, but part of the relation; way 13 and node 105 are left out
+        assert_eq!(ids, vec![1, 11, 12, 101, 102, 103, 104]);
+    }
+}
@@ -1,38 +1,40 @@
 use crate::coordinate_system::cartesian::{XZBBox, XZPoint};
 use crate::coordinate_system::geographic::{LLBBox, LLPoint};
 use crate::coordinate_system::transformation::CoordTransformer;
+use crate::error::ArnisError;
 use crate::progress::emit_gui_progress_update;
+use crate::retrieve_data;
 use colored::Colorize;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 
 // Raw data from OSM
 
-#[derive(Debug, Deserialize)]
-struct OsmMember {
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct OsmMember {
     r#type: String,
     r#ref: u64,
     r#role: String,
 }
 
-#[derive(Debug, Deserialize)]
-struct OsmElement {
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct OsmElement {
     pub r#type: String,
     pub id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub lat: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub lon: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub nodes: Option<Vec<u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub tags: Option<HashMap<String, String>>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub members: Vec<OsmMember>,
 }
 
-#[derive(Deserialize)]
-struct OsmData {
-    pub elements: Vec<OsmElement>,
-}
-
+#[derive(Default)]
 struct SplitOsmData {
     pub nodes: Vec<OsmElement>,
     pub ways: Vec<OsmElement>,
@@ -45,31 +47,103 @@ impl SplitOsmData {
     fn total_count(&self) -> usize {
         self.nodes.len() + self.ways.len() + self.relations.len() + self.others.len()
     }
-    fn from_raw_osm_data(osm_data: OsmData) -> Self {
-        let mut nodes = Vec::new();
-        let mut ways = Vec::new();
-        let mut relations = Vec::new();
-        let mut others = Vec::new();
-        for element in osm_data.elements {
-            match element.r#type.as_str() {
-                "node" => nodes.push(element),
-                "way" => ways.push(element),
-                "relation" => relations.push(element),
-                _ => others.push(element),
+    fn push(&mut self, element: OsmElement) {
+        match element.r#type.as_str() {
+            "node" => self.nodes.push(element),
+            "way" => self.ways.push(element),
+            "relation" => self.relations.push(element),
+            _ => self.others.push(element),
+        }
+    }
+}
+
+/// OSM data as fetched: parsed JSON, or the response text, which is parsed element by
+/// element without ever holding the whole document as `Value`
+pub enum RawOsmData {
+    Json(Value),
+    Text(String),
+}
+
+impl From<Value> for RawOsmData {
+    fn from(value: Value) -> Self {
+        RawOsmData::Json(value)
+    }
+}
+
+impl From<String> for RawOsmData {
+    fn from(text: String) -> Self {
+        RawOsmData::Text(text)
+    }
+}
+
+/// Deserializes the elements one by one, so that a malformed element doesn't spoil the rest
+fn parse_raw_osm_data(
+    data: RawOsmData,
+    issues: &mut ParseIssues,
+) -> Result<SplitOsmData, ArnisError> {
+    let mut split = SplitOsmData::default();
+
+    let json_data = match data {
+        RawOsmData::Json(json_data) => json_data,
+        RawOsmData::Text(text) => {
+            let mut result: Result<(), ArnisError> = Ok(());
+            let remark = retrieve_data::stream_elements(&text, &mut |element| {
+                if result.is_err() {
+                    return;
+                }
+                match element {
+                    Ok(element) => split.push(element),
+                    Err(message) => result = issues.report(message),
+                }
+            })
+            .map_err(ArnisError::Parse)?;
+            result?;
+
+            if split.total_count() == 0 {
+                return Err(match remark {
+                    Some(remark) => ArnisError::OverpassQuota(format!("API returned: {remark}")),
+                    None => {
+                        ArnisError::Network("API returned no data. Please try again!".to_string())
+                    }
+                });
             }
+            return Ok(split);
         }
-        SplitOsmData {
-            nodes,
-            ways,
-            relations,
-            others,
+    };
+
+    let Some(Value::Array(raw_elements)) = json_data.get("elements") else {
+        return Err(ArnisError::Parse(
+            "OSM data has no \"elements\" list".to_string(),
+        ));
+    };
+    for raw_element in raw_elements {
+        match OsmElement::deserialize(raw_element) {
+            Ok(element) => split.push(element),
+            Err(e) => issues.report(format!("Skipping malformed element {raw_element}: {e}"))?,
         }
     }
+    Ok(split)
 }
 
-fn parse_raw_osm_data(json_data: Value) -> Result<SplitOsmData, serde_json::Error> {
-    let osm_data: OsmData = serde_json::from_value(json_data)?;
-    Ok(SplitOsmData::from_raw_osm_data(osm_data))
+/// Handling of malformed elements: strict mode fails on the first one, lenient mode
+/// repairs or skips them and counts them for a summary
+struct ParseIssues {
+    strict: bool,
+    debug: bool,
+    count: usize,
+}
+
+impl ParseIssues {
+    fn report(&mut self, message: String) -> Result<(), ArnisError> {
+        if self.strict {
+            return Err(ArnisError::Parse(message));
+        }
+        self.count += 1;
+        if self.debug {
+            eprintln!("WARN: {message}");
+        }
+        Ok(())
+    }
 }
 
 // End raw data
@@ -160,26 +234,64 @@ impl ProcessedElement {
             ProcessedElement::Relation(_) => Box::new([].into_iter()),
         }
     }
+
+    /// Center of the element's bounding rectangle, including the ways of relation members
+    pub fn center(&self) -> Option<XZPoint> {
+        let nodes: Box<dyn Iterator<Item = &ProcessedNode>> = match self {
+            ProcessedElement::Relation(rel) => Box::new(
+                rel.members
+                    .iter()
+                    .flat_map(|member| member.way.nodes.iter()),
+            ),
+            _ => self.nodes(),
+        };
+
+        let (mut min_x, mut max_x) = (i32::MAX, i32::MIN);
+        let (mut min_z, mut max_z) = (i32::MAX, i32::MIN);
+        for node in nodes {
+            min_x = min_x.min(node.x);
+            max_x = max_x.max(node.x);
+            min_z = min_z.min(node.z);
+            max_z = max_z.max(node.z);
+        }
+
+        if min_x > max_x {
+            return None;
+        }
+
+        Some(XZPoint::new(
+            min_x + (max_x - min_x) / 2,
+            min_z + (max_z - min_z) / 2,
+        ))
+    }
 }
 
+/// Parses the OSM data into elements in Minecraft coordinates.
+/// With `strict`, the first malformed element is an error instead of being repaired or skipped.
 pub fn parse_osm_data(
-    json_data: Value,
+    json_data: impl Into<RawOsmData>,
     bbox: LLBBox,
     scale: f64,
     debug: bool,
-) -> (Vec<ProcessedElement>, XZBBox) {
+    strict: bool,
+) -> Result<(Vec<ProcessedElement>, XZBBox), ArnisError> {
     println!("{} Parsing data...", "[2/7]".bold());
     println!("Bounding box: {bbox:?}");
     emit_gui_progress_update(5.0, "Parsing data...");
 
+    let mut issues = ParseIssues {
+        strict,
+        debug,
+        count: 0,
+    };
+
     // Deserialize the JSON data into the OSMData structure
-    let data = parse_raw_osm_data(json_data).expect("Failed to parse OSM data");
+    let data = parse_raw_osm_data(json_data.into(), &mut issues)?;
 
-    let (coord_transformer, xzbbox) = CoordTransformer::llbbox_to_xzbbox(&bbox, scale)
-        .unwrap_or_else(|e| {
-            eprintln!("Error in defining coordinate transformation:\n{e}");
-            panic!();
-        });
+    let (coord_transformer, xzbbox) =
+        CoordTransformer::llbbox_to_xzbbox(&bbox, scale).map_err(|e| {
+            ArnisError::Geometry(format!("Error in defining coordinate transformation:\n{e}"))
+        })?;
 
     if debug {
         println!("Total elements: {}", data.total_count());
@@ -194,38 +306,62 @@ pub fn parse_osm_data(
 
     // First pass: store all nodes with Minecraft coordinates and process nodes with tags
     for element in data.nodes {
-        if let (Some(lat), Some(lon)) = (element.lat, element.lon) {
-            let llpoint = LLPoint::new(lat, lon).unwrap_or_else(|e| {
-                eprintln!("Encountered invalid node element:\n{e}");
-                panic!();
-            });
+        let (Some(lat), Some(lon)) = (element.lat, element.lon) else {
+            issues.report(format!("Skipping node {} without coordinates", element.id))?;
+            continue;
+        };
+        let llpoint = match LLPoint::new(lat, lon) {
+            Ok(llpoint) => llpoint,
+            Err(e) => {
+                issues.report(format!(
+                    "Skipping node {} with invalid coordinates: {e}",
+                    element.id
+                ))?;
+                continue;
+            }
+        };
 
-            let xzpoint = coord_transformer.transform_point(llpoint);
+        let xzpoint = coord_transformer.transform_point(llpoint);
 
-            let processed: ProcessedNode = ProcessedNode {
-                id: element.id,
-                tags: element.tags.clone().unwrap_or_default(),
-                x: xzpoint.x,
-                z: xzpoint.z,
-            };
+        let processed: ProcessedNode = ProcessedNode {
+            id: element.id,
+            tags: element.tags.clone().unwrap_or_default(),
+            x: xzpoint.x,
+            z: xzpoint.z,
+        };
 
-            nodes_map.insert(element.id, processed.clone());
+        nodes_map.insert(element.id, processed.clone());
 
-            processed_elements.push(ProcessedElement::Node(processed));
-        }
+        processed_elements.push(ProcessedElement::Node(processed));
     }
 
     // Second pass: process ways and clip them to bbox
     for element in data.ways {
         let mut nodes: Vec<ProcessedNode> = vec![];
+        let mut missing_nodes: usize = 0;
         if let Some(node_ids) = &element.nodes {
             for &node_id in node_ids {
-                if let Some(node) = nodes_map.get(&node_id) {
-                    nodes.push(node.clone());
+                match nodes_map.get(&node_id) {
+                    Some(node) => nodes.push(node.clone()),
+                    None => missing_nodes += 1,
                 }
             }
         }
 
+        if nodes.len() < 2 {
+            issues.report(format!(
+                "Skipping way {} with fewer than 2 nodes",
+                element.id
+            ))?;
+            continue;
+        }
+        if missing_nodes > 0 {
+            issues.report(format!(
+                "Way {} references {missing_nodes} missing nodes, leaving them out",
+                element.id
+            ))?;
+        }
+
         let processed: ProcessedWay = ProcessedWay {
             id: element.id,
             tags: element.tags.clone().unwrap_or_default(),
@@ -247,33 +383,46 @@ pub fn parse_osm_data(
             continue;
         };
 
-        let members: Vec<ProcessedMember> = element
-            .members
-            .iter()
-            .filter_map(|mem: &OsmMember| {
-                if mem.r#type != "way" {
-                    eprintln!("WARN: Unknown relation member type \"{}\"", mem.r#type);
-                    return None;
-                }
+        let mut members: Vec<ProcessedMember> = Vec::new();
+        for mem in &element.members {
+            if mem.r#type != "way" {
+                issues.report(format!(
+                    "Multipolygon {} has a {} member, only ways are used",
+                    element.id, mem.r#type
+                ))?;
+                continue;
+            }
 
-                let role = match mem.role.as_str() {
-                    "outer" => ProcessedMemberRole::Outer,
-                    "inner" => ProcessedMemberRole::Inner,
-                    _ => return None,
-                };
+            let role = match mem.role.as_str() {
+                "outer" => ProcessedMemberRole::Outer,
+                "inner" => ProcessedMemberRole::Inner,
+                // Members without a role are outer rings in practice
+                "" => {
+                    issues.report(format!(
+                        "Multipolygon {} has a member without role, using it as outer",
+                        element.id
+                    ))?;
+                    ProcessedMemberRole::Outer
+                }
+                role => {
+                    issues.report(format!(
+                        "Multipolygon {} has a member with unknown role \"{role}\"",
+                        element.id
+                    ))?;
+                    continue;
+                }
+            };
 
-                // Check if the way exists in ways_map
-                let way: ProcessedWay = match ways_map.get(&mem.r#ref) {
-                    Some(w) => w.clone(),
-                    None => {
-                        // Way was likely filtered out because it was completely outside the bbox
-                        return None;
-                    }
-                };
+            // Way was likely filtered out because it was completely outside the bbox
+            let Some(way) = ways_map.get(&mem.r#ref) else {
+                continue;
+            };
 
-                Some(ProcessedMember { role, way })
-            })
-            .collect();
+            members.push(ProcessedMember {
+                role,
+                way: way.clone(),
+            });
+        }
 
         if !members.is_empty() {
             processed_elements.push(ProcessedElement::Relation(ProcessedRelation {
@@ -284,9 +433,21 @@ pub fn parse_osm_data(
         }
     }
 
+    if issues.count > 0 {
+        println!(
+            "Repaired or skipped {} malformed elements{}",
+            issues.count,
+            if debug {
+                ""
+            } else {
+                " (details with --debug, fail instead with --strict)"
+            }
+        );
+    }
+
     emit_gui_progress_update(15.0, "");
 
-    (processed_elements, xzbbox)
+    Ok((processed_elements, xzbbox))
 }
 
 const PRIORITY_ORDER: [&str; 6] = [
@@ -304,3 +465,67 @@ pub fn get_priority(element: &ProcessedElement) -> usize {
     // Return a default priority if none of the tags match
     PRIORITY_ORDER.len()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn malformed_data() -> Value {
+        json!({
+            "elements": [
+                { "type": "node", "id": 1, "lat": 54.627, "lon": 9.927 },
+                { "type": "node", "id": 2, "lat": 54.628, "lon": 9.928 },
+                { "type": "node", "id": 3, "lat": 54.629, "lon": 9.929 },
+                { "type": "node", "id": 4, "lat": 154.0, "lon": 9.929 },
+                { "type": "node", "id": "five" },
+                { "type": "way", "id": 10, "nodes": [1, 2, 3, 99], "tags": { "highway": "path" } },
+                { "type": "way", "id": 11, "nodes": [1], "tags": { "barrier": "fence" } },
+                { "type": "way", "id": 12, "nodes": [1, 2, 3, 1] },
+                {
+                    "type": "relation",
+                    "id": 20,
+                    "tags": { "type": "multipolygon", "landuse": "grass" },
+                    "members": [{ "type": "way", "ref": 12, "role": "" }]
+                }
+            ]
+        })
+    }
+
+    #[test]
+    fn test_lenient_parsing_repairs_and_skips() {
+        let bbox = LLBBox::new(54.62, 9.92, 54.63, 9.93).unwrap();
+        let (elements, _) = parse_osm_data(malformed_data(), bbox, 1.0, false, false).unwrap();
+
+        let ids: Vec<u64> = elements.iter().map(|element| element.id()).collect();
+        assert_eq!(ids, vec![1, 2, 3, 10, 12, 20]);
+
+        let ProcessedElement::Relation(relation) = &elements[5] else {
+            panic!("expected a relation");
+        };
+        assert_eq!(relation.members[0].role, ProcessedMemberRole::Outer);
+    }
+
+    #[test]
+    fn test_streaming_parsing_matches() {
+        let bbox = LLBBox::new(54.62, 9.92, 54.63, 9.93).unwrap();
+        let text = malformed_data().to_string();
+        let (elements, _) = parse_osm_data(text.clone(), bbox, 1.0, false, false).unwrap();
+
+        let ids: Vec<u64> = elements.iter().map(|element| element.id()).collect();
+        assert_eq!(ids, vec![1, 2, 3, 10, 12, 20]);
+        assert!(parse_osm_data(text, bbox, 1.0, false, true).is_err());
+
+        let empty = r#"{"elements": [], "remark": "runtime error: out of memory"}"#.to_string();
+        let error = parse_osm_data(empty, bbox, 1.0, false, false).unwrap_err();
+        assert_eq!(error.code(), "overpass_quota");
+        assert!(error.to_string().contains("out of memory"));
+    }
+
+    #[test]
+    fn test_strict_parsing_fails() {
+        let bbox = LLBBox::new(54.62, 9.92, 54.63, 9.93).unwrap();
+        let result = parse_osm_data(malformed_data(), bbox, 1.0, false, true);
+        assert_eq!(result.unwrap_err().code(), "parse");
+    }
+}
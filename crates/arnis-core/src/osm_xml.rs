@@ -0,0 +1,244 @@
+//! Plain `.osm` XML files, as exported from JOSM or the OSM website, converted into the same
+//! JSON as an Overpass response. Objects deleted in JOSM are left out, and the negative ids
+//! of objects that were not uploaded yet are mapped to unused positive ones.
+
+use serde_json::{json, Map, Value};
+
+/// Ids of objects not uploaded yet start here, far above the ids OSM hands out
+const NEW_ID_BASE: u64 = 1 << 60;
+
+/// Whether a data file is an OSM XML file, by its name
+pub fn is_osm_xml(file: &str) -> bool {
+    file.ends_with(".osm") || file.ends_with(".osm.gz")
+}
+
+/// Converts the nodes, ways and relations of an OSM XML document into Overpass JSON
+pub fn parse_osm_xml(contents: &str) -> Result<Value, String> {
+    let mut elements: Vec<Value> = Vec::new();
+    // The open node, way or relation, and whether it is kept
+    let mut current: Option<(Value, bool)> = None;
+
+    let mut rest = contents;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        // Comments, declarations and processing instructions
+        if rest.starts_with("!--") {
+            let end = rest.find("-->").ok_or("Unterminated comment in OSM file")?;
+            rest = &rest[end + 3..];
+            continue;
+        }
+        let end = rest.find('>').ok_or("Unterminated element in OSM file")?;
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with(['?', '!']) {
+            continue;
+        }
+
+        if let Some(name) = tag.strip_prefix('/') {
+            if matches!(name.trim(), "node" | "way" | "relation") {
+                if let Some((element, true)) = current.take() {
+                    elements.push(element);
+                }
+            }
+            continue;
+        }
+
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        let attributes = parse_attributes(attributes);
+        let attribute = |key: &str| -> Option<&str> {
+            attributes
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.as_str())
+        };
+
+        match name {
+            "node" | "way" | "relation" => {
+                let id = parse_id(attribute("id"), name)?;
+                let mut element = json!({ "type": name, "id": id });
+                if name == "node" {
+                    for key in ["lat", "lon"] {
+                        let value: f64 = attribute(key)
+                            .and_then(|value| value.parse().ok())
+                            .ok_or_else(|| {
+                                format!("Node {id} without a valid {key} in OSM file")
+                            })?;
+                        element[key] = json!(value);
+                    }
+                }
+                let kept =
+                    attribute("action") != Some("delete") && attribute("visible") != Some("false");
+                if self_closing {
+                    if kept {
+                        elements.push(element);
+                    }
+                } else {
+                    current = Some((element, kept));
+                }
+            }
+            "tag" => {
+                if let (Some((element, _)), Some(key), Some(value)) =
+                    (current.as_mut(), attribute("k"), attribute("v"))
+                {
+                    let tags = element
+                        .as_object_mut()
+                        .and_then(|object| {
+                            object
+                                .entry("tags")
+                                .or_insert_with(|| Value::Object(Map::new()))
+                                .as_object_mut()
+                        })
+                        .ok_or("Malformed element in OSM file")?;
+                    tags.insert(key.to_string(), json!(value));
+                }
+            }
+            "nd" => {
+                if let Some((element, _)) = current.as_mut() {
+                    let id = parse_id(attribute("ref"), "nd")?;
+                    push(element, "nodes", json!(id));
+                }
+            }
+            "member" => {
+                if let Some((element, _)) = current.as_mut() {
+                    let id = parse_id(attribute("ref"), "member")?;
+                    let member = json!({
+                        "type": attribute("type").unwrap_or("way"),
+                        "ref": id,
+                        "role": attribute("role").unwrap_or(""),
+                    });
+                    push(element, "members", member);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(json!({ "elements": elements }))
+}
+
+fn push(element: &mut Value, list: &str, value: Value) {
+    if let Some(values) = element[list].as_array_mut() {
+        values.push(value);
+    } else {
+        element[list] = json!([value]);
+    }
+}
+
+/// Id of an object, with negative ids of new objects mapped above `NEW_ID_BASE`
+fn parse_id(value: Option<&str>, element: &str) -> Result<u64, String> {
+    let id: i64 = value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("<{element}> without a valid id in OSM file"))?;
+    Ok(if id < 0 {
+        NEW_ID_BASE + id.unsigned_abs()
+    } else {
+        id as u64
+    })
+}
+
+/// Name and unescaped value of every `name="value"` attribute
+fn parse_attributes(attributes: &str) -> Vec<(&str, String)> {
+    let mut parsed = Vec::new();
+    let mut rest = attributes;
+    while let Some(equals) = rest.find('=') {
+        let name = rest[..equals].trim();
+        let value = rest[equals + 1..].trim_start();
+        let Some(quote) = value.chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+            break;
+        };
+        let Some(end) = value[1..].find(quote) else {
+            break;
+        };
+        parsed.push((name, unescape(&value[1..end + 1])));
+        rest = &value[end + 2..];
+    }
+    parsed
+}
+
+fn unescape(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_string();
+    }
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..end];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match character {
+            Some(character) => {
+                unescaped.push(character);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_OSM: &str = r#"<?xml version='1.0' encoding='UTF-8'?>
+<osm version='0.6' generator='JOSM'>
+  <!-- <node id='9'/> -->
+  <node id='1' lat='54.6290' lon='9.9300' />
+  <node id='-2' action='modify' lat='54.6300' lon='9.9320'>
+    <tag k='name' v='Caf&#233; &amp; Bar' />
+  </node>
+  <node id='3' action='delete' lat='54.6310' lon='9.9350' />
+  <way id='-5' action='modify'>
+    <nd ref='1' />
+    <nd ref='-2' />
+    <tag k='highway' v='residential' />
+  </way>
+  <relation id='7' visible='true'>
+    <member type='way' ref='-5' role="outer" />
+    <tag k='type' v='multipolygon' />
+  </relation>
+</osm>"#;
+
+    #[test]
+    fn test_parse_osm_xml() {
+        let data = parse_osm_xml(SAMPLE_OSM).unwrap();
+        let elements = data["elements"].as_array().unwrap();
+        assert_eq!(elements.len(), 4);
+
+        let new_node = NEW_ID_BASE + 2;
+        assert_eq!(elements[1]["id"], new_node);
+        assert_eq!(elements[1]["lat"], 54.63);
+        assert_eq!(elements[1]["tags"]["name"], "Café & Bar");
+        assert!(elements[0].get("tags").is_none());
+
+        assert_eq!(elements[2]["type"], "way");
+        assert_eq!(elements[2]["nodes"], json!([1, new_node]));
+        assert_eq!(elements[2]["tags"]["highway"], "residential");
+
+        assert_eq!(elements[3]["members"][0]["ref"], NEW_ID_BASE + 5);
+        assert_eq!(elements[3]["members"][0]["role"], "outer");
+
+        assert!(parse_osm_xml("<osm><node id='1' lat='1.0'/></osm>").is_err());
+    }
+}
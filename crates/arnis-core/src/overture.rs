@@ -0,0 +1,408 @@
+//! Overture Maps data (`--overture`) as data source: GeoParquet files of the buildings,
+//! transportation segments and places themes, local or downloaded from a URL. Rows are
+//! converted into the same JSON as an Overpass response, with Overture properties mapped to
+//! OSM tags. Overture often knows building heights where OSM has none.
+//!
+//! Remote files are downloaded as a whole before reading, so extract the area first (e.g.
+//! with `overturemaps download --bbox ... -f geoparquet`) instead of pointing at a release.
+
+use crate::api_scheduler;
+use crate::coordinate_system::geographic::LLBBox;
+use crate::disk_io;
+use crate::progress::emit_gui_progress_update;
+use colored::Colorize;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::{Field, Row};
+use reqwest::blocking::ClientBuilder;
+use serde_json::{json, Map, Value};
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Reads Overture GeoParquet files and returns the rows inside `bbox` as Overpass JSON
+pub fn fetch_data_from_overture(
+    bbox: LLBBox,
+    sources: &[String],
+) -> Result<Value, Box<dyn std::error::Error>> {
+    println!("{} Loading Overture Maps data...", "[1/7]".bold());
+    emit_gui_progress_update(1.0, "Loading Overture Maps data...");
+
+    let mut builder = ElementBuilder::default();
+    for (i, source) in sources.iter().enumerate() {
+        let path = local_path(source)?;
+        let reader = SerializedFileReader::new(File::open(&path)?)?;
+        for row in reader.get_row_iter(None)? {
+            let row = row?;
+            if let Some(feature) = OvertureFeature::from_row(&row) {
+                if feature.intersects(&bbox) {
+                    builder.add(&feature);
+                }
+            }
+        }
+        emit_gui_progress_update(1.0 + 4.0 * (i + 1) as f64 / sources.len() as f64, "");
+    }
+
+    println!(
+        "Loaded {} elements from Overture Maps",
+        builder.elements.len()
+    );
+    Ok(json!({ "elements": builder.elements }))
+}
+
+/// Downloads remote files into the temporary directory, parquet needs random access
+fn local_path(source: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if !source.starts_with("http://") && !source.starts_with("https://") {
+        return Ok(PathBuf::from(source));
+    }
+
+    let name = source.rsplit('/').next().unwrap_or("overture.parquet");
+    let path = disk_io::temp_dir().join("arnis-overture").join(name);
+    if path.is_file() {
+        return Ok(path);
+    }
+    std::fs::create_dir_all(path.parent().unwrap())?;
+
+    println!("Downloading {source}...");
+    let client = ClientBuilder::new()
+        .timeout(Duration::from_secs(3600))
+        .build()?;
+    let bytes = api_scheduler::get(&client, source, &[])?
+        .error_for_status()?
+        .bytes()?;
+    std::fs::write(&path, bytes)?;
+    Ok(path)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Geometry {
+    Point(f64, f64),
+    Line(Vec<(f64, f64)>),
+    /// Outer rings of a polygon or multipolygon, holes are left out
+    Areas(Vec<Vec<(f64, f64)>>),
+}
+
+impl Geometry {
+    /// All coordinates as (lon, lat)
+    fn points(&self) -> Vec<(f64, f64)> {
+        match self {
+            Geometry::Point(lon, lat) => vec![(*lon, *lat)],
+            Geometry::Line(points) => points.clone(),
+            Geometry::Areas(rings) => rings.iter().flatten().copied().collect(),
+        }
+    }
+}
+
+struct OvertureFeature {
+    geometry: Geometry,
+    tags: Map<String, Value>,
+}
+
+impl OvertureFeature {
+    fn from_row(row: &Row) -> Option<Self> {
+        let mut geometry = None;
+        let mut properties: Map<String, Value> = Map::new();
+        for (name, field) in row.get_column_iter() {
+            match (name.as_str(), field) {
+                ("geometry", Field::Bytes(wkb)) => geometry = parse_wkb(wkb.data()),
+                ("names" | "categories", Field::Group(group)) => {
+                    // Only the primary name and category are used
+                    if let Some(Field::Str(primary)) = group
+                        .get_column_iter()
+                        .find(|(name, _)| name.as_str() == "primary")
+                        .map(|(_, field)| field)
+                    {
+                        properties.insert(name.clone(), Value::String(primary.clone()));
+                    }
+                }
+                (_, Field::Str(value)) => {
+                    properties.insert(name.clone(), Value::String(value.clone()));
+                }
+                (_, Field::Double(value)) => {
+                    properties.insert(name.clone(), json!(value));
+                }
+                (_, Field::Float(value)) => {
+                    properties.insert(name.clone(), json!(value));
+                }
+                (_, Field::Int(value)) => {
+                    properties.insert(name.clone(), json!(value));
+                }
+                (_, Field::Long(value)) => {
+                    properties.insert(name.clone(), json!(value));
+                }
+                _ => {}
+            }
+        }
+
+        let geometry = geometry?;
+        let tags = osm_tags(&geometry, &properties)?;
+        Some(OvertureFeature { geometry, tags })
+    }
+
+    fn intersects(&self, bbox: &LLBBox) -> bool {
+        self.geometry.points().iter().any(|&(lon, lat)| {
+            (bbox.min().lat()..=bbox.max().lat()).contains(&lat)
+                && (bbox.min().lng()..=bbox.max().lng()).contains(&lon)
+        })
+    }
+}
+
+/// OSM tags for an Overture feature, `None` for features Arnis has no use for
+fn osm_tags(geometry: &Geometry, properties: &Map<String, Value>) -> Option<Map<String, Value>> {
+    let text = |key: &str| properties.get(key).and_then(Value::as_str);
+    let number = |key: &str| properties.get(key).and_then(Value::as_f64);
+    let mut tags = Map::new();
+    let mut tag = |key: &str, value: String| {
+        tags.insert(key.to_string(), Value::String(value));
+    };
+
+    match geometry {
+        // Transportation segments
+        Geometry::Line(_) => match (text("subtype"), text("class")) {
+            (Some("rail"), _) => tag("railway", "rail".to_string()),
+            (Some("road"), Some("unknown") | None) => tag("highway", "road".to_string()),
+            (Some("road"), Some(class)) => tag("highway", class.to_string()),
+            _ => return None,
+        },
+        // Places
+        Geometry::Point(..) => {
+            let category = text("categories")?;
+            let amenity = match category {
+                "restaurant" | "cafe" | "bank" | "pharmacy" | "school" | "hospital" | "library"
+                | "fuel" | "bar" | "pub" => category,
+                "fast_food_restaurant" => "fast_food",
+                _ => return None,
+            };
+            tag("amenity", amenity.to_string());
+        }
+        // Buildings
+        Geometry::Areas(_) => {
+            tag("building", text("class").unwrap_or("yes").to_string());
+            if let Some(height) = number("height") {
+                tag("height", format!("{height:.1}"));
+            }
+            if let Some(min_height) = number("min_height") {
+                tag("min_height", format!("{min_height:.1}"));
+            }
+            if let Some(floors) = number("num_floors") {
+                tag("building:levels", format!("{floors:.0}"));
+            }
+            if let Some(roof_shape) = text("roof_shape") {
+                tag("roof:shape", roof_shape.to_string());
+            }
+        }
+    }
+
+    if let Some(name) = text("names") {
+        tag("name", name.to_string());
+    }
+    Some(tags)
+}
+
+/// Reads the WKB geometry Overture stores its shapes in
+fn parse_wkb(data: &[u8]) -> Option<Geometry> {
+    let mut reader = WkbReader { data, pos: 0 };
+    reader.geometry()
+}
+
+struct WkbReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl WkbReader<'_> {
+    fn geometry(&mut self) -> Option<Geometry> {
+        let little_endian = self.byte()? == 1;
+        let kind = self.u32(little_endian)?;
+        match kind {
+            1 => Some(Geometry::Point(
+                self.f64(little_endian)?,
+                self.f64(little_endian)?,
+            )),
+            2 => Some(Geometry::Line(self.points(little_endian)?)),
+            3 => Some(Geometry::Areas(vec![self.polygon(little_endian)?])),
+            6 => {
+                let count = self.u32(little_endian)?;
+                let mut rings = Vec::new();
+                for _ in 0..count {
+                    match self.geometry()? {
+                        Geometry::Areas(polygon) => rings.extend(polygon),
+                        _ => return None,
+                    }
+                }
+                Some(Geometry::Areas(rings))
+            }
+            _ => None,
+        }
+    }
+
+    /// The outer ring of a polygon
+    fn polygon(&mut self, little_endian: bool) -> Option<Vec<(f64, f64)>> {
+        let ring_count = self.u32(little_endian)?;
+        let mut outer = None;
+        for _ in 0..ring_count {
+            let ring = self.points(little_endian)?;
+            outer.get_or_insert(ring);
+        }
+        outer
+    }
+
+    fn points(&mut self, little_endian: bool) -> Option<Vec<(f64, f64)>> {
+        let count = self.u32(little_endian)?;
+        (0..count)
+            .map(|_| Some((self.f64(little_endian)?, self.f64(little_endian)?)))
+            .collect()
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self.data.get(self.pos..self.pos + N)?.try_into().ok()?;
+        self.pos += N;
+        Some(bytes)
+    }
+
+    fn u32(&mut self, little_endian: bool) -> Option<u32> {
+        let bytes = self.take::<4>()?;
+        Some(if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn f64(&mut self, little_endian: bool) -> Option<f64> {
+        let bytes = self.take::<8>()?;
+        Some(if little_endian {
+            f64::from_le_bytes(bytes)
+        } else {
+            f64::from_be_bytes(bytes)
+        })
+    }
+}
+
+/// Collects the features as Overpass elements
+#[derive(Default)]
+struct ElementBuilder {
+    elements: Vec<Value>,
+    next_node: u64,
+    next_way: u64,
+}
+
+impl ElementBuilder {
+    fn add(&mut self, feature: &OvertureFeature) {
+        let tags = &feature.tags;
+        match &feature.geometry {
+            Geometry::Point(lon, lat) => {
+                self.next_node += 1;
+                self.elements.push(json!({
+                    "type": "node", "id": self.next_node, "lat": lat, "lon": lon, "tags": tags,
+                }));
+            }
+            Geometry::Line(points) => self.add_way(points, tags),
+            Geometry::Areas(rings) => {
+                for ring in rings {
+                    self.add_way(ring, tags);
+                }
+            }
+        }
+    }
+
+    /// Rings of polygons repeat their first point at the end, so they become closed ways
+    fn add_way(&mut self, points: &[(f64, f64)], tags: &Map<String, Value>) {
+        if points.len() < 2 {
+            return;
+        }
+        let closed = points.len() > 3 && points.first() == points.last();
+        let unique = if closed {
+            &points[..points.len() - 1]
+        } else {
+            points
+        };
+
+        let first_node = self.next_node + 1;
+        for &(lon, lat) in unique {
+            self.next_node += 1;
+            self.elements.push(json!({
+                "type": "node", "id": self.next_node, "lat": lat, "lon": lon,
+            }));
+        }
+        let mut nodes: Vec<u64> = (first_node..=self.next_node).collect();
+        if closed {
+            nodes.push(first_node);
+        }
+        self.next_way += 1;
+        self.elements.push(json!({
+            "type": "way", "id": self.next_way, "nodes": nodes, "tags": tags,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wkb_polygon(rings: &[&[(f64, f64)]]) -> Vec<u8> {
+        let mut wkb = vec![1];
+        wkb.extend(3u32.to_le_bytes());
+        wkb.extend((rings.len() as u32).to_le_bytes());
+        for ring in rings {
+            wkb.extend((ring.len() as u32).to_le_bytes());
+            for (x, y) in ring.iter() {
+                wkb.extend(x.to_le_bytes());
+                wkb.extend(y.to_le_bytes());
+            }
+        }
+        wkb
+    }
+
+    #[test]
+    fn test_parse_wkb() {
+        let outer = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)];
+        let hole = [(0.2, 0.2), (0.4, 0.2), (0.4, 0.4), (0.2, 0.2)];
+        assert_eq!(
+            parse_wkb(&wkb_polygon(&[&outer, &hole])),
+            Some(Geometry::Areas(vec![outer.to_vec()]))
+        );
+
+        // Big endian point
+        let mut point = vec![0];
+        point.extend(1u32.to_be_bytes());
+        point.extend(9.93f64.to_be_bytes());
+        point.extend(54.63f64.to_be_bytes());
+        assert_eq!(parse_wkb(&point), Some(Geometry::Point(9.93, 54.63)));
+
+        assert_eq!(parse_wkb(&point[..10]), None);
+    }
+
+    #[test]
+    fn test_osm_tags() {
+        let building = Geometry::Areas(vec![]);
+        let properties: Map<String, Value> = serde_json::from_value(json!({
+            "height": 12.5, "num_floors": 4, "names": "Rathaus",
+        }))
+        .unwrap();
+        let tags = osm_tags(&building, &properties).unwrap();
+        assert_eq!(tags["building"], "yes");
+        assert_eq!(tags["height"], "12.5");
+        assert_eq!(tags["building:levels"], "4");
+        assert_eq!(tags["name"], "Rathaus");
+
+        let segment = Geometry::Line(vec![]);
+        let water: Map<String, Value> =
+            serde_json::from_value(json!({ "subtype": "water" })).unwrap();
+        assert!(osm_tags(&segment, &water).is_none());
+    }
+
+    #[test]
+    fn test_closed_ways() {
+        let mut builder = ElementBuilder::default();
+        let ring = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 0.0)];
+        builder.add_way(&ring, &Map::new());
+        assert_eq!(builder.elements.len(), 4);
+        assert_eq!(builder.elements[3]["nodes"], json!([1, 2, 3, 1]));
+    }
+}
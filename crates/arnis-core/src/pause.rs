@@ -0,0 +1,60 @@
+//! Pausing of a running generation, from the GUI or with a signal (SIGUSR1 on Unix).
+//!
+//! Generation stops between two elements (or ground batches) and continues at the same
+//! point when resumed, so no work of the current stage is lost.
+
+use crate::progress::emit_gui_progress_update;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// How often a paused generation checks whether it was resumed
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub fn set_paused(paused: bool) {
+    PAUSED.store(paused, Ordering::SeqCst);
+}
+
+/// Switches between paused and running, safe to call from a signal handler
+pub fn toggle() {
+    PAUSED.fetch_xor(true, Ordering::SeqCst);
+}
+
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+/// Blocks while generation is paused
+pub fn wait_while_paused() {
+    if !is_paused() {
+        return;
+    }
+
+    println!("Generation paused");
+    emit_gui_progress_update(-1.0, "Paused");
+
+    while is_paused() {
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    println!("Generation resumed");
+    emit_gui_progress_update(-1.0, "Resumed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle() {
+        set_paused(false);
+        toggle();
+        assert!(is_paused());
+        toggle();
+        assert!(!is_paused());
+
+        // Returns right away when not paused
+        wait_while_paused();
+    }
+}
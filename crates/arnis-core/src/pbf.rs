@@ -0,0 +1,527 @@
+//! OSM PBF extracts (`--file region.osm.pbf`), e.g. regional downloads from Geofabrik, as data
+//! source instead of Overpass. The elements of the area are converted into the same JSON as
+//! an Overpass response: ways with a node in the area with all of their nodes, and relations
+//! with a member in the area with all of their member ways, as the Overpass query returns them.
+//!
+//! The file is read up to three times, so that only the area is held in memory: for the
+//! elements in the area, for the other member ways of its relations, and for the nodes of the
+//! ways that leave the area.
+
+use crate::coordinate_system::geographic::{LLBBox, LLPoint};
+use crate::progress::emit_gui_progress_update;
+use crate::protobuf::ProtoReader;
+use colored::Colorize;
+use flate2::read::ZlibDecoder;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::Path;
+
+/// Largest blob the format allows
+const MAX_BLOB_SIZE: usize = 32 * 1024 * 1024;
+
+type Tags = Vec<(String, String)>;
+
+struct Node {
+    id: i64,
+    lat: f64,
+    lon: f64,
+    tags: Tags,
+}
+
+struct Way {
+    id: i64,
+    refs: Vec<i64>,
+    tags: Tags,
+}
+
+struct Member {
+    kind: &'static str,
+    id: i64,
+    role: String,
+}
+
+struct Relation {
+    id: i64,
+    members: Vec<Member>,
+    tags: Tags,
+}
+
+/// Elements of one data block of the file
+#[derive(Default)]
+struct Block {
+    nodes: Vec<Node>,
+    ways: Vec<Way>,
+    relations: Vec<Relation>,
+}
+
+/// Whether a data file is an OSM PBF file, by its name
+pub fn is_pbf(file: &str) -> bool {
+    file.ends_with(".pbf")
+}
+
+/// Reads the elements within `bbox` from an OSM PBF file as Overpass JSON
+pub fn fetch_data_from_pbf(file: &str, bbox: LLBBox) -> Result<Value, Box<dyn std::error::Error>> {
+    println!("{} Loading data from PBF file...", "[1/7]".bold());
+    emit_gui_progress_update(1.0, "Loading data from PBF file...");
+    let path = Path::new(file);
+    let inside =
+        |lat: f64, lon: f64| LLPoint::new(lat, lon).is_ok_and(|point| bbox.contains(&point));
+
+    // Files are sorted by type, so the nodes of the area are known when the ways come
+    let mut coords: HashMap<i64, (f64, f64)> = HashMap::new();
+    let mut node_tags: HashMap<i64, Tags> = HashMap::new();
+    let mut ways: HashMap<i64, Way> = HashMap::new();
+    let mut relations: Vec<Relation> = Vec::new();
+    for_each_block(path, |block| {
+        for node in block.nodes {
+            if inside(node.lat, node.lon) {
+                coords.insert(node.id, (node.lat, node.lon));
+                if !node.tags.is_empty() {
+                    node_tags.insert(node.id, node.tags);
+                }
+            }
+        }
+        for way in block.ways {
+            if way.refs.iter().any(|id| coords.contains_key(id)) {
+                ways.insert(way.id, way);
+            }
+        }
+        for relation in block.relations {
+            let in_area = relation.members.iter().any(|member| match member.kind {
+                "node" => coords.contains_key(&member.id),
+                "way" => ways.contains_key(&member.id),
+                _ => false,
+            });
+            if in_area {
+                relations.push(relation);
+            }
+        }
+        Ok(())
+    })?;
+    emit_gui_progress_update(3.0, "");
+
+    let missing_ways: HashSet<i64> = relations
+        .iter()
+        .flat_map(|relation| &relation.members)
+        .filter(|member| member.kind == "way" && !ways.contains_key(&member.id))
+        .map(|member| member.id)
+        .collect();
+    if !missing_ways.is_empty() {
+        for_each_block(path, |block| {
+            for way in block.ways {
+                if missing_ways.contains(&way.id) {
+                    ways.insert(way.id, way);
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    let missing_nodes: HashSet<i64> = ways
+        .values()
+        .flat_map(|way| &way.refs)
+        .filter(|id| !coords.contains_key(id))
+        .copied()
+        .collect();
+    if !missing_nodes.is_empty() {
+        for_each_block(path, |block| {
+            for node in block.nodes {
+                if missing_nodes.contains(&node.id) {
+                    coords.insert(node.id, (node.lat, node.lon));
+                }
+            }
+            Ok(())
+        })?;
+    }
+    emit_gui_progress_update(5.0, "");
+
+    // Untagged nodes are only needed for the ways
+    let needed: HashSet<i64> = ways
+        .values()
+        .flat_map(|way| way.refs.iter().copied())
+        .chain(node_tags.keys().copied())
+        .collect();
+    let mut elements: Vec<Value> = Vec::with_capacity(needed.len() + ways.len() + relations.len());
+    for id in needed {
+        let Some(&(lat, lon)) = coords.get(&id) else {
+            continue;
+        };
+        let mut node = json!({ "type": "node", "id": id, "lat": lat, "lon": lon });
+        if let Some(tags) = node_tags.remove(&id) {
+            node["tags"] = tags_json(tags);
+        }
+        elements.push(node);
+    }
+    let way_count = ways.len();
+    for way in ways.into_values() {
+        elements.push(json!({
+            "type": "way", "id": way.id, "nodes": way.refs, "tags": tags_json(way.tags),
+        }));
+    }
+    let relation_count = relations.len();
+    for relation in relations {
+        let members: Vec<Value> = relation
+            .members
+            .into_iter()
+            .map(|member| json!({ "type": member.kind, "ref": member.id, "role": member.role }))
+            .collect();
+        elements.push(json!({
+            "type": "relation", "id": relation.id, "members": members,
+            "tags": tags_json(relation.tags),
+        }));
+    }
+
+    println!(
+        "Loaded {way_count} ways and {relation_count} relations from {}",
+        path.display()
+    );
+    Ok(json!({ "elements": elements }))
+}
+
+fn tags_json(tags: Tags) -> Value {
+    Value::Object(
+        tags.into_iter()
+            .map(|(key, value)| (key, Value::String(value)))
+            .collect::<Map<String, Value>>(),
+    )
+}
+
+/// Calls `visit` with the elements of every data block of the file
+fn for_each_block(
+    path: &Path,
+    mut visit: impl FnMut(Block) -> Result<(), String>,
+) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let read_error = |e: std::io::Error| format!("Failed to read {}: {e}", path.display());
+
+    loop {
+        let mut header_size = [0u8; 4];
+        match reader.read_exact(&mut header_size) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(read_error(e)),
+        }
+        let mut header = vec![0; (u32::from_be_bytes(header_size) as usize).min(MAX_BLOB_SIZE)];
+        reader.read_exact(&mut header).map_err(read_error)?;
+        let (blob_type, blob_size) = decode_blob_header(&header)?;
+        if blob_size > MAX_BLOB_SIZE {
+            return Err(format!("{} is not a valid PBF file", path.display()));
+        }
+        let mut blob = vec![0; blob_size];
+        reader.read_exact(&mut blob).map_err(read_error)?;
+
+        if blob_type == "OSMData" {
+            visit(decode_block(&decode_blob(&blob)?)?)?;
+        }
+    }
+}
+
+/// Type and size of the blob following the header
+fn decode_blob_header(data: &[u8]) -> Result<(String, usize), String> {
+    let mut reader = ProtoReader::new(data);
+    let (mut blob_type, mut size) = (String::new(), 0);
+    while reader.has_more() {
+        match reader.key()? {
+            (1, 2) => blob_type = String::from_utf8_lossy(reader.bytes()?).into_owned(),
+            (3, 0) => size = reader.varint()? as usize,
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+    Ok((blob_type, size))
+}
+
+/// Uncompressed content of a blob
+fn decode_blob(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = ProtoReader::new(data);
+    while reader.has_more() {
+        match reader.key()? {
+            (1, 2) => return Ok(reader.bytes()?.to_vec()),
+            (3, 2) => {
+                let mut content = Vec::new();
+                ZlibDecoder::new(reader.bytes()?)
+                    .read_to_end(&mut content)
+                    .map_err(|e| format!("Failed to decompress PBF block: {e}"))?;
+                return Ok(content);
+            }
+            (4..=7, _) => return Err("Unsupported compression in PBF file".to_string()),
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+    Ok(Vec::new())
+}
+
+/// Elements of a `PrimitiveBlock`
+fn decode_block(data: &[u8]) -> Result<Block, String> {
+    let mut reader = ProtoReader::new(data);
+    let mut strings: Vec<String> = Vec::new();
+    let mut groups: Vec<&[u8]> = Vec::new();
+    let (mut granularity, mut lat_offset, mut lon_offset): (i64, i64, i64) = (100, 0, 0);
+    while reader.has_more() {
+        match reader.key()? {
+            (1, 2) => {
+                let mut table = ProtoReader::new(reader.bytes()?);
+                while table.has_more() {
+                    match table.key()? {
+                        (1, 2) => {
+                            strings.push(String::from_utf8_lossy(table.bytes()?).into_owned())
+                        }
+                        (_, wire_type) => table.skip(wire_type)?,
+                    }
+                }
+            }
+            (2, 2) => groups.push(reader.bytes()?),
+            (17, 0) => granularity = reader.varint()? as i64,
+            (19, 0) => lat_offset = reader.varint()? as i64,
+            (20, 0) => lon_offset = reader.varint()? as i64,
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+
+    // Coordinates are stored in units of `granularity` nanodegrees
+    let lat = |value: i64| (lat_offset + granularity * value) as f64 * 1e-9;
+    let lon = |value: i64| (lon_offset + granularity * value) as f64 * 1e-9;
+    let tags = |keys: &[u32], values: &[u32]| -> Tags {
+        keys.iter()
+            .zip(values)
+            .filter_map(|(&key, &value)| {
+                Some((
+                    strings.get(key as usize)?.clone(),
+                    strings.get(value as usize)?.clone(),
+                ))
+            })
+            .collect()
+    };
+
+    let mut block = Block::default();
+    for group in groups {
+        let mut reader = ProtoReader::new(group);
+        while reader.has_more() {
+            match reader.key()? {
+                (1, 2) => {
+                    let mut node = ProtoReader::new(reader.bytes()?);
+                    let (mut id, mut keys, mut values, mut node_lat, mut node_lon) =
+                        (0, Vec::new(), Vec::new(), 0, 0);
+                    while node.has_more() {
+                        match node.key()? {
+                            (1, 0) => id = node.sint64()?,
+                            (2, 2) => keys = node.packed()?,
+                            (3, 2) => values = node.packed()?,
+                            (8, 0) => node_lat = node.sint64()?,
+                            (9, 0) => node_lon = node.sint64()?,
+                            (_, wire_type) => node.skip(wire_type)?,
+                        }
+                    }
+                    block.nodes.push(Node {
+                        id,
+                        lat: lat(node_lat),
+                        lon: lon(node_lon),
+                        tags: tags(&keys, &values),
+                    });
+                }
+                (2, 2) => {
+                    let mut dense = ProtoReader::new(reader.bytes()?);
+                    let (mut ids, mut lats, mut lons, mut keys_values) =
+                        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+                    while dense.has_more() {
+                        match dense.key()? {
+                            (1, 2) => ids = dense.packed_delta()?,
+                            (8, 2) => lats = dense.packed_delta()?,
+                            (9, 2) => lons = dense.packed_delta()?,
+                            (10, 2) => keys_values = dense.packed()?,
+                            (_, wire_type) => dense.skip(wire_type)?,
+                        }
+                    }
+                    // The tags of all nodes in a row, each node's ending with 0
+                    let mut node_tags = keys_values.split(|&index| index == 0);
+                    for ((id, node_lat), node_lon) in ids.into_iter().zip(lats).zip(lons) {
+                        let pairs = node_tags.next().unwrap_or_default();
+                        let (keys, values): (Vec<u32>, Vec<u32>) =
+                            pairs.chunks_exact(2).map(|pair| (pair[0], pair[1])).unzip();
+                        block.nodes.push(Node {
+                            id,
+                            lat: lat(node_lat),
+                            lon: lon(node_lon),
+                            tags: tags(&keys, &values),
+                        });
+                    }
+                }
+                (3, 2) => {
+                    let mut way = ProtoReader::new(reader.bytes()?);
+                    let (mut id, mut keys, mut values, mut refs) =
+                        (0, Vec::new(), Vec::new(), Vec::new());
+                    while way.has_more() {
+                        match way.key()? {
+                            (1, 0) => id = way.varint()? as i64,
+                            (2, 2) => keys = way.packed()?,
+                            (3, 2) => values = way.packed()?,
+                            (8, 2) => refs = way.packed_delta()?,
+                            (_, wire_type) => way.skip(wire_type)?,
+                        }
+                    }
+                    block.ways.push(Way {
+                        id,
+                        refs,
+                        tags: tags(&keys, &values),
+                    });
+                }
+                (4, 2) => {
+                    let mut relation = ProtoReader::new(reader.bytes()?);
+                    let (mut id, mut keys, mut values) = (0, Vec::new(), Vec::new());
+                    let (mut roles, mut member_ids, mut types) =
+                        (Vec::new(), Vec::new(), Vec::new());
+                    while relation.has_more() {
+                        match relation.key()? {
+                            (1, 0) => id = relation.varint()? as i64,
+                            (2, 2) => keys = relation.packed()?,
+                            (3, 2) => values = relation.packed()?,
+                            (8, 2) => roles = relation.packed()?,
+                            (9, 2) => member_ids = relation.packed_delta()?,
+                            (10, 2) => types = relation.packed()?,
+                            (_, wire_type) => relation.skip(wire_type)?,
+                        }
+                    }
+                    let members = member_ids
+                        .into_iter()
+                        .zip(types)
+                        .zip(roles)
+                        .map(|((id, kind), role)| Member {
+                            kind: match kind {
+                                0 => "node",
+                                1 => "way",
+                                _ => "relation",
+                            },
+                            id,
+                            role: strings.get(role as usize).cloned().unwrap_or_default(),
+                        })
+                        .collect();
+                    block.relations.push(Relation {
+                        id,
+                        members,
+                        tags: tags(&keys, &values),
+                    });
+                }
+                (_, wire_type) => reader.skip(wire_type)?,
+            }
+        }
+    }
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn field(out: &mut Vec<u8>, number: u64, bytes: &[u8]) {
+        varint(out, number << 3 | 2);
+        varint(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+
+    /// Packed sint64 deltas of the values
+    fn deltas(values: &[i64]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut last = 0;
+        for &value in values {
+            let delta = value - last;
+            varint(&mut out, ((delta << 1) ^ (delta >> 63)) as u64);
+            last = value;
+        }
+        out
+    }
+
+    fn packed(values: &[u64]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for &value in values {
+            varint(&mut out, value);
+        }
+        out
+    }
+
+    fn blob(out: &mut Vec<u8>, blob_type: &str, content: &[u8]) {
+        let mut blob = Vec::new();
+        field(&mut blob, 1, content);
+        let mut header = Vec::new();
+        field(&mut header, 1, blob_type.as_bytes());
+        varint(&mut header, 3 << 3);
+        varint(&mut header, blob.len() as u64);
+        out.extend_from_slice(&(header.len() as u32).to_be_bytes());
+        out.extend_from_slice(&header);
+        out.extend_from_slice(&blob);
+    }
+
+    #[test]
+    fn test_fetch_data_from_pbf() {
+        // A road from inside the area to a node outside of it, and a tagged node outside
+        let mut strings = Vec::new();
+        for string in ["", "highway", "residential", "amenity", "bench"] {
+            field(&mut strings, 1, string.as_bytes());
+        }
+        let mut dense = Vec::new();
+        field(&mut dense, 1, &deltas(&[1, 2, 3]));
+        // Latitudes and longitudes in the default granularity of 100 nanodegrees
+        field(
+            &mut dense,
+            8,
+            &deltas(&[500_100_000, 500_200_000, 510_000_000]),
+        );
+        field(
+            &mut dense,
+            9,
+            &deltas(&[100_100_000, 100_200_000, 110_000_000]),
+        );
+        field(&mut dense, 10, &packed(&[0, 0, 3, 4, 0]));
+        let mut way = Vec::new();
+        varint(&mut way, 1 << 3);
+        varint(&mut way, 10);
+        field(&mut way, 2, &packed(&[1]));
+        field(&mut way, 3, &packed(&[2]));
+        field(&mut way, 8, &deltas(&[1, 2, 3]));
+        let mut group = Vec::new();
+        field(&mut group, 2, &dense);
+        field(&mut group, 3, &way);
+        let mut block = Vec::new();
+        field(&mut block, 1, &strings);
+        field(&mut block, 2, &group);
+
+        let mut data = Vec::new();
+        blob(&mut data, "OSMHeader", &[]);
+        blob(&mut data, "OSMData", &block);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("area.osm.pbf");
+        File::create(&path).unwrap().write_all(&data).unwrap();
+
+        let bbox = LLBBox::new(50.0, 10.0, 50.5, 10.5).unwrap();
+        let json = fetch_data_from_pbf(path.to_str().unwrap(), bbox).unwrap();
+        let elements = json["elements"].as_array().unwrap();
+
+        let way = elements.iter().find(|e| e["type"] == "way").unwrap();
+        assert_eq!(way["id"], 10);
+        assert_eq!(way["nodes"], json!([1, 2, 3]));
+        assert_eq!(way["tags"]["highway"], "residential");
+        // All nodes of the road, the one outside of the area too, but not the bench
+        let mut nodes: Vec<i64> = elements
+            .iter()
+            .filter(|e| e["type"] == "node")
+            .map(|e| e["id"].as_i64().unwrap())
+            .collect();
+        nodes.sort_unstable();
+        assert_eq!(nodes, [1, 2, 3]);
+        let first = elements
+            .iter()
+            .find(|e| e["id"] == 1 && e["type"] == "node")
+            .unwrap();
+        assert!((first["lat"].as_f64().unwrap() - 50.01).abs() < 1e-9);
+    }
+}
@@ -1,5 +1,6 @@
 //! Global performance config for RAM/thread/CPU optimizations (Apple Silicon, cross-platform)
 use crate::cpu_info::{PlatformInfo, SimdFeatures};
+use crate::error::ArnisError;
 use once_cell::sync::OnceCell;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -17,16 +18,50 @@ pub struct PerformanceConfig {
     pub platform: PlatformInfo,
 }
 
+const GB: u64 = 1024 * 1024 * 1024;
+
+/// Memory of a 16x16x16 section of blocks while it is edited
+pub const SECTION_BYTES: u64 = 4096 + 512;
+/// Sections per chunk held in memory on average: the ground plus features above it
+const SECTIONS_PER_CHUNK: u64 = 2;
+/// Additional sections per chunk when the underground is filled with stone
+const FILLGROUND_SECTIONS_PER_CHUNK: u64 = 4;
+/// Memory per element, covering the parsed data and geometry computed while processing it
+const ELEMENT_BYTES: u64 = 4 * 1024;
+
+/// Rough peak memory needed to generate a world, given its area in blocks
+pub fn estimate_generation_bytes(total_blocks: u64, element_count: usize, fillground: bool) -> u64 {
+    let chunks = total_blocks.div_ceil(256);
+    let sections_per_chunk = if fillground {
+        SECTIONS_PER_CHUNK + FILLGROUND_SECTIONS_PER_CHUNK
+    } else {
+        SECTIONS_PER_CHUNK
+    };
+    chunks * sections_per_chunk * SECTION_BYTES + element_count as u64 * ELEMENT_BYTES
+}
+
 static PERF_CONFIG: OnceCell<PerformanceConfig> = OnceCell::new();
 
 impl PerformanceConfig {
-    /// Initialize from detected platform and (future) GUI/CLI settings
+    /// Initialize from detected platform
     pub fn init_default() -> &'static Self {
+        Self::init(None, None)
+    }
+
+    /// Initialize from detected platform, capped by the limits set by the user
+    pub fn init(max_ram_gb: Option<f64>, max_threads: Option<usize>) -> &'static Self {
         let platform = PlatformInfo::detect();
         // Default: 16GB or system RAM, whichever is lower
-        let default_ram = 16 * 1024 * 1024 * 1024u64;
-        let effective_max_ram_bytes = platform.total_ram_bytes.min(default_ram);
-        let effective_threads = platform.logical_cpus.max(1);
+        let default_ram = 16 * GB;
+        let effective_max_ram_bytes = match max_ram_gb {
+            Some(gb) => platform.total_ram_bytes.min((gb * GB as f64) as u64),
+            None => platform.total_ram_bytes.min(default_ram),
+        };
+        let effective_threads = max_threads
+            .map_or(platform.logical_cpus, |threads| {
+                threads.min(platform.logical_cpus)
+            })
+            .max(1);
         let cpu_opt_mode = match platform.simd {
             SimdFeatures::NEON | SimdFeatures::AVX2 | SimdFeatures::AVX512 => CpuOptMode::Native,
             _ => CpuOptMode::Auto,
@@ -46,13 +81,68 @@ impl PerformanceConfig {
             .expect("PerformanceConfig not initialized")
     }
 
+    /// The config, if `init` was called
+    pub fn try_get() -> Option<&'static Self> {
+        PERF_CONFIG.get()
+    }
+
+    /// Refuses work that is estimated to need more memory than allowed, so generation
+    /// fails right away with a helpful message instead of running out of memory midway
+    pub fn check_memory_budget(&self, estimated_bytes: u64) -> Result<(), ArnisError> {
+        if estimated_bytes <= self.effective_max_ram_bytes {
+            return Ok(());
+        }
+        Err(ArnisError::MemoryBudget(format!(
+            "Generating this area needs about {:.1} GB of memory, but only {:.1} GB are available. \
+             Select a smaller area, lower the scale or raise --max-ram-gb.",
+            estimated_bytes as f64 / GB as f64,
+            self.effective_max_ram_bytes as f64 / GB as f64
+        )))
+    }
+
     pub fn log_config(&self) {
         println!(
             "[perf] RAM: {:.1} GB, threads: {}, arch: {}, SIMD: {}",
-            self.effective_max_ram_bytes as f64 / (1024.0 * 1024.0 * 1024.0),
+            self.effective_max_ram_bytes as f64 / GB as f64,
             self.effective_threads,
             self.platform.arch,
             self.platform.simd
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_ram_bytes: u64) -> PerformanceConfig {
+        PerformanceConfig {
+            effective_max_ram_bytes: max_ram_bytes,
+            effective_threads: 1,
+            cpu_opt_mode: CpuOptMode::Auto,
+            platform: PlatformInfo::detect(),
+        }
+    }
+
+    #[test]
+    fn test_estimate_generation_bytes() {
+        // About 77 MB: 3907 chunks of two sections each, and the elements
+        let small = estimate_generation_bytes(1000 * 1000, 10_000, false);
+        assert_eq!(small, 76_966_912);
+
+        // Filling the underground and more elements need more memory
+        assert!(estimate_generation_bytes(1000 * 1000, 10_000, true) > small);
+        assert!(estimate_generation_bytes(1000 * 1000, 100_000, false) > small);
+    }
+
+    #[test]
+    fn test_check_memory_budget() {
+        let config = config(2 * GB);
+        assert!(config.check_memory_budget(GB).is_ok());
+        assert!(config.check_memory_budget(2 * GB).is_ok());
+
+        let err = config.check_memory_budget(3 * GB).unwrap_err().to_string();
+        assert!(err.contains("3.0 GB"));
+        assert!(err.contains("2.0 GB"));
+    }
+}
@@ -0,0 +1,199 @@
+//! Block columns inside GeoJSON polygons, rasterized for the generated area.
+//!
+//! Used for land polygons (`--land-polygons`), e.g. the OSM land polygons of
+//! osmdata.openstreetmap.de converted to GeoJSON. OSM only maps the sea through coastline
+//! ways, which are not part of the downloaded data, so without this the sea gets the same
+//! grass as the land. Ground outside all land polygons becomes water instead.
+//!
+//! Also used for exclusion polygons (`--exclude-polygons`), whose interior is left empty,
+//! e.g. to keep out a restricted site or an area to build by hand.
+
+use crate::coordinate_system::cartesian::{XZBBox, XZPoint};
+use crate::coordinate_system::geographic::{LLBBox, LLPoint};
+use crate::coordinate_system::transformation::CoordTransformer;
+use serde_json::Value;
+use std::path::Path;
+
+/// Where the polygons cross each row of the area
+pub struct PolygonMask {
+    min_z: i32,
+    /// Sorted x positions of the polygon edges crossing each row, the inside lies between
+    /// odd and even crossings
+    crossings: Vec<Vec<f64>>,
+}
+
+impl PolygonMask {
+    pub fn load(path: &Path, llbbox: &LLBBox, xzbbox: &XZBBox, scale: f64) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let data: Value = serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+
+        let (transformer, untranslated) = CoordTransformer::llbbox_to_xzbbox(llbbox, scale)?;
+        // The map may have been moved by a translate operation
+        let offset_x = xzbbox.min_x() - untranslated.min_x();
+        let offset_z = xzbbox.min_z() - untranslated.min_z();
+
+        let rings: Vec<Vec<XZPoint>> = read_rings(&data, llbbox)
+            .into_iter()
+            .map(|ring| {
+                ring.into_iter()
+                    .map(|point| {
+                        let point = transformer.transform_point(point);
+                        XZPoint::new(point.x + offset_x, point.z + offset_z)
+                    })
+                    .collect()
+            })
+            .collect();
+        if rings.is_empty() {
+            return Err("No polygon reaches into the area".to_string());
+        }
+
+        Ok(Self::from_rings(&rings, xzbbox.min_z(), xzbbox.max_z()))
+    }
+
+    /// Mask of the rows `min_z..=max_z`, with the rings of all polygons, holes included
+    fn from_rings(rings: &[Vec<XZPoint>], min_z: i32, max_z: i32) -> Self {
+        let mut crossings: Vec<Vec<f64>> = vec![Vec::new(); (max_z - min_z + 1) as usize];
+        for ring in rings {
+            for edge in ring.windows(2) {
+                let (a, b) = (edge[0], edge[1]);
+                if a.z == b.z {
+                    continue;
+                }
+                // Each row is sampled through the centers of its blocks
+                let (low, high) = if a.z < b.z { (a, b) } else { (b, a) };
+                let first = low.z.max(min_z);
+                let last = (high.z - 1).min(max_z);
+                for z in first..=last {
+                    let t = (f64::from(z) + 0.5 - f64::from(low.z)) / f64::from(high.z - low.z);
+                    let x = f64::from(low.x) + t * f64::from(high.x - low.x);
+                    crossings[(z - min_z) as usize].push(x);
+                }
+            }
+        }
+        for row in &mut crossings {
+            row.sort_by(f64::total_cmp);
+        }
+        PolygonMask { min_z, crossings }
+    }
+
+    /// Whether the block column lies inside a polygon. Outside the masked rows, it doesn't.
+    pub fn contains(&self, x: i32, z: i32) -> bool {
+        let Some(row) = usize::try_from(z - self.min_z)
+            .ok()
+            .and_then(|row| self.crossings.get(row))
+        else {
+            return false;
+        };
+        let center = f64::from(x) + 0.5;
+        row.partition_point(|crossing| *crossing < center) % 2 == 1
+    }
+}
+
+/// All rings of the GeoJSON polygons whose bounding box reaches into `bbox`
+fn read_rings(data: &Value, bbox: &LLBBox) -> Vec<Vec<LLPoint>> {
+    let geometries: Vec<&Value> = match data["type"].as_str() {
+        Some("FeatureCollection") => data["features"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|feature| &feature["geometry"])
+            .collect(),
+        Some("Feature") => vec![&data["geometry"]],
+        _ => vec![data],
+    };
+
+    let mut rings = Vec::new();
+    for geometry in geometries {
+        let polygons: Vec<&Value> = match geometry["type"].as_str() {
+            Some("Polygon") => vec![&geometry["coordinates"]],
+            Some("MultiPolygon") => geometry["coordinates"]
+                .as_array()
+                .map(|polygons| polygons.iter().collect())
+                .unwrap_or_default(),
+            _ => continue,
+        };
+        for polygon in polygons {
+            let polygon_rings: Vec<Vec<LLPoint>> = polygon
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|ring| {
+                    ring.as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|position| {
+                            LLPoint::new(position[1].as_f64()?, position[0].as_f64()?).ok()
+                        })
+                        .collect()
+                })
+                .collect();
+            let Some(outer) = polygon_rings.first() else {
+                continue;
+            };
+            if reaches_into(outer, bbox) {
+                rings.extend(polygon_rings);
+            }
+        }
+    }
+    rings
+}
+
+fn reaches_into(ring: &[LLPoint], bbox: &LLBBox) -> bool {
+    let Some(first) = ring.first() else {
+        return false;
+    };
+    let (mut min, mut max) = ((first.lat(), first.lng()), (first.lat(), first.lng()));
+    for point in ring {
+        min = (min.0.min(point.lat()), min.1.min(point.lng()));
+        max = (max.0.max(point.lat()), max.1.max(point.lng()));
+    }
+    min.0 <= bbox.max().lat()
+        && max.0 >= bbox.min().lat()
+        && min.1 <= bbox.max().lng()
+        && max.1 >= bbox.min().lng()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(points: &[(i32, i32)]) -> Vec<XZPoint> {
+        points.iter().map(|&(x, z)| XZPoint::new(x, z)).collect()
+    }
+
+    #[test]
+    fn test_contains() {
+        // An island from 10 to 30 with a lagoon from 15 to 20
+        let island = ring(&[(10, 10), (30, 10), (30, 30), (10, 30), (10, 10)]);
+        let lagoon = ring(&[(15, 15), (20, 15), (20, 20), (15, 20), (15, 15)]);
+        let mask = PolygonMask::from_rings(&[island, lagoon], 0, 40);
+
+        assert!(!mask.contains(5, 5));
+        assert!(!mask.contains(35, 20));
+        assert!(mask.contains(10, 10));
+        assert!(mask.contains(29, 29));
+        assert!(!mask.contains(30, 20));
+        assert!(!mask.contains(17, 17));
+        assert!(mask.contains(12, 17));
+        assert!(!mask.contains(20, 50));
+    }
+
+    #[test]
+    fn test_read_rings() {
+        let data = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [
+                { "geometry": { "type": "Polygon", "coordinates": [
+                    [[9.9, 54.6], [10.1, 54.6], [10.1, 54.8], [9.9, 54.6]],
+                ] } },
+                { "geometry": { "type": "Polygon", "coordinates": [
+                    [[20.0, 60.0], [21.0, 60.0], [21.0, 61.0], [20.0, 60.0]],
+                ] } },
+            ],
+        });
+        let bbox = LLBBox::new(54.6, 9.9, 54.7, 10.0).unwrap();
+        assert_eq!(read_rings(&data, &bbox).len(), 1);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::ArnisError;
 use once_cell::sync::OnceCell;
 use serde_json::json;
 use tauri::{Emitter, WebviewWindow};
@@ -43,11 +44,18 @@ pub fn emit_gui_progress_update(progress: f64, message: &str) {
     }
 }
 
-pub fn emit_gui_error(message: &str) {
-    let truncated_message = if message.len() > 35 {
-        &message[..35]
-    } else {
-        message
-    };
-    emit_gui_progress_update(0.0, &format!("Error! {truncated_message}"));
+/// Reports a failed generation to the GUI, with what the user can do about it
+pub fn emit_gui_error(error: &ArnisError) {
+    if let Some(window) = get_main_window() {
+        let payload = json!({
+            "code": error.code(),
+            "message": error.to_string(),
+            "guidance": error.guidance()
+        });
+
+        if let Err(e) = window.emit("generation-error", payload) {
+            eprintln!("Failed to emit error event: {e}");
+        }
+    }
+    emit_gui_progress_update(0.0, &format!("Error! {}", error.guidance()));
 }
@@ -0,0 +1,121 @@
+//! Minimal protobuf decoding, enough for vector tiles and OSM PBF files, which are read
+//! field by field without generated code.
+
+/// Reader of protobuf messages, field by field
+pub(crate) struct ProtoReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        ProtoReader { data, pos: 0 }
+    }
+
+    pub(crate) fn has_more(&self) -> bool {
+        self.pos < self.data.len()
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<u64, String> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or("Truncated protobuf message")?;
+            self.pos += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("Invalid varint in protobuf message".to_string())
+    }
+
+    /// Field number and wire type of the next field
+    pub(crate) fn key(&mut self) -> Result<(u32, u8), String> {
+        let key = self.varint()?;
+        Ok(((key >> 3) as u32, (key & 7) as u8))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.varint()? as usize;
+        let end = self
+            .pos
+            .checked_add(len)
+            .ok_or("Truncated protobuf message")?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or("Truncated protobuf message")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn fixed(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or("Truncated protobuf message")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub(crate) fn skip(&mut self, wire_type: u8) -> Result<(), String> {
+        match wire_type {
+            0 => self.varint().map(drop),
+            1 => self.fixed(8).map(drop),
+            2 => self.bytes().map(drop),
+            5 => self.fixed(4).map(drop),
+            _ => Err(format!("Unsupported protobuf wire type {wire_type}")),
+        }
+    }
+
+    /// Packed repeated uint32 field
+    pub(crate) fn packed(&mut self) -> Result<Vec<u32>, String> {
+        let mut reader = ProtoReader::new(self.bytes()?);
+        let mut values = Vec::new();
+        while reader.has_more() {
+            values.push(reader.varint()? as u32);
+        }
+        Ok(values)
+    }
+
+    /// Signed varint in zigzag encoding (`sint64`)
+    pub(crate) fn sint64(&mut self) -> Result<i64, String> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    /// Packed repeated sint64 field whose values are deltas to the previous value, as in
+    /// OSM PBF files
+    pub(crate) fn packed_delta(&mut self) -> Result<Vec<i64>, String> {
+        let mut reader = ProtoReader::new(self.bytes()?);
+        let mut values = Vec::new();
+        let mut last: i64 = 0;
+        while reader.has_more() {
+            last += reader.sint64()?;
+            values.push(last);
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packed_delta() {
+        // Field 1, length 4: deltas 3, -1, 150 (zigzag 6, 1, 300)
+        let data = [0x0a, 0x04, 0x06, 0x01, 0xac, 0x02];
+        let mut reader = ProtoReader::new(&data);
+        assert_eq!(reader.key().unwrap(), (1, 2));
+        assert_eq!(reader.packed_delta().unwrap(), [3, 2, 152]);
+        assert!(!reader.has_more());
+
+        let mut truncated = ProtoReader::new(&[0x0a, 0x04, 0x06]);
+        truncated.key().unwrap();
+        assert!(truncated.bytes().is_err());
+    }
+}
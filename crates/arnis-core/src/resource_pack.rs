@@ -0,0 +1,113 @@
+//! Optional resource pack (`--resource-pack`) with textures for the signage Arnis places,
+//! e.g. white plates with a dark frame instead of oak boards, so that names stay readable
+//! from a distance. It is written as a folder into the world directory: copy it into the
+//! `resourcepacks` folder, or zip it and reference it in `server.properties` on servers.
+
+use image::{Rgba, RgbaImage};
+use std::fs;
+use std::path::Path;
+
+/// Folder of the pack inside the world directory
+pub const PACK_DIR: &str = "arnis_resource_pack";
+/// Resource pack format of Minecraft 1.21
+const PACK_FORMAT: u32 = 34;
+
+const PLATE: Rgba<u8> = Rgba([240, 240, 236, 255]);
+const FRAME: Rgba<u8> = Rgba([24, 48, 112, 255]);
+const POST: Rgba<u8> = Rgba([110, 110, 116, 255]);
+
+pub struct ResourcePack {
+    /// Textures by path below `assets/minecraft/textures`, without extension
+    textures: Vec<(String, RgbaImage)>,
+}
+
+impl ResourcePack {
+    /// The pack with the textures of all signage Arnis generates
+    pub fn signage() -> Self {
+        let mut pack = ResourcePack {
+            textures: Vec::new(),
+        };
+        // Arnis places oak signs for its signage
+        pack.add_texture("entity/signs/oak", street_sign_texture());
+        pack
+    }
+
+    /// Adds a texture, e.g. an icon for POIs shown in item frames
+    pub fn add_texture(&mut self, path: &str, image: RgbaImage) {
+        self.textures.push((path.to_string(), image));
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<(), String> {
+        let textures_dir = dir.join("assets").join("minecraft").join("textures");
+        fs::create_dir_all(&textures_dir)
+            .map_err(|e| format!("Failed to create {}: {e}", textures_dir.display()))?;
+
+        let mcmeta = format!(
+            "{{\"pack\":{{\"pack_format\":{PACK_FORMAT},\"description\":\"Arnis signage\"}}}}\n"
+        );
+        fs::write(dir.join("pack.mcmeta"), mcmeta)
+            .map_err(|e| format!("Failed to write pack.mcmeta: {e}"))?;
+
+        for (path, image) in &self.textures {
+            let file = textures_dir.join(format!("{path}.png"));
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create {}: {e}", parent.display()))?;
+            }
+            image
+                .save(&file)
+                .map_err(|e| format!("Failed to write {}: {e}", file.display()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Sign entity texture (64x32) with white plates in a dark frame on a gray post
+fn street_sign_texture() -> RgbaImage {
+    let mut image = RgbaImage::from_pixel(64, 32, FRAME);
+
+    // The post occupies the lower left part of the texture
+    for x in 0..8 {
+        for y in 14..30 {
+            image.put_pixel(x, y, POST);
+        }
+    }
+
+    // Front and back faces of the board, leaving a one pixel frame around them
+    for (min_x, max_x) in [(3, 25), (29, 51)] {
+        for x in min_x..max_x {
+            for y in 3..13 {
+                image.put_pixel(x, y, PLATE);
+            }
+        }
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_street_sign_texture() {
+        let image = street_sign_texture();
+        assert_eq!(image.dimensions(), (64, 32));
+        // Plate on both faces, framed by the border color
+        assert_eq!(*image.get_pixel(14, 8), PLATE);
+        assert_eq!(*image.get_pixel(40, 8), PLATE);
+        assert_eq!(*image.get_pixel(2, 8), FRAME);
+        assert_eq!(*image.get_pixel(14, 2), FRAME);
+    }
+
+    #[test]
+    fn test_write() {
+        let dir = tempfile::tempdir().unwrap();
+        ResourcePack::signage().write(dir.path()).unwrap();
+
+        assert!(dir.path().join("pack.mcmeta").is_file());
+        let sign = dir
+            .path()
+            .join("assets/minecraft/textures/entity/signs/oak.png");
+        assert_eq!(image::open(sign).unwrap().width(), 64);
+    }
+}
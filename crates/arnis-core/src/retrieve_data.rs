@@ -1,14 +1,43 @@
-use crate::coordinate_system::geographic::LLBBox;
-use crate::progress::{emit_gui_error, emit_gui_progress_update, is_running_with_gui};
+use crate::api_scheduler;
+use crate::categories::FeatureFilter;
+use crate::chunking::{split_into_chunks, ChunkConfig};
+use crate::coordinate_system::geographic::{LLBBox, LLPoint};
+use crate::error::ArnisError;
+use crate::gpx;
+use crate::osm_cache;
+use crate::osm_parser::OsmElement;
+use crate::osm_xml;
+use crate::progress::{emit_gui_progress_update, is_running_with_gui};
 use colored::Colorize;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use fnv::FnvHashMap;
 use rand::seq::SliceRandom;
+use rayon::prelude::*;
 use reqwest::blocking::Client;
 use reqwest::blocking::ClientBuilder;
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::hash_map::Entry;
+use std::fmt;
 use std::fs::File;
-use std::io::{self, BufReader, Write};
+use std::io::{self, Read, Write};
+use std::path::Path;
 use std::process::Command;
-use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Areas larger than this are downloaded in tiles of this size, as single queries for large
+/// areas run out of memory on the Overpass servers
+const DOWNLOAD_TILES: ChunkConfig = ChunkConfig {
+    chunk_size_deg: 0.05,
+};
+/// Tiles downloaded at the same time, about the number of slots the public servers grant
+const PARALLEL_DOWNLOADS: usize = 3;
+/// Time between two reports of the downloaded size
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
 
 /// Function to download data using reqwest
 fn download_with_reqwest(url: &str, query: &str) -> Result<String, Box<dyn std::error::Error>> {
@@ -17,19 +46,28 @@ fn download_with_reqwest(url: &str, query: &str) -> Result<String, Box<dyn std::
         .build()?;
 
     let response: Result<reqwest::blocking::Response, reqwest::Error> =
-        client.get(url).query(&[("data", query)]).send();
+        api_scheduler::get(&client, url, &[("data", query)]);
 
     match response {
         Ok(resp) => {
-            emit_gui_progress_update(3.0, "Downloading data...");
             if resp.status().is_success() {
-                let text = resp.text()?;
+                let text = String::from_utf8(read_body(resp)?)?;
                 if text.is_empty() {
-                    return Err("Error! Received invalid from server".into());
+                    return Err(
+                        ArnisError::Network("Received invalid from server".to_string()).into(),
+                    );
                 }
                 Ok(text)
+            } else if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                Err(
+                    ArnisError::OverpassQuota(format!("Received response code: {}", resp.status()))
+                        .into(),
+                )
             } else {
-                Err(format!("Error! Received response code: {}", resp.status()).into())
+                Err(
+                    ArnisError::Network(format!("Received response code: {}", resp.status()))
+                        .into(),
+                )
             }
         }
         Err(e) => {
@@ -40,21 +78,66 @@ fn download_with_reqwest(url: &str, query: &str) -> Result<String, Box<dyn std::
                         .red()
                         .bold()
                 );
-                emit_gui_error("Request timed out. Try selecting a smaller area.");
+                Err(ArnisError::OverpassQuota(format!("Request timed out: {e}")).into())
             } else {
                 eprintln!("{}", format!("Error! {e:.52}").red().bold());
-                emit_gui_error(&format!("{:.52}", e.to_string()));
+                Err(ArnisError::Network(e.to_string()).into())
             }
-            // Always propagate errors
-            Err(e.into())
         }
     }
 }
 
+/// Reads the response body piece by piece, reporting the downloaded size, until it is
+/// complete or the download is cancelled
+fn read_body(mut resp: reqwest::blocking::Response) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let total: Option<u64> = resp.content_length();
+    let mut body: Vec<u8> = Vec::with_capacity(total.unwrap_or(0) as usize);
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut last_report = Instant::now();
+
+    loop {
+        if api_scheduler::is_cancelled() {
+            return Err(ArnisError::Cancelled.into());
+        }
+        let read = resp.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(body);
+        }
+        body.extend_from_slice(&buffer[..read]);
+
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            last_report = Instant::now();
+            let received = body.len() as u64;
+            match total {
+                // Overpass mostly doesn't know the size in advance, so only the bytes so far
+                Some(total) if total > 0 => emit_gui_progress_update(
+                    1.0 + 4.0 * received.min(total) as f64 / total as f64,
+                    &format!(
+                        "Downloading data... {} / {}",
+                        format_size(received),
+                        format_size(total)
+                    ),
+                ),
+                _ => emit_gui_progress_update(
+                    -1.0,
+                    &format!("Downloading data... {}", format_size(received)),
+                ),
+            }
+        }
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
 /// Function to download data using `curl`
 fn download_with_curl(url: &str, query: &str) -> io::Result<String> {
+    api_scheduler::wait_for_slot(url);
     let output: std::process::Output = Command::new("curl")
         .arg("-s") // Add silent mode to suppress output
+        .arg("-A")
+        .arg(api_scheduler::user_agent())
         .arg(format!("{url}?data={query}"))
         .output()?;
 
@@ -67,8 +150,10 @@ fn download_with_curl(url: &str, query: &str) -> io::Result<String> {
 
 /// Function to download data using `wget`
 fn download_with_wget(url: &str, query: &str) -> io::Result<String> {
+    api_scheduler::wait_for_slot(url);
     let output: std::process::Output = Command::new("wget")
         .arg("-qO-") // Use `-qO-` to output the result directly to stdout
+        .arg(format!("--user-agent={}", api_scheduler::user_agent()))
         .arg(format!("{url}?data={query}"))
         .output()?;
 
@@ -83,148 +168,551 @@ pub fn fetch_data_from_file(file: &str) -> Result<Value, Box<dyn std::error::Err
     println!("{} Loading data from file...", "[1/7]".bold());
     emit_gui_progress_update(1.0, "Loading data from file...");
 
-    let file: File = File::open(file)?;
-    let reader: BufReader<File> = BufReader::new(file);
-    let data: Value = serde_json::from_reader(reader)?;
+    if osm_xml::is_osm_xml(file) {
+        return Ok(osm_xml::parse_osm_xml(&read_data_file(file)?)?);
+    }
+    let data: Value = serde_json::from_str(&read_data_file(file)?)?;
     Ok(data)
 }
 
-/// Main function to fetch data
-pub fn fetch_data_from_overpass(
-    bbox: LLBBox,
-    debug: bool,
-    download_method: &str,
-    save_file: Option<&str>,
-) -> Result<Value, Box<dyn std::error::Error>> {
-    println!("{} Fetching data...", "[1/7]".bold());
-    emit_gui_progress_update(1.0, "Fetching data...");
+/// Reads a plain `.osm` XML file, e.g. exported from JOSM, as Overpass JSON
+pub fn fetch_data_from_osm_xml(file: &str) -> Result<Value, Box<dyn std::error::Error>> {
+    println!("{} Loading data from OSM file...", "[1/7]".bold());
+    emit_gui_progress_update(1.0, "Loading data from OSM file...");
 
-    // List of Overpass API servers
-    let api_servers: Vec<&str> = vec![
-        "https://overpass-api.de/api/interpreter",
-        "https://lz4.overpass-api.de/api/interpreter",
-        "https://z.overpass-api.de/api/interpreter",
-        //"https://overpass.kumi.systems/api/interpreter", // This server is not reliable anymore
-        //"https://overpass.private.coffee/api/interpreter", // This server is not reliable anymore
-    ];
-    let fallback_api_servers: Vec<&str> =
-        vec!["https://maps.mail.ru/osm/tools/overpass/api/interpreter"];
-    let mut url: &&str = api_servers.choose(&mut rand::thread_rng()).unwrap();
+    Ok(osm_xml::parse_osm_xml(&read_data_file(file)?)?)
+}
+
+/// Reads a data file without parsing it, to be parsed element by element
+pub fn fetch_raw_from_file(file: &str) -> Result<String, Box<dyn std::error::Error>> {
+    println!("{} Loading data from file...", "[1/7]".bold());
+    emit_gui_progress_update(1.0, "Loading data from file...");
+
+    read_data_file(file)
+}
+
+/// Reads saved data, gzip-compressed or plain
+fn read_data_file(path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let bytes: Vec<u8> = std::fs::read(path)?;
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+        return Ok(String::from_utf8(bytes)?);
+    }
+    let mut text = String::new();
+    GzDecoder::new(bytes.as_slice()).read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Saves data for later runs, gzip-compressed if the file name ends in `.gz`
+fn save_data_file(path: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file: File = File::create(path)?;
+    if path.ends_with(".gz") {
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(data)?;
+        encoder.finish()?;
+    } else {
+        file.write_all(data)?;
+    }
+    println!("API response saved to: {path}");
+    Ok(())
+}
+
+/// Options that change what data the Overpass query returns
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    /// Query the data as it was mapped at this date (ISO 8601, UTC)
+    pub date: Option<String>,
+    /// Custom query replacing the built-in one, see `load_query_template`
+    pub template: Option<String>,
+    /// Categories the built-in query asks for
+    pub filter: FeatureFilter,
+}
+
+/// Keys the built-in Overpass query asks for, unless the feature filter leaves some out
+const QUERY_KEYS: [&str; 14] = [
+    "building", "highway", "landuse", "natural", "leisure", "water", "waterway", "amenity",
+    "tourism", "bridge", "railway", "barrier", "entrance", "door",
+];
+
+/// Loads a custom Overpass query. `{{bbox}}` is replaced with the bounding box
+/// (`min_lat,min_lng,max_lat,max_lng`) and `{{date}}` with the date setting, if any.
+pub fn load_query_template(path: &Path) -> Result<String, String> {
+    let template = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read query template {}: {e}", path.display()))?;
+
+    if !template.contains("{{bbox}}") {
+        return Err("The query template must contain the {{bbox}} placeholder".to_string());
+    }
+    if !template.contains("[out:json]") {
+        return Err("The query template must request JSON output with [out:json]".to_string());
+    }
+
+    Ok(template)
+}
+
+/// Overpass API query for all features Arnis generates within a bounding box.
+/// With a date (ISO 8601, UTC), the data is queried as it was mapped at that time.
+fn build_overpass_query(bbox: &LLBBox, options: &QueryOptions) -> String {
+    if let Some(template) = &options.template {
+        let bbox_string = format!(
+            "{},{},{},{}",
+            bbox.min().lat(),
+            bbox.min().lng(),
+            bbox.max().lat(),
+            bbox.max().lng()
+        );
+        return template
+            .replace("{{bbox}}", &bbox_string)
+            .replace("{{date}}", &date_setting(options));
+    }
+
+    format!(
+        r#"{}
+    .relsinbbox out body;
+    .waysinbbox out body;
+    .nodesinbbox out skel qt;"#,
+        overpass_selection(bbox, options)
+    )
+}
+
+fn date_setting(options: &QueryOptions) -> String {
+    options
+        .date
+        .as_deref()
+        .map(|date| format!(r#"[date:"{date}"]"#))
+        .unwrap_or_default()
+}
+
+/// Statements of the built-in query selecting the relations, ways and nodes of the area into
+/// `.relsinbbox`, `.waysinbbox` and `.nodesinbbox`, without any output
+fn overpass_selection(bbox: &LLBBox, options: &QueryOptions) -> String {
+    let date_setting = date_setting(options);
 
-    // Generate Overpass API query for bounding box
-    let query: String = format!(
-        r#"[out:json][timeout:360][bbox:{},{},{},{}];
+    // Without a filter, all ways are fetched as well, for features of no category
+    let keys = options.filter.overpass_keys();
+    let selectors: String = QUERY_KEYS
+        .iter()
+        .filter(|&&key| match &keys {
+            Some(keys) => keys.contains(&key),
+            None => true,
+        })
+        .map(|key| format!("        nwr[\"{key}\"];\n"))
+        .chain(keys.is_none().then(|| "        way;\n".to_string()))
+        .collect();
+
+    format!(
+        r#"[out:json][timeout:360][bbox:{},{},{},{}]{date_setting};
     (
-        nwr["building"];
-        nwr["highway"];
-        nwr["landuse"];
-        nwr["natural"];
-        nwr["leisure"];
-        nwr["water"];
-        nwr["waterway"];
-        nwr["amenity"];
-        nwr["tourism"];
-        nwr["bridge"];
-        nwr["railway"];
-        nwr["barrier"];
-        nwr["entrance"];
-        nwr["door"];
-        way;
-    )->.relsinbbox;
+{selectors}    )->.relsinbbox;
     (
         way(r.relsinbbox);
     )->.waysinbbox;
     (
         node(w.waysinbbox);
         node(w.relsinbbox);
-    )->.nodesinbbox;
-    .relsinbbox out body;
-    .waysinbbox out body;
-    .nodesinbbox out skel qt;"#,
+    )->.nodesinbbox;"#,
         bbox.min().lat(),
         bbox.min().lng(),
         bbox.max().lat(),
         bbox.max().lng(),
+    )
+}
+
+/// Number of elements the built-in query returns for an area, without downloading them, to
+/// estimate the memory the area needs. A custom query template is not taken into account.
+pub fn count_elements(
+    bbox: &LLBBox,
+    query_options: &QueryOptions,
+    download_method: &str,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let query = format!(
+        "{}\n    (.relsinbbox; .waysinbbox; .nodesinbbox;);\n    out count;",
+        overpass_selection(bbox, query_options)
     );
+    let response = download_from_overpass(&query, download_method)?;
+    let data: Value = serde_json::from_str(&response)
+        .map_err(|e| ArnisError::Parse(format!("Invalid element count: {e}")))?;
+    data["elements"][0]["tags"]["total"]
+        .as_str()
+        .and_then(|total| total.parse().ok())
+        .ok_or_else(|| ArnisError::Parse("Overpass returned no element count".to_string()).into())
+}
 
-    {
-        // Fetch data from Overpass API
-        let mut attempt = 0;
-        let max_attempts = 1;
-        let response: String = loop {
-            println!("Downloading from {url} with method {download_method}...");
-            let result = match download_method {
-                "requests" => download_with_reqwest(url, &query),
-                "curl" => download_with_curl(url, &query).map_err(|e| e.into()),
-                "wget" => download_with_wget(url, &query).map_err(|e| e.into()),
-                _ => download_with_reqwest(url, &query), // Default to requests
-            };
+/// Runs a query against the Overpass API servers in random order, with the fallback server
+/// last, trying the next one with a growing wait after every failure
+fn download_from_overpass(
+    query: &str,
+    download_method: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    // List of Overpass API servers
+    let api_servers: Vec<&str> = vec![
+        "https://overpass-api.de/api/interpreter",
+        "https://lz4.overpass-api.de/api/interpreter",
+        "https://z.overpass-api.de/api/interpreter",
+        //"https://overpass.kumi.systems/api/interpreter", // This server is not reliable anymore
+        //"https://overpass.private.coffee/api/interpreter", // This server is not reliable anymore
+    ];
+    let fallback_api_servers: Vec<&str> =
+        vec!["https://maps.mail.ru/osm/tools/overpass/api/interpreter"];
+    let mut rng = rand::thread_rng();
+    let mut mirrors: Vec<&str> = api_servers;
+    mirrors.shuffle(&mut rng);
+    mirrors.extend(fallback_api_servers);
+    let policy = api_scheduler::retry_policy();
 
-            match result {
-                Ok(response) => break response,
-                Err(error) => {
-                    if attempt >= max_attempts {
-                        return Err(error);
-                    }
+    let mut attempt = 0;
+    loop {
+        // Rate limits are per server, so a rate limited request is retried on the next one
+        let url = mirrors[attempt as usize % mirrors.len()];
+        println!("Downloading from {url} with method {download_method}...");
+        let result = match download_method {
+            "requests" => download_with_reqwest(url, query),
+            "curl" => download_with_curl(url, query).map_err(|e| e.into()),
+            "wget" => download_with_wget(url, query).map_err(|e| e.into()),
+            _ => download_with_reqwest(url, query), // Default to requests
+        };
 
-                    println!("Request failed. Switching to fallback url...");
-                    url = fallback_api_servers
-                        .choose(&mut rand::thread_rng())
-                        .unwrap();
-                    attempt += 1;
+        match result {
+            Ok(response) => return Ok(response),
+            Err(error) => {
+                attempt += 1;
+                if attempt >= policy.max_attempts || api_scheduler::is_cancelled() {
+                    return Err(error);
                 }
+
+                let delay = policy.delay(attempt, &mut rng);
+                println!(
+                    "Request failed ({error}). Retrying on the next server in {} seconds...",
+                    delay.as_secs()
+                );
+                std::thread::sleep(delay);
             }
-        };
+        }
+    }
+}
+
+/// Reports an Overpass API response without elements and aborts the generation
+fn handle_empty_response(data: &Value, debug: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if data["elements"]
+        .as_array()
+        .map_or(0, |elements: &Vec<Value>| elements.len())
+        != 0
+    {
+        return Ok(());
+    }
 
-        if let Some(save_file) = save_file {
-            let mut file: File = File::create(save_file)?;
-            file.write_all(response.as_bytes())?;
-            println!("API response saved to: {save_file}");
+    let error = if let Some(remark) = data["remark"].as_str() {
+        // Check if the remark mentions memory or other runtime errors
+        if remark.contains("runtime error") && remark.contains("out of memory") {
+            eprintln!("{}", "Error! The query ran out of memory on the Overpass API server. Try using a smaller area.".red().bold());
+        } else {
+            // Handle other Overpass API errors if present in the remark field
+            eprintln!("{}", format!("Error! API returned: {remark}").red().bold());
         }
+        ArnisError::OverpassQuota(format!("API returned: {remark}"))
+    } else {
+        // General case for when there are no elements and no specific remark
+        eprintln!(
+            "{}",
+            "Error! API returned no data. Please try again!"
+                .red()
+                .bold()
+        );
+        ArnisError::Network("API returned no data".to_string())
+    };
 
-        let data: Value = serde_json::from_str(&response)?;
-
-        if data["elements"]
-            .as_array()
-            .map_or(0, |elements: &Vec<Value>| elements.len())
-            == 0
-        {
-            if let Some(remark) = data["remark"].as_str() {
-                // Check if the remark mentions memory or other runtime errors
-                if remark.contains("runtime error") && remark.contains("out of memory") {
-                    eprintln!("{}", "Error! The query ran out of memory on the Overpass API server. Try using a smaller area.".red().bold());
-                    emit_gui_error("Try using a smaller area.");
-                } else {
-                    // Handle other Overpass API errors if present in the remark field
-                    eprintln!("{}", format!("Error! API returned: {remark}").red().bold());
-                    emit_gui_error(&format!("API returned: {remark}"));
-                }
-            } else {
-                // General case for when there are no elements and no specific remark
-                eprintln!(
-                    "{}",
-                    "Error! API returned no data. Please try again!"
-                        .red()
-                        .bold()
-                );
-                emit_gui_error("API returned no data. Please try again!");
-            }
+    if debug {
+        println!("Additional debug information: {data}");
+    }
 
-            if debug {
-                println!("Additional debug information: {data}");
-            }
+    if !is_running_with_gui() {
+        std::process::exit(1);
+    } else {
+        Err(error.into())
+    }
+}
 
-            if !is_running_with_gui() {
-                std::process::exit(1);
+/// Main function to fetch data
+pub fn fetch_data_from_overpass(
+    bbox: LLBBox,
+    query_options: &QueryOptions,
+    debug: bool,
+    download_method: &str,
+    save_file: Option<&str>,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    let response: String =
+        fetch_raw_from_overpass(bbox, query_options, download_method, save_file)?;
+
+    let data: Value = serde_json::from_str(&response)
+        .map_err(|e| ArnisError::Parse(format!("Invalid OSM data: {e}")))?;
+    handle_empty_response(&data, debug)?;
+
+    Ok(data)
+}
+
+/// Downloads the data of an area, bypassing the cache
+pub(crate) fn download_area(
+    bbox: &LLBBox,
+    query_options: &QueryOptions,
+    download_method: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    download_from_overpass(&build_overpass_query(bbox, query_options), download_method)
+}
+
+/// Downloads the data without parsing it, to be parsed element by element. Large responses
+/// take several times their size in memory as `serde_json::Value`.
+pub fn fetch_raw_from_overpass(
+    bbox: LLBBox,
+    query_options: &QueryOptions,
+    download_method: &str,
+    save_file: Option<&str>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    println!("{} Fetching data...", "[1/7]".bold());
+    emit_gui_progress_update(1.0, "Fetching data...");
+
+    let response: String = match osm_cache::lookup(&bbox, query_options) {
+        Some(cached) => cached,
+        None => {
+            let tiles: Vec<LLBBox> = split_into_chunks(&bbox, &DOWNLOAD_TILES);
+            let response: String = if tiles.len() > 1 {
+                println!("Downloading the area in {} tiles...", tiles.len());
+                let responses = download_tiles(&tiles, query_options, download_method)?;
+                merge_responses(responses).map_err(ArnisError::Parse)?
             } else {
-                return Err("Data fetch failed".into());
+                download_area(&bbox, query_options, download_method)?
+            };
+            osm_cache::store(&bbox, query_options, &response);
+            response
+        }
+    };
+
+    if let Some(save_file) = save_file {
+        save_data_file(save_file, response.as_bytes())?;
+    }
+
+    emit_gui_progress_update(5.0, "");
+
+    Ok(response)
+}
+
+/// Deserializes the elements of an Overpass response one at a time, without building a
+/// `serde_json::Value` of the whole response. Malformed elements are passed on as errors.
+/// Returns the remark of the response, which explains why it has no elements.
+pub(crate) fn stream_elements(
+    json: &str,
+    on_element: &mut dyn FnMut(Result<OsmElement, String>),
+) -> Result<Option<String>, String> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    let remark =
+        de::Deserializer::deserialize_map(&mut deserializer, ResponseVisitor { on_element })
+            .map_err(|e| format!("Invalid OSM data: {e}"))?;
+    deserializer
+        .end()
+        .map_err(|e| format!("Invalid OSM data: {e}"))?;
+    Ok(remark)
+}
+
+struct ResponseVisitor<'a> {
+    on_element: &'a mut dyn FnMut(Result<OsmElement, String>),
+}
+
+impl<'de> Visitor<'de> for ResponseVisitor<'_> {
+    type Value = Option<String>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an Overpass API response")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut remark = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "elements" => map.next_value_seed(ElementsSeed {
+                    on_element: &mut *self.on_element,
+                })?,
+                "remark" => remark = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
             }
         }
+        Ok(remark)
+    }
+}
+
+struct ElementsSeed<'a> {
+    on_element: &'a mut dyn FnMut(Result<OsmElement, String>),
+}
+
+/// Keeps going after elements that don't fit `OsmElement`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MaybeElement {
+    Valid(OsmElement),
+    Malformed(IgnoredAny),
+}
+
+impl<'de> DeserializeSeed<'de> for ElementsSeed<'_> {
+    type Value = ();
 
-        emit_gui_progress_update(5.0, "");
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
 
-        Ok(data)
+impl<'de> Visitor<'de> for ElementsSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of OSM elements")
     }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut index: usize = 0;
+        while let Some(element) = seq.next_element::<MaybeElement>()? {
+            (self.on_element)(match element {
+                MaybeElement::Valid(element) => Ok(element),
+                MaybeElement::Malformed(_) => Err(format!("Skipping malformed element #{index}")),
+            });
+            index += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Fetches the data along a route piece by piece, so that long routes only download
+/// a narrow strip instead of their whole bounding box
+pub fn fetch_data_along_route(
+    route: &[LLPoint],
+    corridor_width: f64,
+    query_options: &QueryOptions,
+    debug: bool,
+    download_method: &str,
+    save_file: Option<&str>,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    println!("{} Fetching data along route...", "[1/7]".bold());
+    emit_gui_progress_update(1.0, "Fetching data along route...");
+
+    let bboxes: Vec<LLBBox> = gpx::split_route_for_download(route)
+        .iter()
+        .map(|piece| gpx::corridor_bbox(piece, corridor_width))
+        .collect::<Result<_, _>>()?;
+
+    fetch_merged_data(&bboxes, query_options, debug, download_method, save_file)
+}
+
+/// Fetches the data of several separate areas and merges them into one response
+pub fn fetch_data_from_bboxes(
+    bboxes: &[LLBBox],
+    query_options: &QueryOptions,
+    debug: bool,
+    download_method: &str,
+    save_file: Option<&str>,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    println!(
+        "{} Fetching data for {} areas...",
+        "[1/7]".bold(),
+        bboxes.len()
+    );
+    emit_gui_progress_update(1.0, "Fetching data...");
+
+    fetch_merged_data(bboxes, query_options, debug, download_method, save_file)
+}
+
+fn fetch_merged_data(
+    bboxes: &[LLBBox],
+    query_options: &QueryOptions,
+    debug: bool,
+    download_method: &str,
+    save_file: Option<&str>,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    let responses = download_tiles(bboxes, query_options, download_method)?;
+    let response: String = merge_responses(responses).map_err(ArnisError::Parse)?;
+
+    if let Some(save_file) = save_file {
+        save_data_file(save_file, response.as_bytes())?;
+    }
+
+    let data: Value = serde_json::from_str(&response)
+        .map_err(|e| ArnisError::Parse(format!("Invalid OSM data: {e}")))?;
+
+    handle_empty_response(&data, debug)?;
+
+    Ok(data)
+}
+
+/// Downloads several areas, a few at a time, in their order
+fn download_tiles(
+    bboxes: &[LLBBox],
+    query_options: &QueryOptions,
+    download_method: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(PARALLEL_DOWNLOADS.min(bboxes.len()).max(1))
+        .build()?;
+    let done = AtomicUsize::new(0);
+
+    let responses: Result<Vec<String>, String> = pool.install(|| {
+        bboxes
+            .par_iter()
+            .map(|bbox| {
+                let query = build_overpass_query(bbox, query_options);
+                let response: String =
+                    download_from_overpass(&query, download_method).map_err(|e| e.to_string())?;
+
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                println!("Downloaded area {done}/{}", bboxes.len());
+                emit_gui_progress_update(1.0 + 4.0 * done as f64 / bboxes.len() as f64, "");
+                Ok(response)
+            })
+            .collect()
+    });
+    Ok(responses?)
+}
+
+/// Merges the elements of several responses, streaming one response at a time. Areas
+/// overlap at least in the features crossing their edges, so each element is kept only once,
+/// preferring the version with tags over the bare node positions.
+fn merge_responses(responses: Vec<String>) -> Result<String, String> {
+    let mut elements: Vec<OsmElement> = Vec::new();
+    let mut seen: FnvHashMap<(String, u64), usize> = FnvHashMap::default();
+    let mut remark: Option<String> = None;
+
+    for response in responses {
+        let area_remark = stream_elements(&response, &mut |element| {
+            let element = match element {
+                Ok(element) => element,
+                Err(message) => {
+                    eprintln!("Warning: {message}");
+                    return;
+                }
+            };
+            match seen.entry((element.r#type.clone(), element.id)) {
+                Entry::Occupied(entry) => {
+                    let kept = &mut elements[*entry.get()];
+                    if kept.tags.is_none() && element.tags.is_some() {
+                        *kept = element;
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(elements.len());
+                    elements.push(element);
+                }
+            }
+        })?;
+        remark = remark.or(area_remark);
+    }
+
+    serde_json::to_string(&MergedResponse { elements, remark })
+        .map_err(|e| format!("Failed to merge the OSM data: {e}"))
+}
+
+/// Overpass response of several areas
+#[derive(Serialize)]
+struct MergedResponse {
+    elements: Vec<OsmElement>,
+    /// Explains why the response has no elements
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remark: Option<String>,
 }
 
 /// Fetches a short area name using Nominatim for the given lat/lon
@@ -233,7 +721,7 @@ pub fn fetch_area_name(lat: f64, lon: f64) -> Result<Option<String>, Box<dyn std
 
     let url = format!("https://nominatim.openstreetmap.org/reverse?format=jsonv2&lat={lat}&lon={lon}&addressdetails=1");
 
-    let resp = client.get(&url).header("User-Agent", "arnis-rust").send()?;
+    let resp = api_scheduler::get(&client, &url, &[])?;
 
     if !resp.status().is_success() {
         return Ok(None);
@@ -259,3 +747,135 @@ pub fn fetch_area_name(lat: f64, lon: f64) -> Result<Option<String>, Box<dyn std
 
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::categories::Category;
+
+    #[test]
+    fn test_build_overpass_query() {
+        let bbox = LLBBox::new(54.627053, 9.927928, 54.634902, 9.937563).unwrap();
+
+        let query = build_overpass_query(&bbox, &QueryOptions::default());
+        assert!(query
+            .starts_with("[out:json][timeout:360][bbox:54.627053,9.927928,54.634902,9.937563];"));
+        assert!(!query.contains("[date:"));
+
+        let options = QueryOptions {
+            date: Some("2015-06-01T00:00:00Z".to_string()),
+            ..QueryOptions::default()
+        };
+        let query = build_overpass_query(&bbox, &options);
+        assert!(query.starts_with(
+            r#"[out:json][timeout:360][bbox:54.627053,9.927928,54.634902,9.937563][date:"2015-06-01T00:00:00Z"];"#
+        ));
+        assert!(query.contains("way;"));
+
+        // Left out categories aren't downloaded
+        let options = QueryOptions {
+            filter: FeatureFilter {
+                include: vec![Category::Buildings, Category::Roads],
+                exclude: Vec::new(),
+            },
+            ..QueryOptions::default()
+        };
+        let query = build_overpass_query(&bbox, &options);
+        assert!(query.contains(r#"nwr["building"];"#) && query.contains(r#"nwr["highway"];"#));
+        assert!(!query.contains(r#"nwr["landuse"];"#) && !query.contains("way;"));
+    }
+
+    #[test]
+    fn test_query_template() {
+        let bbox = LLBBox::new(54.627053, 9.927928, 54.634902, 9.937563).unwrap();
+        let options = QueryOptions {
+            date: None,
+            template: Some("[out:json]{{date}};nwr[shop]({{bbox}});out geom;".to_string()),
+            ..QueryOptions::default()
+        };
+        assert_eq!(
+            build_overpass_query(&bbox, &options),
+            "[out:json];nwr[shop](54.627053,9.927928,54.634902,9.937563);out geom;"
+        );
+
+        let options = QueryOptions {
+            date: Some("2015-06-01T00:00:00Z".to_string()),
+            ..options
+        };
+        assert!(build_overpass_query(&bbox, &options)
+            .starts_with(r#"[out:json][date:"2015-06-01T00:00:00Z"];"#));
+    }
+
+    #[test]
+    fn test_merge_responses() {
+        let tile = |elements: Value| serde_json::json!({ "elements": elements }).to_string();
+        let merged = merge_responses(vec![
+            tile(serde_json::json!([
+                { "type": "way", "id": 1, "nodes": [1, 2] },
+                { "type": "node", "id": 1, "lat": 1.0, "lon": 1.0 },
+            ])),
+            tile(serde_json::json!([
+                { "type": "way", "id": 1, "nodes": [1, 2] },
+                { "type": "node", "id": 1, "lat": 1.0, "lon": 1.0, "tags": { "amenity": "bench" } },
+                { "type": "node", "id": 2, "lat": 1.0, "lon": 1.1 },
+            ])),
+        ])
+        .unwrap();
+
+        let merged: Value = serde_json::from_str(&merged).unwrap();
+        let elements = merged["elements"].as_array().unwrap();
+        assert_eq!(elements.len(), 3);
+        assert_eq!(elements[0]["nodes"], serde_json::json!([1, 2]));
+        assert_eq!(elements[1]["tags"]["amenity"], "bench");
+        assert!(elements[2].get("tags").is_none());
+    }
+
+    #[test]
+    fn test_split_large_areas() {
+        // Small areas are a single query, city-sized ones are tiled
+        let small = LLBBox::new(54.627053, 9.927928, 54.634902, 9.937563).unwrap();
+        assert_eq!(split_into_chunks(&small, &DOWNLOAD_TILES).len(), 1);
+        let large = LLBBox::new(48.0, 11.4, 48.2, 11.7).unwrap();
+        assert!(split_into_chunks(&large, &DOWNLOAD_TILES).len() > 1);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0.0 MB");
+        assert_eq!(format_size(35_651_584), "34.0 MB");
+    }
+
+    #[test]
+    fn test_compressed_data_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = r#"{"elements":[]}"#;
+
+        for name in ["data.json", "data.json.gz"] {
+            let path = dir.path().join(name);
+            let path = path.to_str().unwrap();
+            save_data_file(path, data.as_bytes()).unwrap();
+            assert_eq!(read_data_file(path).unwrap(), data);
+        }
+        let compressed = std::fs::read(dir.path().join("data.json.gz")).unwrap();
+        assert!(compressed.starts_with(&[0x1f, 0x8b]));
+    }
+
+    #[test]
+    fn test_load_query_template() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = dir.path().join("valid.overpassql");
+        std::fs::write(&path, "[out:json];nwr({{bbox}});out;").unwrap();
+        assert!(load_query_template(&path).is_ok());
+
+        let path = dir.path().join("no_bbox.overpassql");
+        std::fs::write(&path, "[out:json];nwr(1,2,3,4);out;").unwrap();
+        assert!(load_query_template(&path).is_err());
+
+        let path = dir.path().join("xml.overpassql");
+        std::fs::write(&path, "nwr({{bbox}});out;").unwrap();
+        assert!(load_query_template(&path).is_err());
+
+        assert!(load_query_template(&dir.path().join("missing")).is_err());
+    }
+}
@@ -0,0 +1,683 @@
+//! Schematic export (`--format schematic|litematic`). The world is generated as usual, then
+//! read back from its region files and written next to the world directory as a Sponge
+//! schematic (`.schem`, pasted with WorldEdit) or a Litematica schematic (`.litematic`), so
+//! the area can be pasted into an existing world.
+//!
+//! The schematic spans the generated area and only the height that holds blocks. Empty space
+//! is air, which clears the blocks of the world the schematic is pasted into, or structure
+//! void, which keeps them, in the columns of the categories given with `--structure-void`.
+//! The categories of every column are recorded while generating and kept next to the world.
+
+use crate::categories::Category;
+use crate::trim::{open_region, region_coords};
+use crate::world_editor::WorldMetadata;
+use clap::ValueEnum;
+use fastnbt::{ByteArray, IntArray, LongArray, Value};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use fnv::FnvHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Data version of the generated worlds (Minecraft 1.21.4)
+const DATA_VERSION: i32 = 4189;
+const AIR: &str = "minecraft:air";
+const STRUCTURE_VOID: &str = "minecraft:structure_void";
+/// Categories of the features in every column, in the world directory
+const COLUMN_CATEGORIES_FILE: &str = "column_categories.nbt.gz";
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// A Minecraft world
+    #[default]
+    World,
+    /// The world and a Sponge schematic (.schem) for WorldEdit
+    Schematic,
+    /// The world and a Litematica schematic (.litematic)
+    Litematic,
+}
+
+/// Position relative to the origin and data of a block entity
+type BlockEntity = ((i32, i32, i32), HashMap<String, Value>);
+
+/// Blocks of the exported area as indices into a palette of block states
+struct Volume {
+    /// Block states like `minecraft:oak_stairs[facing=north,half=bottom]`, air first
+    palette: Vec<String>,
+    /// Block coordinates of the lowest north-west corner
+    origin: (i32, i32, i32),
+    /// Width (x), height (y) and length (z)
+    size: (usize, usize, usize),
+    /// Palette indices in x, then z, then y order
+    blocks: Vec<u32>,
+    block_entities: Vec<BlockEntity>,
+}
+
+impl Volume {
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + z * self.size.0 + y * self.size.0 * self.size.2
+    }
+}
+
+/// Categories of the features placed in every column, as bits of `Category::bit`
+#[derive(Serialize, Deserialize)]
+struct ColumnCategories {
+    x: IntArray,
+    z: IntArray,
+    categories: ByteArray,
+}
+
+/// Keeps the categories of the columns next to the world, for `--structure-void`
+pub(crate) fn write_column_categories(
+    world_dir: &Path,
+    columns: &FnvHashMap<(i32, i32), u8>,
+) -> Result<(), String> {
+    let mut x = Vec::with_capacity(columns.len());
+    let mut z = Vec::with_capacity(columns.len());
+    let mut categories = Vec::with_capacity(columns.len());
+    for (&(column_x, column_z), &bits) in columns {
+        x.push(column_x);
+        z.push(column_z);
+        categories.push(bits as i8);
+    }
+    let columns = ColumnCategories {
+        x: IntArray::new(x),
+        z: IntArray::new(z),
+        categories: ByteArray::new(categories),
+    };
+
+    let path = world_dir.join(COLUMN_CATEGORIES_FILE);
+    let bytes = fastnbt::to_bytes(&columns)
+        .map_err(|e| format!("Failed to encode column categories: {e}"))?;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    encoder
+        .write_all(&bytes)
+        .and_then(|_| encoder.finish())
+        .and_then(|compressed| std::fs::write(&path, compressed))
+        .map_err(|e| format!("Failed to write {}: {e}", path.display()))
+}
+
+fn read_column_categories(world_dir: &Path) -> Result<FnvHashMap<(i32, i32), u8>, String> {
+    let path = world_dir.join(COLUMN_CATEGORIES_FILE);
+    let compressed = std::fs::read(&path).map_err(|_| {
+        format!(
+            "{} has no categories of its columns, generate it with --structure-void",
+            world_dir.display()
+        )
+    })?;
+    let mut bytes = Vec::new();
+    GzDecoder::new(compressed.as_slice())
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to decompress {}: {e}", path.display()))?;
+    let columns: ColumnCategories = fastnbt::from_bytes(&bytes)
+        .map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+    Ok(columns
+        .x
+        .iter()
+        .zip(columns.z.iter())
+        .zip(columns.categories.iter())
+        .map(|((&x, &z), &bits)| ((x, z), bits as u8))
+        .collect())
+}
+
+/// Turns the air in the columns whose features all belong to `structure_void` categories
+/// into structure void. Columns without any feature count as terrain.
+fn apply_structure_void(
+    volume: &mut Volume,
+    columns: &FnvHashMap<(i32, i32), u8>,
+    structure_void: &[Category],
+) {
+    let void_bits = structure_void
+        .iter()
+        .fold(0u8, |bits, category| bits | category.bit());
+    if void_bits == 0 {
+        return;
+    }
+
+    let void = volume.palette.len() as u32;
+    volume.palette.push(STRUCTURE_VOID.to_string());
+    let (origin_x, _, origin_z) = volume.origin;
+    let (width, height, length) = volume.size;
+    for x in 0..width {
+        for z in 0..length {
+            let bits = columns
+                .get(&(origin_x + x as i32, origin_z + z as i32))
+                .copied()
+                .unwrap_or(Category::Terrain.bit());
+            if bits & !void_bits != 0 {
+                continue;
+            }
+            for y in 0..height {
+                let index = volume.index(x, y, z);
+                if volume.blocks[index] == 0 {
+                    volume.blocks[index] = void;
+                }
+            }
+        }
+    }
+}
+
+/// Writes the generated world at `world_dir` in `format` next to it, returns the file. Empty
+/// space of the `structure_void` categories is written as structure void instead of air.
+pub fn export(
+    world_dir: &Path,
+    format: OutputFormat,
+    structure_void: &[Category],
+) -> Result<PathBuf, String> {
+    let extension = match format {
+        OutputFormat::World => return Ok(world_dir.to_path_buf()),
+        OutputFormat::Schematic => "schem",
+        OutputFormat::Litematic => "litematic",
+    };
+    let name = world_dir
+        .file_name()
+        .ok_or_else(|| format!("{} is not a world directory", world_dir.display()))?
+        .to_string_lossy()
+        .into_owned();
+
+    let mut volume = read_volume(world_dir)?;
+    if !structure_void.is_empty() {
+        let columns = read_column_categories(world_dir)?;
+        apply_structure_void(&mut volume, &columns, structure_void);
+    }
+    let nbt = match format {
+        OutputFormat::Litematic => litematic_nbt(&volume, &name)?,
+        _ => sponge_nbt(&volume)?,
+    };
+
+    let path = world_dir.with_file_name(format!("{name}.{extension}"));
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&nbt)
+        .and_then(|_| encoder.finish())
+        .and_then(|bytes| std::fs::write(&path, bytes))
+        .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+    Ok(path)
+}
+
+/// Reads the blocks of the generated area from the region files
+fn read_volume(world_dir: &Path) -> Result<Volume, String> {
+    let metadata = WorldMetadata::load(world_dir)?;
+    let (min_x, max_x) = (metadata.min_mc_x, metadata.max_mc_x);
+    let (min_z, max_z) = (metadata.min_mc_z, metadata.max_mc_z);
+
+    let mut palette = vec![AIR.to_string()];
+    let mut palette_lookup = HashMap::from([(AIR.to_string(), 0u32)]);
+    // Non-empty sections by chunk x, section y and chunk z
+    let mut sections: HashMap<(i32, i32, i32), Vec<u32>> = HashMap::new();
+    let mut block_entities = Vec::new();
+
+    let region_dir = world_dir.join("region");
+    let entries = std::fs::read_dir(&region_dir)
+        .map_err(|e| format!("Failed to read {}: {e}", region_dir.display()))?;
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let Some((region_x, region_z)) = region_coords(&path) else {
+            continue;
+        };
+        if region_x * 512 > max_x
+            || region_x * 512 + 511 < min_x
+            || region_z * 512 > max_z
+            || region_z * 512 + 511 < min_z
+        {
+            continue;
+        }
+
+        let mut region = open_region(&path)?;
+        for chunk_x in 0..32 {
+            for chunk_z in 0..32 {
+                let Some(data) = region
+                    .read_chunk(chunk_x, chunk_z)
+                    .map_err(|e| format!("Failed to read chunk in {}: {e}", path.display()))?
+                else {
+                    continue;
+                };
+                let chunk: Value = fastnbt::from_bytes(&data)
+                    .map_err(|e| format!("Failed to parse chunk in {}: {e}", path.display()))?;
+                let Value::Compound(mut chunk) = chunk else {
+                    continue;
+                };
+                // Arnis wraps the chunk in `Level`, Minecraft doesn't once it saved the chunk
+                if let Some(Value::Compound(level)) = chunk.remove("Level") {
+                    chunk = level;
+                }
+
+                let (x, z) = (
+                    region_x * 32 + chunk_x as i32,
+                    region_z * 32 + chunk_z as i32,
+                );
+                if let Some(Value::List(list)) = chunk.get("sections") {
+                    for section in list {
+                        if let Some((y, blocks)) =
+                            read_section(section, &mut palette, &mut palette_lookup)
+                        {
+                            sections.insert((x, y, z), blocks);
+                        }
+                    }
+                }
+                if let Some(Value::List(list)) = chunk.remove("block_entities") {
+                    block_entities.extend(list);
+                }
+            }
+        }
+    }
+
+    // Only the height that holds blocks is exported
+    let in_area = |x: i32, z: i32| (min_x..=max_x).contains(&x) && (min_z..=max_z).contains(&z);
+    let (mut min_y, mut max_y) = (i32::MAX, i32::MIN);
+    for (&(chunk_x, section_y, chunk_z), blocks) in &sections {
+        for (i, &block) in blocks.iter().enumerate() {
+            let (x, y, z) = section_position(chunk_x, section_y, chunk_z, i);
+            if block != 0 && in_area(x, z) {
+                min_y = min_y.min(y);
+                max_y = max_y.max(y);
+            }
+        }
+    }
+    if min_y > max_y {
+        return Err("The generated area holds no blocks".to_string());
+    }
+
+    let origin = (min_x, min_y, min_z);
+    let size = (
+        (max_x - min_x + 1) as usize,
+        (max_y - min_y + 1) as usize,
+        (max_z - min_z + 1) as usize,
+    );
+    let mut volume = Volume {
+        palette,
+        origin,
+        size,
+        blocks: vec![0; size.0 * size.1 * size.2],
+        block_entities: Vec::new(),
+    };
+    for ((chunk_x, section_y, chunk_z), blocks) in sections {
+        for (i, block) in blocks.into_iter().enumerate() {
+            let (x, y, z) = section_position(chunk_x, section_y, chunk_z, i);
+            if block != 0 && in_area(x, z) && (min_y..=max_y).contains(&y) {
+                let index = volume.index(
+                    (x - min_x) as usize,
+                    (y - min_y) as usize,
+                    (z - min_z) as usize,
+                );
+                volume.blocks[index] = block;
+            }
+        }
+    }
+
+    for entity in block_entities {
+        let Value::Compound(mut entity) = entity else {
+            continue;
+        };
+        let (Some(Value::Int(x)), Some(Value::Int(y)), Some(Value::Int(z))) =
+            (entity.remove("x"), entity.remove("y"), entity.remove("z"))
+        else {
+            continue;
+        };
+        if in_area(x, z) && (min_y..=max_y).contains(&y) {
+            volume
+                .block_entities
+                .push(((x - min_x, y - min_y, z - min_z), entity));
+        }
+    }
+
+    Ok(volume)
+}
+
+/// Block coordinates of the block at `index` of a section
+fn section_position(chunk_x: i32, section_y: i32, chunk_z: i32, index: usize) -> (i32, i32, i32) {
+    let index = index as i32;
+    (
+        chunk_x * 16 + index % 16,
+        section_y * 16 + index / 256,
+        chunk_z * 16 + index / 16 % 16,
+    )
+}
+
+/// Section y and the blocks of a section as indices into `palette`, which grows by the block
+/// states it didn't hold yet. Sections of nothing but air are skipped.
+fn read_section(
+    section: &Value,
+    palette: &mut Vec<String>,
+    palette_lookup: &mut HashMap<String, u32>,
+) -> Option<(i32, Vec<u32>)> {
+    let Value::Compound(section) = section else {
+        return None;
+    };
+    let y = match section.get("Y")? {
+        Value::Byte(y) => i32::from(*y),
+        Value::Int(y) => *y,
+        _ => return None,
+    };
+    let Some(Value::Compound(block_states)) = section.get("block_states") else {
+        return None;
+    };
+    let Some(Value::List(section_palette)) = block_states.get("palette") else {
+        return None;
+    };
+
+    let mapping: Vec<u32> = section_palette
+        .iter()
+        .map(|entry| {
+            let state = block_state(entry);
+            *palette_lookup.entry(state.clone()).or_insert_with(|| {
+                palette.push(state);
+                palette.len() as u32 - 1
+            })
+        })
+        .collect();
+    if mapping.iter().all(|&block| block == 0) {
+        return None;
+    }
+
+    let blocks = match block_states.get("data") {
+        Some(Value::LongArray(data)) if mapping.len() > 1 => unpack_section(data, mapping.len())
+            .into_iter()
+            .map(|i| mapping.get(i).copied().unwrap_or(0))
+            .collect(),
+        _ => vec![mapping[0]; 4096],
+    };
+    Some((y, blocks))
+}
+
+/// Block state string of a palette entry, with its properties sorted by name
+pub(crate) fn block_state(entry: &Value) -> String {
+    let Value::Compound(entry) = entry else {
+        return AIR.to_string();
+    };
+    let Some(Value::String(name)) = entry.get("Name") else {
+        return AIR.to_string();
+    };
+    let mut properties: Vec<String> = match entry.get("Properties") {
+        Some(Value::Compound(properties)) => properties
+            .iter()
+            .filter_map(|(key, value)| match value {
+                Value::String(value) => Some(format!("{key}={value}")),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    if properties.is_empty() {
+        return name.clone();
+    }
+    properties.sort_unstable();
+    format!("{name}[{}]", properties.join(","))
+}
+
+/// Palette indices of the 4096 blocks of a section. Entries don't span longs, and use at
+/// least 4 bits as in the world format.
+pub(crate) fn unpack_section(data: &[i64], palette_len: usize) -> Vec<usize> {
+    let bits = (usize::BITS - (palette_len - 1).leading_zeros()).max(4) as usize;
+    let per_long = 64 / bits;
+    let mask = (1u64 << bits) - 1;
+    (0..4096)
+        .map(|i| {
+            let long = data.get(i / per_long).copied().unwrap_or(0) as u64;
+            ((long >> (i % per_long * bits)) & mask) as usize
+        })
+        .collect()
+}
+
+/// Appends a value in the variable length encoding of Sponge schematics
+fn write_varint(out: &mut Vec<i8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte as i8);
+            return;
+        }
+        out.push((byte | 0x80) as i8);
+    }
+}
+
+/// Packs the values tightly into longs with `bits` each, spanning longs as Litematica does
+fn pack_spanning(values: &[u32], bits: usize) -> Vec<i64> {
+    let mut longs = vec![0u64; (values.len() * bits).div_ceil(64)];
+    for (i, &value) in values.iter().enumerate() {
+        let (long, offset) = (i * bits / 64, i * bits % 64);
+        longs[long] |= u64::from(value) << offset;
+        if offset + bits > 64 {
+            longs[long + 1] |= u64::from(value) >> (64 - offset);
+        }
+    }
+    longs.into_iter().map(|long| long as i64).collect()
+}
+
+/// Sponge schematic version 2
+fn sponge_nbt(volume: &Volume) -> Result<Vec<u8>, String> {
+    let (width, height, length) = volume.size;
+    // Sizes are unsigned shorts
+    let short = |size: usize| {
+        u16::try_from(size)
+            .map(|size| Value::Short(size as i16))
+            .map_err(|_| format!("The area is too large for a schematic ({size} blocks)"))
+    };
+
+    let mut block_data = Vec::with_capacity(volume.blocks.len());
+    for &block in &volume.blocks {
+        write_varint(&mut block_data, block);
+    }
+    let palette = volume
+        .palette
+        .iter()
+        .enumerate()
+        .map(|(i, state)| (state.clone(), Value::Int(i as i32)))
+        .collect();
+    let block_entities = volume
+        .block_entities
+        .iter()
+        .map(|((x, y, z), entity)| {
+            let mut entity = entity.clone();
+            if let Some(id) = entity.remove("id") {
+                entity.insert("Id".to_string(), id);
+            }
+            entity.insert(
+                "Pos".to_string(),
+                Value::IntArray(IntArray::new(vec![*x, *y, *z])),
+            );
+            Value::Compound(entity)
+        })
+        .collect();
+    let (x, y, z) = volume.origin;
+
+    let root = Value::Compound(HashMap::from([
+        ("Version".to_string(), Value::Int(2)),
+        ("DataVersion".to_string(), Value::Int(DATA_VERSION)),
+        ("Width".to_string(), short(width)?),
+        ("Height".to_string(), short(height)?),
+        ("Length".to_string(), short(length)?),
+        (
+            "Offset".to_string(),
+            Value::IntArray(IntArray::new(vec![x, y, z])),
+        ),
+        (
+            "PaletteMax".to_string(),
+            Value::Int(volume.palette.len() as i32),
+        ),
+        ("Palette".to_string(), Value::Compound(palette)),
+        (
+            "BlockData".to_string(),
+            Value::ByteArray(ByteArray::new(block_data)),
+        ),
+        ("BlockEntities".to_string(), Value::List(block_entities)),
+    ]));
+    fastnbt::to_bytes_with_opts(&root, fastnbt::SerOpts::new().root_name("Schematic"))
+        .map_err(|e| format!("Failed to encode schematic: {e}"))
+}
+
+/// Litematica schematic with a single region
+fn litematic_nbt(volume: &Volume, name: &str) -> Result<Vec<u8>, String> {
+    let (width, height, length) = volume.size;
+    let xyz = |x: i32, y: i32, z: i32| {
+        Value::Compound(HashMap::from([
+            ("x".to_string(), Value::Int(x)),
+            ("y".to_string(), Value::Int(y)),
+            ("z".to_string(), Value::Int(z)),
+        ]))
+    };
+    let size = xyz(width as i32, height as i32, length as i32);
+
+    let bits = (u32::BITS - (volume.palette.len() as u32 - 1).leading_zeros()).max(2) as usize;
+    // Litematica orders blocks by y, then z, then x, as the volume does
+    let block_states = pack_spanning(&volume.blocks, bits);
+    let palette = volume
+        .palette
+        .iter()
+        .map(|state| palette_entry(state))
+        .collect();
+    let tile_entities = volume
+        .block_entities
+        .iter()
+        .map(|((x, y, z), entity)| {
+            let mut entity = entity.clone();
+            entity.insert("x".to_string(), Value::Int(*x));
+            entity.insert("y".to_string(), Value::Int(*y));
+            entity.insert("z".to_string(), Value::Int(*z));
+            Value::Compound(entity)
+        })
+        .collect();
+    let void = volume
+        .palette
+        .iter()
+        .position(|state| state == STRUCTURE_VOID);
+    let total_blocks = volume
+        .blocks
+        .iter()
+        .filter(|&&block| block != 0 && Some(block as usize) != void)
+        .count();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as i64);
+
+    let region = HashMap::from([
+        ("Position".to_string(), xyz(0, 0, 0)),
+        ("Size".to_string(), size.clone()),
+        ("BlockStatePalette".to_string(), Value::List(palette)),
+        (
+            "BlockStates".to_string(),
+            Value::LongArray(LongArray::new(block_states)),
+        ),
+        ("TileEntities".to_string(), Value::List(tile_entities)),
+        ("Entities".to_string(), Value::List(Vec::new())),
+        ("PendingBlockTicks".to_string(), Value::List(Vec::new())),
+        ("PendingFluidTicks".to_string(), Value::List(Vec::new())),
+    ]);
+    let metadata = HashMap::from([
+        ("Name".to_string(), Value::String(name.to_string())),
+        ("Author".to_string(), Value::String("Arnis".to_string())),
+        ("Description".to_string(), Value::String(String::new())),
+        ("RegionCount".to_string(), Value::Int(1)),
+        ("TotalBlocks".to_string(), Value::Int(total_blocks as i32)),
+        (
+            "TotalVolume".to_string(),
+            Value::Int(volume.blocks.len() as i32),
+        ),
+        ("EnclosingSize".to_string(), size),
+        ("TimeCreated".to_string(), Value::Long(now)),
+        ("TimeModified".to_string(), Value::Long(now)),
+    ]);
+    let root = Value::Compound(HashMap::from([
+        ("MinecraftDataVersion".to_string(), Value::Int(DATA_VERSION)),
+        ("Version".to_string(), Value::Int(6)),
+        ("SubVersion".to_string(), Value::Int(1)),
+        ("Metadata".to_string(), Value::Compound(metadata)),
+        (
+            "Regions".to_string(),
+            Value::Compound(HashMap::from([(name.to_string(), Value::Compound(region))])),
+        ),
+    ]));
+    fastnbt::to_bytes(&root).map_err(|e| format!("Failed to encode schematic: {e}"))
+}
+
+/// Palette entry of a block state string, the inverse of `block_state`
+fn palette_entry(state: &str) -> Value {
+    let (name, properties) = match state.strip_suffix(']').and_then(|s| s.split_once('[')) {
+        Some((name, properties)) => (name, properties),
+        None => (state, ""),
+    };
+    let mut entry = HashMap::from([("Name".to_string(), Value::String(name.to_string()))]);
+    if !properties.is_empty() {
+        let properties = properties
+            .split(',')
+            .filter_map(|property| property.split_once('='))
+            .map(|(key, value)| (key.to_string(), Value::String(value.to_string())))
+            .collect();
+        entry.insert("Properties".to_string(), Value::Compound(properties));
+    }
+    Value::Compound(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        let mut out = Vec::new();
+        for value in [0, 127, 128, 300] {
+            write_varint(&mut out, value);
+        }
+        let bytes: Vec<u8> = out.into_iter().map(|byte| byte as u8).collect();
+        assert_eq!(bytes, [0x00, 0x7f, 0x80, 0x01, 0xac, 0x02]);
+    }
+
+    #[test]
+    fn test_packing() {
+        // Three bits per value, the 22nd value spans the first two longs
+        let values: Vec<u32> = (0..24).map(|i| i % 8).collect();
+        let longs = pack_spanning(&values, 3);
+        assert_eq!(longs.len(), 2);
+        let bit = |n: usize| (longs[n / 64] as u64 >> (n % 64)) & 1;
+        let unpacked: Vec<u32> = (0..24)
+            .map(|i| (0..3).map(|b| (bit(i * 3 + b) as u32) << b).sum())
+            .collect();
+        assert_eq!(unpacked, values);
+
+        // The world format doesn't span longs: 16 values of 4 bits per long
+        let data = [0x0000_0000_0000_0021_i64, 0x3];
+        let blocks = unpack_section(&data, 4);
+        assert_eq!(&blocks[..3], [1, 2, 0]);
+        assert_eq!(blocks[16], 3);
+    }
+
+    #[test]
+    fn test_structure_void() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut volume = Volume {
+            palette: vec![AIR.to_string(), "minecraft:stone".to_string()],
+            origin: (10, -60, 20),
+            size: (3, 2, 1),
+            blocks: vec![1, 1, 1, 0, 0, 0],
+            block_entities: Vec::new(),
+        };
+        // A road, a road crossing a building and a column without features
+        let columns = FnvHashMap::from_iter([
+            ((10, 20), Category::Roads.bit()),
+            ((11, 20), Category::Roads.bit() | Category::Buildings.bit()),
+        ]);
+        write_column_categories(dir.path(), &columns).unwrap();
+        let columns = read_column_categories(dir.path()).unwrap();
+
+        apply_structure_void(&mut volume, &columns, &[Category::Roads, Category::Terrain]);
+        assert_eq!(volume.palette[2], STRUCTURE_VOID);
+        assert_eq!(volume.blocks, [1, 1, 1, 2, 0, 2]);
+    }
+
+    #[test]
+    fn test_block_state() {
+        let entry = palette_entry("minecraft:oak_stairs[facing=north,half=bottom]");
+        assert_eq!(
+            block_state(&entry),
+            "minecraft:oak_stairs[facing=north,half=bottom]"
+        );
+        assert_eq!(
+            block_state(&palette_entry("minecraft:stone")),
+            "minecraft:stone"
+        );
+    }
+}
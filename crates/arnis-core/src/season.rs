@@ -0,0 +1,168 @@
+//! Seasons (`--season`). The data shows the world in summer, so summer leaves it as it is.
+//! In winter snow covers the ground, roofs and canopies, small plants are buried and open
+//! water freezes. Autumn colors grass and tree canopies through the biome, as Minecraft tints
+//! them by biome and has no autumn leaves of its own.
+
+use crate::block_definitions::*;
+use clap::ValueEnum;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Season {
+    #[default]
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    /// Biome of the generated chunks, if the season needs one
+    pub fn biome(self) -> Option<&'static str> {
+        match self {
+            Season::Summer => None,
+            // Brown-orange grass and oak leaves, the closest Minecraft has to autumn colors
+            Season::Autumn => Some("minecraft:wooded_badlands"),
+            // Snows instead of raining, so the snow stays
+            Season::Winter => Some("minecraft:snowy_plains"),
+        }
+    }
+}
+
+/// Plants that disappear under the snow in winter
+pub fn is_buried(block: Block) -> bool {
+    matches!(
+        block,
+        GRASS
+            | TALL_GRASS_BOTTOM
+            | TALL_GRASS_TOP
+            | RED_FLOWER
+            | BLUE_FLOWER
+            | WHITE_FLOWER
+            | YELLOW_FLOWER
+            | DEAD_BUSH
+            | WHEAT
+            | CARROTS
+            | POTATOES
+    )
+}
+
+/// Whether snow settles on top of the block, which needs a full top face
+pub fn holds_snow(block: Block) -> bool {
+    !matches!(
+        block,
+        AIR | WATER
+            | ICE
+            | PACKED_ICE
+            | SNOW_LAYER
+            | GLASS
+            | WHITE_STAINED_GLASS
+            | GRAY_STAINED_GLASS
+            | LIGHT_GRAY_STAINED_GLASS
+            | BROWN_STAINED_GLASS
+            | TINTED_GLASS
+            | IRON_BARS
+            | OAK_FENCE
+            | COBBLESTONE_WALL
+            | ANDESITE_WALL
+            | STONE_BRICK_WALL
+            | OAK_SLAB
+            | STONE_BLOCK_SLAB
+            | STONE_BRICK_SLAB
+            | OAK_STAIRS
+            | STONE_BRICK_STAIRS
+            | MUD_BRICK_STAIRS
+            | POLISHED_BLACKSTONE_BRICK_STAIRS
+            | BRICK_STAIRS
+            | POLISHED_GRANITE_STAIRS
+            | END_STONE_BRICK_STAIRS
+            | POLISHED_DIORITE_STAIRS
+            | SMOOTH_SANDSTONE_STAIRS
+            | QUARTZ_STAIRS
+            | POLISHED_ANDESITE_STAIRS
+            | NETHER_BRICK_STAIRS
+            | RAIL
+            | RAIL_NORTH_SOUTH
+            | RAIL_EAST_WEST
+            | RAIL_ASCENDING_EAST
+            | RAIL_ASCENDING_WEST
+            | RAIL_ASCENDING_NORTH
+            | RAIL_ASCENDING_SOUTH
+            | RAIL_NORTH_EAST
+            | RAIL_NORTH_WEST
+            | RAIL_SOUTH_EAST
+            | RAIL_SOUTH_WEST
+            | POWERED_RAIL_NORTH_SOUTH
+            | POWERED_RAIL_EAST_WEST
+            | UNPOWERED_RAIL_NORTH_SOUTH
+            | UNPOWERED_RAIL_EAST_WEST
+            | POWERED_RAIL_ASCENDING_EAST
+            | POWERED_RAIL_ASCENDING_WEST
+            | POWERED_RAIL_ASCENDING_NORTH
+            | POWERED_RAIL_ASCENDING_SOUTH
+            | LADDER
+            | SIGN
+            | SCAFFOLDING
+            | CAULDRON
+            | BREWING_STAND
+            | ANVIL
+            | CHEST
+            | WHITE_CARPET
+            | RED_CARPET
+            | OAK_PRESSURE_PLATE
+            | STONE_BUTTON
+            | OAK_TRAPDOOR
+            | OAK_DOOR
+            | DARK_OAK_DOOR_LOWER
+            | DARK_OAK_DOOR_UPPER
+            | RED_BED_NORTH_HEAD
+            | RED_BED_NORTH_FOOT
+            | RED_BED_EAST_HEAD
+            | RED_BED_EAST_FOOT
+            | RED_BED_SOUTH_HEAD
+            | RED_BED_SOUTH_FOOT
+            | RED_BED_WEST_HEAD
+            | RED_BED_WEST_FOOT
+            | FARMLAND
+            | DIRT_PATH
+            | BARRIER
+    ) && !is_buried(block)
+}
+
+/// Winter version of a block column, given from the bottom up: the plants on top are buried,
+/// open water freezes and snow settles on the topmost block
+pub fn winter_column(column: &mut Vec<Block>) {
+    while column
+        .last()
+        .is_some_and(|&block| block == AIR || is_buried(block))
+    {
+        column.pop();
+    }
+    match column.last_mut() {
+        Some(top) if *top == WATER => *top = ICE,
+        Some(&mut top) if holds_snow(top) => column.push(SNOW_LAYER),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_winter_column() {
+        let mut meadow = vec![DIRT, GRASS_BLOCK, TALL_GRASS_BOTTOM, TALL_GRASS_TOP];
+        winter_column(&mut meadow);
+        assert_eq!(meadow, [DIRT, GRASS_BLOCK, SNOW_LAYER]);
+
+        let mut lake = vec![DIRT, WATER, WATER];
+        winter_column(&mut lake);
+        assert_eq!(lake, [DIRT, WATER, ICE]);
+
+        // Snow doesn't settle on rails, nor on the ground under them
+        let mut track = vec![GRAVEL, RAIL_NORTH_SOUTH];
+        winter_column(&mut track);
+        assert_eq!(track, [GRAVEL, RAIL_NORTH_SOUTH]);
+        let mut canopy = vec![OAK_LOG, OAK_LEAVES, AIR];
+        winter_column(&mut canopy);
+        assert_eq!(canopy, [OAK_LOG, OAK_LEAVES, SNOW_LAYER]);
+    }
+}
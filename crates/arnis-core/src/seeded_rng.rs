@@ -0,0 +1,91 @@
+//! Random numbers for world generation that can be made reproducible with a seed.
+//!
+//! Without a seed, this behaves like `rand::thread_rng()`. With a seed, the generator is
+//! reseeded from the seed and the element ID before each element is processed, so the
+//! result doesn't depend on how many random numbers earlier elements consumed.
+
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+static SEED: AtomicU64 = AtomicU64::new(0);
+static SEEDED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Makes all further generation reproducible
+pub fn set_seed(seed: u64) {
+    SEED.store(seed, Ordering::SeqCst);
+    SEEDED.store(true, Ordering::SeqCst);
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// The seed set with `set_seed`, if any
+pub fn seed() -> Option<u64> {
+    SEEDED
+        .load(Ordering::SeqCst)
+        .then(|| SEED.load(Ordering::SeqCst))
+}
+
+/// Called before an element is processed, has no effect without a seed
+pub fn reseed_for_element(element_id: u64) {
+    if !SEEDED.load(Ordering::SeqCst) {
+        return;
+    }
+    // Spread neighbouring IDs over the whole seed space
+    let seed = SEED.load(Ordering::SeqCst) ^ element_id.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Handle to the generator of the current thread, used like `rand::thread_rng()`
+pub fn rng() -> SeededRng {
+    SeededRng
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SeededRng;
+
+impl RngCore for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_reseed_is_reproducible() {
+        set_seed(42);
+
+        reseed_for_element(7);
+        let first: Vec<u32> = (0..5).map(|_| rng().gen_range(0..1000)).collect();
+
+        // Other elements consuming numbers in between don't matter
+        reseed_for_element(8);
+        let other: Vec<u32> = (0..5).map(|_| rng().gen_range(0..1000)).collect();
+
+        reseed_for_element(7);
+        let again: Vec<u32> = (0..5).map(|_| rng().gen_range(0..1000)).collect();
+
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+    }
+}
@@ -0,0 +1,379 @@
+//! Simplification of the data for worlds generated below scale 1. Features shrink with the
+//! scale but blocks don't, so detail smaller than a block turns into noise: jagged outlines,
+//! rows of houses one block apart and one-block fragments of tiny features. Before
+//! generation, outlines are simplified, small buildings next to each other are merged into
+//! one and features too small to show are dropped.
+
+use crate::osm_parser::{ProcessedElement, ProcessedNode, ProcessedWay};
+use std::collections::{HashMap, HashSet};
+
+/// Deviation from the original outline allowed when simplifying, in blocks
+const TOLERANCE: f64 = 1.0;
+/// Areas smaller than this, in blocks², are dropped
+const MIN_AREA: f64 = 4.0;
+/// Buildings smaller than this, in blocks², are merged with small neighbors
+const SMALL_BUILDING_AREA: f64 = 24.0;
+/// Small buildings at most this many blocks apart are merged
+const MERGE_GAP: i32 = 1;
+
+/// What the simplification changed
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SimplifyStats {
+    pub merged_buildings: usize,
+    pub dropped: usize,
+}
+
+/// Simplifies the elements for a world at `scale`, if it is below 1
+pub fn simplify_for_scale(elements: &mut Vec<ProcessedElement>, scale: f64) -> SimplifyStats {
+    let mut stats = SimplifyStats::default();
+    if scale >= 1.0 {
+        return stats;
+    }
+
+    for element in elements.iter_mut() {
+        match element {
+            ProcessedElement::Way(way) => way.nodes = simplify_nodes(&way.nodes),
+            ProcessedElement::Relation(relation) => {
+                for member in &mut relation.members {
+                    member.way.nodes = simplify_nodes(&member.way.nodes);
+                }
+            }
+            ProcessedElement::Node(_) => {}
+        }
+    }
+
+    let before = elements.len();
+    elements.retain(|element| match element {
+        ProcessedElement::Way(way) => !is_tiny(way),
+        _ => true,
+    });
+    stats.dropped = before - elements.len();
+
+    stats.merged_buildings = merge_small_buildings(elements);
+    stats
+}
+
+/// Douglas-Peucker simplification of a way, keeping its ends and all tagged nodes
+fn simplify_nodes(nodes: &[ProcessedNode]) -> Vec<ProcessedNode> {
+    if nodes.len() < 3 {
+        return nodes.to_vec();
+    }
+    let mut keep = vec![false; nodes.len()];
+    keep[0] = true;
+    keep[nodes.len() - 1] = true;
+    for (index, node) in nodes.iter().enumerate() {
+        keep[index] |= !node.tags.is_empty();
+    }
+
+    // Simplify between the fixed nodes, so they stay where they are
+    let fixed: Vec<usize> = (0..nodes.len()).filter(|&index| keep[index]).collect();
+    for pair in fixed.windows(2) {
+        mark_kept(nodes, pair[0], pair[1], &mut keep);
+    }
+
+    let simplified: Vec<ProcessedNode> = nodes
+        .iter()
+        .zip(&keep)
+        .filter(|&(_, &kept)| kept)
+        .map(|(node, _)| node.clone())
+        .collect();
+    // A closed way needs at least a triangle
+    if is_closed(nodes) && simplified.len() < 4 {
+        return nodes.to_vec();
+    }
+    simplified
+}
+
+/// Marks the node between `first` and `last` farthest from their line, if it is beyond the
+/// tolerance, and continues on both sides of it
+fn mark_kept(nodes: &[ProcessedNode], first: usize, last: usize, keep: &mut [bool]) {
+    if last <= first + 1 {
+        return;
+    }
+    let (farthest, distance) = (first + 1..last)
+        .map(|index| {
+            (
+                index,
+                distance_to_line(&nodes[index], &nodes[first], &nodes[last]),
+            )
+        })
+        .fold((first, 0.0), |best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        });
+    if distance > TOLERANCE {
+        keep[farthest] = true;
+        mark_kept(nodes, first, farthest, keep);
+        mark_kept(nodes, farthest, last, keep);
+    }
+}
+
+fn distance_to_line(point: &ProcessedNode, start: &ProcessedNode, end: &ProcessedNode) -> f64 {
+    let (dx, dz) = (f64::from(end.x - start.x), f64::from(end.z - start.z));
+    let (px, pz) = (f64::from(point.x - start.x), f64::from(point.z - start.z));
+    let length = dx.hypot(dz);
+    if length == 0.0 {
+        // The ends of a closed way coincide
+        return px.hypot(pz);
+    }
+    (dx * pz - dz * px).abs() / length
+}
+
+fn is_closed(nodes: &[ProcessedNode]) -> bool {
+    nodes.len() > 2
+        && nodes[0].x == nodes[nodes.len() - 1].x
+        && nodes[0].z == nodes[nodes.len() - 1].z
+}
+
+/// Area enclosed by a closed way, in blocks²
+fn area(nodes: &[ProcessedNode]) -> f64 {
+    let twice: i64 = nodes
+        .windows(2)
+        .map(|pair| {
+            i64::from(pair[0].x) * i64::from(pair[1].z)
+                - i64::from(pair[1].x) * i64::from(pair[0].z)
+        })
+        .sum();
+    twice.abs() as f64 / 2.0
+}
+
+/// Whether the way is an area too small to be more than a fragment. Roads are kept whatever
+/// their size, as they connect others.
+fn is_tiny(way: &ProcessedWay) -> bool {
+    is_closed(&way.nodes) && !way.tags.contains_key("highway") && area(&way.nodes) < MIN_AREA
+}
+
+/// Bounding box of a way: min x, min z, max x, max z
+fn bounds(nodes: &[ProcessedNode]) -> (i32, i32, i32, i32) {
+    nodes.iter().fold(
+        (i32::MAX, i32::MAX, i32::MIN, i32::MIN),
+        |(min_x, min_z, max_x, max_z), node| {
+            (
+                min_x.min(node.x),
+                min_z.min(node.z),
+                max_x.max(node.x),
+                max_z.max(node.z),
+            )
+        },
+    )
+}
+
+/// Replaces groups of small buildings close to each other by a building covering their convex
+/// hull, with the tags of the largest. Returns how many buildings were merged away.
+fn merge_small_buildings(elements: &mut Vec<ProcessedElement>) -> usize {
+    let small: Vec<usize> = elements
+        .iter()
+        .enumerate()
+        .filter_map(|(index, element)| match element {
+            ProcessedElement::Way(way)
+                if way.tags.contains_key("building")
+                    && is_closed(&way.nodes)
+                    && area(&way.nodes) < SMALL_BUILDING_AREA =>
+            {
+                Some(index)
+            }
+            _ => None,
+        })
+        .collect();
+    let way = |index: usize| match &elements[index] {
+        ProcessedElement::Way(way) => way,
+        _ => unreachable!(),
+    };
+
+    // Group the buildings whose bounding boxes come within the gap, using a grid of cells
+    const CELL: i32 = 16;
+    let boxes: Vec<(i32, i32, i32, i32)> = small
+        .iter()
+        .map(|&index| bounds(&way(index).nodes))
+        .collect();
+    let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    for (i, &(min_x, min_z, max_x, max_z)) in boxes.iter().enumerate() {
+        for cell_x in (min_x - MERGE_GAP).div_euclid(CELL)..=(max_x + MERGE_GAP).div_euclid(CELL) {
+            for cell_z in
+                (min_z - MERGE_GAP).div_euclid(CELL)..=(max_z + MERGE_GAP).div_euclid(CELL)
+            {
+                cells.entry((cell_x, cell_z)).or_default().push(i);
+            }
+        }
+    }
+    let mut group: Vec<usize> = (0..small.len()).collect();
+    for members in cells.values() {
+        for (n, &a) in members.iter().enumerate() {
+            for &b in &members[n + 1..] {
+                let (a_box, b_box) = (boxes[a], boxes[b]);
+                let near = a_box.0 <= b_box.2 + MERGE_GAP
+                    && b_box.0 <= a_box.2 + MERGE_GAP
+                    && a_box.1 <= b_box.3 + MERGE_GAP
+                    && b_box.1 <= a_box.3 + MERGE_GAP;
+                if near {
+                    let (root_a, root_b) = (find_root(&mut group, a), find_root(&mut group, b));
+                    group[root_a] = root_b;
+                }
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, &index) in small.iter().enumerate() {
+        let root = find_root(&mut group, i);
+        groups.entry(root).or_default().push(index);
+    }
+
+    let mut hulls: Vec<(usize, Vec<ProcessedNode>)> = Vec::new();
+    let mut removed: HashSet<usize> = HashSet::new();
+    for members in groups.into_values().filter(|members| members.len() > 1) {
+        let largest = *members
+            .iter()
+            .max_by(|&&a, &&b| area(&way(a).nodes).total_cmp(&area(&way(b).nodes)))
+            .unwrap();
+        let points: Vec<ProcessedNode> = members
+            .iter()
+            .flat_map(|&index| way(index).nodes.iter().cloned())
+            .collect();
+        hulls.push((largest, convex_hull(points)));
+        removed.extend(members.into_iter().filter(|&index| index != largest));
+    }
+
+    for (index, hull) in hulls {
+        if let ProcessedElement::Way(way) = &mut elements[index] {
+            way.nodes = hull;
+        }
+    }
+    let mut index = 0;
+    elements.retain(|_| {
+        index += 1;
+        !removed.contains(&(index - 1))
+    });
+    removed.len()
+}
+
+/// Group of a building, halving the paths on the way (union-find)
+fn find_root(group: &mut [usize], mut i: usize) -> usize {
+    while group[i] != i {
+        group[i] = group[group[i]];
+        i = group[i];
+    }
+    i
+}
+
+/// Convex hull of the nodes as a closed way (monotone chain)
+fn convex_hull(mut points: Vec<ProcessedNode>) -> Vec<ProcessedNode> {
+    points.sort_by_key(|node| (node.x, node.z));
+    points.dedup_by_key(|node| (node.x, node.z));
+    if points.len() < 3 {
+        return points;
+    }
+    let mut hull = hull_chain(points.iter());
+    hull.extend(hull_chain(points.iter().rev()));
+    hull.push(hull[0].clone());
+    hull
+}
+
+/// One half of the convex hull of sorted points, without its last point, which starts the
+/// other half
+fn hull_chain<'a>(points: impl Iterator<Item = &'a ProcessedNode>) -> Vec<ProcessedNode> {
+    let cross = |o: &ProcessedNode, a: &ProcessedNode, b: &ProcessedNode| {
+        i64::from(a.x - o.x) * i64::from(b.z - o.z) - i64::from(a.z - o.z) * i64::from(b.x - o.x)
+    };
+    let mut chain: Vec<ProcessedNode> = Vec::new();
+    for point in points {
+        while chain.len() >= 2
+            && cross(&chain[chain.len() - 2], &chain[chain.len() - 1], point) <= 0
+        {
+            chain.pop();
+        }
+        chain.push(point.clone());
+    }
+    chain.pop();
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(x: i32, z: i32) -> ProcessedNode {
+        ProcessedNode {
+            id: 0,
+            tags: HashMap::new(),
+            x,
+            z,
+        }
+    }
+
+    fn building(id: u64, x: i32, z: i32, size: i32) -> ProcessedElement {
+        ProcessedElement::Way(ProcessedWay {
+            id,
+            nodes: vec![
+                node(x, z),
+                node(x + size, z),
+                node(x + size, z + size),
+                node(x, z + size),
+                node(x, z),
+            ],
+            tags: HashMap::from([("building".to_string(), "house".to_string())]),
+        })
+    }
+
+    #[test]
+    fn test_simplify_nodes() {
+        // A road wobbling by less than a block becomes straight, a real bend stays
+        let road = vec![
+            node(0, 0),
+            node(5, 1),
+            node(10, 0),
+            node(15, 8),
+            node(20, 16),
+        ];
+        let simplified = simplify_nodes(&road);
+        let points: Vec<(i32, i32)> = simplified.iter().map(|n| (n.x, n.z)).collect();
+        assert_eq!(points, [(0, 0), (10, 0), (20, 16)]);
+
+        // Tagged nodes stay
+        let mut crossing = road.clone();
+        crossing[1]
+            .tags
+            .insert("highway".to_string(), "crossing".to_string());
+        assert_eq!(simplify_nodes(&crossing).len(), 4);
+    }
+
+    #[test]
+    fn test_simplify_for_scale() {
+        let mut elements = vec![
+            building(1, 0, 0, 4),
+            building(2, 5, 0, 3),
+            building(3, 100, 100, 4),
+            building(4, 200, 200, 1),
+            building(5, 300, 300, 20),
+        ];
+        let stats = simplify_for_scale(&mut elements, 0.5);
+
+        // The one-block building is gone and the neighbors are one building
+        assert_eq!(
+            stats,
+            SimplifyStats {
+                merged_buildings: 1,
+                dropped: 1
+            }
+        );
+        let merged = elements
+            .iter()
+            .find_map(|element| match element {
+                ProcessedElement::Way(way) if way.id == 1 => Some(way),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(area(&merged.nodes), 30.0);
+
+        // Nothing changes at full scale
+        let mut elements = vec![building(1, 0, 0, 4), building(2, 5, 0, 3)];
+        assert_eq!(
+            simplify_for_scale(&mut elements, 1.0),
+            SimplifyStats::default()
+        );
+        assert_eq!(elements.len(), 2);
+    }
+}
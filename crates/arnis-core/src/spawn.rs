@@ -0,0 +1,277 @@
+//! Picks a sensible world spawn point and optionally builds a starter kit and gazetteer there.
+
+use crate::args::Args;
+use crate::block_definitions::*;
+use crate::coordinate_system::cartesian::{XZBBox, XZPoint};
+use crate::gazetteer;
+use crate::osm_parser::ProcessedElement;
+use crate::world_editor::WorldEditor;
+use fastnbt::Value;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// How far around the preferred location a walkable spot is searched, in blocks
+const SEARCH_RADIUS: i32 = 96;
+/// Free space required above a walkable spot, so spawns under roofs and trees are avoided
+const HEADROOM: i32 = 16;
+/// Lines that fit on one page of a written book
+const BOOK_PAGE_LINES: usize = 12;
+
+/// Tags of named features that make a good, recognizable spawn location
+const SPAWN_POI_KEYS: [&str; 5] = ["place", "amenity", "tourism", "historic", "leisure"];
+
+/// Finds a walkable spot near the named POI closest to the center of the area,
+/// or near the center itself if there is no such POI
+pub fn find_spawn_point(
+    editor: &WorldEditor,
+    elements: &[ProcessedElement],
+    xzbbox: &XZBBox,
+) -> XZPoint {
+    let rect = xzbbox.bounding_rect();
+    let center = XZPoint::new(
+        rect.min().x + (rect.max().x - rect.min().x) / 2,
+        rect.min().z + (rect.max().z - rect.min().z) / 2,
+    );
+
+    let preferred = elements
+        .iter()
+        .filter(|element| {
+            let tags = element.tags();
+            tags.contains_key("name")
+                && !tags.contains_key("building")
+                && SPAWN_POI_KEYS.iter().any(|key| tags.contains_key(*key))
+        })
+        .filter_map(|element| element.center())
+        .filter(|point| xzbbox.contains(point))
+        .min_by_key(|point| distance_sq(*point, center))
+        .unwrap_or(center);
+
+    // Search in growing square rings around the preferred location
+    for radius in 0..=SEARCH_RADIUS {
+        for dx in -radius..=radius {
+            for dz in -radius..=radius {
+                if dx.abs() != radius && dz.abs() != radius {
+                    continue;
+                }
+                let candidate = XZPoint::new(preferred.x + dx, preferred.z + dz);
+                if is_walkable(editor, xzbbox, candidate) {
+                    return candidate;
+                }
+            }
+        }
+    }
+
+    preferred
+}
+
+/// Solid ground that is not water, with nothing built above it
+fn is_walkable(editor: &WorldEditor, xzbbox: &XZBBox, point: XZPoint) -> bool {
+    xzbbox.contains(&point)
+        && editor.block_at(point.x, 0, point.z)
+        && !editor.check_for_block(point.x, 0, point.z, Some(&[WATER]))
+        && (1..=HEADROOM).all(|y| !editor.block_at(point.x, y, point.z))
+}
+
+fn distance_sq(a: XZPoint, b: XZPoint) -> i64 {
+    let dx = (a.x - b.x) as i64;
+    let dz = (a.z - b.z) as i64;
+    dx * dx + dz * dz
+}
+
+/// Builds the extras requested at the spawn point: a small platform with a chest holding
+/// maps and a book describing the generated area, and/or the gazetteer books
+pub fn build_spawn_extras(
+    editor: &mut WorldEditor,
+    spawn: XZPoint,
+    args: &Args,
+    elements: &[ProcessedElement],
+    xzbbox: &XZBBox,
+) {
+    let mut items = Vec::new();
+
+    if args.starter_kit {
+        for dx in -2..=2 {
+            for dz in -2..=2 {
+                editor.set_block(SMOOTH_STONE, spawn.x + dx, 0, spawn.z + dz, None, Some(&[]));
+                for y in 1..=3 {
+                    editor.set_block(AIR, spawn.x + dx, y, spawn.z + dz, None, Some(&[]));
+                }
+            }
+        }
+
+        items.push(item_stack("minecraft:map", 9, None));
+        items.push(item_stack("minecraft:compass", 1, None));
+        items.push(written_book(
+            "About this world",
+            area_description(spawn, args),
+        ));
+    }
+
+    if args.gazetteer {
+        items.extend(gazetteer::gazetteer_books(elements, xzbbox));
+    }
+
+    if !items.is_empty() {
+        editor.set_chest_with_items(spawn.x, 1, spawn.z - 2, items);
+    }
+}
+
+fn area_description(spawn: XZPoint, args: &Args) -> Vec<String> {
+    let bbox = args.bbox();
+    let yes_no = |enabled: bool| if enabled { "yes" } else { "no" };
+
+    vec![
+        "Generated with Arnis".to_string(),
+        format!("v{}", env!("CARGO_PKG_VERSION")),
+        String::new(),
+        "Area:".to_string(),
+        format!("{:.5}, {:.5}", bbox.min().lat(), bbox.min().lng()),
+        "to".to_string(),
+        format!("{:.5}, {:.5}", bbox.max().lat(), bbox.max().lng()),
+        String::new(),
+        format!("Spawn: {} {}", spawn.x, spawn.z),
+        format!("Scale: {}", args.scale),
+        format!("Terrain: {}", yes_no(args.terrain)),
+        format!("Interiors: {}", yes_no(args.interior)),
+        format!("Roofs: {}", yes_no(args.roof)),
+        String::new(),
+        "Map data (c)".to_string(),
+        "OpenStreetMap".to_string(),
+        "contributors".to_string(),
+    ]
+}
+
+/// An item stack compound as stored in container inventories
+pub fn item_stack(id: &str, count: i8, tag: Option<HashMap<String, Value>>) -> Value {
+    let mut item = HashMap::from([
+        ("id".to_string(), Value::String(id.to_string())),
+        ("Count".to_string(), Value::Byte(count)),
+    ]);
+    if let Some(tag) = tag {
+        item.insert("tag".to_string(), Value::Compound(tag));
+    }
+    Value::Compound(item)
+}
+
+/// A written book item with the given lines, spread over as many pages as needed
+pub fn written_book(title: &str, lines: Vec<String>) -> Value {
+    let pages = book_pages(&lines)
+        .into_iter()
+        .map(|page| Value::String(serde_json::json!({ "text": page }).to_string()))
+        .collect();
+
+    let tag = HashMap::from([
+        ("title".to_string(), Value::String(title.to_string())),
+        ("author".to_string(), Value::String("Arnis".to_string())),
+        ("pages".to_string(), Value::List(pages)),
+        ("resolved".to_string(), Value::Byte(1)),
+    ]);
+
+    item_stack("minecraft:written_book", 1, Some(tag))
+}
+
+fn book_pages(lines: &[String]) -> Vec<String> {
+    if lines.is_empty() {
+        return vec![String::new()];
+    }
+
+    lines
+        .chunks(BOOK_PAGE_LINES)
+        .map(|page| page.join("\n"))
+        .collect()
+}
+
+/// Moves the world spawn and the player in level.dat to the given absolute position
+pub fn set_world_spawn(world_dir: &Path, x: i32, y: i32, z: i32) -> Result<(), String> {
+    let level_path = world_dir.join("level.dat");
+    let level_data =
+        std::fs::read(&level_path).map_err(|e| format!("Failed to read level.dat: {e}"))?;
+
+    let mut decompressed_data = Vec::new();
+    GzDecoder::new(level_data.as_slice())
+        .read_to_end(&mut decompressed_data)
+        .map_err(|e| format!("Failed to decompress level.dat: {e}"))?;
+
+    let mut nbt_data = fastnbt::from_bytes::<Value>(&decompressed_data)
+        .map_err(|e| format!("Failed to parse level.dat NBT data: {e}"))?;
+
+    let Value::Compound(ref mut root) = nbt_data else {
+        return Err("Invalid level.dat structure: root is not a compound".to_string());
+    };
+    let Some(Value::Compound(ref mut data)) = root.get_mut("Data") else {
+        return Err("Invalid level.dat structure: no Data compound".to_string());
+    };
+
+    data.insert("SpawnX".to_string(), Value::Int(x));
+    data.insert("SpawnY".to_string(), Value::Int(y));
+    data.insert("SpawnZ".to_string(), Value::Int(z));
+
+    if let Some(Value::Compound(ref mut player)) = data.get_mut("Player") {
+        player.insert(
+            "Pos".to_string(),
+            Value::List(vec![
+                Value::Double(x as f64 + 0.5),
+                Value::Double(y as f64),
+                Value::Double(z as f64 + 0.5),
+            ]),
+        );
+    }
+
+    let serialized_data = fastnbt::to_bytes(&nbt_data)
+        .map_err(|e| format!("Failed to serialize updated level.dat: {e}"))?;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&serialized_data)
+        .map_err(|e| format!("Failed to compress updated level.dat: {e}"))?;
+    let compressed_data = encoder
+        .finish()
+        .map_err(|e| format!("Failed to finalize compression for level.dat: {e}"))?;
+
+    std::fs::write(level_path, compressed_data)
+        .map_err(|e| format!("Failed to write updated level.dat: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_book_pages() {
+        assert_eq!(book_pages(&[]), vec![String::new()]);
+
+        let lines: Vec<String> = (0..30).map(|i| format!("line {i}")).collect();
+        let pages = book_pages(&lines);
+        assert_eq!(pages.len(), 3);
+        assert!(pages[0].starts_with("line 0\nline 1\n"));
+        assert_eq!(
+            pages[2],
+            "line 24\nline 25\nline 26\nline 27\nline 28\nline 29"
+        );
+    }
+
+    #[test]
+    fn test_written_book() {
+        let Value::Compound(book) = written_book("Test", vec!["Hello".to_string()]) else {
+            panic!("Book is not a compound");
+        };
+        assert_eq!(
+            book.get("id"),
+            Some(&Value::String("minecraft:written_book".to_string()))
+        );
+
+        let Some(Value::Compound(tag)) = book.get("tag") else {
+            panic!("Book has no tag");
+        };
+        assert_eq!(
+            tag.get("pages"),
+            Some(&Value::List(vec![Value::String(
+                r#"{"text":"Hello"}"#.to_string()
+            )]))
+        );
+    }
+}
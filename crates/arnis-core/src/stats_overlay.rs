@@ -0,0 +1,212 @@
+//! Statistics overlay: instead of the real world, a coarse 3D bar chart of element density
+//! or building height per grid cell. Quick to generate even for whole metro regions, to
+//! get an overview before committing to a full generation.
+
+use crate::args::Args;
+use crate::block_definitions::*;
+use crate::coordinate_system::cartesian::XZBBox;
+use crate::coordinate_system::geographic::LLBBox;
+use crate::error::ArnisError;
+use crate::osm_parser::ProcessedElement;
+use crate::progress::emit_gui_progress_update;
+use crate::world_editor::WorldEditor;
+use clap::ValueEnum;
+use colored::Colorize;
+
+/// Height in blocks of the bar of the cell with the highest value
+const MAX_BAR_HEIGHT: i32 = 128;
+/// Buildings without height tags count as two storeys
+const DEFAULT_BUILDING_HEIGHT: f64 = 6.0;
+/// Bar colors from the lowest to the highest values
+const PALETTE: [Block; 5] = [
+    BLUE_CONCRETE,
+    CYAN_CONCRETE,
+    LIME_CONCRETE,
+    YELLOW_CONCRETE,
+    RED_CONCRETE,
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum StatsMetric {
+    /// Number of mapped features
+    Density,
+    /// Average building height
+    Height,
+}
+
+/// Values of a grid of square cells over the area, row by row from the north-west corner
+struct CellGrid {
+    cell_size: i32,
+    columns: usize,
+    rows: usize,
+    values: Vec<f64>,
+}
+
+impl CellGrid {
+    fn collect(
+        elements: &[ProcessedElement],
+        xzbbox: &XZBBox,
+        metric: StatsMetric,
+        cell_size: i32,
+    ) -> Self {
+        let columns = ((xzbbox.max_x() - xzbbox.min_x()) / cell_size + 1) as usize;
+        let rows = ((xzbbox.max_z() - xzbbox.min_z()) / cell_size + 1) as usize;
+        let mut sums = vec![0.0; columns * rows];
+        let mut counts = vec![0usize; columns * rows];
+
+        for element in elements {
+            let value = match metric {
+                StatsMetric::Density if !element.tags().is_empty() => 1.0,
+                StatsMetric::Height => match building_height(element) {
+                    Some(height) => height,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let Some(center) = element.center() else {
+                continue;
+            };
+            if !xzbbox.contains(&center) {
+                continue;
+            }
+
+            let column = ((center.x - xzbbox.min_x()) / cell_size) as usize;
+            let row = ((center.z - xzbbox.min_z()) / cell_size) as usize;
+            sums[row * columns + column] += value;
+            counts[row * columns + column] += 1;
+        }
+
+        let values = match metric {
+            StatsMetric::Density => sums,
+            StatsMetric::Height => sums
+                .iter()
+                .zip(&counts)
+                .map(|(sum, &count)| if count > 0 { sum / count as f64 } else { 0.0 })
+                .collect(),
+        };
+
+        CellGrid {
+            cell_size,
+            columns,
+            rows,
+            values,
+        }
+    }
+
+    fn max_value(&self) -> f64 {
+        self.values.iter().copied().fold(0.0, f64::max)
+    }
+}
+
+/// Height in meters of a building, from its `height` or `building:levels` tag
+fn building_height(element: &ProcessedElement) -> Option<f64> {
+    let tags = element.tags();
+    if !tags.contains_key("building") && !tags.contains_key("building:part") {
+        return None;
+    }
+
+    let height = tags
+        .get("height")
+        .and_then(|height| height.trim_end_matches('m').trim().parse::<f64>().ok());
+    let levels = tags
+        .get("building:levels")
+        .and_then(|levels| levels.parse::<f64>().ok())
+        .map(|levels| levels * 3.0);
+    Some(height.or(levels).unwrap_or(DEFAULT_BUILDING_HEIGHT))
+}
+
+/// Height and color of the bar for a value
+fn bar(value: f64, max_value: f64) -> Option<(i32, Block)> {
+    if value <= 0.0 || max_value <= 0.0 {
+        return None;
+    }
+    let fraction = (value / max_value).min(1.0);
+    let height = ((fraction * MAX_BAR_HEIGHT as f64).ceil() as i32).max(1);
+    let color = PALETTE[((fraction * PALETTE.len() as f64) as usize).min(PALETTE.len() - 1)];
+    Some((height, color))
+}
+
+pub fn generate_stats_world(
+    elements: &[ProcessedElement],
+    xzbbox: XZBBox,
+    llbbox: LLBBox,
+    metric: StatsMetric,
+    args: &Args,
+) -> Result<(), ArnisError> {
+    println!(
+        "{} Generating {:?} statistics overlay...",
+        "[4/7]".bold(),
+        metric
+    );
+    emit_gui_progress_update(25.0, "Generating statistics overlay...");
+
+    let grid = CellGrid::collect(elements, &xzbbox, metric, args.stats_cell_size);
+    let max_value = grid.max_value();
+    let mut editor: WorldEditor = WorldEditor::new(args.path.clone(), &xzbbox, llbbox);
+
+    for row in 0..grid.rows {
+        for column in 0..grid.columns {
+            let x1 = xzbbox.min_x() + column as i32 * grid.cell_size;
+            let z1 = xzbbox.min_z() + row as i32 * grid.cell_size;
+            let x2 = (x1 + grid.cell_size - 1).min(xzbbox.max_x());
+            let z2 = (z1 + grid.cell_size - 1).min(xzbbox.max_z());
+
+            editor.fill_blocks(LIGHT_GRAY_CONCRETE, x1, 0, z1, x2, 0, z2, None, None);
+
+            // A one block gap keeps neighboring bars apart
+            if let Some((height, color)) = bar(grid.values[row * grid.columns + column], max_value)
+            {
+                editor.fill_blocks(color, x1 + 1, 1, z1 + 1, x2, height, z2, None, None);
+            }
+        }
+    }
+
+    println!("Highest value per cell: {max_value:.1}");
+    editor.save()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm_parser::ProcessedNode;
+    use std::collections::HashMap;
+
+    fn node(x: i32, z: i32, tags: &[(&str, &str)]) -> ProcessedElement {
+        ProcessedElement::Node(ProcessedNode {
+            id: 1,
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>(),
+            x,
+            z,
+        })
+    }
+
+    #[test]
+    fn test_cell_grid() {
+        let xzbbox = XZBBox::rect_from_xz_lengths(19.0, 9.0).unwrap();
+        let elements = vec![
+            node(1, 1, &[("amenity", "bench")]),
+            node(2, 3, &[("building", "yes"), ("height", "30 m")]),
+            node(12, 5, &[("building", "yes"), ("building:levels", "4")]),
+            node(15, 5, &[("building", "yes")]),
+            node(16, 6, &[]),
+        ];
+
+        let density = CellGrid::collect(&elements, &xzbbox, StatsMetric::Density, 10);
+        assert_eq!((density.columns, density.rows), (2, 1));
+        assert_eq!(density.values, vec![2.0, 2.0]);
+
+        let height = CellGrid::collect(&elements, &xzbbox, StatsMetric::Height, 10);
+        assert_eq!(height.values, vec![30.0, 9.0]);
+        assert_eq!(height.max_value(), 30.0);
+    }
+
+    #[test]
+    fn test_bar() {
+        assert_eq!(bar(0.0, 10.0), None);
+        assert_eq!(bar(10.0, 10.0), Some((MAX_BAR_HEIGHT, RED_CONCRETE)));
+        assert_eq!(bar(0.01, 10.0), Some((1, BLUE_CONCRETE)));
+    }
+}
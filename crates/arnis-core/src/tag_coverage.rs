@@ -0,0 +1,229 @@
+//! Reports which features of the OSM data no processor generates anything for, with
+//! counts. Tells users why a feature is missing from their world, and contributors which
+//! features are most worth implementing next.
+
+use crate::osm_parser::ProcessedElement;
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Keys that define what a feature is (from the OSM "Map features" list); other tags like
+/// names or addresses only describe it
+const FEATURE_KEYS: [&str; 30] = [
+    "aerialway",
+    "aeroway",
+    "amenity",
+    "barrier",
+    "boundary",
+    "bridge",
+    "building",
+    "craft",
+    "emergency",
+    "geological",
+    "healthcare",
+    "highway",
+    "historic",
+    "landuse",
+    "leisure",
+    "man_made",
+    "military",
+    "natural",
+    "office",
+    "place",
+    "power",
+    "public_transport",
+    "railway",
+    "route",
+    "shop",
+    "sport",
+    "telecom",
+    "tourism",
+    "water",
+    "waterway",
+];
+
+#[derive(Debug, Default)]
+pub struct TagCoverage {
+    /// Elements that are features, leaving out e.g. untagged nodes of ways
+    pub total: usize,
+    pub handled: usize,
+    /// `key=value` of the feature tags of unhandled elements, with the number of elements
+    pub unhandled: HashMap<String, usize>,
+}
+
+impl TagCoverage {
+    pub fn collect(elements: &[ProcessedElement]) -> Self {
+        let mut coverage = TagCoverage::default();
+
+        for element in elements {
+            if is_handled(element) {
+                coverage.total += 1;
+                coverage.handled += 1;
+                continue;
+            }
+
+            let mut is_feature = false;
+            for (key, value) in element.tags() {
+                if FEATURE_KEYS.contains(&key.as_str()) {
+                    is_feature = true;
+                    *coverage
+                        .unhandled
+                        .entry(format!("{key}={value}"))
+                        .or_default() += 1;
+                }
+            }
+            if is_feature {
+                coverage.total += 1;
+            }
+        }
+
+        coverage
+    }
+
+    /// Share of the features that are generated, in percent
+    pub fn percent_handled(&self) -> f64 {
+        if self.total == 0 {
+            return 100.0;
+        }
+        self.handled as f64 / self.total as f64 * 100.0
+    }
+
+    /// Unhandled tags, most frequent first
+    pub fn sorted_unhandled(&self) -> Vec<(&str, usize)> {
+        let mut tags: Vec<(&str, usize)> = self
+            .unhandled
+            .iter()
+            .map(|(tag, count)| (tag.as_str(), *count))
+            .collect();
+        tags.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        tags
+    }
+
+    /// Prints the coverage and the most frequent unhandled tags
+    pub fn print_summary(&self, limit: usize) {
+        println!(
+            "{} of {} features ({:.1}%) are generated",
+            self.handled,
+            self.total,
+            self.percent_handled()
+        );
+        for (tag, count) in self.sorted_unhandled().into_iter().take(limit) {
+            println!("  not handled: {tag} ({count}x)");
+        }
+    }
+
+    pub fn write_json(&self, path: &Path) -> Result<(), String> {
+        let unhandled: Vec<_> = self
+            .sorted_unhandled()
+            .into_iter()
+            .map(|(tag, count)| json!({ "tag": tag, "count": count }))
+            .collect();
+        let report = json!({
+            "elements": self.total,
+            "handled": self.handled,
+            "percent_handled": self.percent_handled(),
+            "unhandled": unhandled,
+        });
+
+        std::fs::write(path, serde_json::to_string_pretty(&report).unwrap())
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))
+    }
+}
+
+/// Whether generation has a processor for the element, in the same order of precedence
+/// as `data_processing::generate_world`
+fn is_handled(element: &ProcessedElement) -> bool {
+    let tags = element.tags();
+    let has = |key: &str| tags.contains_key(key);
+    let is = |key: &str, value: &str| tags.get(key).is_some_and(|v| v == value);
+
+    match element {
+        ProcessedElement::Way(_) => {
+            [
+                "building",
+                "building:part",
+                "highway",
+                "landuse",
+                "natural",
+                "amenity",
+                "leisure",
+                "barrier",
+                "waterway",
+                "railway",
+                "roller_coaster",
+                "aeroway",
+                "area:aeroway",
+                "man_made",
+            ]
+            .iter()
+            .any(|key| has(key))
+                || is("service", "siding")
+        }
+        ProcessedElement::Node(_) => {
+            [
+                "door", "entrance", "amenity", "barrier", "highway", "tourism", "man_made",
+            ]
+            .iter()
+            .any(|key| has(key))
+                || is("natural", "tree")
+                || is("railway", "subway_entrance")
+        }
+        ProcessedElement::Relation(_) => {
+            [
+                "building",
+                "building:part",
+                "water",
+                "natural",
+                "landuse",
+                "man_made",
+            ]
+            .iter()
+            .any(|key| has(key))
+                || is("leisure", "park")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm_parser::{ProcessedNode, ProcessedWay};
+
+    fn node(id: u64, tags: &[(&str, &str)]) -> ProcessedElement {
+        ProcessedElement::Node(ProcessedNode {
+            id,
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            x: 0,
+            z: 0,
+        })
+    }
+
+    #[test]
+    fn test_collect() {
+        let elements = vec![
+            node(1, &[("amenity", "bench")]),
+            node(2, &[("shop", "bakery"), ("name", "Bäckerei")]),
+            node(3, &[("shop", "bakery")]),
+            node(4, &[("natural", "peak")]),
+            node(5, &[]),
+            node(6, &[("name", "Untagged")]),
+            ProcessedElement::Way(ProcessedWay {
+                id: 7,
+                nodes: vec![],
+                tags: HashMap::from([("power".to_string(), "line".to_string())]),
+            }),
+        ];
+
+        let coverage = TagCoverage::collect(&elements);
+        assert_eq!(coverage.total, 5);
+        assert_eq!(coverage.handled, 1);
+        assert_eq!(
+            coverage.sorted_unhandled(),
+            vec![("shop=bakery", 2), ("natural=peak", 1), ("power=line", 1)]
+        );
+        assert!((coverage.percent_handled() - 20.0).abs() < 1e-9);
+    }
+}
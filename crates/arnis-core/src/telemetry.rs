@@ -0,0 +1,152 @@
+//! Opt-in anonymous run statistics (`--telemetry-url`), used to tune default chunk sizes
+//! and the memory estimator with real-world runs. Nothing is sent unless an endpoint is
+//! given. Reports only contain coarse hardware classes, the size of the area (never its
+//! location), the duration of each stage and the stage a failed run stopped in.
+
+use crate::cpu_info::PlatformInfo;
+use once_cell::sync::Lazy;
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const GB: u64 = 1024 * 1024 * 1024;
+/// Telemetry must never hold up the user for long
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+static RUN: Lazy<Mutex<Option<Run>>> = Lazy::new(|| Mutex::new(None));
+
+struct Run {
+    url: String,
+    report: Report,
+    stage: Option<(&'static str, Instant)>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct Report {
+    version: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    /// Logical CPUs, rounded down to a power of two
+    cpu_class: usize,
+    /// Installed memory in GB, rounded down to a power of two
+    ram_class_gb: u64,
+    /// Area rounded to two significant digits
+    area_km2: f64,
+    blocks: u64,
+    elements: usize,
+    /// Seconds spent in each stage, in the order they ran
+    stages: Vec<(&'static str, f64)>,
+    failed_stage: Option<&'static str>,
+}
+
+/// Enables telemetry for this run, reporting to `url`
+pub fn enable(url: &str) {
+    let platform = PlatformInfo::detect();
+    println!("Sending anonymous run statistics to {url}");
+    *RUN.lock().unwrap() = Some(Run {
+        url: url.to_string(),
+        report: Report {
+            version: env!("CARGO_PKG_VERSION"),
+            os: std::env::consts::OS,
+            arch: platform.arch,
+            cpu_class: power_of_two_floor(platform.logical_cpus as u64) as usize,
+            ram_class_gb: power_of_two_floor(platform.total_ram_bytes / GB),
+            area_km2: 0.0,
+            blocks: 0,
+            elements: 0,
+            stages: Vec::new(),
+            failed_stage: None,
+        },
+        stage: None,
+    });
+}
+
+/// Marks the start of a stage, ending the previous one
+pub fn enter_stage(name: &'static str) {
+    if let Some(run) = RUN.lock().unwrap().as_mut() {
+        run.end_stage();
+        run.stage = Some((name, Instant::now()));
+    }
+}
+
+/// Records the size of the generated area
+pub fn set_area(area_km2: f64, blocks: u64, elements: usize) {
+    if let Some(run) = RUN.lock().unwrap().as_mut() {
+        run.report.area_km2 = round_significant(area_km2, 2);
+        run.report.blocks = blocks;
+        run.report.elements = elements;
+    }
+}
+
+/// Sends the report of a run, recording the current stage as failed unless it succeeded
+pub fn finish(success: bool) {
+    let Some(mut run) = RUN.lock().unwrap().take() else {
+        return;
+    };
+    if !success {
+        run.report.failed_stage = run.stage.map(|(name, _)| name);
+    }
+    run.end_stage();
+
+    let result = Client::builder()
+        .timeout(SEND_TIMEOUT)
+        .build()
+        .and_then(|client| client.post(&run.url).json(&run.report).send())
+        .and_then(|response| response.error_for_status());
+    if let Err(e) = result {
+        eprintln!("Failed to send run statistics: {e}");
+    }
+}
+
+/// Reports the run as failed and exits
+pub fn exit_failure() -> ! {
+    finish(false);
+    std::process::exit(1);
+}
+
+impl Run {
+    fn end_stage(&mut self) {
+        if let Some((name, start)) = self.stage.take() {
+            self.report
+                .stages
+                .push((name, start.elapsed().as_secs_f64()));
+        }
+    }
+}
+
+fn power_of_two_floor(value: u64) -> u64 {
+    match value {
+        0 => 0,
+        value => 1 << (63 - value.leading_zeros()),
+    }
+}
+
+fn round_significant(value: f64, digits: i32) -> f64 {
+    if value <= 0.0 {
+        return 0.0;
+    }
+    let factor = 10f64.powi(digits - 1 - value.log10().floor() as i32);
+    (value * factor).round() / factor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hardware_classes() {
+        assert_eq!(power_of_two_floor(0), 0);
+        assert_eq!(power_of_two_floor(12), 8);
+        assert_eq!(power_of_two_floor(16), 16);
+        assert_eq!(power_of_two_floor(31), 16);
+    }
+
+    #[test]
+    fn test_round_significant() {
+        assert_eq!(round_significant(12.345, 2), 12.0);
+        assert_eq!(round_significant(0.4567, 2), 0.46);
+        assert_eq!(round_significant(98765.0, 2), 99000.0);
+        assert_eq!(round_significant(0.0, 2), 0.0);
+    }
+}
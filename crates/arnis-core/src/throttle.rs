@@ -0,0 +1,139 @@
+//! Power saving mode for long runs on laptops: generation pauses while the CPU is too hot
+//! and slows down while running on battery.
+//!
+//! Temperatures come from sysinfo. Battery status is read from the OS where this is cheap
+//! (Linux and macOS); elsewhere only the temperature is watched.
+
+use std::time::{Duration, Instant};
+use sysinfo::Components;
+
+/// How often the sensors are read
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Generation pauses when the CPU reaches this temperature...
+const HOT_CELSIUS: f32 = 85.0;
+/// ...and continues once it has cooled down to this one
+const COOL_CELSIUS: f32 = 75.0;
+/// Generation continues after this long even if the CPU did not cool down
+const MAX_COOLDOWN: Duration = Duration::from_secs(120);
+/// Pause per check interval on battery, slowing generation down by about a third
+const BATTERY_PAUSE: Duration = Duration::from_secs(2);
+
+pub struct Throttle {
+    enabled: bool,
+    components: Option<Components>,
+    last_check: Instant,
+}
+
+impl Throttle {
+    pub fn new(enabled: bool) -> Self {
+        Throttle {
+            enabled,
+            components: enabled.then(Components::new_with_refreshed_list),
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Called between two pieces of work; returns right away unless the sensors are due
+    pub fn pause_if_needed(&mut self) {
+        if !self.enabled || self.last_check.elapsed() < CHECK_INTERVAL {
+            return;
+        }
+
+        if let Some(temperature) = self.cpu_temperature().filter(|t| *t >= HOT_CELSIUS) {
+            println!("CPU at {temperature:.0}°C, pausing generation to let it cool down...");
+            let start = Instant::now();
+            while start.elapsed() < MAX_COOLDOWN {
+                std::thread::sleep(CHECK_INTERVAL);
+                if !matches!(self.cpu_temperature(), Some(t) if t > COOL_CELSIUS) {
+                    break;
+                }
+            }
+        }
+
+        if on_battery() {
+            std::thread::sleep(BATTERY_PAUSE);
+        }
+
+        self.last_check = Instant::now();
+    }
+
+    fn cpu_temperature(&mut self) -> Option<f32> {
+        let components = self.components.as_mut()?;
+        components.refresh();
+        hottest_cpu(
+            components
+                .list()
+                .iter()
+                .map(|component| (component.label(), component.temperature())),
+        )
+    }
+}
+
+/// Highest temperature of the sensors that belong to the CPU
+fn hottest_cpu<'a>(readings: impl Iterator<Item = (&'a str, f32)>) -> Option<f32> {
+    readings
+        .filter(|(label, _)| {
+            let label = label.to_lowercase();
+            ["cpu", "core", "package", "tctl", "tdie"]
+                .iter()
+                .any(|name| label.contains(name))
+        })
+        .map(|(_, temperature)| temperature)
+        .filter(|temperature| temperature.is_finite() && *temperature > 0.0)
+        .reduce(f32::max)
+}
+
+/// Whether the machine currently runs on battery
+pub fn on_battery() -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        let Ok(supplies) = std::fs::read_dir("/sys/class/power_supply") else {
+            return false;
+        };
+        supplies.flatten().any(|supply| {
+            let read = |file: &str| std::fs::read_to_string(supply.path().join(file));
+            read("type").is_ok_and(|kind| kind.trim() == "Battery")
+                && read("status").is_ok_and(|status| status.trim() == "Discharging")
+        })
+    }
+    #[cfg(target_os = "macos")]
+    {
+        std::process::Command::new("pmset")
+            .args(["-g", "batt"])
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("Battery Power"))
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hottest_cpu() {
+        let readings = [
+            ("acpitz temp1", 95.0),
+            ("coretemp Core 0", 70.0),
+            ("coretemp Package id 0", 82.5),
+            ("nvme Composite", 50.0),
+        ];
+        assert_eq!(hottest_cpu(readings.into_iter()), Some(82.5));
+
+        let readings = [("k10temp Tctl", f32::NAN), ("nvme Composite", 50.0)];
+        assert_eq!(hottest_cpu(readings.into_iter()), None);
+    }
+
+    #[test]
+    fn test_disabled_throttle_never_pauses() {
+        let mut throttle = Throttle::new(false);
+        throttle.last_check = Instant::now() - CHECK_INTERVAL * 2;
+
+        let start = Instant::now();
+        throttle.pause_if_needed();
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
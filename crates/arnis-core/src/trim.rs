@@ -0,0 +1,198 @@
+//! `arnis trim`: deletes the chunks of an existing Arnis world that lie outside of a
+//! geographic bounding box, e.g. the overlap or padding around the area of interest.
+//! Positions are located with the georeference Arnis saves in `metadata.json`.
+
+use crate::coordinate_system::geographic::LLBBox;
+use crate::world_editor::WorldMetadata;
+use clap::Parser;
+use fastanvil::Region;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Delete the chunks of an Arnis world outside of a bounding box
+#[derive(Parser, Debug)]
+#[command(name = "arnis trim")]
+pub struct TrimArgs {
+    /// Directory of the world to trim
+    #[arg(long, value_name = "PATH")]
+    pub world: PathBuf,
+
+    /// Area to keep (min_lat,min_lng,max_lat,max_lng)
+    #[arg(long, allow_hyphen_values = true, value_parser = LLBBox::from_str)]
+    pub bbox: LLBBox,
+
+    /// Only report how many chunks would be deleted
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// Block coordinates of the area to keep, inclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct KeepArea {
+    min_x: i32,
+    max_x: i32,
+    min_z: i32,
+    max_z: i32,
+}
+
+impl KeepArea {
+    fn new(metadata: &WorldMetadata, bbox: &LLBBox) -> Self {
+        let (min_x, min_z) = metadata.block_position(bbox.max().lat(), bbox.min().lng());
+        let (max_x, max_z) = metadata.block_position(bbox.min().lat(), bbox.max().lng());
+        KeepArea {
+            min_x,
+            max_x,
+            min_z,
+            max_z,
+        }
+    }
+
+    /// Whether any block of the square of `size` blocks starting at (x, z) is kept
+    fn overlaps(&self, x: i32, z: i32, size: i32) -> bool {
+        x + size > self.min_x && x <= self.max_x && z + size > self.min_z && z <= self.max_z
+    }
+}
+
+pub fn run(args: TrimArgs) -> Result<(), String> {
+    let metadata = WorldMetadata::load(&args.world)?;
+    let keep = KeepArea::new(&metadata, &args.bbox);
+    println!(
+        "Keeping blocks from {},{} to {},{}",
+        keep.min_x, keep.min_z, keep.max_x, keep.max_z
+    );
+
+    let region_dir = args.world.join("region");
+    let entries = std::fs::read_dir(&region_dir)
+        .map_err(|e| format!("Failed to read {}: {e}", region_dir.display()))?;
+
+    let mut deleted_chunks: usize = 0;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some((region_x, region_z)) = region_coords(&path) else {
+            continue;
+        };
+
+        if !keep.overlaps(region_x * 512, region_z * 512, 512) {
+            deleted_chunks += count_chunks(&path)?;
+            if !args.dry_run {
+                std::fs::remove_file(&path)
+                    .map_err(|e| format!("Failed to delete {}: {e}", path.display()))?;
+            }
+            continue;
+        }
+
+        deleted_chunks += trim_region(&path, region_x, region_z, &keep, args.dry_run)?;
+    }
+
+    if args.dry_run {
+        println!("Would delete {deleted_chunks} chunks");
+    } else {
+        // The georeference stays valid, the corners of the world are unchanged
+        println!("Deleted {deleted_chunks} chunks");
+    }
+    Ok(())
+}
+
+/// Region coordinates from a file name like `r.-1.2.mca`
+pub(crate) fn region_coords(path: &Path) -> Option<(i32, i32)> {
+    let name = path.file_name()?.to_str()?;
+    let mut parts = name.strip_prefix("r.")?.strip_suffix(".mca")?.split('.');
+    let coords = (parts.next()?.parse().ok()?, parts.next()?.parse().ok()?);
+    parts.next().is_none().then_some(coords)
+}
+
+pub(crate) fn open_region(path: &Path) -> Result<Region<File>, String> {
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+    Region::from_stream(file).map_err(|e| format!("Failed to load {}: {e}", path.display()))
+}
+
+fn count_chunks(path: &Path) -> Result<usize, String> {
+    Ok(open_region(path)?.iter().flatten().count())
+}
+
+/// Deletes the chunks of a region outside of the area, returns how many there were
+fn trim_region(
+    path: &Path,
+    region_x: i32,
+    region_z: i32,
+    keep: &KeepArea,
+    dry_run: bool,
+) -> Result<usize, String> {
+    let mut region = open_region(path)?;
+    let mut deleted: usize = 0;
+
+    for chunk_x in 0..32 {
+        for chunk_z in 0..32 {
+            let x = (region_x * 32 + chunk_x as i32) * 16;
+            let z = (region_z * 32 + chunk_z as i32) * 16;
+            if keep.overlaps(x, z, 16) {
+                continue;
+            }
+
+            let exists = region
+                .read_chunk(chunk_x, chunk_z)
+                .map_err(|e| format!("Failed to read {}: {e}", path.display()))?
+                .is_some();
+            if !exists {
+                continue;
+            }
+
+            if !dry_run {
+                region
+                    .remove_chunk(chunk_x, chunk_z)
+                    .map_err(|e| format!("Failed to delete chunk in {}: {e}", path.display()))?;
+            }
+            deleted += 1;
+        }
+    }
+
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_coords() {
+        assert_eq!(region_coords(Path::new("region/r.-1.2.mca")), Some((-1, 2)));
+        assert_eq!(region_coords(Path::new("region/r.0.0.mca.tmp")), None);
+        assert_eq!(region_coords(Path::new("region/r.0.mca")), None);
+    }
+
+    #[test]
+    fn test_keep_area() {
+        let metadata = WorldMetadata {
+            min_mc_x: 0,
+            max_mc_x: 1000,
+            min_mc_z: 0,
+            max_mc_z: 2000,
+            min_geo_lat: 50.0,
+            max_geo_lat: 50.2,
+            min_geo_lon: 10.0,
+            max_geo_lon: 10.1,
+        };
+        let bbox = LLBBox::new(50.05, 10.02, 50.15, 10.06).unwrap();
+        let keep = KeepArea::new(&metadata, &bbox);
+        assert_eq!(
+            keep,
+            KeepArea {
+                min_x: 200,
+                max_x: 600,
+                min_z: 500,
+                max_z: 1500,
+            }
+        );
+
+        // The chunk with blocks 192..=207 is partially inside
+        assert!(keep.overlaps(192, 496, 16));
+        assert!(!keep.overlaps(176, 800, 16));
+        assert!(!keep.overlaps(608, 800, 16));
+        assert!(!keep.overlaps(512, 1504, 16));
+        assert!(!keep.overlaps(-512, 0, 512));
+    }
+}
@@ -0,0 +1,167 @@
+//! Incremental updates (`--update`). Generation with `--track-updates` keeps a snapshot of
+//! the OSM data it used in the world directory. An update fetches the data again, compares
+//! it with the snapshot and generates the area into a staging world, from which only the
+//! chunks of the changed features are copied into the world. All other chunks, including what players
+//! built there, stay as they are.
+
+use crate::diff;
+use crate::disk_io;
+use crate::error::ArnisError;
+use crate::low_memory;
+use crate::merge;
+use crate::osm_parser::{ProcessedElement, RawOsmData};
+use crate::world_editor::WorldMetadata;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Snapshot of the OSM data in the world directory
+const SNAPSHOT_FILE: &str = "osm_snapshot.json.gz";
+/// Blocks around a changed feature that are regenerated with it, for the width of roads and
+/// what is placed beside features
+const MARGIN: i32 = 8;
+
+/// Keeps the data the world at `world_dir` is generated from, for later updates
+pub fn save_snapshot(world_dir: &Path, data: &RawOsmData) -> Result<(), ArnisError> {
+    let path = world_dir.join(SNAPSHOT_FILE);
+    let file = File::create(&path)
+        .map_err(|e| ArnisError::Io(format!("Failed to create {}: {e}", path.display())))?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    let written = match data {
+        RawOsmData::Json(value) => {
+            serde_json::to_writer(&mut encoder, value).map_err(|e| e.to_string())
+        }
+        RawOsmData::Text(text) => encoder
+            .write_all(text.as_bytes())
+            .map_err(|e| e.to_string()),
+    };
+    written
+        .and_then(|_| encoder.finish().map_err(|e| e.to_string()))
+        .map(|_| ())
+        .map_err(|e| ArnisError::Io(format!("Failed to write {}: {e}", path.display())))
+}
+
+/// The data the world at `world_dir` was generated from
+pub fn load_snapshot(world_dir: &Path) -> Result<RawOsmData, ArnisError> {
+    let path = world_dir.join(SNAPSHOT_FILE);
+    let file = File::open(&path).map_err(|_| {
+        ArnisError::Io(format!(
+            "{} has no snapshot of the data it was generated from, only worlds generated \
+             with --track-updates can be updated",
+            world_dir.display()
+        ))
+    })?;
+    let mut text = String::new();
+    GzDecoder::new(file)
+        .read_to_string(&mut text)
+        .map_err(|e| ArnisError::Parse(format!("Failed to read {}: {e}", path.display())))?;
+    Ok(RawOsmData::Text(text))
+}
+
+/// Creates the empty world the update is generated into, with the settings of `world_dir`
+pub fn staging_world(world_dir: &Path) -> Result<PathBuf, ArnisError> {
+    let staging = disk_io::temp_dir().join(format!("arnis-update-{}", std::process::id()));
+    low_memory::prepare_world(world_dir, &staging)?;
+    Ok(staging)
+}
+
+/// Chunks touched by the features that were added, removed or changed between the two
+/// versions of the data, with both the old and the new geometry of changed features
+pub fn changed_chunks(
+    old_elements: &[ProcessedElement],
+    new_elements: &[ProcessedElement],
+) -> HashSet<(i32, i32)> {
+    // The reverse comparison yields the old versions of the changed features
+    let changes = diff::diff_elements(old_elements, new_elements)
+        .into_iter()
+        .chain(diff::diff_elements(new_elements, old_elements));
+
+    let mut chunks = HashSet::new();
+    for change in changes {
+        let Some((min_x, min_z, max_x, max_z)) =
+            change.element.nodes().fold(None, |bounds, node| {
+                let (min_x, min_z, max_x, max_z) =
+                    bounds.unwrap_or((node.x, node.z, node.x, node.z));
+                Some((
+                    min_x.min(node.x),
+                    min_z.min(node.z),
+                    max_x.max(node.x),
+                    max_z.max(node.z),
+                ))
+            })
+        else {
+            continue;
+        };
+        for chunk_x in (min_x - MARGIN).div_euclid(16)..=(max_x + MARGIN).div_euclid(16) {
+            for chunk_z in (min_z - MARGIN).div_euclid(16)..=(max_z + MARGIN).div_euclid(16) {
+                chunks.insert((chunk_x, chunk_z));
+            }
+        }
+    }
+    chunks
+}
+
+/// Copies the changed chunks from the staging world into the world and keeps the new
+/// snapshot. Returns how many chunks were written.
+pub fn apply(
+    staging: &Path,
+    world_dir: &Path,
+    chunks: &HashSet<(i32, i32)>,
+) -> Result<usize, ArnisError> {
+    let (old, new) = (
+        WorldMetadata::load(world_dir).map_err(ArnisError::Io)?,
+        WorldMetadata::load(staging).map_err(ArnisError::Io)?,
+    );
+    if (old.min_mc_x, old.max_mc_x, old.min_mc_z, old.max_mc_z)
+        != (new.min_mc_x, new.max_mc_x, new.min_mc_z, new.max_mc_z)
+    {
+        return Err(ArnisError::Geometry(
+            "The area differs from the one the world was generated from, use the same \
+             --bbox and --scale"
+                .to_string(),
+        ));
+    }
+
+    let written = merge::write_chunks(staging, world_dir, (0, 0), |x, z| chunks.contains(&(x, z)))
+        .map_err(ArnisError::Io)?;
+    std::fs::copy(staging.join(SNAPSHOT_FILE), world_dir.join(SNAPSHOT_FILE))
+        .map_err(|e| ArnisError::Io(format!("Failed to keep the snapshot: {e}")))?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::osm_parser::{ProcessedNode, ProcessedWay};
+    use std::collections::HashMap;
+
+    fn building(id: u64, x: i32, levels: &str) -> ProcessedElement {
+        let node = |x: i32, z: i32| ProcessedNode {
+            id: 0,
+            tags: HashMap::new(),
+            x,
+            z,
+        };
+        ProcessedElement::Way(ProcessedWay {
+            id,
+            nodes: vec![node(x, 0), node(x + 10, 0), node(x + 10, 10), node(x, 0)],
+            tags: HashMap::from([("building:levels".to_string(), levels.to_string())]),
+        })
+    }
+
+    #[test]
+    fn test_changed_chunks() {
+        let old = [building(1, 100, "2"), building(2, 200, "3")];
+        let new = [building(1, 100, "2"), building(2, 300, "4")];
+        let chunks = changed_chunks(&old, &new);
+
+        // The moved building is regenerated at both places, the other one not at all
+        assert!(chunks.contains(&(12, 0)) && chunks.contains(&(18, 0)));
+        assert!(!chunks.contains(&(6, 0)));
+        assert!(changed_chunks(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,451 @@
+//! Mapbox vector tiles (MVT) from a self-hosted tile server as data source (`--tile-url`),
+//! for organizations that already serve OSM-derived tiles. The tiles covering the area are
+//! downloaded at one zoom level and their features are converted into the same JSON as an
+//! Overpass response, with the OpenMapTiles schema mapped back to OSM tags.
+//!
+//! Tiles only carry what the schema keeps: polygons lose their holes, and features crossing
+//! tile borders are split into one piece per tile.
+
+use crate::api_scheduler;
+use crate::coordinate_system::geographic::LLBBox;
+use crate::progress::emit_gui_progress_update;
+use crate::protobuf::ProtoReader;
+use colored::Colorize;
+use flate2::read::GzDecoder;
+use reqwest::blocking::{Client, ClientBuilder};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::io::Read;
+use std::time::Duration;
+
+/// Zoom level of the most detailed OpenMapTiles data
+pub const DEFAULT_ZOOM: u8 = 14;
+
+const CMD_MOVE_TO: u32 = 1;
+const CMD_LINE_TO: u32 = 2;
+const CMD_CLOSE_PATH: u32 = 7;
+
+const GEOM_POINT: u32 = 1;
+const GEOM_LINE: u32 = 2;
+const GEOM_POLYGON: u32 = 3;
+
+/// Downloads the tiles covering `bbox` from a tile server URL with `{z}`, `{x}` and `{y}`
+/// placeholders and returns their features as Overpass JSON
+pub fn fetch_data_from_tiles(
+    bbox: LLBBox,
+    url_template: &str,
+    zoom: u8,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    println!("{} Fetching vector tiles...", "[1/7]".bold());
+    emit_gui_progress_update(1.0, "Fetching vector tiles...");
+
+    if !["{z}", "{x}", "{y}"]
+        .iter()
+        .all(|placeholder| url_template.contains(placeholder))
+    {
+        return Err("The tile URL must contain the {z}, {x} and {y} placeholders".into());
+    }
+
+    let client: Client = ClientBuilder::new()
+        .timeout(Duration::from_secs(60))
+        .build()?;
+    let (min_x, min_y) = tile_of(bbox.max().lat(), bbox.min().lng(), zoom);
+    let (max_x, max_y) = tile_of(bbox.min().lat(), bbox.max().lng(), zoom);
+    let tile_count = (max_x - min_x + 1) * (max_y - min_y + 1);
+
+    let mut builder = ElementBuilder::default();
+    let mut done: u32 = 0;
+    for x in min_x..=max_x {
+        for y in min_y..=max_y {
+            let url = url_template
+                .replace("{z}", &zoom.to_string())
+                .replace("{x}", &x.to_string())
+                .replace("{y}", &y.to_string());
+            let response = api_scheduler::get(&client, &url, &[])?;
+            // Empty areas are often missing on the server
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                continue;
+            }
+            let bytes = response.error_for_status()?.bytes()?;
+            let data = decompress(&bytes)?;
+
+            for layer in decode_tile(&data)? {
+                builder.add_layer(&layer, zoom, x, y);
+            }
+
+            done += 1;
+            emit_gui_progress_update(1.0 + 4.0 * done as f64 / tile_count as f64, "");
+        }
+    }
+
+    println!("Loaded {} elements from {tile_count} tiles", builder.len());
+    Ok(json!({ "elements": builder.elements }))
+}
+
+/// Tiles are often served gzip-compressed without saying so
+fn decompress(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+        return Ok(bytes.to_vec());
+    }
+    let mut data = Vec::new();
+    GzDecoder::new(bytes)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("Failed to decompress tile: {e}"))?;
+    Ok(data)
+}
+
+/// Tile containing a location, in the XYZ scheme
+fn tile_of(lat: f64, lng: f64, zoom: u8) -> (u32, u32) {
+    let n = f64::from(1u32 << zoom);
+    let x = ((lng + 180.0) / 360.0 * n).floor();
+    let y = ((1.0 - lat.to_radians().tan().asinh() / PI) / 2.0 * n).floor();
+    (x.clamp(0.0, n - 1.0) as u32, y.clamp(0.0, n - 1.0) as u32)
+}
+
+/// Location of a point given in fractional tile coordinates
+fn tile_position(x: f64, y: f64, zoom: u8) -> (f64, f64) {
+    let n = f64::from(1u32 << zoom);
+    let lng = x / n * 360.0 - 180.0;
+    let lat = (PI * (1.0 - 2.0 * y / n)).sinh().atan().to_degrees();
+    (lat, lng)
+}
+
+struct Layer {
+    name: String,
+    extent: u32,
+    features: Vec<Feature>,
+}
+
+struct Feature {
+    geom_type: u32,
+    properties: HashMap<String, String>,
+    /// Rings or lines, or single points, in tile coordinates
+    parts: Vec<Vec<(i32, i32)>>,
+}
+
+fn decode_tile(data: &[u8]) -> Result<Vec<Layer>, String> {
+    let mut reader = ProtoReader::new(data);
+    let mut layers = Vec::new();
+    while reader.has_more() {
+        match reader.key()? {
+            (3, 2) => layers.push(decode_layer(reader.bytes()?)?),
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+    Ok(layers)
+}
+
+fn decode_layer(data: &[u8]) -> Result<Layer, String> {
+    let mut reader = ProtoReader::new(data);
+    let mut name = String::new();
+    let mut extent = 4096;
+    let mut keys: Vec<String> = Vec::new();
+    let mut values: Vec<String> = Vec::new();
+    // Features refer to keys and values by index, which may come after them
+    let mut raw_features: Vec<(u32, Vec<u32>, Vec<u32>)> = Vec::new();
+
+    while reader.has_more() {
+        match reader.key()? {
+            (1, 2) => name = String::from_utf8_lossy(reader.bytes()?).into_owned(),
+            (2, 2) => raw_features.push(decode_feature(reader.bytes()?)?),
+            (3, 2) => keys.push(String::from_utf8_lossy(reader.bytes()?).into_owned()),
+            (4, 2) => values.push(decode_value(reader.bytes()?)?),
+            (5, 0) => extent = reader.varint()? as u32,
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+
+    let features = raw_features
+        .into_iter()
+        .map(|(geom_type, tags, geometry)| {
+            let properties = tags
+                .chunks_exact(2)
+                .filter_map(|pair| {
+                    let key = keys.get(pair[0] as usize)?;
+                    let value = values.get(pair[1] as usize)?;
+                    Some((key.clone(), value.clone()))
+                })
+                .collect();
+            Feature {
+                geom_type,
+                properties,
+                parts: decode_geometry(&geometry),
+            }
+        })
+        .collect();
+
+    Ok(Layer {
+        name,
+        extent,
+        features,
+    })
+}
+
+fn decode_feature(data: &[u8]) -> Result<(u32, Vec<u32>, Vec<u32>), String> {
+    let mut reader = ProtoReader::new(data);
+    let (mut geom_type, mut tags, mut geometry) = (0, Vec::new(), Vec::new());
+    while reader.has_more() {
+        match reader.key()? {
+            (2, 2) => tags = reader.packed()?,
+            (3, 0) => geom_type = reader.varint()? as u32,
+            (4, 2) => geometry = reader.packed()?,
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+    Ok((geom_type, tags, geometry))
+}
+
+/// Property values are kept as strings, like OSM tags
+fn decode_value(data: &[u8]) -> Result<String, String> {
+    let mut reader = ProtoReader::new(data);
+    let mut value = String::new();
+    while reader.has_more() {
+        value = match reader.key()? {
+            (1, 2) => String::from_utf8_lossy(reader.bytes()?).into_owned(),
+            (2, 5) => f32::from_le_bytes(reader.fixed(4)?.try_into().unwrap()).to_string(),
+            (3, 1) => f64::from_le_bytes(reader.fixed(8)?.try_into().unwrap()).to_string(),
+            (4, 0) => (reader.varint()? as i64).to_string(),
+            (5, 0) => reader.varint()?.to_string(),
+            (6, 0) => zigzag(reader.varint()? as u32).to_string(),
+            (7, 0) => (if reader.varint()? != 0 { "yes" } else { "no" }).to_string(),
+            (_, wire_type) => {
+                reader.skip(wire_type)?;
+                continue;
+            }
+        };
+    }
+    Ok(value)
+}
+
+fn zigzag(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+/// Decodes the drawing commands of a feature into its lines, rings or points
+fn decode_geometry(commands: &[u32]) -> Vec<Vec<(i32, i32)>> {
+    let mut parts: Vec<Vec<(i32, i32)>> = Vec::new();
+    let (mut x, mut y) = (0i32, 0i32);
+    let mut i = 0;
+
+    while i < commands.len() {
+        let command = commands[i] & 7;
+        let count = (commands[i] >> 3) as usize;
+        i += 1;
+        match command {
+            CMD_MOVE_TO | CMD_LINE_TO => {
+                for _ in 0..count {
+                    let (Some(&dx), Some(&dy)) = (commands.get(i), commands.get(i + 1)) else {
+                        return parts;
+                    };
+                    i += 2;
+                    x += zigzag(dx);
+                    y += zigzag(dy);
+                    match parts.last_mut() {
+                        Some(part) if command == CMD_LINE_TO => part.push((x, y)),
+                        _ => parts.push(vec![(x, y)]),
+                    }
+                }
+            }
+            CMD_CLOSE_PATH => {
+                if let Some(part) = parts.last_mut() {
+                    if let Some(&first) = part.first() {
+                        part.push(first);
+                    }
+                }
+            }
+            _ => return parts,
+        }
+    }
+    parts
+}
+
+/// Twice the signed area of a ring; exterior rings are positive in tile coordinates
+fn ring_area(ring: &[(i32, i32)]) -> i64 {
+    ring.windows(2)
+        .map(|pair| {
+            let ((x1, y1), (x2, y2)) = (pair[0], pair[1]);
+            i64::from(x1) * i64::from(y2) - i64::from(x2) * i64::from(y1)
+        })
+        .sum()
+}
+
+/// OSM tags for a feature of the OpenMapTiles schema, `None` for features Arnis has no use for
+fn osm_tags(layer: &str, properties: &HashMap<String, String>) -> Option<Map<String, Value>> {
+    let class = properties.get("class").map(String::as_str);
+    let mut tags = Map::new();
+    let mut tag = |key: &str, value: &str| {
+        tags.insert(key.to_string(), Value::String(value.to_string()));
+    };
+
+    match (layer, class) {
+        ("building", _) => {
+            tag("building", "yes");
+            if let Some(height) = properties.get("render_height") {
+                tag("height", height);
+            }
+            if let Some(min_height) = properties.get("render_min_height") {
+                tag("min_height", min_height);
+            }
+        }
+        ("transportation", Some("rail" | "transit")) => tag("railway", "rail"),
+        ("transportation", Some("minor")) => tag("highway", "residential"),
+        ("transportation", Some("path")) => tag(
+            "highway",
+            properties.get("subclass").map_or("footway", String::as_str),
+        ),
+        ("transportation", Some(class)) => tag("highway", class),
+        ("water", _) => tag("natural", "water"),
+        ("waterway", Some(class)) => tag("waterway", class),
+        ("landuse", Some(class)) => tag("landuse", class),
+        ("landcover", Some("wood")) => tag("landuse", "forest"),
+        ("landcover", Some("grass")) => tag("landuse", "grass"),
+        ("landcover", Some("farmland")) => tag("landuse", "farmland"),
+        ("landcover", Some("wetland")) => tag("natural", "wetland"),
+        ("landcover", Some("sand")) => tag("natural", "sand"),
+        ("park", _) => tag("leisure", "park"),
+        _ => return None,
+    }
+
+    if let Some(name) = properties.get("name") {
+        tags.insert("name".to_string(), Value::String(name.clone()));
+    }
+    Some(tags)
+}
+
+/// Collects the features of all tiles as Overpass elements
+#[derive(Default)]
+struct ElementBuilder {
+    elements: Vec<Value>,
+    next_node: u64,
+    next_way: u64,
+}
+
+impl ElementBuilder {
+    fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    fn add_layer(&mut self, layer: &Layer, zoom: u8, tile_x: u32, tile_y: u32) {
+        let extent = f64::from(layer.extent);
+        let position = |(x, y): (i32, i32)| {
+            tile_position(
+                f64::from(tile_x) + f64::from(x) / extent,
+                f64::from(tile_y) + f64::from(y) / extent,
+                zoom,
+            )
+        };
+
+        for feature in &layer.features {
+            let Some(tags) = osm_tags(&layer.name, &feature.properties) else {
+                continue;
+            };
+            match feature.geom_type {
+                GEOM_POINT => {
+                    for &point in feature.parts.iter().flatten() {
+                        let (lat, lon) = position(point);
+                        self.next_node += 1;
+                        self.elements.push(json!({
+                            "type": "node", "id": self.next_node, "lat": lat, "lon": lon, "tags": tags,
+                        }));
+                    }
+                }
+                GEOM_LINE | GEOM_POLYGON => {
+                    for part in &feature.parts {
+                        // Holes are left out, only the outline of polygons is kept
+                        if part.len() < 2
+                            || (feature.geom_type == GEOM_POLYGON && ring_area(part) <= 0)
+                        {
+                            continue;
+                        }
+                        // Rings end at their first node, as closed OSM ways do
+                        let points = match feature.geom_type {
+                            GEOM_POLYGON => &part[..part.len() - 1],
+                            _ => &part[..],
+                        };
+                        let first_node = self.next_node + 1;
+                        for &point in points {
+                            let (lat, lon) = position(point);
+                            self.next_node += 1;
+                            self.elements.push(json!({
+                                "type": "node", "id": self.next_node, "lat": lat, "lon": lon,
+                            }));
+                        }
+                        let mut nodes: Vec<u64> = (first_node..=self.next_node).collect();
+                        if feature.geom_type == GEOM_POLYGON {
+                            nodes.push(first_node);
+                        }
+                        self.next_way += 1;
+                        self.elements.push(json!({
+                            "type": "way", "id": self.next_way, "nodes": nodes, "tags": tags,
+                        }));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_geometry() {
+        // Examples from the vector tile specification
+        assert_eq!(decode_geometry(&[9, 50, 34]), vec![vec![(25, 17)]]);
+        assert_eq!(
+            decode_geometry(&[9, 4, 4, 18, 0, 16, 16, 0]),
+            vec![vec![(2, 2), (2, 10), (10, 10)]]
+        );
+        let polygon = decode_geometry(&[9, 6, 12, 18, 10, 12, 24, 44, 15]);
+        assert_eq!(polygon, vec![vec![(3, 6), (8, 12), (20, 34), (3, 6)]]);
+        assert!(ring_area(&polygon[0]) > 0);
+    }
+
+    #[test]
+    fn test_tile_math() {
+        assert_eq!(tile_of(0.0, 0.0, 1), (1, 1));
+        let (x, y) = tile_of(54.63, 9.93, DEFAULT_ZOOM);
+        let (lat, lng) = tile_position(f64::from(x) + 0.5, f64::from(y) + 0.5, DEFAULT_ZOOM);
+        assert!((lat - 54.63).abs() < 0.02 && (lng - 9.93).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_decode_tile() {
+        // A layer "water" with one point feature tagged class=lake
+        let feature = [
+            0x18, 0x01, 0x22, 0x03, 0x09, 0x32, 0x22, 0x12, 0x02, 0x00, 0x00,
+        ];
+        let value = [0x0a, 0x04, b'l', b'a', b'k', b'e'];
+        let mut layer = vec![0x0a, 0x05, b'w', b'a', b't', b'e', b'r'];
+        layer.extend([0x12, feature.len() as u8]);
+        layer.extend(feature);
+        layer.extend([0x1a, 0x05, b'c', b'l', b'a', b's', b's']);
+        layer.extend([0x22, value.len() as u8]);
+        layer.extend(value);
+        let mut tile = vec![0x1a, layer.len() as u8];
+        tile.extend(layer);
+
+        let layers = decode_tile(&tile).unwrap();
+        assert_eq!(layers[0].name, "water");
+        assert_eq!(layers[0].extent, 4096);
+        let feature = &layers[0].features[0];
+        assert_eq!(feature.geom_type, GEOM_POINT);
+        assert_eq!(feature.parts, vec![vec![(25, 17)]]);
+        assert_eq!(feature.properties["class"], "lake");
+    }
+
+    #[test]
+    fn test_osm_tags() {
+        let properties = HashMap::from([
+            ("class".to_string(), "minor".to_string()),
+            ("name".to_string(), "Dorfstraße".to_string()),
+        ]);
+        let tags = osm_tags("transportation", &properties).unwrap();
+        assert_eq!(tags["highway"], "residential");
+        assert_eq!(tags["name"], "Dorfstraße");
+        assert!(osm_tags("poi", &properties).is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::api_scheduler;
 use colored::Colorize;
 use reqwest::blocking::Client;
 use reqwest::{Error as ReqwestError, StatusCode};
@@ -16,7 +17,7 @@ pub fn check_for_updates() -> Result<bool, Box<dyn Error>> {
     // Fetch the remote Cargo.toml file with a User-Agent header
     let response: Result<reqwest::blocking::Response, ReqwestError> = client
         .get(REMOTE_CARGO_TOML_URL)
-        .header("User-Agent", "arnis-client")
+        .header("User-Agent", api_scheduler::user_agent())
         .send();
 
     match response {
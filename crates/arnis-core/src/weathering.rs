@@ -0,0 +1,97 @@
+//! Weathering (`--weathering`): a pass over the world before it is saved that swaps some
+//! masonry for cracked and mossy variants and wears down exposed edges, so large areas don't
+//! look uniformly new. Every choice depends only on the seed and the block position, so the
+//! same seed weathers the same blocks.
+
+use crate::block_definitions::*;
+
+/// Blocks in a thousand swapped for a weathered variant
+const VARIANT_PER_MILLE: u64 = 80;
+/// Exposed edge blocks in a thousand removed
+const EROSION_PER_MILLE: u64 = 120;
+
+/// Weathered variants of a block, if it has any
+fn variants(block: Block) -> &'static [Block] {
+    match block {
+        STONE_BRICKS => &[CRACKED_STONE_BRICKS, MOSSY_STONE_BRICKS],
+        COBBLESTONE => &[MOSSY_COBBLESTONE],
+        DEEPSLATE_BRICKS => &[CRACKED_DEEPSLATE_BRICKS],
+        POLISHED_ANDESITE => &[ANDESITE],
+        SMOOTH_STONE => &[STONE],
+        _ => &[],
+    }
+}
+
+/// Masonry that crumbles at exposed edges
+fn erodes(block: Block) -> bool {
+    matches!(
+        block,
+        STONE_BRICKS
+            | CRACKED_STONE_BRICKS
+            | MOSSY_STONE_BRICKS
+            | COBBLESTONE
+            | MOSSY_COBBLESTONE
+            | BRICK
+            | MUD_BRICKS
+            | SANDSTONE
+            | DEEPSLATE_BRICKS
+    )
+}
+
+/// The weathered variant a block at this position gets, if any
+pub fn variant(block: Block, seed: u64, (x, y, z): (i32, i32, i32)) -> Option<Block> {
+    let variants = variants(block);
+    if variants.is_empty() {
+        return None;
+    }
+    let hash = position_hash(seed, x, y, z);
+    (hash % 1000 < VARIANT_PER_MILLE).then(|| variants[(hash / 1000) as usize % variants.len()])
+}
+
+/// Whether a block at this position, open above and on at least two sides, is worn away
+pub fn erodes_at(block: Block, seed: u64, (x, y, z): (i32, i32, i32)) -> bool {
+    // Independent of the choice of variants at the same position
+    erodes(block) && position_hash(!seed, x, y, z) % 1000 < EROSION_PER_MILLE
+}
+
+/// Well-mixed hash of a position (SplitMix64 finalizer)
+fn position_hash(seed: u64, x: i32, y: i32, z: i32) -> u64 {
+    let mut hash = seed
+        ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (z as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    hash ^ (hash >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variant() {
+        let positions = (0..100).flat_map(|x| (0..100).map(move |z| (x, 64, z)));
+        let weathered: Vec<Option<Block>> = positions
+            .clone()
+            .map(|position| variant(STONE_BRICKS, 7, position))
+            .collect();
+
+        // Some, but not most blocks change, and the same seed changes the same ones
+        let count = weathered.iter().flatten().count();
+        assert!((400..1200).contains(&count), "{count} of 10000 weathered");
+        assert!(weathered.iter().flatten().any(|&b| b == MOSSY_STONE_BRICKS));
+        assert!(weathered
+            .iter()
+            .flatten()
+            .any(|&b| b == CRACKED_STONE_BRICKS));
+        let again: Vec<Option<Block>> = positions
+            .map(|position| variant(STONE_BRICKS, 7, position))
+            .collect();
+        assert_eq!(weathered, again);
+
+        // Blocks without variants stay as they are
+        assert_eq!(variant(GLASS, 7, (0, 64, 0)), None);
+        assert!(!(0..1000).any(|x| erodes_at(GRASS_BLOCK, 7, (x, 64, 0))));
+    }
+}
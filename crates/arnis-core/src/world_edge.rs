@@ -0,0 +1,102 @@
+//! Treatment of the edge of the generated area (`--world-edge`), so that the world ends in
+//! an intentional way rather than with an abrupt cliff into the void.
+
+use crate::block_definitions::{BARRIER, BEDROCK, SAND, WATER};
+use crate::coordinate_system::cartesian::XZBBox;
+use crate::data_processing::{MAX_Y, MIN_Y};
+use crate::world_editor::WorldEditor;
+use clap::ValueEnum;
+
+/// Width of the ocean around the area
+const OCEAN_WIDTH: i32 = 48;
+/// Depth of the ocean below the lowest ground at the edge
+const OCEAN_DEPTH: i32 = 8;
+/// Width of the zone in which the terrain is blended to flat ground
+pub const FEATHER_WIDTH: usize = 64;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum WorldEdge {
+    /// Nothing beyond the area
+    #[default]
+    Void,
+    /// Surround the area with ocean
+    Ocean,
+    /// Bedrock up to the surface and invisible barrier blocks above it, around the area
+    Wall,
+    /// Blend the terrain near the edge to flat ground
+    Feather,
+}
+
+/// Builds the ocean or wall around the area. Feathering is applied to the ground data
+/// before generation instead, see `Ground::feather_edges`.
+pub fn build_world_edge(editor: &mut WorldEditor, xzbbox: &XZBBox, edge: WorldEdge) {
+    let rect = xzbbox.bounding_rect();
+    let (min_x, min_z) = (rect.min().x, rect.min().z);
+    let (max_x, max_z) = (rect.max().x, rect.max().z);
+
+    match edge {
+        WorldEdge::Void | WorldEdge::Feather => {}
+        WorldEdge::Ocean => {
+            let sea_level = perimeter(min_x, min_z, max_x, max_z, 0)
+                .map(|(x, z)| editor.get_absolute_y(x, 0, z))
+                .min()
+                .unwrap_or(MIN_Y + OCEAN_DEPTH + 1);
+            let sea_floor = (sea_level - OCEAN_DEPTH).max(MIN_Y);
+
+            for distance in 1..=OCEAN_WIDTH {
+                for (x, z) in perimeter(min_x, min_z, max_x, max_z, distance) {
+                    editor.set_block_unclipped(SAND, x, sea_floor, z);
+                    for y in sea_floor + 1..=sea_level {
+                        editor.set_block_unclipped(WATER, x, y, z);
+                    }
+                }
+            }
+        }
+        WorldEdge::Wall => {
+            for (x, z) in perimeter(min_x, min_z, max_x, max_z, 1) {
+                // Surface height of the nearest position inside the area
+                let surface =
+                    editor.get_absolute_y(x.clamp(min_x, max_x), 0, z.clamp(min_z, max_z));
+                for y in MIN_Y..=MAX_Y {
+                    let block = if y <= surface { BEDROCK } else { BARRIER };
+                    editor.set_block_unclipped(block, x, y, z);
+                }
+            }
+        }
+    }
+}
+
+/// Positions on the ring at `distance` blocks outside of the rectangle (0 is its border)
+fn perimeter(
+    min_x: i32,
+    min_z: i32,
+    max_x: i32,
+    max_z: i32,
+    distance: i32,
+) -> impl Iterator<Item = (i32, i32)> {
+    let (min_x, min_z) = (min_x - distance, min_z - distance);
+    let (max_x, max_z) = (max_x + distance, max_z + distance);
+
+    let horizontal = (min_x..=max_x).flat_map(move |x| [(x, min_z), (x, max_z)]);
+    let vertical = (min_z + 1..max_z).flat_map(move |z| [(min_x, z), (max_x, z)]);
+    horizontal.chain(vertical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_perimeter() {
+        let ring: Vec<(i32, i32)> = perimeter(0, 0, 2, 1, 1).collect();
+        let unique: HashSet<&(i32, i32)> = ring.iter().collect();
+
+        // A 5x4 rectangle has 14 positions on its border
+        assert_eq!(ring.len(), 14);
+        assert_eq!(unique.len(), 14);
+        assert!(ring
+            .iter()
+            .all(|&(x, z)| x == -1 || x == 3 || z == -1 || z == 2));
+    }
+}
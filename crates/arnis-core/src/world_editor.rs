@@ -1,20 +1,36 @@
 use crate::block_definitions::*;
+use crate::block_palette;
+use crate::categories::Category;
 use crate::coordinate_system::cartesian::{XZBBox, XZPoint};
 use crate::coordinate_system::geographic::LLBBox;
+use crate::data_processing::MAX_Y;
+use crate::disk_io;
+use crate::edge_clip::{self, EdgeClip};
+use crate::error::ArnisError;
 use crate::ground::Ground;
+use crate::perf_config::SECTION_BYTES;
+use crate::polygon_mask::PolygonMask;
 use crate::progress::emit_gui_progress_update;
+use crate::schematic;
+use crate::season::{self, Season};
+use crate::weathering;
 use colored::Colorize;
 use fastanvil::Region;
-use fastnbt::{LongArray, Value};
+use fastnbt::{ByteArray, LongArray, Value};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use fnv::FnvHashMap;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -96,6 +112,42 @@ impl SectionToModify {
         usize::from(y) % 16 * 256 + usize::from(z) * 16 + usize::from(x)
     }
 
+    /// Swaps blocks for weathered variants and wears down exposed edges, see `weathering`.
+    /// Neighbors outside the section count as solid.
+    fn weather(&mut self, seed: u64, (min_x, min_y, min_z): (i32, i32, i32)) {
+        let is_open = |blocks: &[Block; 4096], x: usize, y: usize, z: usize| {
+            x < 16 && y < 16 && z < 16 && blocks[y * 256 + z * 16 + x] == AIR
+        };
+        for index in 0..4096 {
+            let block = self.blocks[index];
+            if block == AIR {
+                continue;
+            }
+            let (x, y, z) = (index % 16, index / 256, index / 16 % 16);
+            let position = (min_x + x as i32, min_y + y as i32, min_z + z as i32);
+
+            // Lower blocks come first, so erosion doesn't eat into what it uncovers
+            let open_sides = [
+                is_open(&self.blocks, x + 1, y, z),
+                is_open(&self.blocks, x.wrapping_sub(1), y, z),
+                is_open(&self.blocks, x, y, z + 1),
+                is_open(&self.blocks, x, y, z.wrapping_sub(1)),
+            ]
+            .into_iter()
+            .filter(|&open| open)
+            .count();
+            if is_open(&self.blocks, x, y + 1, z)
+                && open_sides >= 2
+                && weathering::erodes_at(block, seed, position)
+            {
+                self.blocks[index] = AIR;
+                self.properties.remove(&index);
+            } else if let Some(variant) = weathering::variant(block, seed, position) {
+                self.blocks[index] = variant;
+            }
+        }
+    }
+
     fn to_section(&self, y: i8) -> Section {
         // Create a map of unique block+properties combinations to palette indices
         let mut unique_blocks: Vec<(Block, Option<Value>)> = Vec::new();
@@ -148,7 +200,7 @@ impl SectionToModify {
         let palette = unique_blocks
             .iter()
             .map(|(block, stored_props)| PaletteItem {
-                name: block.name().to_string(),
+                name: block_palette::block_name(block.name()),
                 properties: stored_props.clone().or_else(|| block.properties()),
             })
             .collect();
@@ -178,6 +230,8 @@ impl Default for SectionToModify {
 struct ChunkToModify {
     sections: FnvHashMap<i8, SectionToModify>,
     other: FnvHashMap<String, Value>,
+    /// Biome of all sections of the chunk, if it replaces the default
+    biome: Option<&'static str>,
 }
 
 impl ChunkToModify {
@@ -211,6 +265,33 @@ impl ChunkToModify {
         section.set_block_with_properties(x, (y & 15).try_into().unwrap(), z, block_with_props);
     }
 
+    /// Buries plants, freezes water and adds snow on every block column, see `season`
+    fn winter(&mut self) {
+        let (Some(&bottom), Some(&top)) = (self.sections.keys().min(), self.sections.keys().max())
+        else {
+            return;
+        };
+        let heights = i32::from(bottom) * 16..=i32::from(top) * 16 + 15;
+        for x in 0..16 {
+            for z in 0..16 {
+                let before: Vec<Block> = heights
+                    .clone()
+                    .map(|y| self.get_block(x, y, z).unwrap_or(AIR))
+                    .collect();
+                let mut column = before.clone();
+                season::winter_column(&mut column);
+
+                // The column may grow by a block of snow
+                for (index, y) in (*heights.start()..=heights.end() + 1).enumerate() {
+                    let block = column.get(index).copied().unwrap_or(AIR);
+                    if block != before.get(index).copied().unwrap_or(AIR) && y <= MAX_Y {
+                        self.set_block_with_properties(x, y, z, BlockWithProperties::simple(block));
+                    }
+                }
+            }
+        }
+    }
+
     fn sections(&self) -> impl Iterator<Item = Section> + '_ {
         self.sections.iter().map(|(y, s)| s.to_section(*y))
     }
@@ -229,11 +310,36 @@ impl RegionToModify {
     fn get_chunk(&self, x: i32, z: i32) -> Option<&ChunkToModify> {
         self.chunks.get(&(x, z))
     }
+
+    fn weather(&mut self, seed: u64, (region_x, region_z): (i32, i32)) {
+        for (&(chunk_x, chunk_z), chunk) in &mut self.chunks {
+            for (&y, section) in &mut chunk.sections {
+                let min_x = (region_x * 32 + chunk_x) * 16;
+                let min_z = (region_z * 32 + chunk_z) * 16;
+                section.weather(seed, (min_x, i32::from(y) * 16, min_z));
+            }
+        }
+    }
+
+    fn apply_season(&mut self, season: Season) {
+        for chunk in self.chunks.values_mut() {
+            if season == Season::Winter {
+                chunk.winter();
+            }
+            chunk.biome = season.biome();
+        }
+    }
+
+    fn section_count(&self) -> usize {
+        self.chunks.values().map(|chunk| chunk.sections.len()).sum()
+    }
 }
 
 #[derive(Default)]
 struct WorldToModify {
     regions: FnvHashMap<(i32, i32), RegionToModify>,
+    /// Sections held in memory, which take up most of it
+    sections: usize,
 }
 
 impl WorldToModify {
@@ -270,12 +376,15 @@ impl WorldToModify {
         let region: &mut RegionToModify = self.get_or_create_region(region_x, region_z);
         let chunk: &mut ChunkToModify = region.get_or_create_chunk(chunk_x & 31, chunk_z & 31);
 
+        let sections = chunk.sections.len();
         chunk.set_block(
             (x & 15).try_into().unwrap(),
             y,
             (z & 15).try_into().unwrap(),
             block,
         );
+        let added = chunk.sections.len() - sections;
+        self.sections += added;
     }
 
     fn set_block_with_properties(
@@ -293,27 +402,54 @@ impl WorldToModify {
         let region: &mut RegionToModify = self.get_or_create_region(region_x, region_z);
         let chunk: &mut ChunkToModify = region.get_or_create_chunk(chunk_x & 31, chunk_z & 31);
 
+        let sections = chunk.sections.len();
         chunk.set_block_with_properties(
             (x & 15).try_into().unwrap(),
             y,
             (z & 15).try_into().unwrap(),
             block_with_props,
         );
+        let added = chunk.sections.len() - sections;
+        self.sections += added;
     }
 }
 
-#[derive(Serialize)]
+/// Georeference of a generated world, saved as `metadata.json` in the world directory
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
-struct WorldMetadata {
-    min_mc_x: i32,
-    max_mc_x: i32,
-    min_mc_z: i32,
-    max_mc_z: i32,
-
-    min_geo_lat: f64,
-    max_geo_lat: f64,
-    min_geo_lon: f64,
-    max_geo_lon: f64,
+pub struct WorldMetadata {
+    pub min_mc_x: i32,
+    pub max_mc_x: i32,
+    pub min_mc_z: i32,
+    pub max_mc_z: i32,
+
+    pub min_geo_lat: f64,
+    pub max_geo_lat: f64,
+    pub min_geo_lon: f64,
+    pub max_geo_lon: f64,
+}
+
+impl WorldMetadata {
+    pub fn load(world_dir: &Path) -> Result<Self, String> {
+        let path = world_dir.join("metadata.json");
+        let contents = std::fs::read_to_string(&path).map_err(|e| {
+            format!(
+                "Failed to read {} (was the world generated by Arnis?): {e}",
+                path.display()
+            )
+        })?;
+        serde_json::from_str(&contents).map_err(|e| format!("Invalid {}: {e}", path.display()))
+    }
+
+    /// Block coordinates of a geographic position, interpolated between the corners
+    pub fn block_position(&self, lat: f64, lng: f64) -> (i32, i32) {
+        let rel_x = (lng - self.min_geo_lon) / (self.max_geo_lon - self.min_geo_lon);
+        let rel_z = (self.max_geo_lat - lat) / (self.max_geo_lat - self.min_geo_lat);
+        (
+            self.min_mc_x + (rel_x * (self.max_mc_x - self.min_mc_x) as f64).round() as i32,
+            self.min_mc_z + (rel_z * (self.max_mc_z - self.min_mc_z) as f64).round() as i32,
+        )
+    }
 }
 
 // Notes for someone not familiar with lifetime parameter:
@@ -326,6 +462,25 @@ pub struct WorldEditor<'a> {
     xzbbox: &'a XZBBox,
     llbbox: LLBBox,
     ground: Option<Box<Ground>>,
+    edge_clip: EdgeClip,
+    allow_overhang: bool,
+    exclusion: Option<PolygonMask>,
+    /// Sections kept in memory before regions are moved to disk, see `set_memory_budget`
+    section_limit: Option<usize>,
+    /// Files with the modifications of regions moved to disk. They are brought back before
+    /// the region is changed again, so a region is either in memory or on disk.
+    spilled: FnvHashMap<(i32, i32), PathBuf>,
+    /// Directory the regions are moved to
+    spill_dir: PathBuf,
+    /// Seed of the weathering applied when saving, if enabled
+    weathering: Option<u64>,
+    /// Season applied when saving
+    season: Season,
+    /// Categories of the features placed in every column, for the structure void of
+    /// schematics, if tracked
+    column_categories: Option<FnvHashMap<(i32, i32), u8>>,
+    /// Category of the element being generated
+    category: Option<Category>,
 }
 
 // template<lifetime A>
@@ -339,6 +494,190 @@ impl<'a> WorldEditor<'a> {
             xzbbox,
             llbbox,
             ground: None,
+            edge_clip: EdgeClip::Hard,
+            allow_overhang: false,
+            exclusion: None,
+            section_limit: None,
+            spilled: FnvHashMap::default(),
+            spill_dir: default_spill_dir(),
+            weathering: None,
+            season: Season::Summer,
+            column_categories: None,
+            category: None,
+        }
+    }
+
+    pub fn set_edge_clip(&mut self, edge_clip: EdgeClip) {
+        self.edge_clip = edge_clip;
+    }
+
+    /// With `EdgeClip::Whole`, lets the element being generated extend beyond the edge
+    pub fn set_allow_overhang(&mut self, allow_overhang: bool) {
+        self.allow_overhang = allow_overhang;
+    }
+
+    /// Limits the memory taken by the modifications to a share of `max_bytes`. Beyond it,
+    /// `enforce_memory_budget` moves regions to disk, and they are read back when changed
+    /// again or when saving.
+    pub fn set_memory_budget(&mut self, max_bytes: u64) {
+        let bytes = max_bytes as f64 * MODIFICATIONS_MEMORY_SHARE;
+        self.section_limit = Some((bytes / SECTION_BYTES as f64) as usize);
+    }
+
+    /// Moves the regions with the most modifications to disk while the modifications take
+    /// more memory than the budget allows. Checks are cheap, so this is called often.
+    /// Regions are written before this returns, so on slow disks processing waits for them
+    /// instead of growing past the budget.
+    pub fn enforce_memory_budget(&mut self) {
+        let Some(limit) = self.section_limit else {
+            return;
+        };
+        if self.world.sections <= limit {
+            return;
+        }
+
+        // Free half of the budget, so this doesn't happen again right away
+        match self.move_regions_to_disk(limit / 2) {
+            Ok(moved) => println!("Memory budget reached, moved {moved} regions to disk"),
+            Err(e) => eprintln!("Warning: Memory budget reached, but moving regions failed: {e}"),
+        }
+    }
+
+    /// Moves all regions to disk, e.g. while generation is paused. They are read back when
+    /// changed again or when saving.
+    pub fn release_memory(&mut self) {
+        match self.move_regions_to_disk(0) {
+            Ok(0) => {}
+            Ok(moved) => println!("Moved {moved} regions to disk"),
+            Err(e) => eprintln!("Warning: Failed to move regions to disk: {e}"),
+        }
+    }
+
+    /// Moves the regions with the most modifications to disk until at most `max_sections`
+    /// are left in memory, returns the number of regions moved. Regions that were moved before
+    /// an error stay on disk.
+    fn move_regions_to_disk(&mut self, max_sections: usize) -> Result<usize, ArnisError> {
+        let mut regions: Vec<((i32, i32), usize)> = self
+            .world
+            .regions
+            .iter()
+            .map(|(&position, region)| (position, region.section_count()))
+            .collect();
+        regions.sort_unstable_by_key(|&(_, sections)| std::cmp::Reverse(sections));
+
+        let mut moved = 0;
+        for (position, sections) in regions {
+            if self.world.sections <= max_sections {
+                break;
+            }
+            let region = self.world.regions.remove(&position).unwrap();
+            if let Err(e) = self.spill_region(position, &region) {
+                self.world.regions.insert(position, region);
+                return Err(e);
+            }
+            self.world.sections -= sections;
+            moved += 1;
+        }
+        Ok(moved)
+    }
+
+    fn spill_region(
+        &mut self,
+        position: (i32, i32),
+        region: &RegionToModify,
+    ) -> Result<(), ArnisError> {
+        let dir = &self.spill_dir;
+        std::fs::create_dir_all(dir)
+            .map_err(|e| ArnisError::Io(format!("Failed to create {}: {e}", dir.display())))?;
+        let path = dir.join(spill_file_name(position));
+        let chunks = snapshot_chunks(position, region).collect();
+        write_compressed(
+            &Snapshot {
+                chunks,
+                spilled: Vec::new(),
+            },
+            &path,
+        )?;
+        self.spilled.insert(position, path);
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_spill_dir(&mut self, dir: PathBuf) {
+        self.spill_dir = dir;
+    }
+
+    /// Brings the region of this block position back into memory if it was moved to disk,
+    /// so blocks are placed against those already there, overrides included
+    #[inline]
+    fn restore_region_at(&mut self, x: i32, z: i32) {
+        if self.spilled.is_empty() {
+            return;
+        }
+        let position = (x >> 9, z >> 9);
+        let Some(path) = self.spilled.remove(&position) else {
+            return;
+        };
+        let region = read_spilled(&path);
+        let _ = std::fs::remove_file(&path);
+        self.world.sections += region.section_count();
+        self.world.regions.insert(position, region);
+    }
+
+    /// Weathers the world with this seed when it is saved
+    pub fn set_weathering(&mut self, seed: u64) {
+        self.weathering = Some(seed);
+    }
+
+    /// Changes the world to this season when it is saved
+    pub fn set_season(&mut self, season: Season) {
+        self.season = season;
+    }
+
+    /// Records the categories of the features placed in every column, written next to the
+    /// world when saving for `--structure-void`
+    pub fn track_categories(&mut self) {
+        self.column_categories = Some(FnvHashMap::default());
+    }
+
+    /// Category of the element whose blocks are placed next
+    pub fn set_category(&mut self, category: Option<Category>) {
+        self.category = category;
+    }
+
+    #[inline]
+    fn mark_column(&mut self, x: i32, z: i32) {
+        if let (Some(columns), Some(category)) = (&mut self.column_categories, self.category) {
+            *columns.entry((x, z)).or_default() |= category.bit();
+        }
+    }
+
+    /// Leaves the block columns inside the mask empty, whatever is generated there
+    pub fn set_exclusion(&mut self, exclusion: PolygonMask) {
+        self.exclusion = Some(exclusion);
+    }
+
+    /// Whether a block may be placed at this position, given its height above the ground
+    #[inline]
+    fn accepts(&self, x: i32, z: i32, height_above_ground: impl FnOnce() -> i32) -> bool {
+        if self
+            .exclusion
+            .as_ref()
+            .is_some_and(|exclusion| exclusion.contains(x, z))
+        {
+            return false;
+        }
+        let point = XZPoint::new(x, z);
+        match self.edge_clip {
+            EdgeClip::Hard => self.xzbbox.contains(&point),
+            EdgeClip::Whole => {
+                self.xzbbox.contains(&point)
+                    || (self.allow_overhang && edge_clip::in_overhang(self.xzbbox, point))
+            }
+            EdgeClip::Fade => {
+                self.xzbbox.contains(&point)
+                    && edge_clip::fade_allows(self.xzbbox, point, height_above_ground())
+            }
         }
     }
 
@@ -366,10 +705,13 @@ impl<'a> WorldEditor<'a> {
     }
 
     /// Creates a region for the given region coordinates.
-    fn create_region(&self, region_x: i32, region_z: i32) -> Region<File> {
+    fn create_region(&self, region_x: i32, region_z: i32) -> Result<Region<File>, ArnisError> {
         let out_path = self
             .world_dir
             .join(format!("region/r.{}.{}.mca", region_x, region_z));
+        let io_error = |e: &dyn std::fmt::Display| {
+            ArnisError::Io(format!("Failed to write {}: {e}", out_path.display()))
+        };
 
         const REGION_TEMPLATE: &[u8] = include_bytes!("../../../assets/minecraft/region.template");
 
@@ -379,13 +721,13 @@ impl<'a> WorldEditor<'a> {
             .create(true)
             .truncate(true)
             .open(&out_path)
-            .expect("Failed to open region file");
+            .map_err(|e| io_error(&e))?;
 
         region_file
             .write_all(REGION_TEMPLATE)
-            .expect("Could not write region template");
+            .map_err(|e| io_error(&e))?;
 
-        Region::from_stream(region_file).expect("Failed to load region")
+        Region::from_stream(region_file).map_err(|e| io_error(&e))
     }
 
     pub fn get_min_coords(&self) -> (i32, i32) {
@@ -416,10 +758,6 @@ impl<'a> WorldEditor<'a> {
         _rotation: i8,
     ) {
         let absolute_y = self.get_absolute_y(x, y, z);
-        let chunk_x = x >> 4;
-        let chunk_z = z >> 4;
-        let region_x = chunk_x >> 5;
-        let region_z = chunk_z >> 5;
 
         let mut block_entities = HashMap::new();
 
@@ -442,25 +780,78 @@ impl<'a> WorldEditor<'a> {
         );
         block_entities.insert("is_waxed".to_string(), Value::Byte(0));
         block_entities.insert("keepPacked".to_string(), Value::Byte(0));
-        block_entities.insert("x".to_string(), Value::Int(x));
-        block_entities.insert("y".to_string(), Value::Int(absolute_y));
-        block_entities.insert("z".to_string(), Value::Int(z));
+        self.insert_block_entity(x, absolute_y, z, block_entities);
+        self.set_block(SIGN, x, y, z, None, None);
+    }
+
+    /// Places a chest filled with the given item stacks (compounds with `id`, `Count` and
+    /// optionally `tag`). Slots are assigned in order.
+    pub fn set_chest_with_items(&mut self, x: i32, y: i32, z: i32, items: Vec<Value>) {
+        let absolute_y = self.get_absolute_y(x, y, z);
+
+        let items = items
+            .into_iter()
+            .take(27)
+            .enumerate()
+            .map(|(slot, item)| match item {
+                Value::Compound(mut item) => {
+                    item.insert("Slot".to_string(), Value::Byte(slot as i8));
+                    Value::Compound(item)
+                }
+                other => other,
+            })
+            .collect();
+
+        let mut block_entities = HashMap::new();
+        block_entities.insert(
+            "id".to_string(),
+            Value::String("minecraft:chest".to_string()),
+        );
+        block_entities.insert("Items".to_string(), Value::List(items));
+        block_entities.insert("keepPacked".to_string(), Value::Byte(0));
+
+        self.insert_block_entity(x, absolute_y, z, block_entities);
+        self.set_block(CHEST, x, y, z, None, Some(&[]));
+    }
+
+    /// Stores the block entity data of the block at the given coordinates with absolute Y value
+    fn insert_block_entity(
+        &mut self,
+        x: i32,
+        absolute_y: i32,
+        z: i32,
+        mut block_entity: HashMap<String, Value>,
+    ) {
+        if self
+            .exclusion
+            .as_ref()
+            .is_some_and(|exclusion| exclusion.contains(x, z))
+        {
+            return;
+        }
+        let chunk_x = x >> 4;
+        let chunk_z = z >> 4;
+        let region_x = chunk_x >> 5;
+        let region_z = chunk_z >> 5;
+
+        self.restore_region_at(x, z);
+        block_entity.insert("x".to_string(), Value::Int(x));
+        block_entity.insert("y".to_string(), Value::Int(absolute_y));
+        block_entity.insert("z".to_string(), Value::Int(z));
 
         let region: &mut RegionToModify = self.world.get_or_create_region(region_x, region_z);
         let chunk: &mut ChunkToModify = region.get_or_create_chunk(chunk_x & 31, chunk_z & 31);
 
         if let Some(chunk_data) = chunk.other.get_mut("block_entities") {
             if let Value::List(entities) = chunk_data {
-                entities.push(Value::Compound(block_entities));
+                entities.push(Value::Compound(block_entity));
             }
         } else {
             chunk.other.insert(
                 "block_entities".to_string(),
-                Value::List(vec![Value::Compound(block_entities)]),
+                Value::List(vec![Value::Compound(block_entity)]),
             );
         }
-
-        self.set_block(SIGN, x, y, z, None, None);
     }
 
     /// Sets a block of the specified type at the given coordinates.
@@ -476,13 +867,14 @@ impl<'a> WorldEditor<'a> {
         override_blacklist: Option<&[Block]>,
     ) {
         // Check if coordinates are within bounds
-        if !self.xzbbox.contains(&XZPoint::new(x, z)) {
+        if !self.accepts(x, z, || y) {
             return;
         }
 
         // Calculate the absolute Y coordinate based on ground level
         let absolute_y = self.get_absolute_y(x, y, z);
 
+        self.restore_region_at(x, z);
         let should_insert = if let Some(existing_block) = self.world.get_block(x, absolute_y, z) {
             // Check against whitelist and blacklist
             if let Some(whitelist) = override_whitelist {
@@ -502,6 +894,7 @@ impl<'a> WorldEditor<'a> {
 
         if should_insert {
             self.world.set_block(x, absolute_y, z, block);
+            self.mark_column(x, z);
         }
     }
 
@@ -517,10 +910,11 @@ impl<'a> WorldEditor<'a> {
         override_blacklist: Option<&[Block]>,
     ) {
         // Check if coordinates are within bounds
-        if !self.xzbbox.contains(&XZPoint::new(x, z)) {
+        if !self.accepts(x, z, || absolute_y - self.get_absolute_y(x, 0, z)) {
             return;
         }
 
+        self.restore_region_at(x, z);
         let should_insert = if let Some(existing_block) = self.world.get_block(x, absolute_y, z) {
             // Check against whitelist and blacklist
             if let Some(whitelist) = override_whitelist {
@@ -540,9 +934,16 @@ impl<'a> WorldEditor<'a> {
 
         if should_insert {
             self.world.set_block(x, absolute_y, z, block);
+            self.mark_column(x, z);
         }
     }
 
+    /// Sets a block outside of the area as well, for the treatment of the world edge
+    pub fn set_block_unclipped(&mut self, block: Block, x: i32, absolute_y: i32, z: i32) {
+        self.restore_region_at(x, z);
+        self.world.set_block(x, absolute_y, z, block);
+    }
+
     /// Sets a block with properties at the given coordinates with absolute Y value.
     #[inline]
     pub fn set_block_with_properties_absolute(
@@ -555,10 +956,11 @@ impl<'a> WorldEditor<'a> {
         override_blacklist: Option<&[Block]>,
     ) {
         // Check if coordinates are within bounds
-        if !self.xzbbox.contains(&XZPoint::new(x, z)) {
+        if !self.accepts(x, z, || absolute_y - self.get_absolute_y(x, 0, z)) {
             return;
         }
 
+        self.restore_region_at(x, z);
         let should_insert = if let Some(existing_block) = self.world.get_block(x, absolute_y, z) {
             // Check against whitelist and blacklist
             if let Some(whitelist) = override_whitelist {
@@ -579,6 +981,7 @@ impl<'a> WorldEditor<'a> {
         if should_insert {
             self.world
                 .set_block_with_properties(x, absolute_y, z, block_with_props);
+            self.mark_column(x, z);
         }
     }
 
@@ -718,8 +1121,8 @@ impl<'a> WorldEditor<'a> {
         self.world.get_block(x, absolute_y, z).is_some()
     }
 
-    /// Helper function to create a base chunk with grass blocks at Y -62
-    fn create_base_chunk(abs_chunk_x: i32, abs_chunk_z: i32) -> (Vec<u8>, bool) {
+    /// Base chunk with grass blocks at Y -62, for chunks of a region that were not modified
+    fn base_chunk() -> Chunk {
         let mut chunk = ChunkToModify::default();
 
         // Fill the bottom layer with grass blocks at Y -62
@@ -729,27 +1132,17 @@ impl<'a> WorldEditor<'a> {
             }
         }
 
-        // Prepare chunk data
-        let chunk_data = Chunk {
+        Chunk {
             sections: chunk.sections().collect(),
-            x_pos: abs_chunk_x,
-            z_pos: abs_chunk_z,
+            x_pos: 0,
+            z_pos: 0,
             is_light_on: 0,
             other: chunk.other,
-        };
-
-        // Create the Level wrapper
-        let level_data = create_level_wrapper(&chunk_data);
-
-        // Serialize the chunk with Level wrapper
-        let mut ser_buffer = Vec::with_capacity(8192);
-        fastnbt::to_writer(&mut ser_buffer, &level_data).unwrap();
-
-        (ser_buffer, true)
+        }
     }
 
     /// Saves all changes made to the world by writing modified chunks to the appropriate region files.
-    pub fn save(&mut self) {
+    pub fn save(&mut self) -> Result<(), ArnisError> {
         println!("{} Saving world...", "[7/7]".bold());
         emit_gui_progress_update(90.0, "Saving world...");
 
@@ -758,8 +1151,21 @@ impl<'a> WorldEditor<'a> {
             eprintln!("Warning: Failed to save world metadata: {}", e);
             // Continue with world saving even if metadata fails
         }
+        if let Some(columns) = self.column_categories.take() {
+            if let Err(e) = schematic::write_column_categories(&self.world_dir, &columns) {
+                eprintln!("Warning: {e}");
+            }
+        }
+
+        // Modifications are dropped region by region once encoded, so memory goes down
+        // while saving instead of holding both the modifications and the encoded chunks
+        let mut regions = std::mem::take(&mut self.world.regions);
+        let spilled = std::mem::take(&mut self.spilled);
+        for position in spilled.keys() {
+            regions.entry(*position).or_default();
+        }
 
-        let total_regions = self.world.regions.len() as u64;
+        let total_regions = regions.len() as u64;
         let save_pb = ProgressBar::new(total_regions);
         save_pb.set_style(
             ProgressStyle::default_bar()
@@ -775,135 +1181,91 @@ impl<'a> WorldEditor<'a> {
         let current_progress = AtomicU64::new(900);
         let regions_processed = AtomicU64::new(0);
 
-        self.world
-            .regions
-            .par_iter()
-            .for_each(|((region_x, region_z), region_to_modify)| {
-                let mut region = self.create_region(*region_x, *region_z);
-                let mut ser_buffer = Vec::with_capacity(8192);
-
-                for (&(chunk_x, chunk_z), chunk_to_modify) in &region_to_modify.chunks {
-                    if !chunk_to_modify.sections.is_empty() || !chunk_to_modify.other.is_empty() {
-                        // Read existing chunk data if it exists
-                        let existing_data = region
-                            .read_chunk(chunk_x as usize, chunk_z as usize)
-                            .unwrap()
-                            .unwrap_or_default();
-
-                        // Parse existing chunk or create new one
-                        let mut chunk: Chunk = if !existing_data.is_empty() {
-                            fastnbt::from_bytes(&existing_data).unwrap()
-                        } else {
-                            Chunk {
-                                sections: Vec::new(),
-                                x_pos: chunk_x + (region_x * 32),
-                                z_pos: chunk_z + (region_z * 32),
-                                is_light_on: 0,
-                                other: FnvHashMap::default(),
-                            }
-                        };
-
-                        // Update sections while preserving existing data
-                        let new_sections: Vec<Section> = chunk_to_modify.sections().collect();
-                        for new_section in new_sections {
-                            if let Some(existing_section) =
-                                chunk.sections.iter_mut().find(|s| s.y == new_section.y)
-                            {
-                                // Merge block states
-                                existing_section.block_states.palette =
-                                    new_section.block_states.palette;
-                                existing_section.block_states.data = new_section.block_states.data;
-                            } else {
-                                // Add new section if it doesn't exist
-                                chunk.sections.push(new_section);
-                            }
-                        }
-
-                        // Preserve existing block entities and merge with new ones
-                        if let Some(existing_entities) = chunk.other.get_mut("block_entities") {
-                            if let Some(new_entities) = chunk_to_modify.other.get("block_entities")
-                            {
-                                if let (Value::List(existing), Value::List(new)) =
-                                    (existing_entities, new_entities)
-                                {
-                                    // Remove old entities that are replaced by new ones
-                                    existing.retain(|e| {
-                                        if let Value::Compound(map) = e {
-                                            let (x, y, z) = get_entity_coords(map);
-                                            !new.iter().any(|new_e| {
-                                                if let Value::Compound(new_map) = new_e {
-                                                    let (nx, ny, nz) = get_entity_coords(new_map);
-                                                    x == nx && y == ny && z == nz
-                                                } else {
-                                                    false
-                                                }
-                                            })
-                                        } else {
-                                            true
-                                        }
-                                    });
-                                    // Add new entities
-                                    existing.extend(new.clone());
-                                }
-                            }
-                        } else {
-                            // If no existing entities, just add the new ones
-                            if let Some(new_entities) = chunk_to_modify.other.get("block_entities")
-                            {
-                                chunk
-                                    .other
-                                    .insert("block_entities".to_string(), new_entities.clone());
-                            }
-                        }
-
-                        // Update chunk coordinates and flags
-                        chunk.x_pos = chunk_x + (region_x * 32);
-                        chunk.z_pos = chunk_z + (region_z * 32);
-
-                        // Create Level wrapper and save
-                        let level_data = create_level_wrapper(&chunk);
-                        ser_buffer.clear();
-                        fastnbt::to_writer(&mut ser_buffer, &level_data).unwrap();
-                        region
-                            .write_chunk(chunk_x as usize, chunk_z as usize, &ser_buffer)
-                            .unwrap();
+        let editor = &*self;
+        let (sender, receiver) = mpsc::sync_channel::<EncodedRegion>(WRITE_QUEUE_DEPTH);
+
+        let written = std::thread::scope(|scope| {
+            // Regions are written to disk while others are encoded. Encoding waits while the
+            // queue is full, so slow disks don't let encoded regions pile up in memory.
+            let writer = scope.spawn(|| {
+                for encoded in receiver {
+                    // Stops encoding as well, sending fails once the receiver is dropped
+                    editor.write_region(encoded)?;
+
+                    // Update progress
+                    let regions_done = regions_processed.fetch_add(1, Ordering::SeqCst);
+                    let new_progress =
+                        (90.0 + (regions_done as f64 * progress_increment_save)) * 10.0;
+                    let prev_progress =
+                        current_progress.fetch_max(new_progress as u64, Ordering::SeqCst);
+
+                    if new_progress as u64 - prev_progress > 1 {
+                        emit_gui_progress_update(new_progress / 10.0, "Saving world...");
                     }
-                }
 
-                // Second pass: ensure all chunks exist
-                for chunk_x in 0..32 {
-                    for chunk_z in 0..32 {
-                        let abs_chunk_x = chunk_x + (region_x * 32);
-                        let abs_chunk_z = chunk_z + (region_z * 32);
-
-                        // Check if chunk exists in our modifications
-                        let chunk_exists =
-                            region_to_modify.chunks.contains_key(&(chunk_x, chunk_z));
-
-                        // If chunk doesn't exist, create it with base layer
-                        if !chunk_exists {
-                            let (ser_buffer, _) = Self::create_base_chunk(abs_chunk_x, abs_chunk_z);
-                            region
-                                .write_chunk(chunk_x as usize, chunk_z as usize, &ser_buffer)
-                                .unwrap();
-                        }
-                    }
+                    save_pb.inc(1);
                 }
+                Ok(())
+            });
 
-                // Update progress
-                let regions_done = regions_processed.fetch_add(1, Ordering::SeqCst);
-                let new_progress = (90.0 + (regions_done as f64 * progress_increment_save)) * 10.0;
-                let prev_progress =
-                    current_progress.fetch_max(new_progress as u64, Ordering::SeqCst);
-
-                if new_progress as u64 - prev_progress > 1 {
-                    emit_gui_progress_update(new_progress / 10.0, "Saving world...");
+            let encode = |sender: &mpsc::SyncSender<EncodedRegion>,
+                          ((region_x, region_z), region_to_modify): (
+                (i32, i32),
+                RegionToModify,
+            )| {
+                let mut region_to_modify = match spilled.get(&(region_x, region_z)) {
+                    Some(path) => read_spilled(path),
+                    None => region_to_modify,
+                };
+                if let Some(seed) = editor.weathering {
+                    region_to_modify.weather(seed, (region_x, region_z));
                 }
-
-                save_pb.inc(1);
-            });
+                if editor.season != Season::Summer {
+                    region_to_modify.apply_season(editor.season);
+                }
+                let encoded = encode_region(region_x, region_z, &region_to_modify);
+                drop(region_to_modify);
+                sender.send(encoded).map_err(|_| ())
+            };
+            if spilled.is_empty() {
+                let _ = regions
+                    .into_par_iter()
+                    .try_for_each_with(sender, |sender, region| encode(sender, region));
+            } else {
+                // Memory is short, so only one region is restored and encoded at a time
+                for region in regions {
+                    if encode(&sender, region).is_err() {
+                        break;
+                    }
+                }
+                drop(sender);
+            }
+            writer.join().unwrap()
+        });
 
         save_pb.finish();
+        if !spilled.is_empty() {
+            let _ = std::fs::remove_dir_all(&self.spill_dir);
+        }
+        written
+    }
+
+    /// Writes the encoded chunks of a region to its region file
+    fn write_region(&self, encoded: EncodedRegion) -> Result<(), ArnisError> {
+        let mut region = self.create_region(encoded.x, encoded.z)?;
+
+        for ((chunk_x, chunk_z), buffer) in encoded.chunks {
+            region
+                .write_chunk(chunk_x as usize, chunk_z as usize, &buffer)
+                .map_err(|e| {
+                    ArnisError::Io(format!(
+                        "Failed to write region {} {}: {e}",
+                        encoded.x, encoded.z
+                    ))
+                })?;
+            disk_io::throttle_write(buffer.len());
+        }
+        Ok(())
     }
 
     fn save_metadata(&mut self) -> Result<(), Box<dyn std::error::Error>> {
@@ -937,27 +1299,318 @@ impl<'a> WorldEditor<'a> {
 
         Ok(())
     }
+
+    /// Writes the modifications made so far to `path`, to continue from them later. Regions
+    /// moved to disk are copied next to it instead of being read back into memory.
+    pub fn write_snapshot(&self, path: &Path) -> Result<(), ArnisError> {
+        for (&position, spilled) in &self.spilled {
+            let copy = path.with_file_name(spill_file_name(position));
+            std::fs::copy(spilled, &copy)
+                .map_err(|e| ArnisError::Io(format!("Failed to write {}: {e}", copy.display())))?;
+        }
+        let chunks: Vec<SnapshotChunk> = self
+            .world
+            .regions
+            .iter()
+            .flat_map(|(&position, region)| snapshot_chunks(position, region))
+            .collect();
+        let spilled = self
+            .spilled
+            .keys()
+            .map(|&(x, z)| SnapshotRegion { x, z })
+            .collect();
+
+        // Replace the previous snapshot only once the new one is complete
+        let partial = path.with_extension("partial");
+        write_compressed(&Snapshot { chunks, spilled }, &partial)?;
+        std::fs::rename(&partial, path)
+            .map_err(|e| ArnisError::Io(format!("Failed to write {}: {e}", path.display())))
+    }
+
+    /// Restores the modifications written by `write_snapshot`
+    pub fn load_snapshot(&mut self, path: &Path) -> Result<(), ArnisError> {
+        let snapshot = read_compressed(path)?;
+        for chunk in snapshot.chunks {
+            let ((x, z), restored) = restore_chunk(chunk)
+                .map_err(|e| ArnisError::Parse(format!("{e} in {}", path.display())))?;
+            self.world.sections += restored.sections.len();
+            self.world
+                .get_or_create_region(x >> 5, z >> 5)
+                .chunks
+                .insert((x & 31, z & 31), restored);
+        }
+
+        // Regions that were on disk stay there, the snapshot keeps its copies for later runs
+        if !snapshot.spilled.is_empty() {
+            let dir = &self.spill_dir;
+            std::fs::create_dir_all(dir)
+                .map_err(|e| ArnisError::Io(format!("Failed to create {}: {e}", dir.display())))?;
+        }
+        for SnapshotRegion { x, z } in snapshot.spilled {
+            let copy = path.with_file_name(spill_file_name((x, z)));
+            let spilled = self.spill_dir.join(spill_file_name((x, z)));
+            std::fs::copy(&copy, &spilled)
+                .map_err(|e| ArnisError::Io(format!("Failed to read {}: {e}", copy.display())))?;
+            self.spilled.insert((x, z), spilled);
+        }
+        Ok(())
+    }
 }
 
-// Helper function to get entity coordinates
-#[inline]
-fn get_entity_coords(entity: &HashMap<String, Value>) -> (i32, i32, i32) {
-    let x = if let Value::Int(x) = entity.get("x").unwrap_or(&Value::Int(0)) {
-        *x
-    } else {
-        0
+/// Share of the memory budget for the modifications, the rest is left for the elements and
+/// for encoding the regions when saving
+const MODIFICATIONS_MEMORY_SHARE: f64 = 0.5;
+
+/// Directory of the regions moved to disk by this process
+fn default_spill_dir() -> PathBuf {
+    disk_io::temp_dir().join(format!("arnis-spill-{}", std::process::id()))
+}
+
+/// Name of the file a region is moved to
+fn spill_file_name((region_x, region_z): (i32, i32)) -> String {
+    format!("r.{region_x}.{region_z}.nbt.gz")
+}
+
+/// The modifications of a region moved to disk
+fn read_spilled(path: &Path) -> RegionToModify {
+    let mut region = RegionToModify::default();
+    let chunks = match read_compressed(path) {
+        Ok(snapshot) => snapshot.chunks,
+        Err(e) => {
+            eprintln!("Warning: Lost the modifications moved to disk: {e}");
+            return region;
+        }
     };
-    let y = if let Value::Int(y) = entity.get("y").unwrap_or(&Value::Int(0)) {
-        *y
-    } else {
-        0
+    for chunk in chunks {
+        match restore_chunk(chunk) {
+            Ok(((x, z), chunk)) => {
+                region.chunks.insert((x & 31, z & 31), chunk);
+            }
+            Err(e) => eprintln!("Warning: {e} in {}", path.display()),
+        }
+    }
+    region
+}
+
+fn snapshot_chunks(
+    (region_x, region_z): (i32, i32),
+    region: &RegionToModify,
+) -> impl Iterator<Item = SnapshotChunk> + '_ {
+    region.chunks.iter().map(move |(&(x, z), chunk)| {
+        let sections = chunk
+            .sections
+            .iter()
+            .map(|(&y, section)| SnapshotSection {
+                y,
+                blocks: ByteArray::new(
+                    section
+                        .blocks
+                        .iter()
+                        .map(|block| block.id() as i8)
+                        .collect(),
+                ),
+                properties: section
+                    .properties
+                    .iter()
+                    .map(|(&index, value)| SnapshotProperty {
+                        index: index as i32,
+                        value: value.clone(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        SnapshotChunk {
+            x: region_x * 32 + x,
+            z: region_z * 32 + z,
+            sections,
+            other: chunk.other.clone(),
+        }
+    })
+}
+
+/// A chunk of a snapshot with its absolute chunk coordinates
+fn restore_chunk(chunk: SnapshotChunk) -> Result<((i32, i32), ChunkToModify), String> {
+    let mut restored = ChunkToModify {
+        sections: FnvHashMap::default(),
+        other: chunk.other,
+        biome: None,
     };
-    let z = if let Value::Int(z) = entity.get("z").unwrap_or(&Value::Int(0)) {
-        *z
-    } else {
-        0
+    for section in chunk.sections {
+        if section.blocks.len() != 4096 {
+            return Err("Invalid section".to_string());
+        }
+        let mut blocks = [AIR; 4096];
+        for (block, &id) in blocks.iter_mut().zip(section.blocks.iter()) {
+            *block = Block::from_id(id as u8)?;
+        }
+        let properties = section
+            .properties
+            .into_iter()
+            .map(|property| (property.index as usize, property.value))
+            .collect();
+        restored
+            .sections
+            .insert(section.y, SectionToModify { blocks, properties });
+    }
+    Ok(((chunk.x, chunk.z), restored))
+}
+
+fn write_compressed(snapshot: &Snapshot, path: &Path) -> Result<(), ArnisError> {
+    let bytes = fastnbt::to_bytes(snapshot)
+        .map_err(|e| ArnisError::Io(format!("Failed to encode snapshot: {e}")))?;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    encoder
+        .write_all(&bytes)
+        .map_err(|e| ArnisError::Io(format!("Failed to compress snapshot: {e}")))?;
+    let compressed = encoder
+        .finish()
+        .map_err(|e| ArnisError::Io(format!("Failed to compress snapshot: {e}")))?;
+    std::fs::write(path, compressed)
+        .map_err(|e| ArnisError::Io(format!("Failed to write {}: {e}", path.display())))
+}
+
+fn read_compressed(path: &Path) -> Result<Snapshot, ArnisError> {
+    let compressed = std::fs::read(path)
+        .map_err(|e| ArnisError::Io(format!("Failed to read {}: {e}", path.display())))?;
+    let mut bytes = Vec::new();
+    GzDecoder::new(compressed.as_slice())
+        .read_to_end(&mut bytes)
+        .map_err(|e| ArnisError::Parse(format!("Failed to decompress {}: {e}", path.display())))?;
+    fastnbt::from_bytes(&bytes)
+        .map_err(|e| ArnisError::Parse(format!("Failed to parse {}: {e}", path.display())))
+}
+
+/// Modifications of the world as written by `WorldEditor::write_snapshot`
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    chunks: Vec<SnapshotChunk>,
+    /// Regions moved to disk, in files next to the snapshot
+    #[serde(default)]
+    spilled: Vec<SnapshotRegion>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotRegion {
+    x: i32,
+    z: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotChunk {
+    x: i32,
+    z: i32,
+    sections: Vec<SnapshotSection>,
+    other: FnvHashMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotSection {
+    y: i8,
+    blocks: ByteArray,
+    properties: Vec<SnapshotProperty>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotProperty {
+    index: i32,
+    value: Value,
+}
+
+/// Encoded regions waiting to be written, before encoding waits for the disk. While
+/// elements are processed, `enforce_memory_budget` bounds the memory instead.
+const WRITE_QUEUE_DEPTH: usize = 2;
+/// Initial capacity of the buffers chunks are encoded into
+const CHUNK_BUFFER_CAPACITY: usize = 8192;
+
+thread_local! {
+    /// Scratch buffer for the NBT of chunks, one per thread, so it grows once instead of for
+    /// every chunk
+    static CHUNK_BUFFER: RefCell<Vec<u8>> =
+        RefCell::new(Vec::with_capacity(CHUNK_BUFFER_CAPACITY));
+}
+
+/// The NBT of a chunk, encoded in the scratch buffer of this thread and copied out at its
+/// exact size
+fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    CHUNK_BUFFER.with_borrow_mut(|buffer| {
+        buffer.clear();
+        fastnbt::to_writer(&mut *buffer, &create_level_wrapper(chunk)).unwrap();
+        buffer.clone()
+    })
+}
+
+/// The NBT of all chunks of a region, ready to be written
+struct EncodedRegion {
+    x: i32,
+    z: i32,
+    chunks: Vec<((i32, i32), Vec<u8>)>,
+}
+
+/// Encodes the modified chunks of a region in parallel, and base chunks for the others
+fn encode_region(region_x: i32, region_z: i32, region_to_modify: &RegionToModify) -> EncodedRegion {
+    let mut chunks: Vec<((i32, i32), Vec<u8>)> = region_to_modify
+        .chunks
+        .par_iter()
+        .filter(|(_, chunk)| !chunk.sections.is_empty() || !chunk.other.is_empty())
+        .map(|(&(chunk_x, chunk_z), chunk_to_modify)| {
+            // Region files are recreated from the template, so there is no existing data
+            let chunk = build_chunk(
+                chunk_to_modify,
+                chunk_x + (region_x * 32),
+                chunk_z + (region_z * 32),
+            );
+            ((chunk_x, chunk_z), encode_chunk(&chunk))
+        })
+        .collect();
+
+    // Ensure all chunks exist
+    let mut base_chunk = WorldEditor::base_chunk();
+    for chunk_x in 0..32 {
+        for chunk_z in 0..32 {
+            if !region_to_modify.chunks.contains_key(&(chunk_x, chunk_z)) {
+                base_chunk.x_pos = chunk_x + (region_x * 32);
+                base_chunk.z_pos = chunk_z + (region_z * 32);
+                chunks.push(((chunk_x, chunk_z), encode_chunk(&base_chunk)));
+            }
+        }
+    }
+
+    EncodedRegion {
+        x: region_x,
+        z: region_z,
+        chunks,
+    }
+}
+
+/// A chunk with the modifications of `chunk_to_modify`
+fn build_chunk(chunk_to_modify: &ChunkToModify, x_pos: i32, z_pos: i32) -> Chunk {
+    let mut chunk = Chunk {
+        sections: chunk_to_modify.sections().collect(),
+        x_pos,
+        z_pos,
+        is_light_on: 0,
+        other: FnvHashMap::default(),
     };
-    (x, y, z)
+
+    // The biome goes to every section, so the whole chunk matches
+    if let Some(biome) = chunk_to_modify.biome {
+        for section in &mut chunk.sections {
+            section.other.insert(
+                "biomes".to_string(),
+                Value::Compound(HashMap::from([(
+                    "palette".to_string(),
+                    Value::List(vec![Value::String(biome.to_string())]),
+                )])),
+            );
+        }
+    }
+
+    if let Some(block_entities) = chunk_to_modify.other.get("block_entities") {
+        chunk
+            .other
+            .insert("block_entities".to_string(), block_entities.clone());
+    }
+    chunk
 }
 
 #[inline]
@@ -1013,10 +1666,14 @@ fn create_level_wrapper(chunk: &Chunk) -> HashMap<String, Value> {
                                 }
                             }
 
-                            Value::Compound(HashMap::from([
+                            let mut section_nbt = HashMap::from([
                                 ("Y".to_string(), Value::Byte(section.y)),
                                 ("block_states".to_string(), Value::Compound(block_states)),
-                            ]))
+                            ]);
+                            if let Some(biomes) = section.other.get("biomes") {
+                                section_nbt.insert("biomes".to_string(), biomes.clone());
+                            }
+                            Value::Compound(section_nbt)
                         })
                         .collect(),
                 ),
@@ -1024,3 +1681,115 @@ fn create_level_wrapper(chunk: &Chunk) -> HashMap<String, Value> {
         ])),
     )])
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utilities::get_llbbox_arnis;
+
+    #[test]
+    fn test_memory_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let xzbbox = XZBBox::rect_from_xz_lengths(100.0, 100.0).unwrap();
+        let mut editor = WorldEditor::new(dir.path().to_path_buf(), &xzbbox, get_llbbox_arnis());
+        editor.set_spill_dir(dir.path().join("spill"));
+
+        // Room for a single section
+        editor.set_memory_budget(SECTION_BYTES * 2);
+        editor.set_block_absolute(STONE, 10, 0, 10, None, None);
+        editor.set_block_absolute(STONE, 10, 16, 10, None, None);
+        editor.enforce_memory_budget();
+        assert!(editor.world.regions.is_empty());
+        assert_eq!(editor.world.sections, 0);
+
+        // Blocks placed before the region moved to disk are kept, as without the budget
+        editor.set_block_absolute(DIRT, 10, 0, 10, None, None);
+        editor.set_block_absolute(DIRT, 10, 1, 10, None, None);
+        assert!(editor.spilled.is_empty());
+        assert_eq!(editor.world.sections, 2);
+        let chunk = editor.world.regions[&(0, 0)].get_chunk(0, 0).unwrap();
+        assert_eq!(chunk.get_block(10, 0, 10), Some(STONE));
+        assert_eq!(chunk.get_block(10, 1, 10), Some(DIRT));
+        assert_eq!(chunk.get_block(10, 16, 10), Some(STONE));
+
+        // Releasing the memory moves regions within the budget as well
+        editor.release_memory();
+        assert!(editor.world.regions.is_empty());
+        assert!(editor.spilled[&(0, 0)].exists());
+    }
+
+    #[test]
+    fn test_snapshot_with_spilled_regions() {
+        let dir = tempfile::tempdir().unwrap();
+        let xzbbox = XZBBox::rect_from_xz_lengths(1100.0, 100.0).unwrap();
+        let snapshot = dir.path().join("snapshot").join("world.nbt.gz");
+        std::fs::create_dir_all(snapshot.parent().unwrap()).unwrap();
+
+        let mut editor = WorldEditor::new(dir.path().to_path_buf(), &xzbbox, get_llbbox_arnis());
+        editor.set_spill_dir(dir.path().join("spill"));
+        editor.set_memory_budget(SECTION_BYTES * 4);
+        editor.set_block_absolute(STONE, 10, 0, 10, None, None);
+        editor.set_block_absolute(STONE, 10, 16, 10, None, None);
+        editor.set_block_absolute(STONE, 10, 32, 10, None, None);
+        editor.set_block_absolute(GLASS, 1030, 0, 10, None, None);
+        editor.enforce_memory_budget();
+        assert_eq!(editor.spilled.len(), 1);
+        assert_eq!(editor.world.regions.len(), 1);
+        editor.write_snapshot(&snapshot).unwrap();
+
+        // The spilled region stays on disk when restored, until it is changed
+        let mut restored = WorldEditor::new(dir.path().to_path_buf(), &xzbbox, get_llbbox_arnis());
+        restored.set_spill_dir(dir.path().join("restored"));
+        restored.load_snapshot(&snapshot).unwrap();
+        assert!(restored.spilled.contains_key(&(0, 0)));
+        assert!(restored.block_at_absolute(1030, 0, 10));
+        restored.set_block_absolute(DIRT, 10, 0, 10, None, None);
+        assert!(restored.check_for_block_absolute(10, 0, 10, Some(&[STONE]), None));
+        assert!(restored.block_at_absolute(10, 32, 10));
+    }
+
+    #[test]
+    fn test_spilled_region_restores() {
+        let dir = tempfile::tempdir().unwrap();
+        let xzbbox = XZBBox::rect_from_xz_lengths(1100.0, 100.0).unwrap();
+        let mut editor = WorldEditor::new(dir.path().to_path_buf(), &xzbbox, get_llbbox_arnis());
+        editor.set_spill_dir(dir.path().join("spill"));
+
+        editor.set_block_absolute(SPRUCE_LOG_NORTH_SOUTH, 1030, 5, 10, None, None);
+        editor.set_block_absolute(GLASS, 1030, 6, 10, None, None);
+        editor.set_block_absolute(GRASS_BLOCK, 1030, 7, 10, None, None);
+        editor.release_memory();
+        assert!(editor.world.regions.is_empty());
+
+        let path = editor.spilled[&(2, 0)].clone();
+        let restored = read_spilled(&path);
+        let chunk = restored.get_chunk(0, 0).unwrap();
+        assert_eq!(chunk.get_block(6, 5, 10), Some(SPRUCE_LOG_NORTH_SOUTH));
+        assert_eq!(chunk.get_block(6, 6, 10), Some(GLASS));
+
+        // Overrides placed after the region moved to disk apply to the blocks placed before
+        editor.set_block_absolute(STONE, 1030, 6, 10, Some(&[GLASS]), None);
+        editor.set_block_absolute(DIRT, 1030, 5, 10, Some(&[GLASS]), None);
+        editor.set_block_absolute(STONE, 1030, 7, 10, None, Some(&[GRASS_BLOCK]));
+        editor.release_memory();
+        let restored = read_spilled(&editor.spilled[&(2, 0)]);
+        let chunk = restored.get_chunk(0, 0).unwrap();
+        assert_eq!(chunk.get_block(6, 5, 10), Some(SPRUCE_LOG_NORTH_SOUTH));
+        assert_eq!(chunk.get_block(6, 6, 10), Some(STONE));
+        assert_eq!(chunk.get_block(6, 7, 10), Some(GRASS_BLOCK));
+
+        // Ids no block has are an error instead of a panic
+        assert!(Block::from_id(150).is_err());
+        let invalid = SnapshotChunk {
+            x: 0,
+            z: 0,
+            sections: vec![SnapshotSection {
+                y: 0,
+                blocks: ByteArray::new(vec![150u8 as i8; 4096]),
+                properties: Vec::new(),
+            }],
+            other: FnvHashMap::default(),
+        };
+        assert!(restore_chunk(invalid).is_err());
+    }
+}
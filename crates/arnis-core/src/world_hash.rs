@@ -0,0 +1,168 @@
+//! Canonical hash of a generated world, used by `--verify` to check that repeated runs with
+//! the same seed produce the exact same blocks.
+//!
+//! Chunks are digested in a fixed order (region, then chunk coordinates) and NBT compounds
+//! with their keys sorted, so the hash doesn't depend on the order of parallel writes or the
+//! iteration order of hash maps.
+
+use fastanvil::Region;
+use fastnbt::Value;
+use fnv::FnvHasher;
+use std::fs::File;
+use std::hash::Hasher;
+use std::path::Path;
+
+/// Hex encoded hash over all chunks of all region files of the world
+pub fn world_hash(world_dir: &Path) -> Result<String, String> {
+    let region_dir = world_dir.join("region");
+    let mut regions: Vec<(i32, i32)> = std::fs::read_dir(&region_dir)
+        .map_err(|e| format!("Failed to read {}: {e}", region_dir.display()))?
+        .filter_map(|entry| region_coords(&entry.ok()?.file_name().to_string_lossy()))
+        .collect();
+    regions.sort_unstable();
+
+    let mut hasher = FnvHasher::default();
+    for (region_x, region_z) in regions {
+        let path = region_dir.join(format!("r.{region_x}.{region_z}.mca"));
+        let file =
+            File::open(&path).map_err(|e| format!("Failed to open {}: {e}", path.display()))?;
+        let mut region = Region::from_stream(file)
+            .map_err(|e| format!("Failed to load {}: {e}", path.display()))?;
+
+        for chunk_x in 0..32 {
+            for chunk_z in 0..32 {
+                let Some(data) = region
+                    .read_chunk(chunk_x, chunk_z)
+                    .map_err(|e| format!("Failed to read chunk in {}: {e}", path.display()))?
+                else {
+                    continue;
+                };
+                let chunk: Value = fastnbt::from_bytes(&data)
+                    .map_err(|e| format!("Failed to parse chunk in {}: {e}", path.display()))?;
+
+                hasher.write_i32(region_x);
+                hasher.write_i32(region_z);
+                hasher.write_usize(chunk_x);
+                hasher.write_usize(chunk_z);
+                hash_value(&mut hasher, &chunk);
+            }
+        }
+    }
+
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Coordinates from a region file name like `r.-1.2.mca`
+fn region_coords(file_name: &str) -> Option<(i32, i32)> {
+    let coords = file_name.strip_prefix("r.")?.strip_suffix(".mca")?;
+    let (x, z) = coords.split_once('.')?;
+    Some((x.parse().ok()?, z.parse().ok()?))
+}
+
+/// Feeds an NBT value into the hasher, with compound keys in sorted order
+fn hash_value(hasher: &mut FnvHasher, value: &Value) {
+    match value {
+        Value::Byte(v) => {
+            hasher.write_u8(1);
+            hasher.write_i8(*v);
+        }
+        Value::Short(v) => {
+            hasher.write_u8(2);
+            hasher.write_i16(*v);
+        }
+        Value::Int(v) => {
+            hasher.write_u8(3);
+            hasher.write_i32(*v);
+        }
+        Value::Long(v) => {
+            hasher.write_u8(4);
+            hasher.write_i64(*v);
+        }
+        Value::Float(v) => {
+            hasher.write_u8(5);
+            hasher.write_u32(v.to_bits());
+        }
+        Value::Double(v) => {
+            hasher.write_u8(6);
+            hasher.write_u64(v.to_bits());
+        }
+        Value::ByteArray(v) => {
+            hasher.write_u8(7);
+            hasher.write_usize(v.len());
+            v.iter().for_each(|b| hasher.write_i8(*b));
+        }
+        Value::String(v) => {
+            hasher.write_u8(8);
+            hasher.write_usize(v.len());
+            hasher.write(v.as_bytes());
+        }
+        Value::List(v) => {
+            hasher.write_u8(9);
+            hasher.write_usize(v.len());
+            v.iter().for_each(|item| hash_value(hasher, item));
+        }
+        Value::Compound(v) => {
+            hasher.write_u8(10);
+            hasher.write_usize(v.len());
+            let mut entries: Vec<(&String, &Value)> = v.iter().collect();
+            entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+            for (key, item) in entries {
+                hasher.write_usize(key.len());
+                hasher.write(key.as_bytes());
+                hash_value(hasher, item);
+            }
+        }
+        Value::IntArray(v) => {
+            hasher.write_u8(11);
+            hasher.write_usize(v.len());
+            v.iter().for_each(|i| hasher.write_i32(*i));
+        }
+        Value::LongArray(v) => {
+            hasher.write_u8(12);
+            hasher.write_usize(v.len());
+            v.iter().for_each(|l| hasher.write_i64(*l));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn digest(value: &Value) -> u64 {
+        let mut hasher = FnvHasher::default();
+        hash_value(&mut hasher, value);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_compound_order_does_not_matter() {
+        let mut a = HashMap::new();
+        let mut b = HashMap::new();
+        for i in 0..50 {
+            a.insert(format!("key{i}"), Value::Int(i));
+        }
+        for i in (0..50).rev() {
+            b.insert(format!("key{i}"), Value::Int(i));
+        }
+        assert_eq!(digest(&Value::Compound(a)), digest(&Value::Compound(b)));
+    }
+
+    #[test]
+    fn test_values_are_distinguished() {
+        assert_ne!(digest(&Value::Int(1)), digest(&Value::Long(1)));
+        assert_ne!(
+            digest(&Value::List(vec![Value::Int(1), Value::Int(2)])),
+            digest(&Value::List(vec![Value::Int(2), Value::Int(1)]))
+        );
+    }
+
+    #[test]
+    fn test_region_coords() {
+        assert_eq!(region_coords("r.-1.2.mca"), Some((-1, 2)));
+        assert_eq!(region_coords("r.0.0.mca"), Some((0, 0)));
+        assert_eq!(region_coords("level.dat"), None);
+        assert_eq!(region_coords("r.a.0.mca"), None);
+    }
+}